@@ -9,8 +9,23 @@ CREATE TABLE Users (
     firebase_user_id VARCHAR(255) NOT NULL UNIQUE,
     user_email VARCHAR(255) NOT NULL UNIQUE,
     user_name VARCHAR(255) NOT NULL,
+    distance_unit ENUM('km', 'mi') NOT NULL DEFAULT 'km',
+    volume_unit ENUM('l', 'us_gal', 'imp_gal') NOT NULL DEFAULT 'l',
+    fuel_economy_unit ENUM('km_per_l', 'l_per_100km', 'mpg_us', 'mpg_imp') NOT NULL DEFAULT 'km_per_l',
+    currency CHAR(3) NOT NULL DEFAULT 'JPY',
+    time_zone VARCHAR(64) NOT NULL DEFAULT 'Asia/Tokyo',
+    language ENUM('ja', 'en') NULL,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP
+    updated_at TIMESTAMP(6) DEFAULT CURRENT_TIMESTAMP(6) ON UPDATE CURRENT_TIMESTAMP(6)
+);
+
+CREATE TABLE AccountDeletions (
+    firebase_user_id VARCHAR(255) PRIMARY KEY,
+    delete_firebase_user BOOLEAN NOT NULL DEFAULT TRUE,
+    requested_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    scheduled_for TIMESTAMP NOT NULL,
+    FOREIGN KEY (firebase_user_id) REFERENCES Users(firebase_user_id),
+    INDEX (scheduled_for)
 );
 
 CREATE TABLE Cars (
@@ -22,13 +37,57 @@ CREATE TABLE Cars (
     car_isflooding BOOLEAN NOT NULL,
     car_issmoked BOOLEAN NOT NULL,
     car_image_url VARCHAR(255),
+    car_maker VARCHAR(255) NULL,
+    car_model VARCHAR(255) NULL,
+    car_grade VARCHAR(255) NULL,
+    car_year INT NULL,
+    car_displacement INT NULL,
+    car_fuel_type ENUM('gasoline', 'premium_gasoline', 'diesel', 'hybrid', 'plugin_hybrid', 'electric', 'hydrogen', 'lpg') NULL,
+    car_tank_capacity FLOAT NULL,
+    car_weight INT NULL,
+    car_transmission ENUM('mt', 'at', 'cvt', 'dct') NULL,
+    car_first_registration VARCHAR(255) NULL,
+    car_plate_number VARCHAR(32) NULL,
+    car_vin VARCHAR(32) NULL,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP
+    updated_at TIMESTAMP(6) DEFAULT CURRENT_TIMESTAMP(6) ON UPDATE CURRENT_TIMESTAMP(6),
+    deleted_at TIMESTAMP NULL,
+    INDEX (deleted_at)
+);
+
+-- 型式ごとの諸元。車両の型式から空欄の諸元を補うのに使う
+CREATE TABLE VehicleSpecs (
+    model_code VARCHAR(32) PRIMARY KEY,
+    maker VARCHAR(255) NOT NULL,
+    model VARCHAR(255) NOT NULL,
+    year_from INT NOT NULL,
+    year_to INT NULL,
+    displacement INT NULL,
+    fuel_type ENUM('gasoline', 'premium_gasoline', 'diesel', 'hybrid', 'plugin_hybrid', 'electric', 'hydrogen', 'lpg') NOT NULL,
+    tank_capacity FLOAT NULL,
+    transmission ENUM('mt', 'at', 'cvt', 'dct') NULL
 );
 
+-- 変速機が複数ある型式は transmission を NULL にしておく
+INSERT INTO VehicleSpecs (model_code, maker, model, year_from, year_to, displacement, fuel_type, tank_capacity, transmission) VALUES
+    ('ZVW30', 'トヨタ', 'プリウス', 2009, 2015, 1797, 'hybrid', 45, 'cvt'),
+    ('ZVW50', 'トヨタ', 'プリウス', 2015, 2022, 1797, 'hybrid', 43, 'cvt'),
+    ('NHP10', 'トヨタ', 'アクア', 2011, 2021, 1496, 'hybrid', 36, 'cvt'),
+    ('AE86', 'トヨタ', 'カローラレビン / スプリンタートレノ', 1983, 1987, 1587, 'premium_gasoline', 50, 'mt'),
+    ('GE8', 'ホンダ', 'フィット', 2007, 2013, 1496, 'gasoline', 42, NULL),
+    ('GK3', 'ホンダ', 'フィット', 2013, 2020, 1317, 'gasoline', 40, 'cvt'),
+    ('E12', '日産', 'ノート', 2012, 2020, 1198, 'gasoline', 41, 'cvt'),
+    ('ZE1', '日産', 'リーフ', 2017, NULL, NULL, 'electric', NULL, NULL),
+    ('ND5RC', 'マツダ', 'ロードスター', 2015, NULL, 1496, 'premium_gasoline', 40, NULL),
+    ('FD3S', 'マツダ', 'RX-7', 1991, 2002, 1308, 'premium_gasoline', 76, NULL),
+    ('GRB', 'スバル', 'インプレッサ WRX STI', 2007, 2014, 1994, 'premium_gasoline', 60, 'mt'),
+    ('ZC33S', 'スズキ', 'スイフトスポーツ', 2017, NULL, 1371, 'premium_gasoline', 37, NULL),
+    ('JB64W', 'スズキ', 'ジムニー', 2018, NULL, 658, 'gasoline', 40, NULL);
+
 CREATE TABLE user_car (
     firebase_user_id VARCHAR(255) NOT NULL,
     car_id INT NOT NULL,
+    role ENUM('owner', 'editor', 'viewer') NOT NULL DEFAULT 'owner',
     FOREIGN KEY (firebase_user_id) REFERENCES Users(firebase_user_id),
     FOREIGN KEY (car_id) REFERENCES Cars(car_id),
     PRIMARY KEY (firebase_user_id, car_id),
@@ -36,16 +95,67 @@ CREATE TABLE user_car (
     INDEX (car_id)
 );
 
+CREATE TABLE CarInvitations (
+    invitation_id INT AUTO_INCREMENT PRIMARY KEY,
+    car_id INT NOT NULL,
+    inviter_user_id VARCHAR(255) NOT NULL,
+    invitee_email VARCHAR(255) NOT NULL,
+    role ENUM('editor', 'viewer') NOT NULL,
+    status ENUM('pending', 'accepted', 'declined', 'revoked') NOT NULL DEFAULT 'pending',
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    FOREIGN KEY (car_id) REFERENCES Cars(car_id),
+    FOREIGN KEY (inviter_user_id) REFERENCES Users(firebase_user_id),
+    INDEX (car_id),
+    INDEX (invitee_email)
+);
+
+CREATE TABLE CarShareLinks (
+    share_link_id INT AUTO_INCREMENT PRIMARY KEY,
+    car_id INT NOT NULL,
+    token VARCHAR(64) NOT NULL UNIQUE,
+    sections JSON NOT NULL,
+    expires_at TIMESTAMP NULL,
+    revoked_at TIMESTAMP NULL,
+    created_by VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    FOREIGN KEY (car_id) REFERENCES Cars(car_id),
+    FOREIGN KEY (created_by) REFERENCES Users(firebase_user_id),
+    INDEX (car_id)
+);
+
+-- 譲渡ログは車両削除後も売り手のスナップショットとして残すため car_id に外部キーを張らない
+CREATE TABLE CarTransfers (
+    transfer_id INT AUTO_INCREMENT PRIMARY KEY,
+    car_id INT NOT NULL,
+    from_user_id VARCHAR(255) NOT NULL,
+    to_email VARCHAR(255) NOT NULL,
+    to_user_id VARCHAR(255),
+    keep_snapshot BOOLEAN NOT NULL DEFAULT FALSE,
+    status ENUM('pending', 'accepted', 'declined', 'cancelled') NOT NULL DEFAULT 'pending',
+    snapshot JSON,
+    completed_at TIMESTAMP NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    FOREIGN KEY (from_user_id) REFERENCES Users(firebase_user_id),
+    INDEX (car_id),
+    INDEX (to_email)
+);
+
 CREATE TABLE Tunings (
     tuning_id INT AUTO_INCREMENT PRIMARY KEY,
     car_id INT NOT NULL,
     tuning_name VARCHAR(255) NOT NULL,
     tuning_date VARCHAR(255) NOT NULL,
     tuning_description TEXT NOT NULL,
+    tuning_removed_date VARCHAR(255) NULL,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP(6) DEFAULT CURRENT_TIMESTAMP(6) ON UPDATE CURRENT_TIMESTAMP(6),
+    deleted_at TIMESTAMP NULL,
     FOREIGN KEY (car_id) REFERENCES Cars(car_id),
-    INDEX (car_id)
+    INDEX (car_id),
+    INDEX (deleted_at)
 );
 
 CREATE TABLE Maintenances (
@@ -56,9 +166,49 @@ CREATE TABLE Maintenances (
     maint_date VARCHAR(255) NOT NULL,
     maint_description TEXT NOT NULL,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP(6) DEFAULT CURRENT_TIMESTAMP(6) ON UPDATE CURRENT_TIMESTAMP(6),
+    deleted_at TIMESTAMP NULL,
     FOREIGN KEY (car_id) REFERENCES Cars(car_id),
-    INDEX (car_id)
+    INDEX (car_id),
+    INDEX (deleted_at)
+);
+
+-- ユーザーごとの部品・消耗品の在庫
+CREATE TABLE Parts (
+    part_id INT AUTO_INCREMENT PRIMARY KEY,
+    firebase_user_id VARCHAR(255) NOT NULL,
+    part_number VARCHAR(255) NOT NULL,
+    part_name VARCHAR(255) NOT NULL,
+    part_brand VARCHAR(255) NULL,
+    quantity INT NOT NULL DEFAULT 0,
+    unit_cost DECIMAL(14, 4) NULL,
+    cost_currency CHAR(3) NOT NULL DEFAULT 'JPY',
+    supplier VARCHAR(255) NULL,
+    notes TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP(6) DEFAULT CURRENT_TIMESTAMP(6) ON UPDATE CURRENT_TIMESTAMP(6),
+    FOREIGN KEY (firebase_user_id) REFERENCES Users(firebase_user_id),
+    INDEX (firebase_user_id, part_number)
+);
+
+-- 整備・チューニングで使った部品。maint_id と tuning_id のどちらか一方を設定する。
+-- 部品を在庫から削除しても品番と名前を残す
+CREATE TABLE PartUsages (
+    usage_id INT AUTO_INCREMENT PRIMARY KEY,
+    part_id INT NULL,
+    maint_id INT NULL,
+    tuning_id INT NULL,
+    part_number VARCHAR(255) NOT NULL,
+    part_name VARCHAR(255) NOT NULL,
+    part_brand VARCHAR(255) NULL,
+    quantity INT NOT NULL,
+    unit_cost DECIMAL(14, 4) NULL,
+    cost_currency CHAR(3) NOT NULL DEFAULT 'JPY',
+    returned BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (part_id) REFERENCES Parts(part_id) ON DELETE SET NULL,
+    FOREIGN KEY (maint_id) REFERENCES Maintenances(maint_id) ON DELETE CASCADE,
+    FOREIGN KEY (tuning_id) REFERENCES Tunings(tuning_id) ON DELETE CASCADE
 );
 
 CREATE TABLE FuelEfficiencies (
@@ -66,12 +216,110 @@ CREATE TABLE FuelEfficiencies (
     car_id INT NOT NULL,
     fe_date VARCHAR(255) NOT NULL,
     fe_amount FLOAT NOT NULL,
-    fe_unitprice INT NOT NULL,
+    fe_unitprice DECIMAL(14, 4) NOT NULL,
+    fe_currency CHAR(3) NOT NULL DEFAULT 'JPY',
     fe_mileage FLOAT NOT NULL,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP(6) DEFAULT CURRENT_TIMESTAMP(6) ON UPDATE CURRENT_TIMESTAMP(6),
+    deleted_at TIMESTAMP NULL,
+    FOREIGN KEY (car_id) REFERENCES Cars(car_id),
+    INDEX (car_id),
+    INDEX (deleted_at)
+);
+
+-- 為替レート。通貨 1 単位あたりの円を日付ごとに管理者が登録する
+CREATE TABLE ExchangeRates (
+    currency CHAR(3) NOT NULL,
+    rate_date DATE NOT NULL,
+    rate DECIMAL(20, 10) NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
     updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (currency, rate_date)
+);
+
+-- 自賠責保険と任意保険。保険料は premium_currency の通貨で保存する
+CREATE TABLE InsurancePolicies (
+    policy_id INT AUTO_INCREMENT PRIMARY KEY,
+    car_id INT NOT NULL,
+    policy_type ENUM('compulsory', 'voluntary') NOT NULL,
+    insurer VARCHAR(255) NOT NULL,
+    policy_number VARCHAR(255) NOT NULL,
+    coverage_start VARCHAR(255) NOT NULL,
+    coverage_end VARCHAR(255) NOT NULL,
+    premium DECIMAL(14, 4) NOT NULL,
+    premium_currency CHAR(3) NOT NULL DEFAULT 'JPY',
+    notes TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP(6) DEFAULT CURRENT_TIMESTAMP(6) ON UPDATE CURRENT_TIMESTAMP(6),
+    deleted_at TIMESTAMP NULL,
     FOREIGN KEY (car_id) REFERENCES Cars(car_id),
-    INDEX (car_id)
+    INDEX (car_id, policy_type, coverage_end),
+    INDEX (deleted_at)
+);
+
+-- 自動車税（軽自動車税）と重量税。税額は省略すると車両の諸元から計算して保存する
+CREATE TABLE VehicleTaxes (
+    tax_id INT AUTO_INCREMENT PRIMARY KEY,
+    car_id INT NOT NULL,
+    tax_type ENUM('automobile_tax', 'light_vehicle_tax', 'weight_tax') NOT NULL,
+    fiscal_year INT NOT NULL,
+    due_date VARCHAR(255) NOT NULL,
+    tax_amount DECIMAL(14, 4) NOT NULL,
+    tax_currency CHAR(3) NOT NULL DEFAULT 'JPY',
+    payment_status ENUM('unpaid', 'paid') NOT NULL DEFAULT 'unpaid',
+    paid_date VARCHAR(255) NULL,
+    notes TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP(6) DEFAULT CURRENT_TIMESTAMP(6) ON UPDATE CURRENT_TIMESTAMP(6),
+    deleted_at TIMESTAMP NULL,
+    FOREIGN KEY (car_id) REFERENCES Cars(car_id),
+    INDEX (car_id, due_date),
+    INDEX (payment_status, due_date),
+    INDEX (deleted_at)
+);
+
+CREATE TABLE TireSets (
+    tire_set_id INT AUTO_INCREMENT PRIMARY KEY,
+    car_id INT NOT NULL,
+    tire_season ENUM('summer', 'winter', 'all_season') NOT NULL,
+    tire_size VARCHAR(32) NOT NULL,
+    tire_brand VARCHAR(255) NOT NULL,
+    tire_model VARCHAR(255) NULL,
+    purchase_date VARCHAR(255) NULL,
+    initial_tread_depth FLOAT NULL,
+    notes TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP(6) DEFAULT CURRENT_TIMESTAMP(6) ON UPDATE CURRENT_TIMESTAMP(6),
+    deleted_at TIMESTAMP NULL,
+    FOREIGN KEY (car_id) REFERENCES Cars(car_id),
+    INDEX (car_id),
+    INDEX (deleted_at)
+);
+
+-- タイヤセットの装着・取り外し。セットを削除すると一緒に削除する
+CREATE TABLE TireEvents (
+    tire_event_id INT AUTO_INCREMENT PRIMARY KEY,
+    tire_set_id INT NOT NULL,
+    car_id INT NOT NULL,
+    event_type ENUM('mount', 'unmount') NOT NULL,
+    event_date VARCHAR(255) NOT NULL,
+    odometer INT NOT NULL,
+    notes TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (tire_set_id) REFERENCES TireSets(tire_set_id) ON DELETE CASCADE,
+    FOREIGN KEY (car_id) REFERENCES Cars(car_id),
+    INDEX (car_id, tire_event_id)
+);
+
+CREATE TABLE TireMeasurements (
+    measurement_id INT AUTO_INCREMENT PRIMARY KEY,
+    tire_set_id INT NOT NULL,
+    measured_date VARCHAR(255) NOT NULL,
+    position ENUM('front_left', 'front_right', 'rear_left', 'rear_right', 'spare') NULL,
+    tread_depth FLOAT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (tire_set_id) REFERENCES TireSets(tire_set_id) ON DELETE CASCADE,
+    INDEX (tire_set_id, measured_date)
 );
 
 CREATE TABLE Accidents (
@@ -79,10 +327,27 @@ CREATE TABLE Accidents (
     car_id INT NOT NULL,
     accident_date VARCHAR(255) NOT NULL,
     accident_description TEXT NOT NULL,
+    accident_location VARCHAR(255) NULL,
+    accident_latitude DOUBLE NULL,
+    accident_longitude DOUBLE NULL,
+    accident_severity ENUM('minor', 'moderate', 'severe', 'total_loss') NULL,
+    counterparty VARCHAR(255) NULL,
+    police_report_number VARCHAR(255) NULL,
+    fault_ratio INT NULL,
+    policy_id INT NULL,
+    claim_number VARCHAR(255) NULL,
+    claim_status ENUM('not_filed', 'filed', 'in_review', 'approved', 'denied', 'paid', 'withdrawn') NOT NULL DEFAULT 'not_filed',
+    repair_shop VARCHAR(255) NULL,
+    repair_cost DECIMAL(14, 4) NULL,
+    repair_currency CHAR(3) NOT NULL DEFAULT 'JPY',
+    repair_completed_date VARCHAR(255) NULL,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP(6) DEFAULT CURRENT_TIMESTAMP(6) ON UPDATE CURRENT_TIMESTAMP(6),
+    deleted_at TIMESTAMP NULL,
     FOREIGN KEY (car_id) REFERENCES Cars(car_id),
-    INDEX (car_id)
+    FOREIGN KEY (policy_id) REFERENCES InsurancePolicies(policy_id) ON DELETE SET NULL,
+    INDEX (car_id),
+    INDEX (deleted_at)
 );
 
 CREATE TABLE PeriodicInspection (
@@ -92,8 +357,39 @@ CREATE TABLE PeriodicInspection (
     pi_date VARCHAR(255) NOT NULL,
     pi_nextdate VARCHAR(255) NOT NULL,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP(6) DEFAULT CURRENT_TIMESTAMP(6) ON UPDATE CURRENT_TIMESTAMP(6),
+    deleted_at TIMESTAMP NULL,
     FOREIGN KEY (car_id) REFERENCES Cars(car_id),
-    INDEX (car_id)
+    INDEX (car_id),
+    INDEX (deleted_at)
 );
 
+
+CREATE TABLE Attachments (
+    attachment_id INT AUTO_INCREMENT PRIMARY KEY,
+    owner_type ENUM('maintenance', 'tuning', 'accident', 'periodic_inspection', 'insurance_policy') NOT NULL,
+    owner_id INT NOT NULL,
+    file_name VARCHAR(255) NOT NULL,
+    content_type VARCHAR(255) NOT NULL,
+    storage_key VARCHAR(255) NOT NULL,
+    file_url VARCHAR(255),
+    is_private BOOLEAN NOT NULL DEFAULT FALSE,
+    sort_order INT NOT NULL DEFAULT 0,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    INDEX (owner_type, owner_id)
+);
+
+-- 変更履歴。追記のみで、記録や車両を削除しても残す
+CREATE TABLE AuditLogs (
+    audit_log_id BIGINT AUTO_INCREMENT PRIMARY KEY,
+    car_id INT NULL,
+    actor_user_id VARCHAR(255) NOT NULL,
+    entity_type VARCHAR(64) NOT NULL,
+    entity_id VARCHAR(255) NOT NULL,
+    action ENUM('create', 'update', 'delete', 'restore') NOT NULL,
+    changes JSON NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    INDEX (car_id, audit_log_id),
+    INDEX (entity_type, entity_id)
+);
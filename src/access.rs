@@ -0,0 +1,124 @@
+use crate::middleware::auth::AppError;
+use crate::models::user_car::CarRole;
+use axum::http::StatusCode;
+use sqlx::{query_scalar, MySqlPool};
+use std::env;
+
+/// ADMIN_USER_IDS（カンマ区切り）に含まれるユーザーか
+pub fn is_admin(firebase_user_id: &str) -> bool {
+    env::var("ADMIN_USER_IDS").is_ok_and(|ids| {
+        ids.split(',')
+            .map(str::trim)
+            .any(|id| !id.is_empty() && id == firebase_user_id)
+    })
+}
+
+/// 管理者であることを確認する
+pub fn require_admin(firebase_user_id: &str) -> Result<(), AppError> {
+    if is_admin(firebase_user_id) {
+        Ok(())
+    } else {
+        Err(AppError::new(
+            StatusCode::FORBIDDEN,
+            "auth/admin-required".to_string(),
+            "This operation requires an administrator.".to_string(),
+        ))
+    }
+}
+
+/// ユーザーの車両に対するロールを取得する。紐づいていない場合やゴミ箱にある場合は None
+pub async fn car_role(
+    db_pool: &MySqlPool,
+    firebase_user_id: &str,
+    car_id: i32,
+) -> Result<Option<CarRole>, sqlx::Error> {
+    query_scalar(
+        "SELECT uc.role FROM user_car uc
+         JOIN Cars c ON c.car_id = uc.car_id
+         WHERE uc.firebase_user_id = ? AND uc.car_id = ? AND c.deleted_at IS NULL",
+    )
+    .bind(firebase_user_id)
+    .bind(car_id)
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// ユーザーが車両に対して `required` 以上のロールを持っていることを確認する
+pub async fn require_car_role(
+    db_pool: &MySqlPool,
+    firebase_user_id: &str,
+    car_id: i32,
+    required: CarRole,
+) -> Result<CarRole, AppError> {
+    let role = car_role(db_pool, firebase_user_id, car_id)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to fetch car role: {:?}", e);
+            AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "car/internal-error".to_string(),
+                "Failed to check car access.".to_string(),
+            )
+        })?;
+
+    match role {
+        Some(role) if role >= required => Ok(role),
+        Some(_) => Err(AppError::new(
+            StatusCode::FORBIDDEN,
+            "car/insufficient-role".to_string(),
+            format!("This operation requires the {} role.", required.as_str()),
+        )),
+        None => Err(AppError::new(
+            StatusCode::FORBIDDEN,
+            "car/forbidden".to_string(),
+            "You do not have access to this car.".to_string(),
+        )),
+    }
+}
+
+/// 記録が属する車両のIDを取得する。記録が存在しない場合やゴミ箱にある場合は None
+pub async fn record_car_id(
+    db_pool: &MySqlPool,
+    table: &str,
+    id_column: &str,
+    id: i32,
+) -> Result<Option<i32>, sqlx::Error> {
+    query_scalar(&format!(
+        "SELECT car_id FROM {} WHERE {} = ? AND deleted_at IS NULL",
+        table, id_column
+    ))
+    .bind(id)
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// 記録が属する車両に対して `required` 以上のロールを持っていることを確認する
+pub async fn require_record_role(
+    db_pool: &MySqlPool,
+    firebase_user_id: &str,
+    table: &str,
+    id_column: &str,
+    id: i32,
+    required: CarRole,
+) -> Result<i32, AppError> {
+    let car_id = record_car_id(db_pool, table, id_column, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to fetch record: {:?}", e);
+            AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "car/internal-error".to_string(),
+                "Failed to check car access.".to_string(),
+            )
+        })?
+        .ok_or_else(|| {
+            AppError::new(
+                StatusCode::NOT_FOUND,
+                "record/not-found".to_string(),
+                "The record was not found.".to_string(),
+            )
+        })?;
+
+    require_car_role(db_pool, firebase_user_id, car_id, required).await?;
+    Ok(car_id)
+}
@@ -0,0 +1,181 @@
+use crate::firebase::FirebaseAuth;
+use crate::handlers::cars;
+use crate::models::user_car::CarRole;
+use crate::state::AppState;
+use crate::storage::Storage;
+use rs_firebase_admin_sdk::auth::FirebaseAuthService;
+use sqlx::{query, query_as, query_scalar, MySqlPool};
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+// 削除予定のアカウントを確認する間隔
+const DELETION_JOB_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// 削除を申請してから実際に削除するまでの日数。0 の場合は即時に削除する
+pub fn grace_period_days() -> i64 {
+    env::var("ACCOUNT_DELETION_GRACE_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(30)
+}
+
+/// アカウントとそのデータを削除する。
+/// 単独で所有している車両は記録や画像ごと削除し、共有している車両は他のメンバーに引き継ぐ
+pub async fn delete_account(
+    db_pool: &MySqlPool,
+    storage: &Storage,
+    firebase_auth: Option<&FirebaseAuth>,
+    firebase_user_id: &str,
+    delete_firebase_user: bool,
+) -> anyhow::Result<()> {
+    let mut tx = db_pool.begin().await?;
+
+    let user_email: Option<String> =
+        query_scalar("SELECT user_email FROM Users WHERE firebase_user_id = ? FOR UPDATE")
+            .bind(firebase_user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+    let memberships: Vec<(i32, CarRole)> =
+        query_as("SELECT car_id, role FROM user_car WHERE firebase_user_id = ? FOR UPDATE")
+            .bind(firebase_user_id)
+            .fetch_all(&mut *tx)
+            .await?;
+
+    let mut storage_keys = Vec::new();
+    for (car_id, role) in memberships {
+        if role == CarRole::Owner {
+            // 編集者、閲覧者の順に次のオーナーを選ぶ
+            let next_owner: Option<String> = query_scalar(
+                "SELECT firebase_user_id FROM user_car WHERE car_id = ? AND firebase_user_id <> ? ORDER BY role, firebase_user_id LIMIT 1",
+            )
+            .bind(car_id)
+            .bind(firebase_user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            match next_owner {
+                Some(next_owner) => {
+                    query("UPDATE user_car SET role = 'owner' WHERE car_id = ? AND firebase_user_id = ?")
+                        .bind(car_id)
+                        .bind(&next_owner)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                None => {
+                    storage_keys.extend(cars::image_key_to_delete(&mut tx, storage, car_id).await?);
+                    storage_keys.extend(cars::delete_car_data(&mut tx, car_id).await?);
+                    continue;
+                }
+            }
+        }
+
+        query("DELETE FROM user_car WHERE car_id = ? AND firebase_user_id = ?")
+            .bind(car_id)
+            .bind(firebase_user_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    if let Some(user_email) = &user_email {
+        query("UPDATE CarInvitations SET status = 'revoked' WHERE invitee_email = ? AND status = 'pending'")
+            .bind(user_email)
+            .execute(&mut *tx)
+            .await?;
+        query("UPDATE CarTransfers SET status = 'cancelled' WHERE to_email = ? AND status = 'pending'")
+            .bind(user_email)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    // ユーザーを参照している行を削除してからユーザーを削除する
+    for (table, column) in [
+        ("CarInvitations", "inviter_user_id"),
+        ("CarShareLinks", "created_by"),
+        ("CarTransfers", "from_user_id"),
+        ("AccountDeletions", "firebase_user_id"),
+        ("Parts", "firebase_user_id"),
+        ("Users", "firebase_user_id"),
+    ] {
+        query(&format!("DELETE FROM {} WHERE {} = ?", table, column))
+            .bind(firebase_user_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    storage.delete_objects(&storage_keys).await;
+
+    if delete_firebase_user {
+        match firebase_auth {
+            Some(firebase_auth) => {
+                if let Err(e) = firebase_auth
+                    .delete_user(firebase_user_id.to_string())
+                    .await
+                {
+                    tracing::error!(
+                        "Failed to delete Firebase user {}: {:?}",
+                        firebase_user_id,
+                        e
+                    );
+                }
+            }
+            None => tracing::warn!(
+                "Firebase Auth is not configured; Firebase user {} was not deleted",
+                firebase_user_id
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// 猶予期間を過ぎたアカウントを削除する
+pub async fn delete_due_accounts(state: &Arc<Mutex<AppState>>) -> anyhow::Result<()> {
+    let (db_pool, storage, firebase_auth) = {
+        let state = state.lock().await;
+        (
+            state.db_pool.clone(),
+            state.storage.clone(),
+            state.firebase_auth.clone(),
+        )
+    };
+
+    let due: Vec<(String, bool)> = query_as(
+        "SELECT firebase_user_id, delete_firebase_user FROM AccountDeletions WHERE scheduled_for <= CURRENT_TIMESTAMP",
+    )
+    .fetch_all(&db_pool)
+    .await?;
+
+    for (firebase_user_id, delete_firebase_user) in due {
+        if let Err(e) = delete_account(
+            &db_pool,
+            &storage,
+            firebase_auth.as_deref(),
+            &firebase_user_id,
+            delete_firebase_user,
+        )
+        .await
+        {
+            tracing::error!("Failed to delete account {}: {:?}", firebase_user_id, e);
+        } else {
+            tracing::info!("Deleted account {}", firebase_user_id);
+        }
+    }
+
+    Ok(())
+}
+
+/// 削除予定のアカウントを定期的に削除する
+pub async fn run_deletion_job(state: Arc<Mutex<AppState>>) {
+    let mut interval = tokio::time::interval(DELETION_JOB_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = delete_due_accounts(&state).await {
+            tracing::error!("Account deletion job failed: {:?}", e);
+        }
+    }
+}
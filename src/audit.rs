@@ -0,0 +1,160 @@
+use crate::models::audit_log::AuditAction;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use sqlx::types::Json;
+use sqlx::{query, MySqlExecutor};
+
+// 記録のたびに変わるため差分に含めない項目
+const IGNORED_FIELDS: [&str; 3] = ["created_at", "updated_at", "deleted_at"];
+
+fn fields<T: Serialize>(value: Option<&T>) -> Map<String, Value> {
+    match value.map(serde_json::to_value) {
+        Some(Ok(Value::Object(map))) => map,
+        Some(Ok(value)) => Map::from_iter([("value".to_string(), value)]),
+        Some(Err(e)) => {
+            eprintln!("Failed to serialize audited value: {:?}", e);
+            Map::new()
+        }
+        None => Map::new(),
+    }
+}
+
+/// 変更前と変更後を比べ、値が変わった項目だけを `{"項目": {"before": .., "after": ..}}` の形で返す
+pub fn diff<T: Serialize>(before: Option<&T>, after: Option<&T>) -> Value {
+    let before = fields(before);
+    let mut after = fields(after);
+
+    let mut changes = Map::new();
+    for (key, old) in before {
+        let new = after.remove(&key).unwrap_or(Value::Null);
+        if old != new {
+            changes.insert(key, json!({ "before": old, "after": new }));
+        }
+    }
+    for (key, new) in after {
+        if !new.is_null() {
+            changes.insert(key, json!({ "before": null, "after": new }));
+        }
+    }
+    for field in IGNORED_FIELDS {
+        changes.remove(field);
+    }
+    Value::Object(changes)
+}
+
+/// 監査ログの1件分
+pub struct AuditEntry<'a> {
+    pub actor_user_id: &'a str,
+    pub car_id: Option<i32>,
+    pub entity_type: &'a str,
+    pub entity_id: String,
+    pub action: AuditAction,
+    pub changes: Value,
+}
+
+impl<'a> AuditEntry<'a> {
+    pub fn new<T: Serialize>(
+        actor_user_id: &'a str,
+        car_id: Option<i32>,
+        entity_type: &'a str,
+        entity_id: impl ToString,
+        action: AuditAction,
+        before: Option<&T>,
+        after: Option<&T>,
+    ) -> Self {
+        Self {
+            actor_user_id,
+            car_id,
+            entity_type,
+            entity_id: entity_id.to_string(),
+            action,
+            changes: diff(before, after),
+        }
+    }
+
+    pub fn create<T: Serialize>(
+        actor_user_id: &'a str,
+        car_id: Option<i32>,
+        entity_type: &'a str,
+        entity_id: impl ToString,
+        after: &T,
+    ) -> Self {
+        Self::new(
+            actor_user_id,
+            car_id,
+            entity_type,
+            entity_id,
+            AuditAction::Create,
+            None,
+            Some(after),
+        )
+    }
+
+    pub fn update<T: Serialize>(
+        actor_user_id: &'a str,
+        car_id: Option<i32>,
+        entity_type: &'a str,
+        entity_id: impl ToString,
+        before: &T,
+        after: &T,
+    ) -> Self {
+        Self::new(
+            actor_user_id,
+            car_id,
+            entity_type,
+            entity_id,
+            AuditAction::Update,
+            Some(before),
+            Some(after),
+        )
+    }
+
+    pub fn delete<T: Serialize>(
+        actor_user_id: &'a str,
+        car_id: Option<i32>,
+        entity_type: &'a str,
+        entity_id: impl ToString,
+        before: &T,
+    ) -> Self {
+        Self::new(
+            actor_user_id,
+            car_id,
+            entity_type,
+            entity_id,
+            AuditAction::Delete,
+            Some(before),
+            None,
+        )
+    }
+
+    /// 監査ログに書き込む。値の変わらない更新は記録しない
+    pub async fn save<'e>(&self, executor: impl MySqlExecutor<'e>) -> Result<(), sqlx::Error> {
+        if self.action == AuditAction::Update && self.changes.as_object().is_some_and(Map::is_empty)
+        {
+            return Ok(());
+        }
+
+        query(
+            "INSERT INTO AuditLogs (car_id, actor_user_id, entity_type, entity_id, action, changes) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(self.car_id)
+        .bind(self.actor_user_id)
+        .bind(self.entity_type)
+        .bind(&self.entity_id)
+        .bind(self.action)
+        .bind(Json(&self.changes))
+        .execute(executor)
+        .await?;
+        Ok(())
+    }
+
+    /// 監査ログに書き込む。失敗しても元の操作は取り消さず、エラーを出力するだけにする
+    pub async fn log<'e>(&self, executor: impl MySqlExecutor<'e>) {
+        if let Err(e) = self.save(executor).await {
+            eprintln!(
+                "Failed to write audit log for {} {}: {:?}",
+                self.entity_type, self.entity_id, e
+            );
+        }
+    }
+}
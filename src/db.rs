@@ -10,3 +10,12 @@ pub async fn establish_connection() -> MySqlPool {
         .await
         .expect("Failed to create pool.")
 }
+
+/// `X_COLUMNS` の各列に JOIN したテーブルの別名を付ける
+pub fn prefixed_columns(columns: &str, alias: &str) -> String {
+    columns
+        .split(',')
+        .map(|column| format!("{}.{}", alias, column.trim()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,84 @@
+use crate::middleware::auth::AppError;
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use time::OffsetDateTime;
+
+/// 更新日時から ETag を作る。updated_at はマイクロ秒まで保存しているので、更新のたびに変わる
+pub fn etag(updated_at: Option<OffsetDateTime>) -> Option<String> {
+    updated_at.map(|updated_at| format!("\"{:x}\"", updated_at.unix_timestamp_nanos()))
+}
+
+// If-Match / If-None-Match のいずれかの値が現在の ETag と一致するか。弱い ETag の W/ は無視する
+fn matches(headers: &HeaderMap, name: header::HeaderName, current: Option<&str>) -> Option<bool> {
+    let values: Vec<&str> = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .collect();
+    if values.is_empty() {
+        return None;
+    }
+
+    Some(values.into_iter().any(|value| match value {
+        "*" => current.is_some(),
+        value => Some(value.trim_start_matches("W/")) == current,
+    }))
+}
+
+/// 読み込んだ後に他の人が更新していたときのエラー
+pub fn precondition_failed() -> AppError {
+    AppError::new(
+        StatusCode::PRECONDITION_FAILED,
+        "request/precondition-failed".to_string(),
+        "The resource has been modified by someone else. Fetch it again and retry.".to_string(),
+    )
+}
+
+/// If-Match があれば現在の ETag と比べ、一致しなければ 412 を返す
+pub fn check_if_match(
+    headers: &HeaderMap,
+    updated_at: Option<OffsetDateTime>,
+) -> Result<(), AppError> {
+    let current = etag(updated_at);
+    match matches(headers, header::IF_MATCH, current.as_deref()) {
+        Some(false) => Err(precondition_failed()),
+        _ => Ok(()),
+    }
+}
+
+/// 本文に ETag を付けて返す
+pub fn with_etag<T: Serialize>(
+    status: StatusCode,
+    updated_at: Option<OffsetDateTime>,
+    body: T,
+) -> Response {
+    let mut response = (status, Json(body)).into_response();
+    if let Some(value) = etag(updated_at).and_then(|etag| HeaderValue::from_str(&etag).ok()) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    response
+}
+
+/// GET の応答。If-None-Match が現在の ETag と一致すれば本文を省いて 304 を返す
+pub fn conditional_get<T: Serialize>(
+    headers: &HeaderMap,
+    updated_at: Option<OffsetDateTime>,
+    body: T,
+) -> Response {
+    let current = etag(updated_at);
+    if matches(headers, header::IF_NONE_MATCH, current.as_deref()) == Some(true) {
+        let mut response = StatusCode::NOT_MODIFIED.into_response();
+        if let Some(value) = current.and_then(|etag| HeaderValue::from_str(&etag).ok()) {
+            response.headers_mut().insert(header::ETAG, value);
+        }
+        return response;
+    }
+    with_etag(StatusCode::OK, updated_at, body)
+}
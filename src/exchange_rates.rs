@@ -0,0 +1,82 @@
+use crate::models::exchange_rate::{ExchangeRate, EXCHANGE_RATE_COLUMNS};
+use bigdecimal::{BigDecimal, One, RoundingMode};
+use serde::Serialize;
+use sqlx::{query_as, MySqlPool};
+use std::collections::{BTreeMap, HashMap};
+
+/// 為替レートの基準にする通貨。レートは他の通貨 1 単位あたりの円で保存する
+pub const BASE_CURRENCY: &str = "JPY";
+
+// 換算した金額は小数点以下 2 桁に丸める
+const CONVERTED_SCALE: i64 = 2;
+
+/// 金額と ISO 4217 の通貨コード
+#[derive(Debug, Clone, Serialize)]
+pub struct Money {
+    pub amount: BigDecimal,
+    pub currency: String,
+}
+
+/// 通貨ごとの日付順のレート。換算には支出日以前で最も新しいレートを使う
+#[derive(Debug, Default)]
+pub struct RateTable {
+    rates: HashMap<String, BTreeMap<String, BigDecimal>>,
+}
+
+impl RateTable {
+    /// 指定した通貨のレートをすべて読み込む
+    pub async fn load(db_pool: &MySqlPool, currencies: &[&str]) -> Result<Self, sqlx::Error> {
+        let currencies: Vec<&str> = currencies
+            .iter()
+            .copied()
+            .filter(|currency| *currency != BASE_CURRENCY)
+            .collect();
+        let mut table = Self::default();
+        if currencies.is_empty() {
+            return Ok(table);
+        }
+
+        let sql = format!(
+            "SELECT {} FROM ExchangeRates WHERE currency IN ({})",
+            EXCHANGE_RATE_COLUMNS,
+            vec!["?"; currencies.len()].join(", ")
+        );
+        let mut select = query_as::<_, ExchangeRate>(&sql);
+        for currency in &currencies {
+            select = select.bind(*currency);
+        }
+        for rate in select.fetch_all(db_pool).await? {
+            table
+                .rates
+                .entry(rate.currency)
+                .or_default()
+                .insert(rate.rate_date, rate.rate);
+        }
+        Ok(table)
+    }
+
+    // date（YYYY-MM-DD）時点の 1 単位あたりの円
+    fn rate(&self, currency: &str, date: &str) -> Option<BigDecimal> {
+        if currency == BASE_CURRENCY {
+            return Some(BigDecimal::one());
+        }
+        self.rates
+            .get(currency)?
+            .range(..=date.to_string())
+            .next_back()
+            .map(|(_, rate)| rate.clone())
+    }
+
+    /// date 時点のレートで換算する。レートがない場合は None
+    pub fn convert(&self, amount: &BigDecimal, from: &str, to: &str, date: &str) -> Option<Money> {
+        let amount = if from == to {
+            amount.clone()
+        } else {
+            amount * self.rate(from, date)? / self.rate(to, date)?
+        };
+        Some(Money {
+            amount: amount.with_scale_round(CONVERTED_SCALE, RoundingMode::HalfEven),
+            currency: to.to_string(),
+        })
+    }
+}
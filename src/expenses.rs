@@ -0,0 +1,268 @@
+use crate::exchange_rates::{Money, RateTable};
+use crate::models::accident::{Accident, ACCIDENT_COLUMNS};
+use crate::models::expense::{
+    CategoryExpense, Expense, ExpenseCategory, ExpenseReport, MonthlyExpense,
+};
+use crate::models::fuel_efficiency::{FuelEfficiency, FUEL_EFFICIENCY_COLUMNS};
+use crate::models::insurance_policy::{InsurancePolicy, INSURANCE_POLICY_COLUMNS};
+use crate::models::part::{PartUsage, PART_USAGE_COLUMNS};
+use crate::models::vehicle_tax::{VehicleTax, VEHICLE_TAX_COLUMNS};
+use bigdecimal::{BigDecimal, RoundingMode, Zero};
+use sqlx::{query_as, FromRow, MySqlPool};
+use std::collections::BTreeMap;
+
+// 記録した通貨での金額も小数点以下 2 桁に丸める
+const AMOUNT_SCALE: i64 = 2;
+
+/// 給油量 × 単価を給油の支出にする
+pub fn fuel_expense(fe: &FuelEfficiency) -> Expense {
+    // f32 の給油量は表示どおりの 10 進数として扱う
+    let litres = fe
+        .fe_amount
+        .to_string()
+        .parse::<BigDecimal>()
+        .unwrap_or_default();
+    Expense {
+        category: ExpenseCategory::Fuel,
+        source_id: fe.fe_id,
+        date: fe.fe_date.clone(),
+        amount: Money {
+            amount: (litres * &fe.fe_unitprice)
+                .with_scale_round(AMOUNT_SCALE, RoundingMode::HalfEven),
+            currency: fe.fe_currency.clone(),
+        },
+        converted: None,
+    }
+}
+
+/// 保険料を保険期間の開始日の支出にする
+pub fn insurance_expense(policy: &InsurancePolicy) -> Expense {
+    Expense {
+        category: ExpenseCategory::Insurance,
+        source_id: policy.policy_id,
+        date: policy.coverage_start.clone(),
+        amount: Money {
+            amount: policy
+                .premium
+                .with_scale_round(AMOUNT_SCALE, RoundingMode::HalfEven),
+            currency: policy.premium_currency.clone(),
+        },
+        converted: None,
+    }
+}
+
+/// 事故の修理費を修理の完了日（未完了なら事故の日）の支出にする。修理費がなければ None
+pub fn repair_expense(accident: &Accident) -> Option<Expense> {
+    let cost = accident.repair_cost.as_ref()?;
+    Some(Expense {
+        category: ExpenseCategory::Repair,
+        source_id: accident.accident_id,
+        date: accident
+            .repair_completed_date
+            .clone()
+            .unwrap_or_else(|| accident.accident_date.clone()),
+        amount: Money {
+            amount: cost.with_scale_round(AMOUNT_SCALE, RoundingMode::HalfEven),
+            currency: accident.repair_currency.clone(),
+        },
+        converted: None,
+    })
+}
+
+/// 税額を納付日（未納なら納期限）の支出にする。税額や日付がなければ None
+pub fn tax_expense(vehicle_tax: &VehicleTax) -> Option<Expense> {
+    let amount = vehicle_tax.tax_amount.as_ref()?;
+    Some(Expense {
+        category: ExpenseCategory::Tax,
+        source_id: vehicle_tax.tax_id,
+        date: vehicle_tax
+            .paid_date
+            .clone()
+            .or_else(|| vehicle_tax.due_date.clone())?,
+        amount: Money {
+            amount: amount.with_scale_round(AMOUNT_SCALE, RoundingMode::HalfEven),
+            currency: vehicle_tax.tax_currency.clone(),
+        },
+        converted: None,
+    })
+}
+
+#[derive(FromRow)]
+struct DatedPartUsage {
+    #[sqlx(flatten)]
+    usage: PartUsage,
+    usage_date: String,
+}
+
+/// 部品の数量 × 単価を整備・チューニングの日の支出にする。単価がなければ None
+pub fn part_expense(usage: &PartUsage, date: &str) -> Option<Expense> {
+    let unit_cost = usage.unit_cost.as_ref()?;
+    Some(Expense {
+        category: ExpenseCategory::Parts,
+        source_id: Some(usage.usage_id),
+        date: date.to_string(),
+        amount: Money {
+            amount: (unit_cost * BigDecimal::from(usage.quantity))
+                .with_scale_round(AMOUNT_SCALE, RoundingMode::HalfEven),
+            currency: usage.cost_currency.clone(),
+        },
+        converted: None,
+    })
+}
+
+/// 車両のゴミ箱にない記録から支出を日付順に集める。from / to（YYYY-MM-DD）で期間を絞り込める
+pub async fn load_car_expenses(
+    db_pool: &MySqlPool,
+    car_id: i32,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<Vec<Expense>, sqlx::Error> {
+    let fuel_efficiencies = query_as::<_, FuelEfficiency>(&format!(
+        "SELECT {} FROM FuelEfficiencies WHERE car_id = ? AND deleted_at IS NULL AND (? IS NULL OR fe_date >= ?) AND (? IS NULL OR fe_date <= ?) ORDER BY fe_date, fe_id",
+        FUEL_EFFICIENCY_COLUMNS
+    ))
+    .bind(car_id)
+    .bind(from)
+    .bind(from)
+    .bind(to)
+    .bind(to)
+    .fetch_all(db_pool)
+    .await?;
+
+    let insurance_policies = query_as::<_, InsurancePolicy>(&format!(
+        "SELECT {} FROM InsurancePolicies WHERE car_id = ? AND deleted_at IS NULL AND (? IS NULL OR coverage_start >= ?) AND (? IS NULL OR coverage_start <= ?) ORDER BY coverage_start, policy_id",
+        INSURANCE_POLICY_COLUMNS
+    ))
+    .bind(car_id)
+    .bind(from)
+    .bind(from)
+    .bind(to)
+    .bind(to)
+    .fetch_all(db_pool)
+    .await?;
+
+    let accidents = query_as::<_, Accident>(&format!(
+        "SELECT {} FROM Accidents WHERE car_id = ? AND deleted_at IS NULL AND repair_cost IS NOT NULL AND (? IS NULL OR COALESCE(repair_completed_date, accident_date) >= ?) AND (? IS NULL OR COALESCE(repair_completed_date, accident_date) <= ?) ORDER BY accident_date, accident_id",
+        ACCIDENT_COLUMNS
+    ))
+    .bind(car_id)
+    .bind(from)
+    .bind(from)
+    .bind(to)
+    .bind(to)
+    .fetch_all(db_pool)
+    .await?;
+
+    let vehicle_taxes = query_as::<_, VehicleTax>(&format!(
+        "SELECT {} FROM VehicleTaxes WHERE car_id = ? AND deleted_at IS NULL AND (? IS NULL OR COALESCE(paid_date, due_date) >= ?) AND (? IS NULL OR COALESCE(paid_date, due_date) <= ?) ORDER BY due_date, tax_id",
+        VEHICLE_TAX_COLUMNS
+    ))
+    .bind(car_id)
+    .bind(from)
+    .bind(from)
+    .bind(to)
+    .bind(to)
+    .fetch_all(db_pool)
+    .await?;
+
+    // 使った日は整備・チューニングの日
+    let part_usages = query_as::<_, DatedPartUsage>(&format!(
+        "SELECT {}, COALESCE(
+           (SELECT m.maint_date FROM Maintenances m WHERE m.maint_id = PartUsages.maint_id),
+           (SELECT t.tuning_date FROM Tunings t WHERE t.tuning_id = PartUsages.tuning_id)
+         ) AS usage_date FROM PartUsages
+         WHERE unit_cost IS NOT NULL
+         AND (maint_id IN (SELECT maint_id FROM Maintenances WHERE car_id = ? AND deleted_at IS NULL)
+           OR tuning_id IN (SELECT tuning_id FROM Tunings WHERE car_id = ? AND deleted_at IS NULL))
+         HAVING (? IS NULL OR usage_date >= ?) AND (? IS NULL OR usage_date <= ?)
+         ORDER BY usage_date, usage_id",
+        PART_USAGE_COLUMNS
+    ))
+    .bind(car_id)
+    .bind(car_id)
+    .bind(from)
+    .bind(from)
+    .bind(to)
+    .bind(to)
+    .fetch_all(db_pool)
+    .await?;
+
+    let mut expenses: Vec<Expense> = fuel_efficiencies
+        .iter()
+        .map(fuel_expense)
+        .chain(insurance_policies.iter().map(insurance_expense))
+        .chain(accidents.iter().filter_map(repair_expense))
+        .chain(vehicle_taxes.iter().filter_map(tax_expense))
+        .chain(
+            part_usages
+                .iter()
+                .filter_map(|p| part_expense(&p.usage, &p.usage_date)),
+        )
+        .collect();
+    expenses.sort_by(|a, b| a.date.cmp(&b.date));
+    Ok(expenses)
+}
+
+/// 支出を currency に換算し、月ごと・種類ごとに集計する
+pub async fn build_report(
+    db_pool: &MySqlPool,
+    car_id: i32,
+    mut expenses: Vec<Expense>,
+    currency: &str,
+) -> Result<ExpenseReport, sqlx::Error> {
+    let mut currencies: Vec<&str> = expenses
+        .iter()
+        .map(|expense| expense.amount.currency.as_str())
+        .chain([currency])
+        .collect();
+    currencies.sort_unstable();
+    currencies.dedup();
+    let rates = RateTable::load(db_pool, &currencies).await?;
+
+    let mut total = BigDecimal::zero();
+    let mut by_month: BTreeMap<String, BigDecimal> = BTreeMap::new();
+    let mut by_category: BTreeMap<ExpenseCategory, BigDecimal> = BTreeMap::new();
+    let mut unconverted_count = 0;
+    for expense in &mut expenses {
+        expense.converted = rates.convert(
+            &expense.amount.amount,
+            &expense.amount.currency,
+            currency,
+            &expense.date,
+        );
+        let Some(converted) = &expense.converted else {
+            unconverted_count += 1;
+            continue;
+        };
+        total += &converted.amount;
+        let month = expense.date.get(..7).unwrap_or(&expense.date).to_string();
+        *by_month.entry(month).or_default() += &converted.amount;
+        *by_category.entry(expense.category).or_default() += &converted.amount;
+    }
+
+    let money = |amount: BigDecimal| Money {
+        amount,
+        currency: currency.to_string(),
+    };
+    Ok(ExpenseReport {
+        car_id,
+        currency: currency.to_string(),
+        total: money(total),
+        by_month: by_month
+            .into_iter()
+            .map(|(month, total)| MonthlyExpense {
+                month,
+                total: money(total),
+            })
+            .collect(),
+        by_category: by_category
+            .into_iter()
+            .map(|(category, total)| CategoryExpense {
+                category,
+                total: money(total),
+            })
+            .collect(),
+        unconverted_count,
+        expenses,
+    })
+}
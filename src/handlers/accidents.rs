@@ -1,39 +1,149 @@
-use crate::models::accident::Accident;
+use crate::access;
+use crate::audit::AuditEntry;
+use crate::etag;
+use crate::expenses;
+use crate::handlers::user_preferences;
+use crate::middleware::auth::AppError;
+use crate::models::accident::{
+    Accident, AccidentSummary, AccidentSummaryQuery, ACCIDENT_COLUMNS, ACCIDENT_PATCH,
+};
+use crate::models::user_car::CarRole;
+use crate::patch;
 use crate::state::AppState;
+use crate::validation::{self, UpdateJson, ValidatedJson};
 use axum::{
-    extract::{Extension, Json, Path},
-    http::StatusCode,
+    extract::{Extension, Json, Path, Query},
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
 };
-use sqlx::{query, query_as};
+use serde_json::Value;
+use sqlx::{query, query_as, query_scalar, MySqlPool};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+/// 事故歴に紐付ける保険が同じ車両のものか確認する。ゴミ箱にある保険も紐付けられる
+async fn check_policy(db_pool: &MySqlPool, accident: &Accident) -> Result<(), AppError> {
+    let Some(policy_id) = accident.policy_id else {
+        return Ok(());
+    };
+    let car_id = query_scalar::<_, i32>("SELECT car_id FROM InsurancePolicies WHERE policy_id = ?")
+        .bind(policy_id)
+        .fetch_optional(db_pool)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to fetch insurance policy: {:?}", e);
+            AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "accident/internal-error".to_string(),
+                "Failed to check the insurance policy.".to_string(),
+            )
+        })?;
+    if car_id == Some(accident.car_id) {
+        Ok(())
+    } else {
+        Err(AppError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "accident/invalid-policy".to_string(),
+            "The insurance policy must belong to the same car.".to_string(),
+        ))
+    }
+}
+
+/// 請求の状態は決まった順にしか進めない
+fn check_claim_transition(before: &Accident, after: &Accident) -> Result<(), AppError> {
+    if before.claim_status == after.claim_status
+        || before.claim_status.can_transition_to(after.claim_status)
+    {
+        return Ok(());
+    }
+    Err(AppError::new(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "accident/invalid-claim-transition".to_string(),
+        format!(
+            "The claim status cannot change from {} to {}.",
+            before.claim_status.as_str(),
+            after.claim_status.as_str()
+        ),
+    ))
+}
+
+async fn fetch_accident(db_pool: &MySqlPool, id: i32) -> Result<Accident, sqlx::Error> {
+    query_as::<_, Accident>(&format!(
+        "SELECT {} FROM Accidents WHERE accident_id = ?",
+        ACCIDENT_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(db_pool)
+    .await
+}
+
 pub async fn create_accident(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
-    Json(new_accident): Json<Accident>,
+    Extension(firebase_user_id): Extension<String>,
+    ValidatedJson(new_accident): ValidatedJson<Accident>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query!(
-        "INSERT INTO Accidents (car_id, accident_date, accident_description) VALUES (?, ?, ?)",
+    if let Err(e) = access::require_car_role(
+        &db_pool,
+        &firebase_user_id,
         new_accident.car_id,
-        new_accident.accident_date,
-        new_accident.accident_description
+        CarRole::Editor,
     )
+    .await
+    {
+        return e.into_response();
+    }
+
+    if let Err(e) = check_policy(&db_pool, &new_accident).await {
+        return e.into_response();
+    }
+
+    match query(
+        "INSERT INTO Accidents (car_id, accident_date, accident_description, accident_location, accident_latitude, accident_longitude, accident_severity, counterparty, police_report_number, fault_ratio, policy_id, claim_number, claim_status, repair_shop, repair_cost, repair_currency, repair_completed_date) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(new_accident.car_id)
+    .bind(&new_accident.accident_date)
+    .bind(&new_accident.accident_description)
+    .bind(&new_accident.accident_location)
+    .bind(new_accident.accident_latitude)
+    .bind(new_accident.accident_longitude)
+    .bind(new_accident.accident_severity)
+    .bind(&new_accident.counterparty)
+    .bind(&new_accident.police_report_number)
+    .bind(new_accident.fault_ratio)
+    .bind(new_accident.policy_id)
+    .bind(&new_accident.claim_number)
+    .bind(new_accident.claim_status)
+    .bind(&new_accident.repair_shop)
+    .bind(&new_accident.repair_cost)
+    .bind(&new_accident.repair_currency)
+    .bind(&new_accident.repair_completed_date)
     .execute(&db_pool)
     .await
     {
         Ok(result) => {
-            match query_as!(
-                Accident,
-                "SELECT * FROM Accidents WHERE accident_id = ?",
-                result.last_insert_id()
-            )
+            match query_as::<_, Accident>(&format!(
+                "SELECT {} FROM Accidents WHERE accident_id = ?",
+                ACCIDENT_COLUMNS
+            ))
+            .bind(result.last_insert_id())
             .fetch_one(&db_pool)
             .await
             {
-                Ok(accident) => (StatusCode::CREATED, Json(accident)).into_response(),
+                Ok(accident) => {
+                    AuditEntry::create(
+                        &firebase_user_id,
+                        Some(accident.car_id),
+                        "accident",
+                        result.last_insert_id(),
+                        &accident,
+                    )
+                    .log(&db_pool)
+                    .await;
+                    etag::with_etag(StatusCode::CREATED, accident.updated_at, accident)
+                }
                 Err(e) => {
                     eprintln!("Failed to fetch accident after creation: {:?}", e);
                     StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -50,9 +160,12 @@ pub async fn create_accident(
 pub async fn get_accidents(Extension(state): Extension<Arc<Mutex<AppState>>>) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query_as!(Accident, "SELECT * FROM Accidents")
-        .fetch_all(&db_pool)
-        .await
+    match query_as::<_, Accident>(&format!(
+        "SELECT {} FROM Accidents WHERE deleted_at IS NULL",
+        ACCIDENT_COLUMNS
+    ))
+    .fetch_all(&db_pool)
+    .await
     {
         Ok(accidents) => (StatusCode::OK, Json(accidents)).into_response(),
         Err(e) => {
@@ -65,18 +178,19 @@ pub async fn get_accidents(Extension(state): Extension<Arc<Mutex<AppState>>>) ->
 pub async fn get_accident(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
     Path(accident_id): Path<i32>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query_as!(
-        Accident,
-        "SELECT * FROM Accidents WHERE accident_id = ?",
-        accident_id
-    )
+    match query_as::<_, Accident>(&format!(
+        "SELECT {} FROM Accidents WHERE accident_id = ? AND deleted_at IS NULL",
+        ACCIDENT_COLUMNS
+    ))
+    .bind(accident_id)
     .fetch_one(&db_pool)
     .await
     {
-        Ok(accident) => (StatusCode::OK, Json(accident)).into_response(),
+        Ok(accident) => etag::conditional_get(&headers, accident.updated_at, accident),
         Err(e) => {
             eprintln!("Failed to fetch accident: {:?}", e);
             StatusCode::NOT_FOUND.into_response()
@@ -84,37 +198,195 @@ pub async fn get_accident(
     }
 }
 
+/// 車両の事故の件数、請求の状況と修理費を集計する。修理費は修理の完了日のレートで換算する
+pub async fn get_car_accident_summary(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+    Query(params): Query<AccidentSummaryQuery>,
+) -> impl IntoResponse {
+    if let Err(e) = validation::validate(&params) {
+        return e.into_response();
+    }
+
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Viewer).await
+    {
+        return e.into_response();
+    }
+
+    let currency = match params.currency {
+        Some(currency) => currency,
+        None => {
+            user_preferences::preferences_or_default(&db_pool, &firebase_user_id)
+                .await
+                .currency
+        }
+    };
+
+    let accidents = match query_as::<_, Accident>(&format!(
+        "SELECT {} FROM Accidents WHERE car_id = ? AND deleted_at IS NULL ORDER BY accident_date, accident_id",
+        ACCIDENT_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(accidents) => accidents,
+        Err(e) => {
+            eprintln!("Failed to fetch accidents: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let repairs = accidents
+        .iter()
+        .filter_map(expenses::repair_expense)
+        .collect();
+    let repair_report = match expenses::build_report(&db_pool, car_id, repairs, &currency).await {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Failed to convert repair costs: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let mut by_severity = BTreeMap::new();
+    let mut by_claim_status = BTreeMap::new();
+    for accident in &accidents {
+        if let Some(severity) = accident.accident_severity {
+            *by_severity.entry(severity).or_default() += 1;
+        }
+        *by_claim_status.entry(accident.claim_status).or_default() += 1;
+    }
+    let fault_ratios: Vec<i32> = accidents.iter().filter_map(|a| a.fault_ratio).collect();
+
+    let summary = AccidentSummary {
+        car_id,
+        accident_count: accidents.len(),
+        by_severity,
+        by_claim_status,
+        open_claim_count: accidents
+            .iter()
+            .filter(|a| a.claim_status.is_open())
+            .count(),
+        average_fault_ratio: (!fault_ratios.is_empty()).then(|| {
+            fault_ratios
+                .iter()
+                .map(|&ratio| f64::from(ratio))
+                .sum::<f64>()
+                / fault_ratios.len() as f64
+        }),
+        total_repair_cost: repair_report.total,
+        unconverted_count: repair_report.unconverted_count,
+        last_accident_date: accidents.last().map(|a| a.accident_date.clone()),
+    };
+
+    (StatusCode::OK, Json(summary)).into_response()
+}
+
 pub async fn update_accident(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
     Path(accident_id): Path<i32>,
-    Json(updated_accident): Json<Accident>,
+    headers: HeaderMap,
+    UpdateJson(updated_accident): UpdateJson<Accident>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query!(
-        "UPDATE Accidents SET car_id = ?, accident_date = ?, accident_description = ? WHERE accident_id = ?",
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "Accidents",
+        "accident_id",
+        accident_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    if let Err(e) = access::require_car_role(
+        &db_pool,
+        &firebase_user_id,
         updated_accident.car_id,
-        updated_accident.accident_date,
-        updated_accident.accident_description,
-        accident_id
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    if let Err(e) = check_policy(&db_pool, &updated_accident).await {
+        return e.into_response();
+    }
+
+    let before = match fetch_accident(&db_pool, accident_id).await {
+        Ok(accident) => accident,
+        Err(e) => {
+            eprintln!("Failed to fetch accident: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    if let Err(e) = validation::validate_changes(&before, &updated_accident) {
+        return e.into_response();
+    }
+
+    if let Err(e) = check_claim_transition(&before, &updated_accident) {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE Accidents SET car_id = ?, accident_date = ?, accident_description = ?, accident_location = ?, accident_latitude = ?, accident_longitude = ?, accident_severity = ?, counterparty = ?, police_report_number = ?, fault_ratio = ?, policy_id = ?, claim_number = ?, claim_status = ?, repair_shop = ?, repair_cost = ?, repair_currency = ?, repair_completed_date = ? WHERE accident_id = ? AND updated_at <=> ?",
     )
+    .bind(updated_accident.car_id)
+    .bind(&updated_accident.accident_date)
+    .bind(&updated_accident.accident_description)
+    .bind(&updated_accident.accident_location)
+    .bind(updated_accident.accident_latitude)
+    .bind(updated_accident.accident_longitude)
+    .bind(updated_accident.accident_severity)
+    .bind(&updated_accident.counterparty)
+    .bind(&updated_accident.police_report_number)
+    .bind(updated_accident.fault_ratio)
+    .bind(updated_accident.policy_id)
+    .bind(&updated_accident.claim_number)
+    .bind(updated_accident.claim_status)
+    .bind(&updated_accident.repair_shop)
+    .bind(&updated_accident.repair_cost)
+    .bind(&updated_accident.repair_currency)
+    .bind(&updated_accident.repair_completed_date)
+    .bind(accident_id)
+    .bind(before.updated_at)
     .execute(&db_pool)
     .await
     {
-        Ok(_) => {
-            match query_as!(
-                Accident,
-                "SELECT accident_id, car_id, accident_date, accident_description, created_at, updated_at FROM Accidents WHERE accident_id = ?",
-                accident_id
-            )
-            .fetch_one(&db_pool)
-            .await
-            {
-                Ok(accident) => (StatusCode::OK, Json(accident)).into_response(),
-                Err(e) => {
-                    eprintln!("Failed to fetch accident after update: {:?}", e);
-                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
-                }
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
+        Ok(_) => match fetch_accident(&db_pool, accident_id).await {
+            Ok(accident) => {
+                AuditEntry::update(
+                    &firebase_user_id,
+                    Some(accident.car_id),
+                    "accident",
+                    accident_id,
+                    &before,
+                    &accident,
+                )
+                .log(&db_pool)
+                .await;
+                etag::with_etag(StatusCode::OK, accident.updated_at, accident)
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch accident after update: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
             }
         },
         Err(e) => {
@@ -124,17 +396,162 @@ pub async fn update_accident(
     }
 }
 
+/// JSON Merge Patch で指定された項目だけを更新する
+pub async fn patch_accident(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "Accidents",
+        "accident_id",
+        id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_accident(&db_pool, id).await {
+        Ok(accident) => accident,
+        Err(e) => {
+            eprintln!("Failed to fetch accident: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    let accident = match patch::apply(&ACCIDENT_PATCH, &before, &patch) {
+        Ok(accident) => accident,
+        Err(e) => return e.into_response(),
+    };
+
+    // 別の車両に移す場合は移動先の車両にも編集者以上のロールが必要
+    if accident.car_id != before.car_id {
+        if let Err(e) = access::require_car_role(
+            &db_pool,
+            &firebase_user_id,
+            accident.car_id,
+            CarRole::Editor,
+        )
+        .await
+        {
+            return e.into_response();
+        }
+    }
+
+    if let Err(e) = check_claim_transition(&before, &accident) {
+        return e.into_response();
+    }
+
+    if accident.car_id != before.car_id || accident.policy_id != before.policy_id {
+        if let Err(e) = check_policy(&db_pool, &accident).await {
+            return e.into_response();
+        }
+    }
+
+    match patch::update(
+        &db_pool,
+        &ACCIDENT_PATCH,
+        id,
+        &before,
+        &accident,
+        before.updated_at,
+    )
+    .await
+    {
+        Ok(false) => etag::precondition_failed().into_response(),
+        Ok(true) => match fetch_accident(&db_pool, id).await {
+            Ok(accident) => {
+                AuditEntry::update(
+                    &firebase_user_id,
+                    Some(accident.car_id),
+                    "accident",
+                    id,
+                    &before,
+                    &accident,
+                )
+                .log(&db_pool)
+                .await;
+                etag::with_etag(StatusCode::OK, accident.updated_at, accident)
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch accident after update: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to update accident: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 事故歴をゴミ箱に移す。添付ファイルは復元に備えて残す
 pub async fn delete_accident(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
     Path(accident_id): Path<i32>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query!("DELETE FROM Accidents WHERE accident_id = ?", accident_id)
-        .execute(&db_pool)
-        .await
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "Accidents",
+        "accident_id",
+        accident_id,
+        CarRole::Editor,
+    )
+    .await
     {
-        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        return e.into_response();
+    }
+
+    let before = match fetch_accident(&db_pool, accident_id).await {
+        Ok(accident) => accident,
+        Err(e) => {
+            eprintln!("Failed to fetch accident: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE Accidents SET deleted_at = CURRENT_TIMESTAMP WHERE accident_id = ? AND deleted_at IS NULL AND updated_at <=> ?",
+    )
+    .bind(accident_id)
+    .bind(before.updated_at)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
+        Ok(_) => {
+            AuditEntry::delete(
+                &firebase_user_id,
+                Some(before.car_id),
+                "accident",
+                accident_id,
+                &before,
+            )
+            .log(&db_pool)
+            .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
         Err(e) => {
             eprintln!("Failed to delete accident: {:?}", e);
             StatusCode::INTERNAL_SERVER_ERROR.into_response()
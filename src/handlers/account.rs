@@ -0,0 +1,993 @@
+use crate::account_deletion;
+use crate::audit::AuditEntry;
+use crate::handlers::{attachments, cars};
+use crate::history;
+use crate::middleware::auth::AppError;
+use crate::models::account_archive::{
+    AccountArchive, AccountImportResult, ArchivedCar, ACCOUNT_ARCHIVE_FILES_DIR,
+    ACCOUNT_ARCHIVE_JSON, ACCOUNT_ARCHIVE_VERSION,
+};
+use crate::models::account_deletion::{
+    AccountDeletion, RequestAccountDeletionRequest, ACCOUNT_DELETION_COLUMNS,
+};
+use crate::models::attachment::{AttachmentOwner, ALLOWED_CONTENT_TYPES, ATTACHMENT_OWNERS};
+use crate::models::car::Car;
+use crate::models::maintenance::MaintenanceType;
+use crate::models::part::{Part, PART_COLUMNS};
+use crate::models::user::{User, USER_COLUMNS};
+use crate::models::user_car::CarRole;
+use crate::state::AppState;
+use crate::storage::{image_prefix, Storage};
+use axum::{
+    extract::{multipart::Multipart, Extension, Json},
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use sqlx::{query, query_as, query_scalar, MySql, MySqlConnection, MySqlPool, Transaction};
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Write};
+use std::sync::Arc;
+use time::OffsetDateTime;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+// 展開後のサイズがこれを超えるファイルは読み込まない
+const MAX_ARCHIVE_ENTRY_SIZE: u64 = 100 * 1024 * 1024;
+
+fn archive_file_path(storage_key: &str) -> String {
+    format!("{}{}", ACCOUNT_ARCHIVE_FILES_DIR, storage_key)
+}
+
+fn write_archive(archive: &AccountArchive, files: &[(String, Vec<u8>)]) -> anyhow::Result<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file(ACCOUNT_ARCHIVE_JSON, options)?;
+    zip.write_all(&serde_json::to_vec_pretty(archive)?)?;
+
+    // 画像は圧縮しても小さくならないのでそのまま格納する
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    for (path, content) in files {
+        zip.start_file(path.as_str(), stored)?;
+        zip.write_all(content)?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
+
+/// ログイン中のユーザーと紐づくすべての車両・記録・画像を ZIP で出力する
+pub async fn export_account(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+) -> impl IntoResponse {
+    let (db_pool, storage) = {
+        let state = state.lock().await;
+        (state.db_pool.clone(), state.storage.clone())
+    };
+
+    let user = match query_as::<_, User>(&format!(
+        "SELECT {} FROM Users WHERE firebase_user_id = ?",
+        USER_COLUMNS
+    ))
+    .bind(&firebase_user_id)
+    .fetch_optional(&db_pool)
+    .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch user: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let mut conn = match db_pool.acquire().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            eprintln!("Failed to acquire connection: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let user_cars: Vec<(i32, CarRole)> = match query_as(
+        "SELECT car_id, role FROM user_car WHERE firebase_user_id = ? ORDER BY car_id",
+    )
+    .bind(&firebase_user_id)
+    .fetch_all(&mut *conn)
+    .await
+    {
+        Ok(user_cars) => user_cars,
+        Err(e) => {
+            eprintln!("Failed to fetch user cars: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let mut cars = Vec::new();
+    let mut files = Vec::new();
+    for (car_id, role) in user_cars {
+        let history = match history::load_car_history(&mut conn, car_id).await {
+            Ok(Some(history)) => history,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("Failed to load car history: {:?}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+        let attachments = match attachments::car_attachments(&mut conn, car_id).await {
+            Ok(attachments) => attachments,
+            Err(e) => {
+                eprintln!("Failed to fetch car attachments: {:?}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+
+        // ストレージから取得できなかったファイルはアーカイブに含めない
+        for attachment in &attachments {
+            match storage.get(&attachment.storage_key).await {
+                Some(content) => files.push((archive_file_path(&attachment.storage_key), content)),
+                None => eprintln!("Attachment file not found: {}", attachment.storage_key),
+            }
+        }
+
+        let image_key = match cars::uploaded_image_key(&mut conn, &storage, car_id).await {
+            Ok(key) => key,
+            Err(e) => {
+                eprintln!("Failed to fetch car image: {:?}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+        let mut image_file = None;
+        if let Some(key) = image_key {
+            match storage.get(&key).await {
+                Some(content) => {
+                    let path = archive_file_path(&key);
+                    files.push((path.clone(), content));
+                    image_file = Some(path);
+                }
+                None => eprintln!("Car image not found: {}", key),
+            }
+        }
+
+        cars.push(ArchivedCar {
+            role,
+            history,
+            attachments,
+            image_file,
+        });
+    }
+
+    let parts = match query_as::<_, Part>(&format!(
+        "SELECT {} FROM Parts WHERE firebase_user_id = ? ORDER BY part_id",
+        PART_COLUMNS
+    ))
+    .bind(&firebase_user_id)
+    .fetch_all(&mut *conn)
+    .await
+    {
+        Ok(parts) => parts,
+        Err(e) => {
+            eprintln!("Failed to fetch parts: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    drop(conn);
+
+    let archive = AccountArchive {
+        version: ACCOUNT_ARCHIVE_VERSION,
+        exported_at: OffsetDateTime::now_utc(),
+        user,
+        cars,
+        parts,
+    };
+
+    let content = match tokio::task::spawn_blocking(move || write_archive(&archive, &files)).await {
+        Ok(Ok(content)) => content,
+        Ok(Err(e)) => {
+            eprintln!("Failed to write account archive: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+        Err(e) => {
+            eprintln!("Failed to write account archive: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"autotrack-export-{}.zip\"",
+                    OffsetDateTime::now_utc().date()
+                ),
+            ),
+        ],
+        content,
+    )
+        .into_response()
+}
+
+fn read_entry(zip: &mut ZipArchive<Cursor<Vec<u8>>>, path: &str) -> anyhow::Result<Vec<u8>> {
+    let entry = zip.by_name(path)?;
+    if entry.size() > MAX_ARCHIVE_ENTRY_SIZE {
+        anyhow::bail!("{} is too large", path);
+    }
+    let mut content = Vec::new();
+    entry
+        .take(MAX_ARCHIVE_ENTRY_SIZE)
+        .read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// アーカイブを読み込み、JSON と参照されているファイルを取り出す
+fn read_archive(content: Vec<u8>) -> anyhow::Result<(AccountArchive, HashMap<String, Vec<u8>>)> {
+    let mut zip = ZipArchive::new(Cursor::new(content))?;
+    let archive: AccountArchive =
+        serde_json::from_slice(&read_entry(&mut zip, ACCOUNT_ARCHIVE_JSON)?)?;
+    if archive.version != ACCOUNT_ARCHIVE_VERSION {
+        anyhow::bail!("Unsupported archive version: {}", archive.version);
+    }
+
+    let mut files = HashMap::new();
+    for car in &archive.cars {
+        let paths = car
+            .attachments
+            .iter()
+            .map(|attachment| archive_file_path(&attachment.storage_key))
+            .chain(car.image_file.clone());
+        for path in paths {
+            // 欠けているファイルは取り込み時に読み飛ばす
+            if let Ok(content) = read_entry(&mut zip, &path) {
+                files.insert(path, content);
+            }
+        }
+    }
+
+    Ok((archive, files))
+}
+
+// 受け付ける形式のファイルだけを取り込む。戻り値は (Content-Type, 拡張子)
+fn allowed_file_type(path: &str) -> Option<(&'static str, &'static str)> {
+    let extension = path.rsplit_once('.')?.1.to_lowercase();
+    ALLOWED_CONTENT_TYPES
+        .into_iter()
+        .find(|(_, ext)| *ext == extension)
+}
+
+// 取り込み中にアップロードしたファイルを覚えておき、失敗したときに消せるようにする
+struct Importer<'a> {
+    storage: &'a Storage,
+    files: &'a HashMap<String, Vec<u8>>,
+    uploaded_keys: Vec<String>,
+    imported_records: usize,
+    /// アーカイブの部品 ID から取り込んだ部品 ID への対応
+    part_ids: HashMap<i32, i32>,
+}
+
+impl Importer<'_> {
+    async fn upload(
+        &mut self,
+        path: &str,
+        key: String,
+        content_type: &str,
+    ) -> anyhow::Result<bool> {
+        let Some(content) = self.files.get(path) else {
+            return Ok(false);
+        };
+        self.storage.put(&key, content, content_type).await?;
+        self.uploaded_keys.push(key);
+        Ok(true)
+    }
+
+    async fn import_parts(
+        &mut self,
+        conn: &mut MySqlConnection,
+        firebase_user_id: &str,
+        parts: &[Part],
+    ) -> anyhow::Result<()> {
+        for p in parts {
+            let new_id = query(
+                "INSERT INTO Parts (firebase_user_id, part_number, part_name, part_brand, quantity, unit_cost, cost_currency, supplier, notes) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(firebase_user_id)
+            .bind(&p.part_number)
+            .bind(&p.part_name)
+            .bind(&p.part_brand)
+            .bind(p.quantity)
+            .bind(&p.unit_cost)
+            .bind(&p.cost_currency)
+            .bind(&p.supplier)
+            .bind(&p.notes)
+            .execute(&mut *conn)
+            .await?
+            .last_insert_id() as i32;
+            if let Some(old_id) = p.part_id {
+                self.part_ids.insert(old_id, new_id);
+            }
+        }
+        self.imported_records += parts.len();
+        Ok(())
+    }
+
+    async fn import_car(
+        &mut self,
+        conn: &mut MySqlConnection,
+        firebase_user_id: &str,
+        archived: &ArchivedCar,
+    ) -> anyhow::Result<()> {
+        let car = &archived.history.car;
+
+        let car_image_url = match &archived.image_file {
+            Some(path) => match allowed_file_type(path) {
+                Some((content_type, extension)) => {
+                    let key = format!(
+                        "{}{}.{}",
+                        image_prefix(firebase_user_id),
+                        Uuid::new_v4(),
+                        extension
+                    );
+                    if self.upload(path, key.clone(), content_type).await? {
+                        Some(self.storage.public_url(&key))
+                    } else {
+                        None
+                    }
+                }
+                None => None,
+            },
+            // アーカイブに画像がなければ URL も取り込まない。ほかのユーザーのファイルを指していることがある
+            None => None,
+        };
+
+        let car_id = query(
+            "INSERT INTO Cars (car_name, carmodelnum, car_color, car_mileage, car_isflooding, car_issmoked, car_image_url, car_maker, car_model, car_grade, car_year, car_displacement, car_fuel_type, car_tank_capacity, car_weight, car_transmission, car_first_registration, car_plate_number, car_vin) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&car.car_name)
+        .bind(&car.carmodelnum)
+        .bind(&car.car_color)
+        .bind(car.car_mileage)
+        .bind(car.car_isflooding)
+        .bind(car.car_issmoked)
+        .bind(&car_image_url)
+        .bind(&car.car_maker)
+        .bind(&car.car_model)
+        .bind(&car.car_grade)
+        .bind(car.car_year)
+        .bind(car.car_displacement)
+        .bind(car.car_fuel_type)
+        .bind(car.car_tank_capacity)
+        .bind(car.car_weight)
+        .bind(car.car_transmission)
+        .bind(&car.car_first_registration)
+        .bind(&car.car_plate_number)
+        .bind(&car.car_vin)
+        .execute(&mut *conn)
+        .await?
+        .last_insert_id() as i32;
+
+        query("INSERT INTO user_car (firebase_user_id, car_id, role) VALUES (?, ?, 'owner')")
+            .bind(firebase_user_id)
+            .bind(car_id)
+            .execute(&mut *conn)
+            .await?;
+
+        AuditEntry::create(
+            firebase_user_id,
+            Some(car_id),
+            "car",
+            car_id,
+            &Car {
+                car_id: Some(car_id),
+                car_image_url,
+                ..car.clone()
+            },
+        )
+        .save(&mut *conn)
+        .await?;
+
+        // 古い ID から新しい ID への対応表。添付ファイルの付け替えに使う
+        let mut id_maps: HashMap<AttachmentOwner, HashMap<i32, i32>> = HashMap::new();
+
+        for m in &archived.history.maintenances {
+            let new_id = query(
+                "INSERT INTO Maintenances (car_id, maint_type, maint_title, maint_date, maint_description) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(car_id)
+            .bind(m.maint_type)
+            .bind(match m.maint_type {
+                // 以前のアーカイブには日本語のタイトルが入っているので、種類から決まるものは保存しない
+                MaintenanceType::Other => m.maint_title.as_str(),
+                _ => "",
+            })
+            .bind(&m.maint_date)
+            .bind(&m.maint_description)
+            .execute(&mut *conn)
+            .await?
+            .last_insert_id() as i32;
+            if let Some(old_id) = m.maint_id {
+                id_maps
+                    .entry(AttachmentOwner::Maintenance)
+                    .or_default()
+                    .insert(old_id, new_id);
+            }
+        }
+
+        for t in &archived.history.tunings {
+            let new_id = query(
+                "INSERT INTO Tunings (car_id, tuning_name, tuning_date, tuning_description, tuning_removed_date) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(car_id)
+            .bind(&t.tuning_name)
+            .bind(&t.tuning_date)
+            .bind(&t.tuning_description)
+            .bind(&t.tuning_removed_date)
+            .execute(&mut *conn)
+            .await?
+            .last_insert_id() as i32;
+            if let Some(old_id) = t.tuning_id {
+                id_maps
+                    .entry(AttachmentOwner::Tuning)
+                    .or_default()
+                    .insert(old_id, new_id);
+            }
+        }
+
+        for fe in &archived.history.fuel_efficiencies {
+            query(
+                "INSERT INTO FuelEfficiencies (car_id, fe_date, fe_amount, fe_unitprice, fe_currency, fe_mileage) VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(car_id)
+            .bind(&fe.fe_date)
+            .bind(fe.fe_amount)
+            .bind(&fe.fe_unitprice)
+            .bind(&fe.fe_currency)
+            .bind(fe.fe_mileage)
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        for p in &archived.history.insurance_policies {
+            let new_id = query(
+                "INSERT INTO InsurancePolicies (car_id, policy_type, insurer, policy_number, coverage_start, coverage_end, premium, premium_currency, notes) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(car_id)
+            .bind(p.policy_type)
+            .bind(&p.insurer)
+            .bind(&p.policy_number)
+            .bind(&p.coverage_start)
+            .bind(&p.coverage_end)
+            .bind(&p.premium)
+            .bind(&p.premium_currency)
+            .bind(&p.notes)
+            .execute(&mut *conn)
+            .await?
+            .last_insert_id() as i32;
+            if let Some(old_id) = p.policy_id {
+                id_maps
+                    .entry(AttachmentOwner::InsurancePolicy)
+                    .or_default()
+                    .insert(old_id, new_id);
+            }
+        }
+
+        for t in &archived.history.vehicle_taxes {
+            query(
+                "INSERT INTO VehicleTaxes (car_id, tax_type, fiscal_year, due_date, tax_amount, tax_currency, payment_status, paid_date, notes) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(car_id)
+            .bind(t.tax_type)
+            .bind(t.fiscal_year)
+            .bind(&t.due_date)
+            .bind(&t.tax_amount)
+            .bind(&t.tax_currency)
+            .bind(t.payment_status)
+            .bind(&t.paid_date)
+            .bind(&t.notes)
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        for a in &archived.history.accidents {
+            // 保険は取り込んだものに付け替え、アーカイブにない保険への参照は外す
+            let policy_id = a.policy_id.and_then(|old_id| {
+                id_maps
+                    .get(&AttachmentOwner::InsurancePolicy)
+                    .and_then(|ids| ids.get(&old_id))
+                    .copied()
+            });
+            let new_id = query(
+                "INSERT INTO Accidents (car_id, accident_date, accident_description, accident_location, accident_latitude, accident_longitude, accident_severity, counterparty, police_report_number, fault_ratio, policy_id, claim_number, claim_status, repair_shop, repair_cost, repair_currency, repair_completed_date) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(car_id)
+            .bind(&a.accident_date)
+            .bind(&a.accident_description)
+            .bind(&a.accident_location)
+            .bind(a.accident_latitude)
+            .bind(a.accident_longitude)
+            .bind(a.accident_severity)
+            .bind(&a.counterparty)
+            .bind(&a.police_report_number)
+            .bind(a.fault_ratio)
+            .bind(policy_id)
+            .bind(&a.claim_number)
+            .bind(a.claim_status)
+            .bind(&a.repair_shop)
+            .bind(&a.repair_cost)
+            .bind(&a.repair_currency)
+            .bind(&a.repair_completed_date)
+            .execute(&mut *conn)
+            .await?
+            .last_insert_id() as i32;
+            if let Some(old_id) = a.accident_id {
+                id_maps
+                    .entry(AttachmentOwner::Accident)
+                    .or_default()
+                    .insert(old_id, new_id);
+            }
+        }
+
+        for pi in &archived.history.periodic_inspections {
+            let new_id = query(
+                "INSERT INTO PeriodicInspection (car_id, pi_name, pi_date, pi_nextdate) VALUES (?, ?, ?, ?)",
+            )
+            .bind(car_id)
+            .bind(&pi.pi_name)
+            .bind(&pi.pi_date)
+            .bind(&pi.pi_nextdate)
+            .execute(&mut *conn)
+            .await?
+            .last_insert_id() as i32;
+            if let Some(old_id) = pi.pi_id {
+                id_maps
+                    .entry(AttachmentOwner::PeriodicInspection)
+                    .or_default()
+                    .insert(old_id, new_id);
+            }
+        }
+
+        // 使った部品は取り込んだ整備・チューニングに付け替える。在庫はアーカイブの数量のままにする
+        for u in &archived.history.part_usages {
+            let (column, owner) = match (u.maint_id, u.tuning_id) {
+                (Some(id), _) => (
+                    "maint_id",
+                    id_maps
+                        .get(&AttachmentOwner::Maintenance)
+                        .and_then(|ids| ids.get(&id)),
+                ),
+                (None, Some(id)) => (
+                    "tuning_id",
+                    id_maps
+                        .get(&AttachmentOwner::Tuning)
+                        .and_then(|ids| ids.get(&id)),
+                ),
+                (None, None) => continue,
+            };
+            let Some(&owner_id) = owner else {
+                continue;
+            };
+            // ほかのユーザーの部品への参照は外す
+            let part_id = u
+                .part_id
+                .and_then(|old_id| self.part_ids.get(&old_id).copied());
+            query(&format!(
+                "INSERT INTO PartUsages (part_id, {}, part_number, part_name, part_brand, quantity, unit_cost, cost_currency, returned) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                column
+            ))
+            .bind(part_id)
+            .bind(owner_id)
+            .bind(&u.part_number)
+            .bind(&u.part_name)
+            .bind(&u.part_brand)
+            .bind(u.quantity)
+            .bind(&u.unit_cost)
+            .bind(&u.cost_currency)
+            .bind(u.returned)
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        // 装着と測定の記録は取り込んだタイヤセットに付け替える
+        let mut tire_set_ids: HashMap<i32, i32> = HashMap::new();
+        for s in &archived.history.tire_sets {
+            let new_id = query(
+                "INSERT INTO TireSets (car_id, tire_season, tire_size, tire_brand, tire_model, purchase_date, initial_tread_depth, notes) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(car_id)
+            .bind(s.tire_season)
+            .bind(&s.tire_size)
+            .bind(&s.tire_brand)
+            .bind(&s.tire_model)
+            .bind(&s.purchase_date)
+            .bind(s.initial_tread_depth)
+            .bind(&s.notes)
+            .execute(&mut *conn)
+            .await?
+            .last_insert_id() as i32;
+            if let Some(old_id) = s.tire_set_id {
+                tire_set_ids.insert(old_id, new_id);
+            }
+        }
+
+        for e in &archived.history.tire_events {
+            let Some(&tire_set_id) = tire_set_ids.get(&e.tire_set_id) else {
+                continue;
+            };
+            query(
+                "INSERT INTO TireEvents (tire_set_id, car_id, event_type, event_date, odometer, notes) VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(tire_set_id)
+            .bind(car_id)
+            .bind(e.event_type)
+            .bind(&e.event_date)
+            .bind(e.odometer)
+            .bind(&e.notes)
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        for m in &archived.history.tire_measurements {
+            let Some(&tire_set_id) = tire_set_ids.get(&m.tire_set_id) else {
+                continue;
+            };
+            query(
+                "INSERT INTO TireMeasurements (tire_set_id, measured_date, position, tread_depth) VALUES (?, ?, ?, ?)",
+            )
+            .bind(tire_set_id)
+            .bind(&m.measured_date)
+            .bind(m.position)
+            .bind(m.tread_depth)
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        self.imported_records += archived.history.maintenances.len()
+            + archived.history.tunings.len()
+            + archived.history.fuel_efficiencies.len()
+            + archived.history.insurance_policies.len()
+            + archived.history.vehicle_taxes.len()
+            + archived.history.accidents.len()
+            + archived.history.periodic_inspections.len()
+            + archived.history.tire_sets.len();
+
+        for attachment in &archived.attachments {
+            let Some(owner) = ATTACHMENT_OWNERS
+                .into_iter()
+                .find(|owner| owner.as_str() == attachment.owner_type)
+            else {
+                continue;
+            };
+            let Some(&owner_id) = id_maps
+                .get(&owner)
+                .and_then(|ids| ids.get(&attachment.owner_id))
+            else {
+                continue;
+            };
+
+            let Some((content_type, extension)) = allowed_file_type(&attachment.storage_key) else {
+                continue;
+            };
+            let key = attachments::attachment_storage_key(
+                owner,
+                owner_id,
+                attachment.is_private,
+                extension,
+            );
+            let path = archive_file_path(&attachment.storage_key);
+            if !self.upload(&path, key.clone(), content_type).await? {
+                continue;
+            }
+            let file_url = (!attachment.is_private).then(|| self.storage.public_url(&key));
+
+            query(
+                "INSERT INTO Attachments (owner_type, owner_id, file_name, content_type, storage_key, file_url, is_private, sort_order) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(owner.as_str())
+            .bind(owner_id)
+            .bind(&attachment.file_name)
+            .bind(content_type)
+            .bind(&key)
+            .bind(&file_url)
+            .bind(attachment.is_private)
+            .bind(attachment.sort_order)
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// エクスポートしたアーカイブを、車両を持っていないアカウントに新しい ID で取り込む
+pub async fn import_account(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    mut payload: Multipart,
+) -> impl IntoResponse {
+    let (db_pool, storage) = {
+        let state = state.lock().await;
+        (state.db_pool.clone(), state.storage.clone())
+    };
+
+    let mut content = None;
+    loop {
+        let field = match payload.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("Error reading field: {:?}", e);
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Error reading field: {}", e),
+                )
+                    .into_response();
+            }
+        };
+        if field.name() != Some("file") {
+            continue;
+        }
+        match field.bytes().await {
+            Ok(bytes) => content = Some(bytes.to_vec()),
+            Err(e) => {
+                eprintln!("Error reading file content: {:?}", e);
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Error reading file content: {}", e),
+                )
+                    .into_response();
+            }
+        }
+    }
+    let Some(content) = content else {
+        return (StatusCode::BAD_REQUEST, "Error: No file uploaded").into_response();
+    };
+
+    let (archive, files) = match tokio::task::spawn_blocking(move || read_archive(content)).await {
+        Ok(Ok(archive)) => archive,
+        Ok(Err(e)) => {
+            return AppError::new(
+                StatusCode::BAD_REQUEST,
+                "import/invalid-archive".to_string(),
+                format!("Invalid archive: {}", e),
+            )
+            .into_response();
+        }
+        Err(e) => {
+            eprintln!("Failed to read account archive: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    match query_as::<_, User>(&format!(
+        "SELECT {} FROM Users WHERE firebase_user_id = ?",
+        USER_COLUMNS
+    ))
+    .bind(&firebase_user_id)
+    .fetch_optional(&db_pool)
+    .await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch user: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    // 既存のデータと混ざらないよう、車両を持っていないアカウントにだけ取り込む
+    let car_count: i64 =
+        match query_scalar("SELECT COUNT(*) FROM user_car WHERE firebase_user_id = ?")
+            .bind(&firebase_user_id)
+            .fetch_one(&db_pool)
+            .await
+        {
+            Ok(count) => count,
+            Err(e) => {
+                eprintln!("Failed to fetch user cars: {:?}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+    if car_count > 0 {
+        return AppError::new(
+            StatusCode::CONFLICT,
+            "import/account-not-empty".to_string(),
+            "Archives can only be imported into an account without cars.".to_string(),
+        )
+        .into_response();
+    }
+
+    let mut tx: Transaction<'_, MySql> = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            eprintln!("Failed to start transaction: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let mut importer = Importer {
+        storage: &storage,
+        files: &files,
+        uploaded_keys: Vec::new(),
+        imported_records: 0,
+        part_ids: HashMap::new(),
+    };
+
+    let mut result = query("UPDATE Users SET user_name = ? WHERE firebase_user_id = ?")
+        .bind(&archive.user.user_name)
+        .bind(&firebase_user_id)
+        .execute(&mut *tx)
+        .await
+        .map(|_| ())
+        .map_err(anyhow::Error::from);
+    if result.is_ok() {
+        result = importer
+            .import_parts(&mut tx, &firebase_user_id, &archive.parts)
+            .await;
+    }
+    for car in &archive.cars {
+        if result.is_err() {
+            break;
+        }
+        result = importer.import_car(&mut tx, &firebase_user_id, car).await;
+    }
+
+    if let Err(e) = result {
+        tx.rollback().await.unwrap();
+        storage.delete_objects(&importer.uploaded_keys).await;
+        eprintln!("Failed to import account archive: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    if let Err(e) = tx.commit().await {
+        storage.delete_objects(&importer.uploaded_keys).await;
+        eprintln!("Failed to commit transaction: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    (
+        StatusCode::CREATED,
+        Json(AccountImportResult {
+            imported_cars: archive.cars.len(),
+            imported_records: importer.imported_records,
+            imported_files: importer.uploaded_keys.len(),
+        }),
+    )
+        .into_response()
+}
+
+async fn fetch_account_deletion(
+    db_pool: &MySqlPool,
+    firebase_user_id: &str,
+) -> Result<Option<AccountDeletion>, sqlx::Error> {
+    query_as::<_, AccountDeletion>(&format!(
+        "SELECT {} FROM AccountDeletions WHERE firebase_user_id = ?",
+        ACCOUNT_DELETION_COLUMNS
+    ))
+    .bind(firebase_user_id)
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// アカウントの削除を申請する。猶予期間が過ぎると削除ジョブが削除する
+pub async fn request_account_deletion(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Json(req): Json<RequestAccountDeletionRequest>,
+) -> impl IntoResponse {
+    let (db_pool, storage, firebase_auth) = {
+        let state = state.lock().await;
+        (
+            state.db_pool.clone(),
+            state.storage.clone(),
+            state.firebase_auth.clone(),
+        )
+    };
+
+    match query_as::<_, User>(&format!(
+        "SELECT {} FROM Users WHERE firebase_user_id = ?",
+        USER_COLUMNS
+    ))
+    .bind(&firebase_user_id)
+    .fetch_optional(&db_pool)
+    .await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch user: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    let grace_period_days = account_deletion::grace_period_days();
+    if grace_period_days <= 0 {
+        return match account_deletion::delete_account(
+            &db_pool,
+            &storage,
+            firebase_auth.as_deref(),
+            &firebase_user_id,
+            req.delete_firebase_user,
+        )
+        .await
+        {
+            Ok(_) => StatusCode::NO_CONTENT.into_response(),
+            Err(e) => {
+                eprintln!("Failed to delete account: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        };
+    }
+
+    match query(
+        "INSERT INTO AccountDeletions (firebase_user_id, delete_firebase_user, scheduled_for) VALUES (?, ?, CURRENT_TIMESTAMP + INTERVAL ? DAY)",
+    )
+    .bind(&firebase_user_id)
+    .bind(req.delete_firebase_user)
+    .bind(grace_period_days)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return AppError::new(
+                StatusCode::CONFLICT,
+                "account/deletion-already-requested".to_string(),
+                "Account deletion has already been requested.".to_string(),
+            )
+            .into_response();
+        }
+        Err(e) => {
+            eprintln!("Failed to request account deletion: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    match fetch_account_deletion(&db_pool, &firebase_user_id).await {
+        Ok(Some(deletion)) => (StatusCode::ACCEPTED, Json(deletion)).into_response(),
+        Ok(None) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch account deletion: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn get_account_deletion(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    match fetch_account_deletion(&db_pool, &firebase_user_id).await {
+        Ok(Some(deletion)) => (StatusCode::OK, Json(deletion)).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch account deletion: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 猶予期間中のアカウント削除を取り消す
+pub async fn cancel_account_deletion(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    match query("DELETE FROM AccountDeletions WHERE firebase_user_id = ?")
+        .bind(&firebase_user_id)
+        .execute(&db_pool)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => StatusCode::NOT_FOUND.into_response(),
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            eprintln!("Failed to cancel account deletion: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
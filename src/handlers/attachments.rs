@@ -0,0 +1,615 @@
+use crate::access;
+use crate::audit::AuditEntry;
+use crate::models::attachment::{
+    Attachment, AttachmentOwner, ReorderAttachmentsRequest, UploadAttachmentsQuery,
+    ALLOWED_CONTENT_TYPES, ATTACHMENT_OWNERS,
+};
+use crate::models::user_car::CarRole;
+use crate::state::AppState;
+use crate::storage::{Storage, PRIVATE_PREFIX};
+use axum::{
+    extract::{multipart::Multipart, Extension, Json, Path, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+use sqlx::{query, query_as, query_scalar, MySql, MySqlConnection, MySqlPool, Transaction};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+const SELECT_ATTACHMENTS: &str = "SELECT attachment_id, owner_type, owner_id, file_name, content_type, storage_key, file_url, is_private, sort_order, created_at, updated_at FROM Attachments";
+
+/// 添付ファイルを保存する新しいキーを作る
+pub(crate) fn attachment_storage_key(
+    owner: AttachmentOwner,
+    owner_id: i32,
+    private: bool,
+    extension: &str,
+) -> String {
+    format!(
+        "{}attachments/{}/{}/{}.{}",
+        if private { PRIVATE_PREFIX } else { "" },
+        owner.as_str(),
+        owner_id,
+        Uuid::new_v4(),
+        extension
+    )
+}
+
+/// 添付先の記録が存在し、ユーザーがその車両に `required` 以上のロールを持っていることを確認し、車両のIDを返す
+async fn authorize_owner(
+    db_pool: &MySqlPool,
+    firebase_user_id: &str,
+    owner: AttachmentOwner,
+    owner_id: i32,
+    required: CarRole,
+) -> Result<i32, Response> {
+    access::require_record_role(
+        db_pool,
+        firebase_user_id,
+        owner.table(),
+        owner.id_column(),
+        owner_id,
+        required,
+    )
+    .await
+    .map_err(IntoResponse::into_response)
+}
+
+/// 非公開の添付ファイルに有効期限付きの URL を設定する
+async fn sign_urls(storage: &Storage, attachments: &mut [Attachment]) -> anyhow::Result<()> {
+    for attachment in attachments.iter_mut().filter(|a| a.is_private) {
+        let signed = storage.signed_url(&attachment.storage_key).await?;
+        attachment.file_url = Some(signed.url);
+        attachment.url_expires_at = Some(signed.expires_at);
+    }
+    Ok(())
+}
+
+async fn fetch_attachments(
+    db_pool: &MySqlPool,
+    storage: &Storage,
+    owner: AttachmentOwner,
+    owner_id: i32,
+) -> anyhow::Result<Vec<Attachment>> {
+    let mut attachments = query_as::<_, Attachment>(&format!(
+        "{} WHERE owner_type = ? AND owner_id = ? ORDER BY sort_order, attachment_id",
+        SELECT_ATTACHMENTS
+    ))
+    .bind(owner.as_str())
+    .bind(owner_id)
+    .fetch_all(db_pool)
+    .await?;
+
+    sign_urls(storage, &mut attachments).await?;
+    Ok(attachments)
+}
+
+fn resolve_content_type(
+    file_name: &str,
+    content_type: Option<&str>,
+) -> Option<(&'static str, &'static str)> {
+    if let Some(content_type) = content_type {
+        if let Some(&allowed) = ALLOWED_CONTENT_TYPES
+            .iter()
+            .find(|(ct, _)| *ct == content_type)
+        {
+            return Some(allowed);
+        }
+    }
+
+    // Content-Type が付いていない場合は拡張子から判定する
+    let extension = file_name.rsplit('.').next()?.to_ascii_lowercase();
+    let extension = if extension == "jpeg" {
+        "jpg".to_string()
+    } else {
+        extension
+    };
+    ALLOWED_CONTENT_TYPES
+        .iter()
+        .find(|(_, ext)| *ext == extension)
+        .copied()
+}
+
+pub async fn get_attachments(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(owner): Extension<AttachmentOwner>,
+    Extension(firebase_user_id): Extension<String>,
+    Path((owner_id,)): Path<(i32,)>,
+) -> impl IntoResponse {
+    let (db_pool, storage) = {
+        let state = state.lock().await;
+        (state.db_pool.clone(), state.storage.clone())
+    };
+
+    if let Err(response) = authorize_owner(
+        &db_pool,
+        &firebase_user_id,
+        owner,
+        owner_id,
+        CarRole::Viewer,
+    )
+    .await
+    {
+        return response;
+    }
+
+    match fetch_attachments(&db_pool, &storage, owner, owner_id).await {
+        Ok(attachments) => (StatusCode::OK, Json(attachments)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch attachments: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn get_attachment(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(owner): Extension<AttachmentOwner>,
+    Extension(firebase_user_id): Extension<String>,
+    Path((owner_id, attachment_id)): Path<(i32, i32)>,
+) -> impl IntoResponse {
+    let (db_pool, storage) = {
+        let state = state.lock().await;
+        (state.db_pool.clone(), state.storage.clone())
+    };
+
+    if let Err(response) = authorize_owner(
+        &db_pool,
+        &firebase_user_id,
+        owner,
+        owner_id,
+        CarRole::Viewer,
+    )
+    .await
+    {
+        return response;
+    }
+
+    let mut attachment = match query_as::<_, Attachment>(&format!(
+        "{} WHERE attachment_id = ? AND owner_type = ? AND owner_id = ?",
+        SELECT_ATTACHMENTS
+    ))
+    .bind(attachment_id)
+    .bind(owner.as_str())
+    .bind(owner_id)
+    .fetch_optional(&db_pool)
+    .await
+    {
+        Ok(Some(attachment)) => attachment,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch attachment: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    match sign_urls(&storage, std::slice::from_mut(&mut attachment)).await {
+        Ok(_) => (StatusCode::OK, Json(attachment)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to sign attachment URL: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn upload_attachments(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(owner): Extension<AttachmentOwner>,
+    Extension(firebase_user_id): Extension<String>,
+    Path((owner_id,)): Path<(i32,)>,
+    Query(params): Query<UploadAttachmentsQuery>,
+    mut payload: Multipart,
+) -> impl IntoResponse {
+    let (db_pool, storage) = {
+        let state = state.lock().await;
+        (state.db_pool.clone(), state.storage.clone())
+    };
+
+    let car_id = match authorize_owner(
+        &db_pool,
+        &firebase_user_id,
+        owner,
+        owner_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        Ok(car_id) => car_id,
+        Err(response) => return response,
+    };
+
+    // アップロード前にすべてのファイルを読み込み、形式を検証する
+    let mut files = Vec::new();
+    loop {
+        let field = match payload.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("Error reading field: {:?}", e);
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Error reading field: {}", e),
+                )
+                    .into_response();
+            }
+        };
+
+        let Some(file_name) = field.file_name().map(|name| name.to_owned()) else {
+            continue;
+        };
+        let Some((content_type, extension)) =
+            resolve_content_type(&file_name, field.content_type())
+        else {
+            return (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!("Unsupported file type: {}", file_name),
+            )
+                .into_response();
+        };
+
+        match field.bytes().await {
+            Ok(content) => files.push((file_name, content_type, extension, content)),
+            Err(e) => {
+                eprintln!("Error reading file content: {:?}", e);
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Error reading file content: {}", e),
+                )
+                    .into_response();
+            }
+        }
+    }
+
+    if files.is_empty() {
+        return (StatusCode::BAD_REQUEST, "Error: No file uploaded").into_response();
+    }
+
+    let next_order: Option<i32> = match query_scalar(
+        "SELECT MAX(sort_order) FROM Attachments WHERE owner_type = ? AND owner_id = ?",
+    )
+    .bind(owner.as_str())
+    .bind(owner_id)
+    .fetch_one(&db_pool)
+    .await
+    {
+        Ok(max) => max,
+        Err(e) => {
+            eprintln!("Failed to fetch attachment order: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let first_order = next_order.map_or(0, |max| max + 1);
+
+    // 途中で失敗したときは、それまでにアップロードしたオブジェクトを削除する
+    let mut uploaded = Vec::new();
+    for (file_name, content_type, extension, content) in &files {
+        let key = attachment_storage_key(owner, owner_id, params.private, extension);
+        if let Err(e) = storage.put(&key, content, content_type).await {
+            eprintln!("Failed to upload attachment {}: {:?}", file_name, e);
+            storage.delete_objects(&uploaded).await;
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+        uploaded.push(key);
+    }
+
+    // 行の追加はまとめて行い、1 件でも失敗したらアップロードしたオブジェクトも削除する
+    let mut tx: Transaction<'_, MySql> = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            eprintln!("Failed to start transaction: {:?}", e);
+            storage.delete_objects(&uploaded).await;
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let mut attachment_ids = Vec::new();
+    for (sort_order, ((file_name, content_type, _, _), key)) in
+        (first_order..).zip(files.iter().zip(&uploaded))
+    {
+        let file_url = (!params.private).then(|| storage.public_url(key));
+
+        let inserted = query(
+            "INSERT INTO Attachments (owner_type, owner_id, file_name, content_type, storage_key, file_url, is_private, sort_order) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(owner.as_str())
+        .bind(owner_id)
+        .bind(file_name)
+        .bind(*content_type)
+        .bind(key)
+        .bind(&file_url)
+        .bind(params.private)
+        .bind(sort_order)
+        .execute(&mut *tx)
+        .await;
+
+        match inserted {
+            Ok(result) => attachment_ids.push(result.last_insert_id()),
+            Err(e) => {
+                tx.rollback().await.unwrap();
+                eprintln!("Failed to create attachment: {:?}", e);
+                storage.delete_objects(&uploaded).await;
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+    }
+
+    if let Err(e) = tx.commit().await {
+        eprintln!("Failed to commit transaction: {:?}", e);
+        storage.delete_objects(&uploaded).await;
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    let mut created = Vec::new();
+    for attachment_id in attachment_ids {
+        match query_as::<_, Attachment>(&format!("{} WHERE attachment_id = ?", SELECT_ATTACHMENTS))
+            .bind(attachment_id)
+            .fetch_one(&db_pool)
+            .await
+        {
+            Ok(attachment) => {
+                AuditEntry::create(
+                    &firebase_user_id,
+                    Some(car_id),
+                    "attachment",
+                    attachment.attachment_id,
+                    &attachment,
+                )
+                .log(&db_pool)
+                .await;
+                created.push(attachment);
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch attachment after creation: {:?}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+    }
+
+    match sign_urls(&storage, &mut created).await {
+        Ok(_) => (StatusCode::CREATED, Json(created)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to sign attachment URL: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn reorder_attachments(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(owner): Extension<AttachmentOwner>,
+    Extension(firebase_user_id): Extension<String>,
+    Path((owner_id,)): Path<(i32,)>,
+    Json(req): Json<ReorderAttachmentsRequest>,
+) -> impl IntoResponse {
+    let (db_pool, storage) = {
+        let state = state.lock().await;
+        (state.db_pool.clone(), state.storage.clone())
+    };
+
+    let car_id = match authorize_owner(
+        &db_pool,
+        &firebase_user_id,
+        owner,
+        owner_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        Ok(car_id) => car_id,
+        Err(response) => return response,
+    };
+
+    let current_ids: Vec<i32> = match query_scalar(
+        "SELECT attachment_id FROM Attachments WHERE owner_type = ? AND owner_id = ? ORDER BY sort_order, attachment_id",
+    )
+    .bind(owner.as_str())
+    .bind(owner_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(ids) => ids,
+        Err(e) => {
+            eprintln!("Failed to fetch attachments: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    // 並び替えには現在の添付ファイルをすべて、重複なく指定する必要がある
+    let requested: HashSet<i32> = req.attachment_ids.iter().copied().collect();
+    let current: HashSet<i32> = current_ids.iter().copied().collect();
+    if requested.len() != req.attachment_ids.len() || requested != current {
+        return (
+            StatusCode::BAD_REQUEST,
+            "attachment_ids must list every attachment of the record exactly once",
+        )
+            .into_response();
+    }
+
+    let mut tx: Transaction<'_, MySql> = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            eprintln!("Failed to start transaction: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    for (sort_order, attachment_id) in req.attachment_ids.iter().enumerate() {
+        let result = query("UPDATE Attachments SET sort_order = ? WHERE attachment_id = ?")
+            .bind(sort_order as i32)
+            .bind(attachment_id)
+            .execute(&mut *tx)
+            .await;
+
+        if let Err(e) = result {
+            tx.rollback().await.unwrap();
+            eprintln!("Failed to reorder attachments: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    AuditEntry::update(
+        &firebase_user_id,
+        Some(car_id),
+        owner.as_str(),
+        owner_id,
+        &json!({ "attachment_order": current_ids }),
+        &json!({ "attachment_order": req.attachment_ids }),
+    )
+    .log(&mut *tx)
+    .await;
+
+    if let Err(e) = tx.commit().await {
+        eprintln!("Failed to commit transaction: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    match fetch_attachments(&db_pool, &storage, owner, owner_id).await {
+        Ok(attachments) => (StatusCode::OK, Json(attachments)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch attachments after reorder: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn delete_attachment(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(owner): Extension<AttachmentOwner>,
+    Extension(firebase_user_id): Extension<String>,
+    Path((owner_id, attachment_id)): Path<(i32, i32)>,
+) -> impl IntoResponse {
+    let (db_pool, storage) = {
+        let state = state.lock().await;
+        (state.db_pool.clone(), state.storage.clone())
+    };
+
+    let car_id = match authorize_owner(
+        &db_pool,
+        &firebase_user_id,
+        owner,
+        owner_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        Ok(car_id) => car_id,
+        Err(response) => return response,
+    };
+
+    let attachment = match query_as::<_, Attachment>(&format!(
+        "{} WHERE attachment_id = ? AND owner_type = ? AND owner_id = ?",
+        SELECT_ATTACHMENTS
+    ))
+    .bind(attachment_id)
+    .bind(owner.as_str())
+    .bind(owner_id)
+    .fetch_optional(&db_pool)
+    .await
+    {
+        Ok(Some(attachment)) => attachment,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch attachment: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    match query("DELETE FROM Attachments WHERE attachment_id = ?")
+        .bind(attachment_id)
+        .execute(&db_pool)
+        .await
+    {
+        Ok(_) => {
+            AuditEntry::delete(
+                &firebase_user_id,
+                Some(car_id),
+                "attachment",
+                attachment_id,
+                &attachment,
+            )
+            .log(&db_pool)
+            .await;
+            storage.delete(&attachment.storage_key).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to delete attachment: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 記録に紐づく添付ファイルの行を削除し、ストレージから消すべきキーを返す
+pub(crate) async fn delete_owner_attachments(
+    conn: &mut MySqlConnection,
+    owner: AttachmentOwner,
+    owner_id: i32,
+) -> Result<Vec<String>, sqlx::Error> {
+    let keys: Vec<String> =
+        query_scalar("SELECT storage_key FROM Attachments WHERE owner_type = ? AND owner_id = ?")
+            .bind(owner.as_str())
+            .bind(owner_id)
+            .fetch_all(&mut *conn)
+            .await?;
+
+    query("DELETE FROM Attachments WHERE owner_type = ? AND owner_id = ?")
+        .bind(owner.as_str())
+        .bind(owner_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(keys)
+}
+
+/// 車両に属するすべての記録の添付ファイルの行を削除し、ストレージから消すべきキーを返す
+pub(crate) async fn delete_car_attachments(
+    conn: &mut MySqlConnection,
+    car_id: i32,
+) -> Result<Vec<String>, sqlx::Error> {
+    let mut keys = Vec::new();
+
+    for owner in ATTACHMENT_OWNERS {
+        let owner_ids: Vec<i32> = query_scalar(&format!(
+            "SELECT {} FROM {} WHERE car_id = ?",
+            owner.id_column(),
+            owner.table()
+        ))
+        .bind(car_id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        for owner_id in owner_ids {
+            keys.extend(delete_owner_attachments(&mut *conn, owner, owner_id).await?);
+        }
+    }
+
+    Ok(keys)
+}
+
+/// 車両に属するゴミ箱にない記録の添付ファイルを取得する
+pub(crate) async fn car_attachments(
+    conn: &mut MySqlConnection,
+    car_id: i32,
+) -> Result<Vec<Attachment>, sqlx::Error> {
+    let mut attachments = Vec::new();
+
+    for owner in ATTACHMENT_OWNERS {
+        attachments.extend(
+            query_as::<_, Attachment>(&format!(
+                "{} WHERE owner_type = ? AND owner_id IN (SELECT {} FROM {} WHERE car_id = ? AND deleted_at IS NULL) ORDER BY owner_id, sort_order, attachment_id",
+                SELECT_ATTACHMENTS,
+                owner.id_column(),
+                owner.table()
+            ))
+            .bind(owner.as_str())
+            .bind(car_id)
+            .fetch_all(&mut *conn)
+            .await?,
+        );
+    }
+
+    Ok(attachments)
+}
@@ -0,0 +1,66 @@
+use crate::access;
+use crate::models::audit_log::{AuditLog, AuditLogQuery, AUDIT_LOG_COLUMNS};
+use crate::models::user_car::CarRole;
+use crate::state::AppState;
+use axum::{
+    extract::{Extension, Json, Path, Query},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use sqlx::query_as;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+const DEFAULT_LIMIT: u32 = 50;
+const MAX_LIMIT: u32 = 200;
+
+/// 車両とその記録の変更履歴を新しい順に返す
+pub async fn get_car_audit_logs(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+    Query(params): Query<AuditLogQuery>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Viewer).await
+    {
+        return e.into_response();
+    }
+
+    let mut sql = format!(
+        "SELECT {} FROM AuditLogs a LEFT JOIN Users u ON u.firebase_user_id = a.actor_user_id WHERE a.car_id = ?",
+        AUDIT_LOG_COLUMNS
+    );
+    if params.entity_type.is_some() {
+        sql.push_str(" AND a.entity_type = ?");
+    }
+    if params.entity_id.is_some() {
+        sql.push_str(" AND a.entity_id = ?");
+    }
+    if params.before_id.is_some() {
+        sql.push_str(" AND a.audit_log_id < ?");
+    }
+    sql.push_str(" ORDER BY a.audit_log_id DESC LIMIT ?");
+
+    let mut logs = query_as::<_, AuditLog>(&sql).bind(car_id);
+    if let Some(entity_type) = &params.entity_type {
+        logs = logs.bind(entity_type);
+    }
+    if let Some(entity_id) = &params.entity_id {
+        logs = logs.bind(entity_id);
+    }
+    if let Some(before_id) = params.before_id {
+        logs = logs.bind(before_id);
+    }
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    match logs.bind(limit).fetch_all(&db_pool).await {
+        Ok(logs) => (StatusCode::OK, Json(logs)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch audit logs: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
@@ -0,0 +1,626 @@
+use crate::access;
+use crate::audit::AuditEntry;
+use crate::middleware::auth::AppError;
+use crate::models::car_invitation::{CarInvitation, CreateInvitationRequest};
+use crate::models::user_car::{
+    CarMember, CarRole, TransferOwnershipRequest, UpdateMemberRoleRequest,
+};
+use crate::state::AppState;
+use crate::validation::ValidatedJson;
+use axum::{
+    extract::{Extension, Json, Path},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde_json::json;
+use sqlx::{query, query_as, query_scalar, MySql, MySqlPool, Transaction};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+const SELECT_INVITATIONS: &str = "SELECT invitation_id, car_id, inviter_user_id, invitee_email, role, status, created_at, updated_at FROM CarInvitations";
+
+async fn user_email(
+    db_pool: &MySqlPool,
+    firebase_user_id: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    query_scalar("SELECT user_email FROM Users WHERE firebase_user_id = ?")
+        .bind(firebase_user_id)
+        .fetch_optional(db_pool)
+        .await
+}
+
+pub async fn get_car_members(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Viewer).await
+    {
+        return e.into_response();
+    }
+
+    match query_as::<_, CarMember>(
+        "SELECT u.firebase_user_id, u.user_email, u.user_name, uc.role
+         FROM user_car uc
+         JOIN Users u ON u.firebase_user_id = uc.firebase_user_id
+         WHERE uc.car_id = ?
+         ORDER BY uc.role, u.user_name",
+    )
+    .bind(car_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(members) => (StatusCode::OK, Json(members)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch car members: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn update_member_role(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path((car_id, member_id)): Path<(i32, String)>,
+    Json(req): Json<UpdateMemberRoleRequest>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Owner).await
+    {
+        return e.into_response();
+    }
+
+    if req.role == CarRole::Owner {
+        return AppError::new(
+            StatusCode::BAD_REQUEST,
+            "car/invalid-role".to_string(),
+            "Use the transfer_ownership endpoint to change the owner.".to_string(),
+        )
+        .into_response();
+    }
+
+    let before = match access::car_role(&db_pool, &member_id, car_id).await {
+        Ok(Some(CarRole::Owner)) => {
+            return AppError::new(
+                StatusCode::BAD_REQUEST,
+                "car/invalid-role".to_string(),
+                "The owner's role cannot be changed.".to_string(),
+            )
+            .into_response();
+        }
+        Ok(Some(role)) => role,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch car member: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    match query("UPDATE user_car SET role = ? WHERE firebase_user_id = ? AND car_id = ?")
+        .bind(req.role)
+        .bind(&member_id)
+        .bind(car_id)
+        .execute(&db_pool)
+        .await
+    {
+        Ok(_) => {
+            AuditEntry::update(
+                &firebase_user_id,
+                Some(car_id),
+                "car_member",
+                &member_id,
+                &json!({ "role": before }),
+                &json!({ "role": req.role }),
+            )
+            .log(&db_pool)
+            .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to update car member role: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// オーナーは他のメンバーを外せる。オーナー以外のメンバーは自分自身を外せる
+pub async fn remove_member(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path((car_id, member_id)): Path<(i32, String)>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let required = if member_id == firebase_user_id {
+        CarRole::Viewer
+    } else {
+        CarRole::Owner
+    };
+    if let Err(e) = access::require_car_role(&db_pool, &firebase_user_id, car_id, required).await {
+        return e.into_response();
+    }
+
+    let before = match access::car_role(&db_pool, &member_id, car_id).await {
+        Ok(Some(CarRole::Owner)) => {
+            return AppError::new(
+                StatusCode::BAD_REQUEST,
+                "car/owner-cannot-leave".to_string(),
+                "Transfer ownership before removing the owner.".to_string(),
+            )
+            .into_response();
+        }
+        Ok(Some(role)) => role,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch car member: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    match query("DELETE FROM user_car WHERE firebase_user_id = ? AND car_id = ?")
+        .bind(&member_id)
+        .bind(car_id)
+        .execute(&db_pool)
+        .await
+    {
+        Ok(_) => {
+            AuditEntry::delete(
+                &firebase_user_id,
+                Some(car_id),
+                "car_member",
+                &member_id,
+                &json!({ "role": before }),
+            )
+            .log(&db_pool)
+            .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to remove car member: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 既存のメンバーにオーナー権限を移す。元のオーナーは editor になる
+pub async fn transfer_ownership(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+    Json(req): Json<TransferOwnershipRequest>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Owner).await
+    {
+        return e.into_response();
+    }
+
+    let new_owner_role = match access::car_role(&db_pool, &req.firebase_user_id, car_id).await {
+        Ok(Some(CarRole::Owner)) => return StatusCode::NO_CONTENT.into_response(),
+        Ok(Some(role)) => role,
+        Ok(None) => {
+            return AppError::new(
+                StatusCode::BAD_REQUEST,
+                "car/not-a-member".to_string(),
+                "Ownership can only be transferred to a member of the car.".to_string(),
+            )
+            .into_response();
+        }
+        Err(e) => {
+            eprintln!("Failed to fetch car member: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let mut tx: Transaction<'_, MySql> = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            eprintln!("Failed to start transaction: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    for (member_id, before, role) in [
+        (&firebase_user_id, CarRole::Owner, CarRole::Editor),
+        (&req.firebase_user_id, new_owner_role, CarRole::Owner),
+    ] {
+        let result =
+            query("UPDATE user_car SET role = ? WHERE firebase_user_id = ? AND car_id = ?")
+                .bind(role)
+                .bind(member_id)
+                .bind(car_id)
+                .execute(&mut *tx)
+                .await;
+
+        if let Err(e) = result {
+            tx.rollback().await.unwrap();
+            eprintln!("Failed to transfer ownership: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        AuditEntry::update(
+            &firebase_user_id,
+            Some(car_id),
+            "car_member",
+            member_id,
+            &json!({ "role": before }),
+            &json!({ "role": role }),
+        )
+        .log(&mut *tx)
+        .await;
+    }
+
+    match tx.commit().await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            eprintln!("Failed to commit transaction: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn create_invitation(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+    ValidatedJson(req): ValidatedJson<CreateInvitationRequest>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Owner).await
+    {
+        return e.into_response();
+    }
+
+    if req.role == CarRole::Owner {
+        return AppError::new(
+            StatusCode::BAD_REQUEST,
+            "car/invalid-role".to_string(),
+            "Invitations can only grant the editor or viewer role.".to_string(),
+        )
+        .into_response();
+    }
+
+    let email = req.email.trim();
+    if email.is_empty() || !email.contains('@') {
+        return AppError::new(
+            StatusCode::BAD_REQUEST,
+            "invitation/invalid-email".to_string(),
+            "A valid email address is required.".to_string(),
+        )
+        .into_response();
+    }
+
+    let already_member: i64 = match query_scalar(
+        "SELECT COUNT(*) FROM user_car uc
+         JOIN Users u ON u.firebase_user_id = uc.firebase_user_id
+         WHERE uc.car_id = ? AND u.user_email = ?",
+    )
+    .bind(car_id)
+    .bind(email)
+    .fetch_one(&db_pool)
+    .await
+    {
+        Ok(count) => count,
+        Err(e) => {
+            eprintln!("Failed to fetch car members: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    if already_member > 0 {
+        return AppError::new(
+            StatusCode::CONFLICT,
+            "invitation/already-member".to_string(),
+            "The user already has access to this car.".to_string(),
+        )
+        .into_response();
+    }
+
+    match query(
+        "INSERT INTO CarInvitations (car_id, inviter_user_id, invitee_email, role) VALUES (?, ?, ?, ?)",
+    )
+    .bind(car_id)
+    .bind(&firebase_user_id)
+    .bind(email)
+    .bind(req.role)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) => {
+            match query_as::<_, CarInvitation>(&format!(
+                "{} WHERE invitation_id = ?",
+                SELECT_INVITATIONS
+            ))
+            .bind(result.last_insert_id())
+            .fetch_one(&db_pool)
+            .await
+            {
+                Ok(invitation) => {
+                    AuditEntry::create(
+                        &firebase_user_id,
+                        Some(car_id),
+                        "car_invitation",
+                        invitation.invitation_id,
+                        &invitation,
+                    )
+                    .log(&db_pool)
+                    .await;
+                    (StatusCode::CREATED, Json(invitation)).into_response()
+                }
+                Err(e) => {
+                    eprintln!("Failed to fetch invitation after creation: {:?}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to create invitation: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn get_car_invitations(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Owner).await
+    {
+        return e.into_response();
+    }
+
+    match query_as::<_, CarInvitation>(&format!(
+        "{} WHERE car_id = ? AND status = 'pending' ORDER BY created_at",
+        SELECT_INVITATIONS
+    ))
+    .bind(car_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(invitations) => (StatusCode::OK, Json(invitations)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch invitations: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn revoke_invitation(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path((car_id, invitation_id)): Path<(i32, i32)>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Owner).await
+    {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE CarInvitations SET status = 'revoked' WHERE invitation_id = ? AND car_id = ? AND status = 'pending'",
+    )
+    .bind(invitation_id)
+    .bind(car_id)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => StatusCode::NOT_FOUND.into_response(),
+        Ok(_) => {
+            AuditEntry::update(
+                &firebase_user_id,
+                Some(car_id),
+                "car_invitation",
+                invitation_id,
+                &json!({ "status": "pending" }),
+                &json!({ "status": "revoked" }),
+            )
+            .log(&db_pool)
+            .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to revoke invitation: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// ログイン中のユーザーのメールアドレス宛ての招待を取得する
+pub async fn get_my_invitations(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let email = match user_email(&db_pool, &firebase_user_id).await {
+        Ok(Some(email)) => email,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch user: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    match query_as::<_, CarInvitation>(&format!(
+        "{} WHERE invitee_email = ? AND status = 'pending' ORDER BY created_at",
+        SELECT_INVITATIONS
+    ))
+    .bind(email)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(invitations) => (StatusCode::OK, Json(invitations)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch invitations: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn fetch_own_pending_invitation(
+    db_pool: &MySqlPool,
+    firebase_user_id: &str,
+    invitation_id: i32,
+) -> Result<CarInvitation, StatusCode> {
+    let email = match user_email(db_pool, firebase_user_id).await {
+        Ok(Some(email)) => email,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            eprintln!("Failed to fetch user: {:?}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    match query_as::<_, CarInvitation>(&format!(
+        "{} WHERE invitation_id = ? AND invitee_email = ? AND status = 'pending'",
+        SELECT_INVITATIONS
+    ))
+    .bind(invitation_id)
+    .bind(email)
+    .fetch_optional(db_pool)
+    .await
+    {
+        Ok(Some(invitation)) => Ok(invitation),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            eprintln!("Failed to fetch invitation: {:?}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+pub async fn accept_invitation(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(invitation_id): Path<i32>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let invitation =
+        match fetch_own_pending_invitation(&db_pool, &firebase_user_id, invitation_id).await {
+            Ok(invitation) => invitation,
+            Err(status) => return status.into_response(),
+        };
+
+    let mut tx: Transaction<'_, MySql> = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            eprintln!("Failed to start transaction: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    // すでにメンバーの場合はロールを変更しない
+    let user_car_result =
+        query("INSERT IGNORE INTO user_car (firebase_user_id, car_id, role) VALUES (?, ?, ?)")
+            .bind(&firebase_user_id)
+            .bind(invitation.car_id)
+            .bind(invitation.role)
+            .execute(&mut *tx)
+            .await;
+
+    match user_car_result {
+        Ok(result) if result.rows_affected() > 0 => {
+            AuditEntry::create(
+                &firebase_user_id,
+                Some(invitation.car_id),
+                "car_member",
+                &firebase_user_id,
+                &json!({ "role": invitation.role }),
+            )
+            .log(&mut *tx)
+            .await;
+        }
+        Ok(_) => {}
+        Err(e) => {
+            tx.rollback().await.unwrap();
+            eprintln!("Failed to create user_car entry: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    let invitation_result =
+        query("UPDATE CarInvitations SET status = 'accepted' WHERE invitation_id = ?")
+            .bind(invitation_id)
+            .execute(&mut *tx)
+            .await;
+
+    match invitation_result {
+        Ok(_) => {
+            AuditEntry::update(
+                &firebase_user_id,
+                Some(invitation.car_id),
+                "car_invitation",
+                invitation_id,
+                &json!({ "status": "pending" }),
+                &json!({ "status": "accepted" }),
+            )
+            .log(&mut *tx)
+            .await;
+            match tx.commit().await {
+                Ok(_) => StatusCode::NO_CONTENT.into_response(),
+                Err(e) => {
+                    eprintln!("Failed to commit transaction: {:?}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+            }
+        }
+        Err(e) => {
+            tx.rollback().await.unwrap();
+            eprintln!("Failed to accept invitation: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn decline_invitation(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(invitation_id): Path<i32>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let invitation =
+        match fetch_own_pending_invitation(&db_pool, &firebase_user_id, invitation_id).await {
+            Ok(invitation) => invitation,
+            Err(status) => return status.into_response(),
+        };
+
+    match query("UPDATE CarInvitations SET status = 'declined' WHERE invitation_id = ?")
+        .bind(invitation_id)
+        .execute(&db_pool)
+        .await
+    {
+        Ok(_) => {
+            AuditEntry::update(
+                &firebase_user_id,
+                Some(invitation.car_id),
+                "car_invitation",
+                invitation_id,
+                &json!({ "status": "pending" }),
+                &json!({ "status": "declined" }),
+            )
+            .log(&db_pool)
+            .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to decline invitation: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
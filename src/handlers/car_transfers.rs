@@ -0,0 +1,538 @@
+use crate::access;
+use crate::audit::AuditEntry;
+use crate::history;
+use crate::middleware::auth::AppError;
+use crate::models::car_transfer::{CarTransfer, CreateTransferRequest, CAR_TRANSFER_COLUMNS};
+use crate::models::user_car::CarRole;
+use crate::state::AppState;
+use crate::validation::ValidatedJson;
+use axum::{
+    extract::{Extension, Json, Path},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde_json::json;
+use sqlx::types::Json as SqlJson;
+use sqlx::{query, query_as, query_scalar, MySql, MySqlPool, Transaction};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+async fn user_email(
+    db_pool: &MySqlPool,
+    firebase_user_id: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    query_scalar("SELECT user_email FROM Users WHERE firebase_user_id = ?")
+        .bind(firebase_user_id)
+        .fetch_optional(db_pool)
+        .await
+}
+
+async fn fetch_transfer(
+    db_pool: &MySqlPool,
+    transfer_id: i32,
+) -> Result<Option<CarTransfer>, sqlx::Error> {
+    query_as::<_, CarTransfer>(&format!(
+        "SELECT {} FROM CarTransfers WHERE transfer_id = ?",
+        CAR_TRANSFER_COLUMNS
+    ))
+    .bind(transfer_id)
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// 売り手（オーナー）が譲渡を開始する
+pub async fn create_transfer(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+    ValidatedJson(req): ValidatedJson<CreateTransferRequest>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Owner).await
+    {
+        return e.into_response();
+    }
+
+    let to_email = req.to_email.trim();
+    match user_email(&db_pool, &firebase_user_id).await {
+        Ok(Some(email)) if email == to_email => {
+            return AppError::new(
+                StatusCode::BAD_REQUEST,
+                "transfer/invalid-recipient".to_string(),
+                "A car cannot be transferred to its current owner.".to_string(),
+            )
+            .into_response();
+        }
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("Failed to fetch user: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    let recipient_exists: i64 =
+        match query_scalar("SELECT COUNT(*) FROM Users WHERE user_email = ?")
+            .bind(to_email)
+            .fetch_one(&db_pool)
+            .await
+        {
+            Ok(count) => count,
+            Err(e) => {
+                eprintln!("Failed to fetch user: {:?}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+    if recipient_exists == 0 {
+        return AppError::new(
+            StatusCode::BAD_REQUEST,
+            "transfer/invalid-recipient".to_string(),
+            "The recipient must be an AutoTrack user.".to_string(),
+        )
+        .into_response();
+    }
+
+    let pending: i64 = match query_scalar(
+        "SELECT COUNT(*) FROM CarTransfers WHERE car_id = ? AND status = 'pending'",
+    )
+    .bind(car_id)
+    .fetch_one(&db_pool)
+    .await
+    {
+        Ok(count) => count,
+        Err(e) => {
+            eprintln!("Failed to fetch car transfers: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    if pending > 0 {
+        return AppError::new(
+            StatusCode::CONFLICT,
+            "transfer/already-pending".to_string(),
+            "This car already has a pending transfer.".to_string(),
+        )
+        .into_response();
+    }
+
+    match query(
+        "INSERT INTO CarTransfers (car_id, from_user_id, to_email, keep_snapshot) VALUES (?, ?, ?, ?)",
+    )
+    .bind(car_id)
+    .bind(&firebase_user_id)
+    .bind(to_email)
+    .bind(req.keep_snapshot)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) => match fetch_transfer(&db_pool, result.last_insert_id() as i32).await {
+            Ok(Some(transfer)) => {
+                AuditEntry::create(
+                    &firebase_user_id,
+                    Some(car_id),
+                    "car_transfer",
+                    transfer.transfer_id,
+                    &transfer,
+                )
+                .log(&db_pool)
+                .await;
+                (StatusCode::CREATED, Json(transfer)).into_response()
+            }
+            Ok(None) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            Err(e) => {
+                eprintln!("Failed to fetch car transfer after creation: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to create car transfer: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 車両の譲渡履歴を取得する
+pub async fn get_car_transfers(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Owner).await
+    {
+        return e.into_response();
+    }
+
+    match query_as::<_, CarTransfer>(&format!(
+        "SELECT {} FROM CarTransfers WHERE car_id = ? ORDER BY created_at, transfer_id",
+        CAR_TRANSFER_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(transfers) => (StatusCode::OK, Json(transfers)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch car transfers: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// ログイン中のユーザーが売り手または買い手になっている譲渡を取得する
+pub async fn get_my_transfers(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let email = match user_email(&db_pool, &firebase_user_id).await {
+        Ok(Some(email)) => email,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch user: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    match query_as::<_, CarTransfer>(&format!(
+        "SELECT {} FROM CarTransfers WHERE from_user_id = ? OR to_email = ? OR to_user_id = ? ORDER BY created_at DESC",
+        CAR_TRANSFER_COLUMNS
+    ))
+    .bind(&firebase_user_id)
+    .bind(&email)
+    .bind(&firebase_user_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(transfers) => (StatusCode::OK, Json(transfers)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch car transfers: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn cancel_transfer(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path((car_id, transfer_id)): Path<(i32, i32)>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Owner).await
+    {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE CarTransfers SET status = 'cancelled' WHERE transfer_id = ? AND car_id = ? AND status = 'pending'",
+    )
+    .bind(transfer_id)
+    .bind(car_id)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => StatusCode::NOT_FOUND.into_response(),
+        Ok(_) => {
+            AuditEntry::update(
+                &firebase_user_id,
+                Some(car_id),
+                "car_transfer",
+                transfer_id,
+                &json!({ "status": "pending" }),
+                &json!({ "status": "cancelled" }),
+            )
+            .log(&db_pool)
+            .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to cancel car transfer: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+fn transfer_not_pending() -> AppError {
+    AppError::new(
+        StatusCode::CONFLICT,
+        "transfer/not-pending".to_string(),
+        "This transfer is no longer pending.".to_string(),
+    )
+}
+
+async fn fetch_incoming_pending_transfer(
+    db_pool: &MySqlPool,
+    firebase_user_id: &str,
+    transfer_id: i32,
+) -> Result<CarTransfer, StatusCode> {
+    let email = match user_email(db_pool, firebase_user_id).await {
+        Ok(Some(email)) => email,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            eprintln!("Failed to fetch user: {:?}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    match fetch_transfer(db_pool, transfer_id).await {
+        Ok(Some(transfer)) if transfer.to_email == email && transfer.status == "pending" => {
+            Ok(transfer)
+        }
+        Ok(_) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            eprintln!("Failed to fetch car transfer: {:?}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// 買い手が譲渡を受け入れる。車両の記録はそのまま、アクセス権だけを買い手に移す
+pub async fn accept_transfer(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(transfer_id): Path<i32>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let transfer =
+        match fetch_incoming_pending_transfer(&db_pool, &firebase_user_id, transfer_id).await {
+            Ok(transfer) => transfer,
+            Err(status) => return status.into_response(),
+        };
+
+    let mut tx: Transaction<'_, MySql> = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            eprintln!("Failed to start transaction: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    // 取り消し・辞退と同時に受け入れないよう、譲渡の行をロックしてから状態を確かめ直す
+    let status: Option<String> =
+        match query_scalar("SELECT status FROM CarTransfers WHERE transfer_id = ? FOR UPDATE")
+            .bind(transfer_id)
+            .fetch_optional(&mut *tx)
+            .await
+        {
+            Ok(status) => status,
+            Err(e) => {
+                tx.rollback().await.unwrap();
+                eprintln!("Failed to fetch car transfer: {:?}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+    if status.as_deref() != Some("pending") {
+        tx.rollback().await.unwrap();
+        return transfer_not_pending().into_response();
+    }
+
+    // 売り手がまだオーナーであることを確認し、同時に実行された譲渡と競合しないようロックする
+    let seller_role: Option<CarRole> = match query_scalar(
+        "SELECT role FROM user_car WHERE firebase_user_id = ? AND car_id = ? FOR UPDATE",
+    )
+    .bind(&transfer.from_user_id)
+    .bind(transfer.car_id)
+    .fetch_optional(&mut *tx)
+    .await
+    {
+        Ok(role) => role,
+        Err(e) => {
+            tx.rollback().await.unwrap();
+            eprintln!("Failed to fetch car owner: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    if seller_role != Some(CarRole::Owner) {
+        tx.rollback().await.unwrap();
+        return AppError::new(
+            StatusCode::CONFLICT,
+            "transfer/owner-changed".to_string(),
+            "The seller no longer owns this car.".to_string(),
+        )
+        .into_response();
+    }
+
+    let snapshot = if transfer.keep_snapshot {
+        match history::load_car_history(&mut tx, transfer.car_id).await {
+            Ok(history) => history,
+            Err(e) => {
+                tx.rollback().await.unwrap();
+                eprintln!("Failed to load car history: {:?}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+    } else {
+        None
+    };
+
+    // 買い手以外のメンバーは外れるので、監査ログに残すためにロールを読んでおく
+    let members: Vec<(String, CarRole)> =
+        match query_as("SELECT firebase_user_id, role FROM user_car WHERE car_id = ? FOR UPDATE")
+            .bind(transfer.car_id)
+            .fetch_all(&mut *tx)
+            .await
+        {
+            Ok(members) => members,
+            Err(e) => {
+                tx.rollback().await.unwrap();
+                eprintln!("Failed to fetch car members: {:?}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+
+    let statements = [
+        query("DELETE FROM user_car WHERE car_id = ?").bind(transfer.car_id),
+        query("INSERT INTO user_car (firebase_user_id, car_id, role) VALUES (?, ?, 'owner')")
+            .bind(&firebase_user_id)
+            .bind(transfer.car_id),
+        query(
+            "UPDATE CarInvitations SET status = 'revoked' WHERE car_id = ? AND status = 'pending'",
+        )
+        .bind(transfer.car_id),
+    ];
+
+    for statement in statements {
+        if let Err(e) = statement.execute(&mut *tx).await {
+            tx.rollback().await.unwrap();
+            eprintln!("Failed to transfer car: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    for (member_id, role) in &members {
+        let entry = AuditEntry::delete(
+            &firebase_user_id,
+            Some(transfer.car_id),
+            "car_member",
+            member_id,
+            &json!({ "role": role }),
+        );
+        if let Err(e) = entry.save(&mut *tx).await {
+            tx.rollback().await.unwrap();
+            eprintln!(
+                "Failed to write audit log for car_member {}: {:?}",
+                member_id, e
+            );
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    match query(
+        "UPDATE CarTransfers SET status = 'accepted', to_user_id = ?, snapshot = ?, completed_at = CURRENT_TIMESTAMP WHERE transfer_id = ? AND status = 'pending'",
+    )
+    .bind(&firebase_user_id)
+    .bind(snapshot.map(SqlJson))
+    .bind(transfer_id)
+    .execute(&mut *tx)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => {
+            tx.rollback().await.unwrap();
+            return transfer_not_pending().into_response();
+        }
+        Ok(_) => {}
+        Err(e) => {
+            tx.rollback().await.unwrap();
+            eprintln!("Failed to transfer car: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    if let Err(e) = tx.commit().await {
+        eprintln!("Failed to commit transaction: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    match fetch_transfer(&db_pool, transfer_id).await {
+        Ok(Some(accepted)) => {
+            AuditEntry::update(
+                &firebase_user_id,
+                Some(transfer.car_id),
+                "car_transfer",
+                transfer_id,
+                &transfer,
+                &accepted,
+            )
+            .log(&db_pool)
+            .await;
+            (StatusCode::OK, Json(accepted)).into_response()
+        }
+        Ok(None) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch car transfer after acceptance: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn decline_transfer(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(transfer_id): Path<i32>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let transfer =
+        match fetch_incoming_pending_transfer(&db_pool, &firebase_user_id, transfer_id).await {
+            Ok(transfer) => transfer,
+            Err(status) => return status.into_response(),
+        };
+
+    match query(
+        "UPDATE CarTransfers SET status = 'declined', completed_at = CURRENT_TIMESTAMP WHERE transfer_id = ? AND status = 'pending'",
+    )
+    .bind(transfer_id)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => transfer_not_pending().into_response(),
+        Ok(_) => {
+            AuditEntry::update(
+                &firebase_user_id,
+                Some(transfer.car_id),
+                "car_transfer",
+                transfer_id,
+                &json!({ "status": "pending" }),
+                &json!({ "status": "declined" }),
+            )
+            .log(&db_pool)
+            .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to decline car transfer: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 売り手が譲渡時点の記録の読み取り専用スナップショットを取得する
+pub async fn get_transfer_snapshot(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(transfer_id): Path<i32>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    match query_scalar::<_, Option<SqlJson<serde_json::Value>>>(
+        "SELECT snapshot FROM CarTransfers WHERE transfer_id = ? AND from_user_id = ? AND status = 'accepted'",
+    )
+    .bind(transfer_id)
+    .bind(&firebase_user_id)
+    .fetch_optional(&db_pool)
+    .await
+    {
+        Ok(Some(Some(SqlJson(snapshot)))) => (StatusCode::OK, Json(snapshot)).into_response(),
+        Ok(_) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch car transfer snapshot: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
@@ -1,34 +1,70 @@
-use crate::models::car::Car;
-use crate::models::fuel_efficiency::FuelEfficiency;
-use crate::models::maintenance::Maintenance;
-use crate::models::tuning::Tuning;
+use crate::access;
+use crate::audit::AuditEntry;
+use crate::db;
+use crate::etag;
+use crate::expenses;
+use crate::handlers::attachments;
+use crate::handlers::user_preferences;
+use crate::handlers::vehicle_specs;
+use crate::history;
+use crate::i18n::Lang;
+use crate::middleware::auth::AppError;
+use crate::models::car::{Car, CAR_COLUMNS, CAR_PATCH};
+use crate::models::expense::ExpenseQuery;
+use crate::models::fuel_efficiency::{FuelEfficiency, FUEL_EFFICIENCY_COLUMNS};
+use crate::models::maintenance::{Maintenance, MAINTENANCE_COLUMNS};
+use crate::models::tuning::{CarTuningQuery, Tuning, TUNING_COLUMNS};
+use crate::models::user_car::CarRole;
+use crate::patch;
+use crate::report_pdf;
 use crate::state::AppState;
+use crate::storage::{image_prefix, Storage};
+use crate::trash;
+use crate::validation::{self, UpdateJson, ValidatedJson};
 use axum::{
-    extract::{Extension, Json, Path},
-    http::StatusCode,
+    extract::{Extension, Json, Path, Query},
+    http::{header, HeaderMap, StatusCode},
     response::IntoResponse,
 };
 use serde::{Deserialize, Serialize};
-use sqlx::{query, query_as, MySql, Transaction};
+use serde_json::Value;
+use sqlx::{query, query_as, query_scalar, MySql, MySqlConnection, MySqlPool, Transaction};
+use std::env;
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use validator::Validate;
 
-#[derive(Debug, Deserialize, Serialize)]
+/// 車両はログイン中のユーザーをオーナーとして登録する
+#[derive(Debug, Deserialize, Serialize, Validate)]
 pub struct CreateCarRequest {
+    #[validate(nested)]
     car: Car,
-    firebase_user_id: String,
+}
+
+pub(crate) async fn fetch_car(db_pool: &MySqlPool, car_id: i32) -> Result<Car, sqlx::Error> {
+    query_as::<_, Car>(&format!(
+        "SELECT {} FROM Cars WHERE car_id = ?",
+        CAR_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_one(db_pool)
+    .await
+}
+
+// VIN と型式のカタログから空欄の諸元を補う
+async fn complete_specs(db_pool: &MySqlPool, car: &mut Car) {
+    car.apply_vin();
+    vehicle_specs::fill_specs(db_pool, car).await;
 }
 
 pub async fn create_car(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
-    Json(req): Json<CreateCarRequest>,
+    Extension(auth_user_id): Extension<String>,
+    ValidatedJson(req): ValidatedJson<CreateCarRequest>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    let CreateCarRequest {
-        car,
-        firebase_user_id,
-    } = req;
+    let CreateCarRequest { car } = req;
 
     let mut tx: Transaction<'_, MySql> = match db_pool.begin().await {
         Ok(tx) => tx,
@@ -38,34 +74,49 @@ pub async fn create_car(
         }
     };
 
-    let car_result = query!(
-        "INSERT INTO Cars (car_name, carmodelnum, car_color, car_mileage, car_isflooding, car_issmoked, car_image_url) VALUES (?, ?, ?, ?, ?, ?, ?)",
-        car.car_name,
-        car.carmodelnum,
-        car.car_color,
-        car.car_mileage,
-        car.car_isflooding as i8,
-        car.car_issmoked as i8,
-        car.car_image_url
+    let mut car = car;
+    complete_specs(&db_pool, &mut car).await;
+
+    let car_result = query(
+        "INSERT INTO Cars (car_name, carmodelnum, car_color, car_mileage, car_isflooding, car_issmoked, car_image_url, car_maker, car_model, car_grade, car_year, car_displacement, car_fuel_type, car_tank_capacity, car_weight, car_transmission, car_first_registration, car_plate_number, car_vin) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
     )
+    .bind(&car.car_name)
+    .bind(&car.carmodelnum)
+    .bind(&car.car_color)
+    .bind(car.car_mileage)
+    .bind(car.car_isflooding)
+    .bind(car.car_issmoked)
+    .bind(&car.car_image_url)
+    .bind(&car.car_maker)
+    .bind(&car.car_model)
+    .bind(&car.car_grade)
+    .bind(car.car_year)
+    .bind(car.car_displacement)
+    .bind(car.car_fuel_type)
+    .bind(car.car_tank_capacity)
+    .bind(car.car_weight)
+    .bind(car.car_transmission)
+    .bind(&car.car_first_registration)
+    .bind(&car.car_plate_number)
+    .bind(&car.car_vin)
     .execute(&mut *tx)
     .await;
 
     match car_result {
         Ok(res) => {
             let car_id = res.last_insert_id();
-            let car = query_as!(
-                Car,
-                "SELECT car_id, car_name, carmodelnum, car_color, car_mileage, car_isflooding as `car_isflooding: bool`, car_issmoked as `car_issmoked: bool`, car_image_url, created_at, updated_at FROM Cars WHERE car_id = ?",
-                car_id
-            )
+            let car = query_as::<_, Car>(&format!(
+                "SELECT {} FROM Cars WHERE car_id = ?",
+                CAR_COLUMNS
+            ))
+            .bind(car_id)
             .fetch_one(&mut *tx)
             .await
             .unwrap();
 
             let user_car_result = query!(
                 "INSERT INTO user_car (firebase_user_id, car_id) VALUES (?, ?)",
-                firebase_user_id,
+                auth_user_id,
                 car_id
             )
             .execute(&mut *tx)
@@ -73,8 +124,11 @@ pub async fn create_car(
 
             match user_car_result {
                 Ok(_) => {
+                    AuditEntry::create(&auth_user_id, Some(car_id as i32), "car", car_id, &car)
+                        .log(&mut *tx)
+                        .await;
                     tx.commit().await.unwrap();
-                    (StatusCode::CREATED, Json(car)).into_response()
+                    etag::with_etag(StatusCode::CREATED, car.updated_at, car)
                 }
                 Err(e) => {
                     tx.rollback().await.unwrap();
@@ -94,10 +148,10 @@ pub async fn create_car(
 pub async fn get_cars(Extension(state): Extension<Arc<Mutex<AppState>>>) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query_as!(
-        Car,
-        "SELECT car_id, car_name, carmodelnum, car_color, car_mileage, car_isflooding as `car_isflooding: bool`, car_issmoked as `car_issmoked: bool`, car_image_url, created_at, updated_at FROM Cars"
-    )
+    match query_as::<_, Car>(&format!(
+        "SELECT {} FROM Cars WHERE deleted_at IS NULL",
+        CAR_COLUMNS
+    ))
     .fetch_all(&db_pool)
     .await
     {
@@ -112,18 +166,19 @@ pub async fn get_cars(Extension(state): Extension<Arc<Mutex<AppState>>>) -> impl
 pub async fn get_car(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
     Path(car_id): Path<i32>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query_as!(
-        Car,
-        "SELECT car_id, car_name, carmodelnum, car_color, car_mileage, car_isflooding as `car_isflooding: bool`, car_issmoked as `car_issmoked: bool`, car_image_url, created_at, updated_at FROM Cars WHERE car_id = ?",
-        car_id
-    )
+    match query_as::<_, Car>(&format!(
+        "SELECT {} FROM Cars WHERE car_id = ? AND deleted_at IS NULL",
+        CAR_COLUMNS
+    ))
+    .bind(car_id)
     .fetch_one(&db_pool)
     .await
     {
-        Ok(car) => (StatusCode::OK, Json(car)).into_response(),
+        Ok(car) => etag::conditional_get(&headers, car.updated_at, car),
         Err(e) => {
             eprintln!("Failed to fetch car: {:?}", e);
             StatusCode::NOT_FOUND.into_response()
@@ -133,42 +188,157 @@ pub async fn get_car(
 
 pub async fn update_car(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
     Path(car_id): Path<i32>,
-    Json(updated_car): Json<Car>,
+    headers: HeaderMap,
+    UpdateJson(updated_car): UpdateJson<Car>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    let result = query!(
-        "UPDATE Cars SET car_name = ?, carmodelnum = ?, car_color = ?, car_mileage = ?, car_isflooding = ?, car_issmoked = ?, car_image_url = ? WHERE car_id = ?",
-        updated_car.car_name,
-        updated_car.carmodelnum,
-        updated_car.car_color,
-        updated_car.car_mileage,
-        updated_car.car_isflooding as i8,
-        updated_car.car_issmoked as i8,
-        updated_car.car_image_url,
-        car_id
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Editor).await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_car(&db_pool, car_id).await {
+        Ok(car) => car,
+        Err(e) => {
+            eprintln!("Failed to fetch car: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    if let Err(e) = validation::validate_changes(&before, &updated_car) {
+        return e.into_response();
+    }
+
+    let mut updated_car = updated_car;
+    complete_specs(&db_pool, &mut updated_car).await;
+
+    let result = query(
+        "UPDATE Cars SET car_name = ?, carmodelnum = ?, car_color = ?, car_mileage = ?, car_isflooding = ?, car_issmoked = ?, car_image_url = ?, car_maker = ?, car_model = ?, car_grade = ?, car_year = ?, car_displacement = ?, car_fuel_type = ?, car_tank_capacity = ?, car_weight = ?, car_transmission = ?, car_first_registration = ?, car_plate_number = ?, car_vin = ? WHERE car_id = ? AND updated_at <=> ?",
     )
+    .bind(&updated_car.car_name)
+    .bind(&updated_car.carmodelnum)
+    .bind(&updated_car.car_color)
+    .bind(updated_car.car_mileage)
+    .bind(updated_car.car_isflooding)
+    .bind(updated_car.car_issmoked)
+    .bind(&updated_car.car_image_url)
+    .bind(&updated_car.car_maker)
+    .bind(&updated_car.car_model)
+    .bind(&updated_car.car_grade)
+    .bind(updated_car.car_year)
+    .bind(updated_car.car_displacement)
+    .bind(updated_car.car_fuel_type)
+    .bind(updated_car.car_tank_capacity)
+    .bind(updated_car.car_weight)
+    .bind(updated_car.car_transmission)
+    .bind(&updated_car.car_first_registration)
+    .bind(&updated_car.car_plate_number)
+    .bind(&updated_car.car_vin)
+    .bind(car_id)
+    .bind(before.updated_at)
     .execute(&db_pool)
     .await;
 
     match result {
-        Ok(_) => {
-            match query_as!(
-                Car,
-                "SELECT car_id, car_name, carmodelnum, car_color, car_mileage, car_isflooding as `car_isflooding: bool`, car_issmoked as `car_issmoked: bool`, car_image_url, created_at, updated_at FROM Cars WHERE car_id = ?",
-                car_id
-            )
-            .fetch_one(&db_pool)
-            .await
-            {
-                Ok(car) => (StatusCode::OK, Json(car)).into_response(),
-                Err(e) => {
-                    eprintln!("Failed to fetch updated car: {:?}", e);
-                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
-                }
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
+        Ok(_) => match fetch_car(&db_pool, car_id).await {
+            Ok(car) => {
+                AuditEntry::update(
+                    &firebase_user_id,
+                    Some(car_id),
+                    "car",
+                    car_id,
+                    &before,
+                    &car,
+                )
+                .log(&db_pool)
+                .await;
+                etag::with_etag(StatusCode::OK, car.updated_at, car)
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch updated car: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
             }
+        },
+        Err(e) => {
+            eprintln!("Failed to update car: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// JSON Merge Patch で指定された項目だけを更新する
+pub async fn patch_car(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Editor).await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_car(&db_pool, car_id).await {
+        Ok(car) => car,
+        Err(e) => {
+            eprintln!("Failed to fetch car: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
         }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    let mut car = match patch::apply(&CAR_PATCH, &before, &patch) {
+        Ok(car) => car,
+        Err(e) => return e.into_response(),
+    };
+    complete_specs(&db_pool, &mut car).await;
+
+    match patch::update(
+        &db_pool,
+        &CAR_PATCH,
+        car_id,
+        &before,
+        &car,
+        before.updated_at,
+    )
+    .await
+    {
+        Ok(false) => etag::precondition_failed().into_response(),
+        Ok(true) => match fetch_car(&db_pool, car_id).await {
+            Ok(car) => {
+                AuditEntry::update(
+                    &firebase_user_id,
+                    Some(car_id),
+                    "car",
+                    car_id,
+                    &before,
+                    &car,
+                )
+                .log(&db_pool)
+                .await;
+                etag::with_etag(StatusCode::OK, car.updated_at, car)
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch updated car: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
         Err(e) => {
             eprintln!("Failed to update car: {:?}", e);
             StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -176,12 +346,33 @@ pub async fn update_car(
     }
 }
 
+/// 車両をその記録ごとゴミ箱に移す
 pub async fn delete_car(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
     Path(car_id): Path<i32>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Owner).await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_car(&db_pool, car_id).await {
+        Ok(car) => car,
+        Err(e) => {
+            eprintln!("Failed to fetch car: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
     let mut tx: Transaction<'_, MySql> = match db_pool.begin().await {
         Ok(tx) => tx,
         Err(e) => {
@@ -190,67 +381,169 @@ pub async fn delete_car(
         }
     };
 
+    match trash::move_car_to_trash(&mut tx, car_id).await {
+        Ok(_) => {
+            AuditEntry::delete(&firebase_user_id, Some(car_id), "car", car_id, &before)
+                .log(&mut *tx)
+                .await;
+            match tx.commit().await {
+                Ok(_) => StatusCode::NO_CONTENT.into_response(),
+                Err(e) => {
+                    eprintln!("Failed to commit transaction: {:?}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+            }
+        }
+        Err(e) => {
+            tx.rollback().await.unwrap();
+            eprintln!("Failed to delete car: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 車両画像の URL が、車両のメンバーがアップロードした画像を指していればそのキーを返す。
+/// URL には任意の値を設定できるため、それ以外のオブジェクトは読み出しも削除もしない
+pub(crate) async fn uploaded_image_key(
+    conn: &mut MySqlConnection,
+    storage: &Storage,
+    car_id: i32,
+) -> Result<Option<String>, sqlx::Error> {
+    let image_url: Option<String> = query_scalar("SELECT car_image_url FROM Cars WHERE car_id = ?")
+        .bind(car_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .flatten();
+    let Some(key) = image_url.and_then(|url| storage.key_from_url(&url)) else {
+        return Ok(None);
+    };
+
+    let members: Vec<String> =
+        query_scalar("SELECT firebase_user_id FROM user_car WHERE car_id = ?")
+            .bind(car_id)
+            .fetch_all(&mut *conn)
+            .await?;
+    Ok(members
+        .iter()
+        .any(|member| key.starts_with(&image_prefix(member)))
+        .then_some(key))
+}
+
+/// 車両を完全に削除するときにストレージから消す画像のキー。ほかの車両でも使っている画像は残す
+pub(crate) async fn image_key_to_delete(
+    conn: &mut MySqlConnection,
+    storage: &Storage,
+    car_id: i32,
+) -> Result<Option<String>, sqlx::Error> {
+    let Some(key) = uploaded_image_key(&mut *conn, storage, car_id).await? else {
+        return Ok(None);
+    };
+
+    let other_cars: i64 =
+        query_scalar("SELECT COUNT(*) FROM Cars WHERE car_image_url = ? AND car_id <> ?")
+            .bind(storage.public_url(&key))
+            .bind(car_id)
+            .fetch_one(&mut *conn)
+            .await?;
+    Ok((other_cars == 0).then_some(key))
+}
+
+/// 車両とそのすべての記録を完全に削除し、ストレージから消すべき添付ファイルのキーを返す
+pub(crate) async fn delete_car_data(
+    conn: &mut MySqlConnection,
+    car_id: i32,
+) -> Result<Vec<String>, sqlx::Error> {
+    let storage_keys = attachments::delete_car_attachments(&mut *conn, car_id).await?;
+
+    // 譲渡ログは売り手のスナップショットのため残し、保留中の譲渡だけ取り消す
+    query("UPDATE CarTransfers SET status = 'cancelled' WHERE car_id = ? AND status = 'pending'")
+        .bind(car_id)
+        .execute(&mut *conn)
+        .await?;
+
     // Delete related data from other tables
     let tables = vec![
         "FuelEfficiencies",
         "Maintenances",
         "Tunings",
         "Accidents",
+        "InsurancePolicies",
+        "VehicleTaxes",
+        "TireSets",
         "PeriodicInspection",
+        "CarInvitations",
+        "CarShareLinks",
         "user_car",
     ];
 
     for table in tables {
-        let delete_result = query(&format!("DELETE FROM {} WHERE car_id = ?", table))
+        query(&format!("DELETE FROM {} WHERE car_id = ?", table))
             .bind(car_id)
-            .execute(&mut *tx)
-            .await;
-
-        if let Err(e) = delete_result {
-            tx.rollback().await.unwrap();
-            eprintln!("Failed to delete from {}: {:?}", table, e);
-            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-        }
+            .execute(&mut *conn)
+            .await?;
     }
 
     // Finally, delete the car itself
-    let car_result = query!("DELETE FROM Cars WHERE car_id = ?", car_id)
-        .execute(&mut *tx)
-        .await;
+    query!("DELETE FROM Cars WHERE car_id = ?", car_id)
+        .execute(&mut *conn)
+        .await?;
 
-    match car_result {
-        Ok(_) => match tx.commit().await {
-            Ok(_) => StatusCode::NO_CONTENT.into_response(),
-            Err(e) => {
-                eprintln!("Failed to commit transaction: {:?}", e);
-                StatusCode::INTERNAL_SERVER_ERROR.into_response()
-            }
-        },
-        Err(e) => {
-            tx.rollback().await.unwrap();
-            eprintln!("Failed to delete car: {:?}", e);
-            StatusCode::INTERNAL_SERVER_ERROR.into_response()
-        }
-    }
+    Ok(storage_keys)
 }
 
 pub async fn update_car_image(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
     Path(car_id): Path<i32>,
+    headers: HeaderMap,
     Json(image_url): Json<String>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    let result = query!(
-        "UPDATE Cars SET car_image_url = ? WHERE car_id = ?",
-        image_url,
-        car_id
-    )
-    .execute(&db_pool)
-    .await;
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Editor).await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_car(&db_pool, car_id).await {
+        Ok(car) => car,
+        Err(e) => {
+            eprintln!("Failed to fetch car: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    let result = query("UPDATE Cars SET car_image_url = ? WHERE car_id = ? AND updated_at <=> ?")
+        .bind(&image_url)
+        .bind(car_id)
+        .bind(before.updated_at)
+        .execute(&db_pool)
+        .await;
 
     match result {
-        Ok(_) => (StatusCode::OK, "Image URL updated successfully").into_response(),
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
+        Ok(_) => {
+            let after = Car {
+                car_image_url: Some(image_url.clone()),
+                ..before.clone()
+            };
+            AuditEntry::update(
+                &firebase_user_id,
+                Some(car_id),
+                "car",
+                car_id,
+                &before,
+                &after,
+            )
+            .log(&db_pool)
+            .await;
+            (StatusCode::OK, "Image URL updated successfully").into_response()
+        }
         Err(e) => {
             eprintln!("Failed to update car image URL: {:?}", e);
             StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -259,19 +552,56 @@ pub async fn update_car_image(
 }
 pub async fn delete_car_image(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
     Path(car_id): Path<i32>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    let result = query!(
-        "UPDATE Cars SET car_image_url = NULL WHERE car_id = ?",
-        car_id
-    )
-    .execute(&db_pool)
-    .await;
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Editor).await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_car(&db_pool, car_id).await {
+        Ok(car) => car,
+        Err(e) => {
+            eprintln!("Failed to fetch car: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    let result =
+        query("UPDATE Cars SET car_image_url = NULL WHERE car_id = ? AND updated_at <=> ?")
+            .bind(car_id)
+            .bind(before.updated_at)
+            .execute(&db_pool)
+            .await;
 
     match result {
-        Ok(_) => (StatusCode::OK, "Car image URL deleted successfully").into_response(),
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
+        Ok(_) => {
+            let after = Car {
+                car_image_url: None,
+                ..before.clone()
+            };
+            AuditEntry::update(
+                &firebase_user_id,
+                Some(car_id),
+                "car",
+                car_id,
+                &before,
+                &after,
+            )
+            .log(&db_pool)
+            .await;
+            (StatusCode::OK, "Car image URL deleted successfully").into_response()
+        }
         Err(e) => {
             eprintln!("Failed to delete car image URL: {:?}", e);
             StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -285,14 +615,14 @@ pub async fn get_user_cars(
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query_as!(
-        Car,
-        "SELECT c.car_id, c.car_name, c.carmodelnum, c.car_color, c.car_mileage, c.car_isflooding as `car_isflooding: bool`, c.car_issmoked as `car_issmoked: bool`, c.car_image_url, c.created_at, c.updated_at 
+    match query_as::<_, Car>(&format!(
+        "SELECT {}
          FROM Cars c
          JOIN user_car uc ON c.car_id = uc.car_id
-         WHERE uc.firebase_user_id = ?",
-        firebase_user_id
-    )
+         WHERE uc.firebase_user_id = ? AND c.deleted_at IS NULL",
+        db::prefixed_columns(CAR_COLUMNS, "c")
+    ))
+    .bind(firebase_user_id)
     .fetch_all(&db_pool)
     .await
     {
@@ -307,12 +637,18 @@ pub async fn get_user_cars(
 pub async fn get_car_tuning(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
     Path(car_id): Path<i32>,
+    Query(params): Query<CarTuningQuery>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query_as!(Tuning, "SELECT * FROM Tunings WHERE car_id = ?", car_id)
-        .fetch_all(&db_pool)
-        .await
+    match query_as::<_, Tuning>(&format!(
+        "SELECT {} FROM Tunings WHERE car_id = ? AND deleted_at IS NULL AND (? = FALSE OR tuning_removed_date IS NULL)",
+        TUNING_COLUMNS
+    ))
+    .bind(car_id)
+    .bind(params.active)
+    .fetch_all(&db_pool)
+    .await
     {
         Ok(tunings) => (StatusCode::OK, Json(tunings)).into_response(),
         Err(e) => {
@@ -325,18 +661,25 @@ pub async fn get_car_tuning(
 pub async fn get_car_maintenance(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
     Path(car_id): Path<i32>,
+    lang: Lang,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query_as!(
-        Maintenance,
-        "SELECT * FROM Maintenances WHERE car_id = ?",
-        car_id
-    )
+    match query_as::<_, Maintenance>(&format!(
+        "SELECT {} FROM Maintenances WHERE car_id = ? AND deleted_at IS NULL",
+        MAINTENANCE_COLUMNS
+    ))
+    .bind(car_id)
     .fetch_all(&db_pool)
     .await
     {
-        Ok(maintenances) => (StatusCode::OK, Json(maintenances)).into_response(),
+        Ok(maintenances) => {
+            let maintenances: Vec<Maintenance> = maintenances
+                .into_iter()
+                .map(|maintenance| maintenance.localized(lang))
+                .collect();
+            (StatusCode::OK, Json(maintenances)).into_response()
+        }
         Err(e) => {
             eprintln!("Failed to fetch maintenances: {:?}", e);
             StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -350,11 +693,11 @@ pub async fn get_car_fuel_efficiency(
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query_as!(
-        FuelEfficiency,
-        "SELECT * FROM FuelEfficiencies WHERE car_id = ?",
-        car_id
-    )
+    match query_as::<_, FuelEfficiency>(&format!(
+        "SELECT {} FROM FuelEfficiencies WHERE car_id = ? AND deleted_at IS NULL",
+        FUEL_EFFICIENCY_COLUMNS
+    ))
+    .bind(car_id)
     .fetch_all(&db_pool)
     .await
     {
@@ -365,3 +708,133 @@ pub async fn get_car_fuel_efficiency(
         }
     }
 }
+
+/// 車両の支出を支出日のレートでユーザーの通貨に換算して集計する
+pub async fn get_car_expenses(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+    Query(params): Query<ExpenseQuery>,
+) -> impl IntoResponse {
+    if let Err(e) = validation::validate(&params) {
+        return e.into_response();
+    }
+
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Viewer).await
+    {
+        return e.into_response();
+    }
+
+    let currency = match params.currency {
+        Some(currency) => currency,
+        None => {
+            user_preferences::preferences_or_default(&db_pool, &firebase_user_id)
+                .await
+                .currency
+        }
+    };
+
+    let report = match expenses::load_car_expenses(
+        &db_pool,
+        car_id,
+        params.from.as_deref(),
+        params.to.as_deref(),
+    )
+    .await
+    {
+        Ok(items) => expenses::build_report(&db_pool, car_id, items, &currency).await,
+        Err(e) => Err(e),
+    };
+
+    match report {
+        Ok(report) => (StatusCode::OK, Json(report)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to build expense report: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+// Debian の fonts-ipafont-gothic パッケージのパス
+const DEFAULT_REPORT_FONT_PATH: &str = "/usr/share/fonts/opentype/ipafont-gothic/ipag.ttf";
+
+/// 車両の整備記録簿を PDF で出力する
+pub async fn get_car_report_pdf(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+    lang: Lang,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Viewer).await
+    {
+        return e.into_response();
+    }
+
+    let mut conn = match db_pool.acquire().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            eprintln!("Failed to acquire connection: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let history = match history::load_car_history(&mut conn, car_id).await {
+        Ok(Some(history)) => history,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to load car history: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    drop(conn);
+    let preferences = user_preferences::preferences_or_default(&db_pool, &firebase_user_id).await;
+
+    let font_path =
+        env::var("REPORT_FONT_PATH").unwrap_or_else(|_| DEFAULT_REPORT_FONT_PATH.to_string());
+    let font_data = match tokio::fs::read(&font_path).await {
+        Ok(font_data) => font_data,
+        Err(e) => {
+            eprintln!("Failed to read report font {}: {:?}", font_path, e);
+            return AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "report/font-unavailable".to_string(),
+                "The font for the PDF report is not available.".to_string(),
+            )
+            .into_response();
+        }
+    };
+
+    // PDF の生成は CPU を使うのでブロッキングスレッドで行う
+    let pdf = tokio::task::spawn_blocking(move || {
+        report_pdf::render_history_pdf(&history, lang, &preferences, &font_data)
+    })
+    .await;
+
+    match pdf {
+        Ok(Ok(pdf)) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "application/pdf".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"car-{}-report.pdf\"", car_id),
+                ),
+            ],
+            pdf,
+        )
+            .into_response(),
+        Ok(Err(e)) => {
+            eprintln!("Failed to render car report: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to render car report: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
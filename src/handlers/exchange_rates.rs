@@ -0,0 +1,303 @@
+use crate::access;
+use crate::audit::AuditEntry;
+use crate::exchange_rates::BASE_CURRENCY;
+use crate::handlers::fuel_efficiency_csv::UTF8_BOM;
+use crate::models::exchange_rate::{
+    ExchangeRate, ExchangeRateImportResult, ExchangeRateQuery, EXCHANGE_RATE_COLUMNS,
+};
+use crate::models::fuel_efficiency::CsvRowError;
+use crate::state::AppState;
+use crate::validation;
+use axum::{
+    extract::{multipart::Multipart, Extension, Json, Query},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use bigdecimal::{BigDecimal, Zero};
+use sqlx::{query, query_as, query_scalar, MySql, Transaction};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+const CSV_HEADERS: [&str; 3] = ["date", "currency", "rate"];
+
+/// 為替レートの一覧。通貨と期間で絞り込める
+pub async fn get_exchange_rates(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Query(params): Query<ExchangeRateQuery>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let mut sql = format!(
+        "SELECT {} FROM ExchangeRates WHERE 1 = 1",
+        EXCHANGE_RATE_COLUMNS
+    );
+    if params.currency.is_some() {
+        sql.push_str(" AND currency = ?");
+    }
+    if params.from.is_some() {
+        sql.push_str(" AND rate_date >= ?");
+    }
+    if params.to.is_some() {
+        sql.push_str(" AND rate_date <= ?");
+    }
+    sql.push_str(" ORDER BY currency, rate_date");
+
+    let mut select = query_as::<_, ExchangeRate>(&sql);
+    if let Some(currency) = &params.currency {
+        select = select.bind(currency.to_ascii_uppercase());
+    }
+    if let Some(from) = &params.from {
+        select = select.bind(from);
+    }
+    if let Some(to) = &params.to {
+        select = select.bind(to);
+    }
+
+    match select.fetch_all(&db_pool).await {
+        Ok(rates) => (StatusCode::OK, Json(rates)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch exchange rates: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+// 1行分を検証し、正しければレートを返す
+fn parse_row(
+    row: u64,
+    record: &csv::StringRecord,
+    errors: &mut Vec<CsvRowError>,
+) -> Option<ExchangeRate> {
+    let value = |index: usize| record.get(index).unwrap_or_default().trim();
+    let mut error = |column: &str, message: &str| {
+        errors.push(CsvRowError {
+            row,
+            column: Some(column.to_string()),
+            message: message.to_string(),
+        })
+    };
+
+    let rate_date = Some(value(0)).filter(|date| validation::parse_date(date).is_some());
+    if rate_date.is_none() {
+        error("date", "Use a valid date in YYYY-MM-DD format.");
+    }
+    let currency = Some(value(1).to_ascii_uppercase()).filter(|currency| {
+        validation::validate_currency(currency).is_ok() && currency != BASE_CURRENCY
+    });
+    if currency.is_none() {
+        error(
+            "currency",
+            "Currency must be a three-letter ISO 4217 code other than JPY.",
+        );
+    }
+    let rate = value(2)
+        .parse::<BigDecimal>()
+        .ok()
+        .filter(|rate| *rate > BigDecimal::zero());
+    if rate.is_none() {
+        error("rate", "Rate must be a number greater than 0.");
+    }
+
+    Some(ExchangeRate {
+        currency: currency?,
+        rate_date: rate_date?.to_string(),
+        rate: rate?,
+    })
+}
+
+/// 為替レートを CSV（date,currency,rate）で登録する。管理者のみ。
+/// 同じ通貨・日付のレートは上書きし、1行でもエラーがあれば何も登録しない
+pub async fn upload_exchange_rates(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    mut payload: Multipart,
+) -> impl IntoResponse {
+    if let Err(e) = access::require_admin(&firebase_user_id) {
+        return e.into_response();
+    }
+
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let mut content = None;
+    loop {
+        let field = match payload.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("Error reading field: {:?}", e);
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Error reading field: {}", e),
+                )
+                    .into_response();
+            }
+        };
+
+        if field.name() == Some("file") {
+            match field.bytes().await {
+                Ok(bytes) => content = Some(bytes),
+                Err(e) => {
+                    eprintln!("Error reading file content: {:?}", e);
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        format!("Error reading file content: {}", e),
+                    )
+                        .into_response();
+                }
+            }
+        }
+    }
+
+    let Some(content) = content else {
+        return (StatusCode::BAD_REQUEST, "Error: No file uploaded").into_response();
+    };
+    let content = content.strip_prefix(UTF8_BOM).unwrap_or(&content);
+
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(content);
+    match reader.headers() {
+        Ok(headers) if headers.iter().map(str::trim).eq(CSV_HEADERS) => {}
+        Ok(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("The CSV header must be {}.", CSV_HEADERS.join(",")),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, format!("Invalid CSV: {}", e)).into_response();
+        }
+    }
+
+    let mut errors = Vec::new();
+    let mut rates = Vec::new();
+    let mut total_rows = 0;
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                total_rows += 1;
+                errors.push(CsvRowError {
+                    row: e.position().map_or(0, |position| position.line()),
+                    column: None,
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+        // 空行は読み飛ばす
+        if record.iter().all(|value| value.trim().is_empty()) {
+            continue;
+        }
+        total_rows += 1;
+        let row = record.position().map_or(0, |position| position.line());
+        if let Some(rate) = parse_row(row, &record, &mut errors) {
+            rates.push(rate);
+        }
+    }
+
+    if !errors.is_empty() {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ExchangeRateImportResult {
+                total_rows,
+                imported_rows: 0,
+                errors,
+            }),
+        )
+            .into_response();
+    }
+
+    let mut tx: Transaction<'_, MySql> = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            eprintln!("Failed to start transaction: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    // 上書きする前のレート。アップロード全体を「通貨 日付」ごとの差分として監査ログに残す
+    let mut before: BTreeMap<String, Option<String>> = BTreeMap::new();
+    let mut after: BTreeMap<String, Option<String>> = BTreeMap::new();
+    for rate in &rates {
+        let key = format!("{} {}", rate.currency, rate.rate_date);
+        if !before.contains_key(&key) {
+            let stored: Option<BigDecimal> = match query_scalar(
+                "SELECT rate FROM ExchangeRates WHERE currency = ? AND rate_date = ? FOR UPDATE",
+            )
+            .bind(&rate.currency)
+            .bind(&rate.rate_date)
+            .fetch_optional(&mut *tx)
+            .await
+            {
+                Ok(stored) => stored,
+                Err(e) => {
+                    tx.rollback().await.unwrap();
+                    eprintln!("Failed to fetch exchange rate: {:?}", e);
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+            };
+            before.insert(key.clone(), stored.map(|r| r.normalized().to_string()));
+        }
+        after.insert(key, Some(rate.rate.normalized().to_string()));
+    }
+
+    for rate in &rates {
+        if let Err(e) = query(
+            "INSERT INTO ExchangeRates (currency, rate_date, rate) VALUES (?, ?, ?) ON DUPLICATE KEY UPDATE rate = VALUES(rate)",
+        )
+        .bind(&rate.currency)
+        .bind(&rate.rate_date)
+        .bind(&rate.rate)
+        .execute(&mut *tx)
+        .await
+        {
+            tx.rollback().await.unwrap();
+            eprintln!("Failed to import exchange rate: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    let first_date = rates
+        .iter()
+        .map(|r| r.rate_date.as_str())
+        .min()
+        .unwrap_or_default();
+    let last_date = rates
+        .iter()
+        .map(|r| r.rate_date.as_str())
+        .max()
+        .unwrap_or_default();
+    if let Err(e) = AuditEntry::update(
+        &firebase_user_id,
+        None,
+        "exchange_rate",
+        format!("{}..{}", first_date, last_date),
+        &before,
+        &after,
+    )
+    .save(&mut *tx)
+    .await
+    {
+        tx.rollback().await.unwrap();
+        eprintln!("Failed to write audit log for exchange_rate: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    if let Err(e) = tx.commit().await {
+        eprintln!("Failed to commit transaction: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    (
+        StatusCode::CREATED,
+        Json(ExchangeRateImportResult {
+            total_rows,
+            imported_rows: rates.len(),
+            errors,
+        }),
+    )
+        .into_response()
+}
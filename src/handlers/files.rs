@@ -0,0 +1,54 @@
+use crate::models::attachment::ALLOWED_CONTENT_TYPES;
+use crate::state::AppState;
+use crate::storage::is_private;
+use axum::{
+    extract::{Extension, Path, Query},
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+#[derive(Debug, Deserialize)]
+pub struct SignedFileQuery {
+    pub expires: Option<i64>,
+    pub signature: Option<String>,
+}
+
+/// ローカルストレージに保存したファイルを配信する。非公開のファイルは署名付きURLが必要
+pub async fn get_file(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(key): Path<String>,
+    Query(params): Query<SignedFileQuery>,
+) -> impl IntoResponse {
+    let storage = state.lock().await.storage.clone();
+
+    if is_private(&key) {
+        let (Some(expires), Some(signature)) = (params.expires, params.signature) else {
+            return StatusCode::FORBIDDEN.into_response();
+        };
+        if !storage.verify_signature(&key, expires, &signature) {
+            return StatusCode::FORBIDDEN.into_response();
+        }
+    }
+
+    let extension = key.rsplit('.').next().unwrap_or_default();
+    let content_type = ALLOWED_CONTENT_TYPES
+        .iter()
+        .find(|(_, ext)| *ext == extension)
+        .map_or("application/octet-stream", |(ct, _)| *ct);
+
+    match storage.get(&key).await {
+        Some(content) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, content_type),
+                (header::CACHE_CONTROL, "private, no-store"),
+            ],
+            content,
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
@@ -1,42 +1,91 @@
-use crate::models::fuel_efficiency::FuelEfficiency;
+use crate::access;
+use crate::audit::AuditEntry;
+use crate::etag;
+use crate::exchange_rates::{Money, RateTable};
+use crate::expenses;
+use crate::handlers::user_preferences;
+use crate::models::fuel_efficiency::{
+    FuelEfficiency, FUEL_EFFICIENCY_COLUMNS, FUEL_EFFICIENCY_PATCH,
+};
+use crate::models::user_car::CarRole;
+use crate::patch;
 use crate::state::AppState;
+use crate::validation::{self, UpdateJson, ValidatedJson};
 use axum::{
     extract::{Extension, Json, Path},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
 };
-use serde_json::json;
-use sqlx::{query, query_as};
+use bigdecimal::{BigDecimal, Zero};
+use serde_json::{json, Value};
+use sqlx::{query, query_as, MySqlPool};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+async fn fetch_fuel_efficiency(
+    db_pool: &MySqlPool,
+    id: i32,
+) -> Result<FuelEfficiency, sqlx::Error> {
+    query_as::<_, FuelEfficiency>(&format!(
+        "SELECT {} FROM FuelEfficiencies WHERE fe_id = ?",
+        FUEL_EFFICIENCY_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(db_pool)
+    .await
+}
+
 pub async fn create_fuel_efficiency(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
-    Json(new_fuel_efficiency): Json<FuelEfficiency>,
+    Extension(firebase_user_id): Extension<String>,
+    ValidatedJson(new_fuel_efficiency): ValidatedJson<FuelEfficiency>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query!(
-        "INSERT INTO FuelEfficiencies (car_id, fe_date, fe_amount, fe_unitprice, fe_mileage) VALUES (?, ?, ?, ?, ?)",
+    if let Err(e) = access::require_car_role(
+        &db_pool,
+        &firebase_user_id,
         new_fuel_efficiency.car_id,
-        new_fuel_efficiency.fe_date,
-        new_fuel_efficiency.fe_amount,
-        new_fuel_efficiency.fe_unitprice,
-        new_fuel_efficiency.fe_mileage
+        CarRole::Editor,
     )
+    .await
+    {
+        return e.into_response();
+    }
+
+    match query(
+        "INSERT INTO FuelEfficiencies (car_id, fe_date, fe_amount, fe_unitprice, fe_currency, fe_mileage) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(new_fuel_efficiency.car_id)
+    .bind(&new_fuel_efficiency.fe_date)
+    .bind(new_fuel_efficiency.fe_amount)
+    .bind(&new_fuel_efficiency.fe_unitprice)
+    .bind(&new_fuel_efficiency.fe_currency)
+    .bind(new_fuel_efficiency.fe_mileage)
     .execute(&db_pool)
     .await
     {
         Ok(result) => {
-            match query_as!(
-                FuelEfficiency,
-                "SELECT fe_id, car_id, fe_date, fe_amount, fe_unitprice, fe_mileage, created_at, updated_at FROM FuelEfficiencies WHERE fe_id = ?",
-                result.last_insert_id()
-            )
+            match query_as::<_, FuelEfficiency>(&format!(
+                "SELECT {} FROM FuelEfficiencies WHERE fe_id = ? AND deleted_at IS NULL",
+                FUEL_EFFICIENCY_COLUMNS
+            ))
+            .bind(result.last_insert_id())
             .fetch_one(&db_pool)
             .await
             {
-                Ok(fuel_efficiency) => (StatusCode::CREATED, Json(fuel_efficiency)).into_response(),
+                Ok(fuel_efficiency) => {
+                    AuditEntry::create(
+                        &firebase_user_id,
+                        Some(fuel_efficiency.car_id),
+                        "fuel_efficiency",
+                        result.last_insert_id(),
+                        &fuel_efficiency,
+                    )
+                    .log(&db_pool)
+                    .await;
+                    etag::with_etag(StatusCode::CREATED, fuel_efficiency.updated_at, fuel_efficiency)
+                }
                 Err(e) => {
                     eprintln!("Failed to fetch fuel efficiency after creation: {:?}", e);
                     StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -55,9 +104,12 @@ pub async fn get_fuel_efficiencies(
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query_as!(FuelEfficiency, "SELECT * FROM FuelEfficiencies")
-        .fetch_all(&db_pool)
-        .await
+    match query_as::<_, FuelEfficiency>(&format!(
+        "SELECT {} FROM FuelEfficiencies WHERE deleted_at IS NULL",
+        FUEL_EFFICIENCY_COLUMNS
+    ))
+    .fetch_all(&db_pool)
+    .await
     {
         Ok(fuel_efficiencies) => (StatusCode::OK, Json(fuel_efficiencies)).into_response(),
         Err(e) => {
@@ -70,18 +122,21 @@ pub async fn get_fuel_efficiencies(
 pub async fn get_fuel_efficiency(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
     Path(id): Path<i32>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query_as!(
-        FuelEfficiency,
-        "SELECT * FROM FuelEfficiencies WHERE fe_id = ?",
-        id
-    )
+    match query_as::<_, FuelEfficiency>(&format!(
+        "SELECT {} FROM FuelEfficiencies WHERE fe_id = ? AND deleted_at IS NULL",
+        FUEL_EFFICIENCY_COLUMNS
+    ))
+    .bind(id)
     .fetch_one(&db_pool)
     .await
     {
-        Ok(fuel_efficiency) => (StatusCode::OK, Json(fuel_efficiency)).into_response(),
+        Ok(fuel_efficiency) => {
+            etag::conditional_get(&headers, fuel_efficiency.updated_at, fuel_efficiency)
+        }
         Err(e) => {
             eprintln!("Failed to fetch fuel efficiency: {:?}", e);
             StatusCode::NOT_FOUND.into_response()
@@ -91,33 +146,90 @@ pub async fn get_fuel_efficiency(
 
 pub async fn update_fuel_efficiency(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
     Path(id): Path<i32>,
-    Json(updated_fuel_efficiency): Json<FuelEfficiency>,
+    headers: HeaderMap,
+    UpdateJson(updated_fuel_efficiency): UpdateJson<FuelEfficiency>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query!(
-        "UPDATE FuelEfficiencies SET car_id = ?, fe_date = ?, fe_amount = ?, fe_unitprice = ?, fe_mileage = ? WHERE fe_id = ?",
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "FuelEfficiencies",
+        "fe_id",
+        id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    if let Err(e) = access::require_car_role(
+        &db_pool,
+        &firebase_user_id,
         updated_fuel_efficiency.car_id,
-        updated_fuel_efficiency.fe_date,
-        updated_fuel_efficiency.fe_amount,
-        updated_fuel_efficiency.fe_unitprice,
-        updated_fuel_efficiency.fe_mileage,
-        id
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_fuel_efficiency(&db_pool, id).await {
+        Ok(fuel_efficiency) => fuel_efficiency,
+        Err(e) => {
+            eprintln!("Failed to fetch fuel efficiency: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    if let Err(e) = validation::validate_changes(&before, &updated_fuel_efficiency) {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE FuelEfficiencies SET car_id = ?, fe_date = ?, fe_amount = ?, fe_unitprice = ?, fe_currency = ?, fe_mileage = ? WHERE fe_id = ? AND updated_at <=> ?",
     )
+    .bind(updated_fuel_efficiency.car_id)
+    .bind(&updated_fuel_efficiency.fe_date)
+    .bind(updated_fuel_efficiency.fe_amount)
+    .bind(&updated_fuel_efficiency.fe_unitprice)
+    .bind(&updated_fuel_efficiency.fe_currency)
+    .bind(updated_fuel_efficiency.fe_mileage)
+    .bind(id)
+    .bind(before.updated_at)
     .execute(&db_pool)
     .await
     {
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
         Ok(_) => {
-            match query_as!(
-                FuelEfficiency,
-                "SELECT fe_id, car_id, fe_date, fe_amount, fe_unitprice, fe_mileage, created_at, updated_at FROM FuelEfficiencies WHERE fe_id = ?",
-                id
-            )
+            match query_as::<_, FuelEfficiency>(&format!(
+                "SELECT {} FROM FuelEfficiencies WHERE fe_id = ? AND deleted_at IS NULL",
+                FUEL_EFFICIENCY_COLUMNS
+            ))
+            .bind(id)
             .fetch_one(&db_pool)
             .await
             {
-                Ok(fuel_efficiency) => (StatusCode::OK, Json(fuel_efficiency)).into_response(),
+                Ok(fuel_efficiency) => {
+                    AuditEntry::update(
+                        &firebase_user_id,
+                        Some(fuel_efficiency.car_id),
+                        "fuel_efficiency",
+                        id,
+                        &before,
+                        &fuel_efficiency,
+                    )
+                    .log(&db_pool)
+                    .await;
+                    etag::with_etag(StatusCode::OK, fuel_efficiency.updated_at, fuel_efficiency)
+                }
                 Err(e) => {
                     eprintln!("Failed to fetch fuel efficiency after update: {:?}", e);
                     StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -131,17 +243,152 @@ pub async fn update_fuel_efficiency(
     }
 }
 
-pub async fn delete_fuel_efficiency(
+/// JSON Merge Patch で指定された項目だけを更新する
+pub async fn patch_fuel_efficiency(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
     Path(id): Path<i32>,
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query!("DELETE FROM FuelEfficiencies WHERE fe_id = ?", id)
-        .execute(&db_pool)
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "FuelEfficiencies",
+        "fe_id",
+        id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_fuel_efficiency(&db_pool, id).await {
+        Ok(fuel_efficiency) => fuel_efficiency,
+        Err(e) => {
+            eprintln!("Failed to fetch fuel efficiency: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    let fuel_efficiency = match patch::apply(&FUEL_EFFICIENCY_PATCH, &before, &patch) {
+        Ok(fuel_efficiency) => fuel_efficiency,
+        Err(e) => return e.into_response(),
+    };
+
+    // 別の車両に移す場合は移動先の車両にも編集者以上のロールが必要
+    if fuel_efficiency.car_id != before.car_id {
+        if let Err(e) = access::require_car_role(
+            &db_pool,
+            &firebase_user_id,
+            fuel_efficiency.car_id,
+            CarRole::Editor,
+        )
         .await
+        {
+            return e.into_response();
+        }
+    }
+
+    match patch::update(
+        &db_pool,
+        &FUEL_EFFICIENCY_PATCH,
+        id,
+        &before,
+        &fuel_efficiency,
+        before.updated_at,
+    )
+    .await
+    {
+        Ok(false) => etag::precondition_failed().into_response(),
+        Ok(true) => match fetch_fuel_efficiency(&db_pool, id).await {
+            Ok(fuel_efficiency) => {
+                AuditEntry::update(
+                    &firebase_user_id,
+                    Some(fuel_efficiency.car_id),
+                    "fuel_efficiency",
+                    id,
+                    &before,
+                    &fuel_efficiency,
+                )
+                .log(&db_pool)
+                .await;
+                etag::with_etag(StatusCode::OK, fuel_efficiency.updated_at, fuel_efficiency)
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch fuel efficiency after update: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to update fuel efficiency: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 給油記録をゴミ箱に移す
+pub async fn delete_fuel_efficiency(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "FuelEfficiencies",
+        "fe_id",
+        id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_fuel_efficiency(&db_pool, id).await {
+        Ok(fuel_efficiency) => fuel_efficiency,
+        Err(e) => {
+            eprintln!("Failed to fetch fuel efficiency: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE FuelEfficiencies SET deleted_at = CURRENT_TIMESTAMP WHERE fe_id = ? AND deleted_at IS NULL AND updated_at <=> ?",
+    )
+    .bind(id)
+    .bind(before.updated_at)
+    .execute(&db_pool)
+    .await
     {
-        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
+        Ok(_) => {
+            AuditEntry::delete(
+                &firebase_user_id,
+                Some(before.car_id),
+                "fuel_efficiency",
+                id,
+                &before,
+            )
+            .log(&db_pool)
+            .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
         Err(e) => {
             eprintln!("Failed to delete fuel efficiency: {:?}", e);
             StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -149,21 +396,21 @@ pub async fn delete_fuel_efficiency(
     }
 }
 
+/// 燃費を計算する。距離・給油量・燃費はユーザーの設定の単位で返す
 pub async fn calculate_fuel_efficiencies(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
     Path(car_id): Path<i32>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
+    let preferences = user_preferences::preferences_or_default(&db_pool, &firebase_user_id).await;
 
     // 給油記録を取得
-    let fuel_efficiencies: Vec<FuelEfficiency> = match query_as!(
-        FuelEfficiency,
-        "SELECT fe_id, car_id, fe_date, fe_amount, fe_unitprice, fe_mileage, created_at, updated_at 
-         FROM FuelEfficiencies 
-         WHERE car_id = ? 
-         ORDER BY fe_date",
-        car_id
-    )
+    let fuel_efficiencies: Vec<FuelEfficiency> = match query_as::<_, FuelEfficiency>(&format!(
+        "SELECT {} FROM FuelEfficiencies WHERE car_id = ? AND deleted_at IS NULL ORDER BY fe_date",
+        FUEL_EFFICIENCY_COLUMNS
+    ))
+    .bind(car_id)
     .fetch_all(&db_pool)
     .await
     {
@@ -182,13 +429,28 @@ pub async fn calculate_fuel_efficiencies(
             .into_response();
     }
 
+    // 給油の費用は給油日のレートでユーザーの通貨に換算する
+    let mut currencies: Vec<&str> = fuel_efficiencies
+        .iter()
+        .map(|fe| fe.fe_currency.as_str())
+        .chain([preferences.currency.as_str()])
+        .collect();
+    currencies.sort_unstable();
+    currencies.dedup();
+    let rates = match RateTable::load(&db_pool, &currencies).await {
+        Ok(rates) => rates,
+        Err(e) => {
+            eprintln!("Failed to fetch exchange rates: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
     let mut total_fuel = 0.0;
     let mut total_distance = 0.0;
+    let mut total_cost = Some(BigDecimal::zero());
     let mut fuel_efficiency_records = Vec::new();
 
-    for i in 1..fuel_efficiencies.len() {
-        let current = &fuel_efficiencies[i];
-
+    for current in fuel_efficiencies.iter().skip(1) {
         let distance = current.fe_mileage;
         let fuel = current.fe_amount;
 
@@ -196,10 +458,25 @@ pub async fn calculate_fuel_efficiencies(
             return (StatusCode::BAD_REQUEST, "Invalid mileage or fuel data").into_response();
         }
 
-        let efficiency = distance as f32 / fuel;
+        let efficiency = distance / fuel;
+        let expense = expenses::fuel_expense(current);
+        let cost = rates.convert(
+            &expense.amount.amount,
+            &expense.amount.currency,
+            &preferences.currency,
+            &expense.date,
+        );
+        total_cost = total_cost
+            .zip(cost.as_ref())
+            .map(|(total, cost)| total + &cost.amount);
         fuel_efficiency_records.push(json!({
             "fe_id": current.fe_id,
-            "fuel_efficiency": efficiency
+            "distance": preferences.distance_unit.from_km(distance.into()),
+            "fuel": preferences.volume_unit.from_litres(fuel.into()),
+            "fuel_efficiency": preferences
+                .fuel_economy_unit
+                .from_km_per_litre(efficiency.into()),
+            "cost": cost,
         }));
 
         total_fuel += fuel;
@@ -214,10 +491,24 @@ pub async fn calculate_fuel_efficiencies(
             .into_response();
     }
 
-    let total_fuel_efficiency = total_distance as f32 / total_fuel;
+    let total_fuel_efficiency = total_distance / total_fuel;
     let response = json!({
         "car_id": car_id,
-        "total_fuel_efficiency": total_fuel_efficiency,
+        "units": {
+            "distance": preferences.distance_unit,
+            "volume": preferences.volume_unit,
+            "fuel_economy": preferences.fuel_economy_unit,
+        },
+        "total_distance": preferences.distance_unit.from_km(total_distance.into()),
+        "total_fuel": preferences.volume_unit.from_litres(total_fuel.into()),
+        "total_fuel_efficiency": preferences
+            .fuel_economy_unit
+            .from_km_per_litre(total_fuel_efficiency.into()),
+        // レートのない給油がある場合は null
+        "total_cost": total_cost.map(|amount| Money {
+            amount,
+            currency: preferences.currency.clone(),
+        }),
         "fuel_efficiencies": fuel_efficiency_records
     });
 
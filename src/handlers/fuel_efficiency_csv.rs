@@ -0,0 +1,430 @@
+use crate::access;
+use crate::audit::AuditEntry;
+use crate::exchange_rates::BASE_CURRENCY;
+use crate::models::fuel_efficiency::{
+    CsvColumn, CsvRowError, FuelEfficiency, FuelEfficiencyCsvMapping, FuelEfficiencyImportResult,
+    ImportFuelEfficienciesQuery, FUEL_EFFICIENCY_COLUMNS,
+};
+use crate::models::user_car::CarRole;
+use crate::state::AppState;
+use crate::validation;
+use axum::{
+    extract::{multipart::Multipart, Extension, Json, Path, Query},
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use bigdecimal::BigDecimal;
+use sqlx::{query, query_as, MySql, Transaction};
+use std::sync::Arc;
+use time::{Date, Month};
+use tokio::sync::Mutex;
+
+const CSV_HEADERS: [&str; 5] = [
+    "fe_date",
+    "fe_amount",
+    "fe_unitprice",
+    "fe_currency",
+    "fe_mileage",
+];
+
+// Excel で開いたときに文字化けしないよう UTF-8 の BOM を付ける
+pub(crate) const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+fn write_csv(fuel_efficiencies: &[FuelEfficiency]) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(UTF8_BOM.to_vec());
+    writer.write_record(CSV_HEADERS)?;
+    for fe in fuel_efficiencies {
+        writer.write_record([
+            fe.fe_date.clone(),
+            fe.fe_amount.to_string(),
+            fe.fe_unitprice.to_string(),
+            fe.fe_currency.clone(),
+            fe.fe_mileage.to_string(),
+        ])?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
+
+/// 車両の給油記録を CSV で出力する
+pub async fn export_fuel_efficiencies(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Viewer).await
+    {
+        return e.into_response();
+    }
+
+    let fuel_efficiencies = match query_as::<_, FuelEfficiency>(&format!(
+        "SELECT {} FROM FuelEfficiencies WHERE car_id = ? AND deleted_at IS NULL ORDER BY fe_date, fe_id",
+        FUEL_EFFICIENCY_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(fuel_efficiencies) => fuel_efficiencies,
+        Err(e) => {
+            eprintln!("Failed to fetch fuel efficiencies: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let content = match write_csv(&fuel_efficiencies) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("Failed to write fuel efficiency CSV: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"car-{}-fuel-efficiencies.csv\"",
+                    car_id
+                ),
+            ),
+        ],
+        content,
+    )
+        .into_response()
+}
+
+fn column_index(headers: &csv::StringRecord, column: &CsvColumn) -> Option<usize> {
+    match column {
+        CsvColumn::Index(index) => (*index < headers.len()).then_some(*index),
+        CsvColumn::Name(name) => headers.iter().position(|header| header.trim() == name),
+    }
+}
+
+// `2024-01-05`、`2024/1/5`、`2024.01.05` を `2024-01-05` に揃える
+fn parse_date(value: &str) -> Option<String> {
+    let parts: Vec<&str> = value.trim().split(['-', '/', '.']).collect();
+    let [year, month, day] = parts.as_slice() else {
+        return None;
+    };
+    let month = Month::try_from(month.parse::<u8>().ok()?).ok()?;
+    let date = Date::from_calendar_date(year.parse().ok()?, month, day.parse().ok()?).ok()?;
+    Some(format!(
+        "{:04}-{:02}-{:02}",
+        date.year(),
+        date.month() as u8,
+        date.day()
+    ))
+}
+
+// 桁区切りのカンマを取り除いてから数値として読む
+fn parse_number(value: &str) -> Option<f64> {
+    let number: f64 = value.trim().replace(',', "").parse().ok()?;
+    (number.is_finite() && number >= 0.0).then_some(number)
+}
+
+// 金額は丸めずに小数のまま読む
+fn parse_price(value: &str) -> Option<BigDecimal> {
+    let price: BigDecimal = value.trim().replace(',', "").parse().ok()?;
+    validation::validate_amount(&price).ok()?;
+    Some(price)
+}
+
+/// 1行分を検証し、すべての項目が正しければ給油記録を返す
+fn parse_row(
+    car_id: i32,
+    row: u64,
+    record: &csv::StringRecord,
+    columns: &[(&'static str, usize); 4],
+    currency_column: Option<(&'static str, usize)>,
+    errors: &mut Vec<CsvRowError>,
+) -> Option<FuelEfficiency> {
+    let mut error = |column: &str, message: &str| {
+        errors.push(CsvRowError {
+            row,
+            column: Some(column.to_string()),
+            message: message.to_string(),
+        });
+    };
+    let value = |index: usize| record.get(index).unwrap_or_default();
+
+    let [(date_name, date_index), (amount_name, amount_index), (unitprice_name, unitprice_index), (mileage_name, mileage_index)] =
+        *columns;
+
+    let fe_date = parse_date(value(date_index));
+    if fe_date.is_none() {
+        error(date_name, "Invalid date. Use YYYY-MM-DD.");
+    }
+    let fe_amount = parse_number(value(amount_index)).filter(|amount| *amount > 0.0);
+    if fe_amount.is_none() {
+        error(amount_name, "Amount must be a positive number.");
+    }
+    let fe_unitprice = parse_price(value(unitprice_index));
+    if fe_unitprice.is_none() {
+        error(unitprice_name, "Unit price must be a non-negative number.");
+    }
+    // 通貨の列がない行や空欄は円とする
+    let fe_currency = match currency_column {
+        Some((_, index)) if !value(index).trim().is_empty() => {
+            Some(value(index).trim().to_ascii_uppercase())
+        }
+        _ => Some(BASE_CURRENCY.to_string()),
+    }
+    .filter(|currency| validation::validate_currency(currency).is_ok());
+    if fe_currency.is_none() {
+        error(
+            currency_column.map_or("fe_currency", |(name, _)| name),
+            "Currency must be a three-letter ISO 4217 code.",
+        );
+    }
+    let fe_mileage = parse_number(value(mileage_index));
+    if fe_mileage.is_none() {
+        error(mileage_name, "Mileage must be a non-negative number.");
+    }
+
+    Some(FuelEfficiency {
+        fe_id: None,
+        car_id,
+        fe_date: fe_date?,
+        fe_amount: fe_amount? as f32,
+        fe_unitprice: fe_unitprice?,
+        fe_currency: fe_currency?,
+        fe_mileage: fe_mileage? as f32,
+        created_at: None,
+        updated_at: None,
+    })
+}
+
+/// CSV から給油記録を取り込む。1行でもエラーがあれば何も登録しない
+pub async fn import_fuel_efficiencies(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+    Query(params): Query<ImportFuelEfficienciesQuery>,
+    mut payload: Multipart,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Editor).await
+    {
+        return e.into_response();
+    }
+
+    let mut content = None;
+    let mut mapping = FuelEfficiencyCsvMapping::default();
+    loop {
+        let field = match payload.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("Error reading field: {:?}", e);
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Error reading field: {}", e),
+                )
+                    .into_response();
+            }
+        };
+
+        match field.name() {
+            Some("file") => match field.bytes().await {
+                Ok(bytes) => content = Some(bytes),
+                Err(e) => {
+                    eprintln!("Error reading file content: {:?}", e);
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        format!("Error reading file content: {}", e),
+                    )
+                        .into_response();
+                }
+            },
+            Some("mapping") => {
+                let parsed = match field.bytes().await {
+                    Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                match parsed {
+                    Ok(parsed) => mapping = parsed,
+                    Err(e) => {
+                        return (StatusCode::BAD_REQUEST, format!("Invalid mapping: {}", e))
+                            .into_response();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let Some(content) = content else {
+        return (StatusCode::BAD_REQUEST, "Error: No file uploaded").into_response();
+    };
+    let content = content.strip_prefix(UTF8_BOM).unwrap_or(&content);
+
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(content);
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, format!("Invalid CSV: {}", e)).into_response();
+        }
+    };
+
+    let mut errors = Vec::new();
+    let mut columns = Vec::new();
+    for (name, column) in [
+        ("fe_date", &mapping.fe_date),
+        ("fe_amount", &mapping.fe_amount),
+        ("fe_unitprice", &mapping.fe_unitprice),
+        ("fe_mileage", &mapping.fe_mileage),
+    ] {
+        match column_index(&headers, column) {
+            Some(index) => columns.push((name, index)),
+            None => errors.push(CsvRowError {
+                row: 1,
+                column: Some(name.to_string()),
+                message: format!("Column {:?} was not found in the CSV header.", column),
+            }),
+        }
+    }
+    // 通貨の列は任意。指定した列が見つからない場合だけエラーにする
+    let currency_column = match &mapping.fe_currency {
+        Some(column) => match column_index(&headers, column) {
+            Some(index) => Some(("fe_currency", index)),
+            None => {
+                errors.push(CsvRowError {
+                    row: 1,
+                    column: Some("fe_currency".to_string()),
+                    message: format!("Column {:?} was not found in the CSV header.", column),
+                });
+                None
+            }
+        },
+        None => column_index(&headers, &CsvColumn::Name("fe_currency".to_string()))
+            .map(|index| ("fe_currency", index)),
+    };
+    let Ok(columns) = <[(&str, usize); 4]>::try_from(columns) else {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(FuelEfficiencyImportResult {
+                dry_run: params.dry_run,
+                total_rows: 0,
+                imported_rows: 0,
+                errors,
+                rows: Vec::new(),
+            }),
+        )
+            .into_response();
+    };
+
+    let mut rows = Vec::new();
+    let mut total_rows = 0;
+    for record in reader.records() {
+        total_rows += 1;
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                errors.push(CsvRowError {
+                    row: e.position().map_or(0, |position| position.line()),
+                    column: None,
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+        // 空行は読み飛ばす
+        if record.iter().all(|value| value.trim().is_empty()) {
+            total_rows -= 1;
+            continue;
+        }
+        let row = record.position().map_or(0, |position| position.line());
+        if let Some(fe) = parse_row(car_id, row, &record, &columns, currency_column, &mut errors) {
+            rows.push(fe);
+        }
+    }
+
+    if params.dry_run || !errors.is_empty() {
+        let status = if errors.is_empty() {
+            StatusCode::OK
+        } else {
+            StatusCode::UNPROCESSABLE_ENTITY
+        };
+        return (
+            status,
+            Json(FuelEfficiencyImportResult {
+                dry_run: params.dry_run,
+                total_rows,
+                imported_rows: 0,
+                errors,
+                rows,
+            }),
+        )
+            .into_response();
+    }
+
+    let mut tx: Transaction<'_, MySql> = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            eprintln!("Failed to start transaction: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    for fe in &mut rows {
+        match query(
+            "INSERT INTO FuelEfficiencies (car_id, fe_date, fe_amount, fe_unitprice, fe_currency, fe_mileage) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(fe.car_id)
+        .bind(&fe.fe_date)
+        .bind(fe.fe_amount)
+        .bind(&fe.fe_unitprice)
+        .bind(&fe.fe_currency)
+        .bind(fe.fe_mileage)
+        .execute(&mut *tx)
+        .await
+        {
+            Ok(result) => {
+                fe.fe_id = Some(result.last_insert_id() as i32);
+                AuditEntry::create(
+                    &firebase_user_id,
+                    Some(car_id),
+                    "fuel_efficiency",
+                    result.last_insert_id(),
+                    &*fe,
+                )
+                .log(&mut *tx)
+                .await;
+            }
+            Err(e) => {
+                tx.rollback().await.unwrap();
+                eprintln!("Failed to import fuel efficiency: {:?}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+    }
+
+    if let Err(e) = tx.commit().await {
+        eprintln!("Failed to commit transaction: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    (
+        StatusCode::CREATED,
+        Json(FuelEfficiencyImportResult {
+            dry_run: false,
+            total_rows,
+            imported_rows: rows.len(),
+            errors,
+            rows,
+        }),
+    )
+        .into_response()
+}
@@ -1,21 +1,20 @@
+use crate::state::AppState;
+use crate::storage::image_prefix;
 use axum::{
-    extract::multipart::Multipart,
+    extract::{multipart::Multipart, Extension},
     response::{Json, Response},
 };
-use cloudflare_r2_rs::r2::R2Manager;
-use dotenv::dotenv;
-use std::env;
+use std::sync::Arc;
+use tokio::sync::Mutex;
 use tracing::{error, info};
+use uuid::Uuid;
 
-pub async fn upload_image(mut payload: Multipart) -> Result<Json<String>, Response> {
-    dotenv().ok(); // 環境変数をロード
-
-    let bucket = env::var("BUCKET_NAME").expect("BUCKET_NAME must be set");
-    let endpoint = env::var("R2_ENDPOINT_URL").expect("R2_ENDPOINT_URL must be set");
-    let client_id = env::var("R2_ACCESS_KEY_ID").expect("R2_ACCESS_KEY_ID must be set");
-    let secret = env::var("R2_SECRET_ACCESS_KEY").expect("R2_SECRET_ACCESS_KEY must be set");
-
-    let r2_manager = R2Manager::new(&bucket, &endpoint, &client_id, &secret).await;
+pub async fn upload_image(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    mut payload: Multipart,
+) -> Result<Json<String>, Response> {
+    let storage = state.lock().await.storage.clone();
 
     while let Some(field) = payload.next_field().await.map_err(|e| {
         error!("Error reading field: {}", e);
@@ -33,12 +32,34 @@ pub async fn upload_image(mut payload: Multipart) -> Result<Json<String>, Respon
                     .body(format!("Error reading file content: {}", e).into())
                     .unwrap()
             })?;
-            let key = format!("images/{}", file_name);
-            r2_manager
-                .upload(&key, &content, None, Some("image/jpeg"))
-                .await;
+            // ファイル名はキーに使わず、ユーザーごとのプレフィックスの下に新しい名前で保存する
+            let extension = file_name
+                .rsplit_once('.')
+                .map(|(_, ext)| ext.to_ascii_lowercase())
+                .filter(|ext| {
+                    !ext.is_empty()
+                        && ext.len() <= 5
+                        && ext.chars().all(|c| c.is_ascii_alphanumeric())
+                })
+                .unwrap_or_else(|| "jpg".to_string());
+            let key = format!(
+                "{}{}.{}",
+                image_prefix(&firebase_user_id),
+                Uuid::new_v4(),
+                extension
+            );
+            storage
+                .put(&key, &content, "image/jpeg")
+                .await
+                .map_err(|e| {
+                    error!("Error uploading file: {}", e);
+                    Response::builder()
+                        .status(500)
+                        .body(format!("Error uploading file: {}", e).into())
+                        .unwrap()
+                })?;
 
-            let url = format!("https://r2.autotrack.work/images/{}", file_name);
+            let url = storage.public_url(&key);
             info!("File uploaded successfully: {}", url);
             return Ok(Json(url));
         }
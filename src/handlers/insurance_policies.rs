@@ -0,0 +1,459 @@
+use crate::access;
+use crate::audit::AuditEntry;
+use crate::etag;
+use crate::handlers::user_preferences;
+use crate::models::insurance_policy::{
+    InsurancePolicy, PolicyRenewal, RenewalQuery, RenewalStatus, INSURANCE_POLICY_COLUMNS,
+    INSURANCE_POLICY_PATCH,
+};
+use crate::models::user_car::CarRole;
+use crate::patch;
+use crate::state::AppState;
+use crate::validation::ValidatedJson;
+use axum::{
+    extract::{Extension, Json, Path, Query},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use serde_json::Value;
+use sqlx::{query, query_as, MySqlPool};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+async fn fetch_policy(db_pool: &MySqlPool, id: i32) -> Result<InsurancePolicy, sqlx::Error> {
+    query_as::<_, InsurancePolicy>(&format!(
+        "SELECT {} FROM InsurancePolicies WHERE policy_id = ?",
+        INSURANCE_POLICY_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(db_pool)
+    .await
+}
+
+pub async fn create_insurance_policy(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    ValidatedJson(new_policy): ValidatedJson<InsurancePolicy>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_car_role(
+        &db_pool,
+        &firebase_user_id,
+        new_policy.car_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    match query(
+        "INSERT INTO InsurancePolicies (car_id, policy_type, insurer, policy_number, coverage_start, coverage_end, premium, premium_currency, notes) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(new_policy.car_id)
+    .bind(new_policy.policy_type)
+    .bind(&new_policy.insurer)
+    .bind(&new_policy.policy_number)
+    .bind(&new_policy.coverage_start)
+    .bind(&new_policy.coverage_end)
+    .bind(&new_policy.premium)
+    .bind(&new_policy.premium_currency)
+    .bind(&new_policy.notes)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) => match fetch_policy(&db_pool, result.last_insert_id() as i32).await {
+            Ok(policy) => {
+                AuditEntry::create(
+                    &firebase_user_id,
+                    Some(policy.car_id),
+                    "insurance_policy",
+                    result.last_insert_id(),
+                    &policy,
+                )
+                .log(&db_pool)
+                .await;
+                etag::with_etag(StatusCode::CREATED, policy.updated_at, policy)
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch insurance policy after creation: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to create insurance policy: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// アクセスできる車両の保険
+pub async fn get_insurance_policies(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    match query_as::<_, InsurancePolicy>(&format!(
+        "SELECT {} FROM InsurancePolicies WHERE deleted_at IS NULL AND car_id IN (SELECT uc.car_id FROM user_car uc JOIN Cars c ON c.car_id = uc.car_id WHERE uc.firebase_user_id = ? AND c.deleted_at IS NULL) ORDER BY car_id, coverage_start, policy_id",
+        INSURANCE_POLICY_COLUMNS
+    ))
+    .bind(&firebase_user_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(policies) => (StatusCode::OK, Json(policies)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch insurance policies: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn get_car_insurance_policies(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Viewer).await
+    {
+        return e.into_response();
+    }
+
+    match query_as::<_, InsurancePolicy>(&format!(
+        "SELECT {} FROM InsurancePolicies WHERE car_id = ? AND deleted_at IS NULL ORDER BY coverage_start, policy_id",
+        INSURANCE_POLICY_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(policies) => (StatusCode::OK, Json(policies)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch car insurance policies: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn get_insurance_policy(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(policy_id): Path<i32>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "InsurancePolicies",
+        "policy_id",
+        policy_id,
+        CarRole::Viewer,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    match query_as::<_, InsurancePolicy>(&format!(
+        "SELECT {} FROM InsurancePolicies WHERE policy_id = ? AND deleted_at IS NULL",
+        INSURANCE_POLICY_COLUMNS
+    ))
+    .bind(policy_id)
+    .fetch_one(&db_pool)
+    .await
+    {
+        Ok(policy) => etag::conditional_get(&headers, policy.updated_at, policy),
+        Err(e) => {
+            eprintln!("Failed to fetch insurance policy: {:?}", e);
+            StatusCode::NOT_FOUND.into_response()
+        }
+    }
+}
+
+/// 更新時期の保険。車両と種類ごとに満了日が最も遅いものだけを対象にし、
+/// 満了日までの日数はユーザーのタイムゾーンの今日から数える
+pub async fn get_insurance_renewals(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Query(params): Query<RenewalQuery>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+    let today = user_preferences::preferences_or_default(&db_pool, &firebase_user_id)
+        .await
+        .today();
+
+    let policies = match query_as::<_, InsurancePolicy>(&format!(
+        "SELECT {} FROM InsurancePolicies p WHERE p.deleted_at IS NULL
+         AND p.car_id IN (SELECT uc.car_id FROM user_car uc JOIN Cars c ON c.car_id = uc.car_id WHERE uc.firebase_user_id = ? AND c.deleted_at IS NULL)
+         AND NOT EXISTS (SELECT 1 FROM InsurancePolicies n WHERE n.car_id = p.car_id AND n.policy_type = p.policy_type AND n.deleted_at IS NULL AND n.coverage_end > p.coverage_end)
+         ORDER BY p.coverage_end, p.policy_id",
+        INSURANCE_POLICY_COLUMNS
+    ))
+    .bind(&firebase_user_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(policies) => policies,
+        Err(e) => {
+            eprintln!("Failed to fetch insurance policies: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let renewals: Vec<PolicyRenewal> = policies
+        .into_iter()
+        .filter_map(|policy| PolicyRenewal::new(policy, today, params.within_days))
+        .filter(|renewal| match renewal.status {
+            RenewalStatus::Active => false,
+            RenewalStatus::DueSoon => true,
+            RenewalStatus::Expired => params.include_expired,
+        })
+        .collect();
+
+    (StatusCode::OK, Json(renewals)).into_response()
+}
+
+pub async fn update_insurance_policy(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(policy_id): Path<i32>,
+    headers: HeaderMap,
+    ValidatedJson(updated_policy): ValidatedJson<InsurancePolicy>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "InsurancePolicies",
+        "policy_id",
+        policy_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    if let Err(e) = access::require_car_role(
+        &db_pool,
+        &firebase_user_id,
+        updated_policy.car_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_policy(&db_pool, policy_id).await {
+        Ok(policy) => policy,
+        Err(e) => {
+            eprintln!("Failed to fetch insurance policy: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE InsurancePolicies SET car_id = ?, policy_type = ?, insurer = ?, policy_number = ?, coverage_start = ?, coverage_end = ?, premium = ?, premium_currency = ?, notes = ? WHERE policy_id = ? AND updated_at <=> ?",
+    )
+    .bind(updated_policy.car_id)
+    .bind(updated_policy.policy_type)
+    .bind(&updated_policy.insurer)
+    .bind(&updated_policy.policy_number)
+    .bind(&updated_policy.coverage_start)
+    .bind(&updated_policy.coverage_end)
+    .bind(&updated_policy.premium)
+    .bind(&updated_policy.premium_currency)
+    .bind(&updated_policy.notes)
+    .bind(policy_id)
+    .bind(before.updated_at)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
+        Ok(_) => match fetch_policy(&db_pool, policy_id).await {
+            Ok(policy) => {
+                AuditEntry::update(
+                    &firebase_user_id,
+                    Some(policy.car_id),
+                    "insurance_policy",
+                    policy_id,
+                    &before,
+                    &policy,
+                )
+                .log(&db_pool)
+                .await;
+                etag::with_etag(StatusCode::OK, policy.updated_at, policy)
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch insurance policy after update: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to update insurance policy: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// JSON Merge Patch で指定された項目だけを更新する
+pub async fn patch_insurance_policy(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "InsurancePolicies",
+        "policy_id",
+        id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_policy(&db_pool, id).await {
+        Ok(policy) => policy,
+        Err(e) => {
+            eprintln!("Failed to fetch insurance policy: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    let policy = match patch::apply(&INSURANCE_POLICY_PATCH, &before, &patch) {
+        Ok(policy) => policy,
+        Err(e) => return e.into_response(),
+    };
+
+    // 別の車両に移す場合は移動先の車両にも編集者以上のロールが必要
+    if policy.car_id != before.car_id {
+        if let Err(e) =
+            access::require_car_role(&db_pool, &firebase_user_id, policy.car_id, CarRole::Editor)
+                .await
+        {
+            return e.into_response();
+        }
+    }
+
+    match patch::update(
+        &db_pool,
+        &INSURANCE_POLICY_PATCH,
+        id,
+        &before,
+        &policy,
+        before.updated_at,
+    )
+    .await
+    {
+        Ok(false) => etag::precondition_failed().into_response(),
+        Ok(true) => match fetch_policy(&db_pool, id).await {
+            Ok(policy) => {
+                AuditEntry::update(
+                    &firebase_user_id,
+                    Some(policy.car_id),
+                    "insurance_policy",
+                    id,
+                    &before,
+                    &policy,
+                )
+                .log(&db_pool)
+                .await;
+                etag::with_etag(StatusCode::OK, policy.updated_at, policy)
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch insurance policy after update: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to update insurance policy: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 保険をゴミ箱に移す。事故歴からの参照と添付ファイルは復元に備えて残す
+pub async fn delete_insurance_policy(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(policy_id): Path<i32>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "InsurancePolicies",
+        "policy_id",
+        policy_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_policy(&db_pool, policy_id).await {
+        Ok(policy) => policy,
+        Err(e) => {
+            eprintln!("Failed to fetch insurance policy: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE InsurancePolicies SET deleted_at = CURRENT_TIMESTAMP WHERE policy_id = ? AND deleted_at IS NULL AND updated_at <=> ?",
+    )
+    .bind(policy_id)
+    .bind(before.updated_at)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
+        Ok(_) => {
+            AuditEntry::delete(
+                &firebase_user_id,
+                Some(before.car_id),
+                "insurance_policy",
+                policy_id,
+                &before,
+            )
+            .log(&db_pool)
+            .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to delete insurance policy: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
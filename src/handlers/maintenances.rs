@@ -1,29 +1,69 @@
-use crate::models::maintenance::{Maintenance, MAINTENANCE_TITLES};
+use crate::access;
+use crate::audit::AuditEntry;
+use crate::etag;
+use crate::i18n::Lang;
+use crate::models::maintenance::{
+    Maintenance, MaintenanceType, MaintenanceTypeOption, MAINTENANCE_COLUMNS, MAINTENANCE_PATCH,
+    MAINTENANCE_TYPES,
+};
+use crate::models::user_car::CarRole;
+use crate::patch;
 use crate::state::AppState;
+use crate::validation::{self, UpdateJson, ValidatedJson};
 use axum::{
     extract::{Extension, Json, Path},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
 };
-use sqlx::{query, query_as};
+use serde_json::Value;
+use sqlx::{query, query_as, MySqlPool};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
 pub fn apply_maintenance_logic(mut maintenance: Maintenance) -> Maintenance {
-    if maintenance.maint_type == "Other" {
-        // maint_titleはそのまま
-    } else if let Some(&title) = MAINTENANCE_TITLES.get(maintenance.maint_type.as_str()) {
-        maintenance.maint_title = title.to_string();
+    if maintenance.maint_type != MaintenanceType::Other {
+        // Other 以外は種類の表示名をタイトルにするので、言語によらないよう空で保存する
+        maintenance.maint_title.clear();
     }
     maintenance
 }
 
+/// 整備の種類の一覧を表示名とともに返す
+pub async fn get_maintenance_types() -> impl IntoResponse {
+    let types: Vec<MaintenanceTypeOption> =
+        MAINTENANCE_TYPES.iter().copied().map(Into::into).collect();
+    (StatusCode::OK, Json(types)).into_response()
+}
+
+async fn fetch_maintenance(db_pool: &MySqlPool, id: i32) -> Result<Maintenance, sqlx::Error> {
+    query_as::<_, Maintenance>(&format!(
+        "SELECT {} FROM Maintenances WHERE maint_id = ?",
+        MAINTENANCE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(db_pool)
+    .await
+}
+
 pub async fn create_maintenance(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
-    Json(new_maintenance): Json<Maintenance>,
+    Extension(firebase_user_id): Extension<String>,
+    lang: Lang,
+    ValidatedJson(new_maintenance): ValidatedJson<Maintenance>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
+    if let Err(e) = access::require_car_role(
+        &db_pool,
+        &firebase_user_id,
+        new_maintenance.car_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
     let new_maintenance = apply_maintenance_logic(new_maintenance);
 
     match query!(
@@ -38,15 +78,30 @@ pub async fn create_maintenance(
     .await
     {
         Ok(result) => {
-            match query_as!(
-                Maintenance,
-                "SELECT * FROM Maintenances WHERE maint_id = ?",
-                result.last_insert_id()
-            )
+            match query_as::<_, Maintenance>(&format!(
+                "SELECT {} FROM Maintenances WHERE maint_id = ? AND deleted_at IS NULL",
+                MAINTENANCE_COLUMNS
+            ))
+            .bind(result.last_insert_id())
             .fetch_one(&db_pool)
             .await
             {
-                Ok(maintenance) => (StatusCode::CREATED, Json(maintenance)).into_response(),
+                Ok(maintenance) => {
+                    AuditEntry::create(
+                        &firebase_user_id,
+                        Some(maintenance.car_id),
+                        "maintenance",
+                        result.last_insert_id(),
+                        &maintenance,
+                    )
+                    .log(&db_pool)
+                    .await;
+                    etag::with_etag(
+                        StatusCode::CREATED,
+                        maintenance.updated_at,
+                        maintenance.localized(lang),
+                    )
+                }
                 Err(e) => {
                     eprintln!("Failed to fetch maintenance after creation: {:?}", e);
                     StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -62,14 +117,24 @@ pub async fn create_maintenance(
 
 pub async fn get_maintenances(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
+    lang: Lang,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query_as!(Maintenance, "SELECT * FROM Maintenances")
-        .fetch_all(&db_pool)
-        .await
+    match query_as::<_, Maintenance>(&format!(
+        "SELECT {} FROM Maintenances WHERE deleted_at IS NULL",
+        MAINTENANCE_COLUMNS
+    ))
+    .fetch_all(&db_pool)
+    .await
     {
-        Ok(maintenances) => (StatusCode::OK, Json(maintenances)).into_response(),
+        Ok(maintenances) => {
+            let maintenances: Vec<Maintenance> = maintenances
+                .into_iter()
+                .map(|maintenance| maintenance.localized(lang))
+                .collect();
+            (StatusCode::OK, Json(maintenances)).into_response()
+        }
         Err(e) => {
             eprintln!("Failed to fetch maintenances: {:?}", e);
             StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -80,18 +145,24 @@ pub async fn get_maintenances(
 pub async fn get_maintenance(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
     Path(id): Path<i32>,
+    headers: HeaderMap,
+    lang: Lang,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query_as!(
-        Maintenance,
-        "SELECT * FROM Maintenances WHERE maint_id = ?",
-        id
-    )
+    match query_as::<_, Maintenance>(&format!(
+        "SELECT {} FROM Maintenances WHERE maint_id = ? AND deleted_at IS NULL",
+        MAINTENANCE_COLUMNS
+    ))
+    .bind(id)
     .fetch_one(&db_pool)
     .await
     {
-        Ok(maintenance) => (StatusCode::OK, Json(maintenance)).into_response(),
+        Ok(maintenance) => etag::conditional_get(
+            &headers,
+            maintenance.updated_at,
+            maintenance.localized(lang),
+        ),
         Err(e) => {
             eprintln!("Failed to fetch maintenance: {:?}", e);
             StatusCode::NOT_FOUND.into_response()
@@ -101,35 +172,96 @@ pub async fn get_maintenance(
 
 pub async fn update_maintenance(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
     Path(id): Path<i32>,
-    Json(updated_maintenance): Json<Maintenance>,
+    headers: HeaderMap,
+    lang: Lang,
+    UpdateJson(updated_maintenance): UpdateJson<Maintenance>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "Maintenances",
+        "maint_id",
+        id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    if let Err(e) = access::require_car_role(
+        &db_pool,
+        &firebase_user_id,
+        updated_maintenance.car_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
     let new_updated_maintenance = apply_maintenance_logic(updated_maintenance);
 
-    match query!(
-        "UPDATE Maintenances SET car_id = ?, maint_type = ?, maint_title = ?, maint_date = ?, maint_description = ? WHERE maint_id = ?",
-        new_updated_maintenance.car_id,
-        new_updated_maintenance.maint_type,
-        new_updated_maintenance.maint_title,
-        new_updated_maintenance.maint_date,
-        new_updated_maintenance.maint_description,
-        id
+    let before = match fetch_maintenance(&db_pool, id).await {
+        Ok(maintenance) => maintenance,
+        Err(e) => {
+            eprintln!("Failed to fetch maintenance: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    if let Err(e) = validation::validate_changes(&before, &new_updated_maintenance) {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE Maintenances SET car_id = ?, maint_type = ?, maint_title = ?, maint_date = ?, maint_description = ? WHERE maint_id = ? AND updated_at <=> ?",
     )
+    .bind(new_updated_maintenance.car_id)
+    .bind(new_updated_maintenance.maint_type)
+    .bind(&new_updated_maintenance.maint_title)
+    .bind(&new_updated_maintenance.maint_date)
+    .bind(&new_updated_maintenance.maint_description)
+    .bind(id)
+    .bind(before.updated_at)
     .execute(&db_pool)
     .await
     {
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
         Ok(_) => {
-            match query_as!(
-                Maintenance,
-                "SELECT * FROM Maintenances WHERE maint_id = ?",
-                id
-            )
+            match query_as::<_, Maintenance>(&format!(
+                "SELECT {} FROM Maintenances WHERE maint_id = ? AND deleted_at IS NULL",
+                MAINTENANCE_COLUMNS
+            ))
+            .bind(id)
             .fetch_one(&db_pool)
             .await
             {
-                Ok(maintenance) => (StatusCode::OK, Json(maintenance)).into_response(),
+                Ok(maintenance) => {
+                    AuditEntry::update(
+                        &firebase_user_id,
+                        Some(maintenance.car_id),
+                        "maintenance",
+                        id,
+                        &before,
+                        &maintenance,
+                    )
+                    .log(&db_pool)
+                    .await;
+                    etag::with_etag(
+                        StatusCode::OK,
+                        maintenance.updated_at,
+                        maintenance.localized(lang),
+                    )
+                }
                 Err(e) => {
                     eprintln!("Failed to fetch maintenance after update: {:?}", e);
                     StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -143,17 +275,157 @@ pub async fn update_maintenance(
     }
 }
 
-pub async fn delete_maintenance(
+/// JSON Merge Patch で指定された項目だけを更新する
+pub async fn patch_maintenance(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
     Path(id): Path<i32>,
+    headers: HeaderMap,
+    lang: Lang,
+    Json(patch): Json<Value>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query!("DELETE FROM Maintenances WHERE maint_id = ?", id)
-        .execute(&db_pool)
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "Maintenances",
+        "maint_id",
+        id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_maintenance(&db_pool, id).await {
+        Ok(maintenance) => maintenance,
+        Err(e) => {
+            eprintln!("Failed to fetch maintenance: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    let maintenance = match patch::apply(&MAINTENANCE_PATCH, &before, &patch) {
+        Ok(maintenance) => apply_maintenance_logic(maintenance),
+        Err(e) => return e.into_response(),
+    };
+
+    // 別の車両に移す場合は移動先の車両にも編集者以上のロールが必要
+    if maintenance.car_id != before.car_id {
+        if let Err(e) = access::require_car_role(
+            &db_pool,
+            &firebase_user_id,
+            maintenance.car_id,
+            CarRole::Editor,
+        )
         .await
+        {
+            return e.into_response();
+        }
+    }
+
+    match patch::update(
+        &db_pool,
+        &MAINTENANCE_PATCH,
+        id,
+        &before,
+        &maintenance,
+        before.updated_at,
+    )
+    .await
     {
-        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => etag::precondition_failed().into_response(),
+        Ok(true) => match fetch_maintenance(&db_pool, id).await {
+            Ok(maintenance) => {
+                AuditEntry::update(
+                    &firebase_user_id,
+                    Some(maintenance.car_id),
+                    "maintenance",
+                    id,
+                    &before,
+                    &maintenance,
+                )
+                .log(&db_pool)
+                .await;
+                etag::with_etag(
+                    StatusCode::OK,
+                    maintenance.updated_at,
+                    maintenance.localized(lang),
+                )
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch maintenance after update: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to update maintenance: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 整備記録をゴミ箱に移す。添付ファイルは復元に備えて残す
+pub async fn delete_maintenance(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "Maintenances",
+        "maint_id",
+        id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_maintenance(&db_pool, id).await {
+        Ok(maintenance) => maintenance,
+        Err(e) => {
+            eprintln!("Failed to fetch maintenance: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE Maintenances SET deleted_at = CURRENT_TIMESTAMP WHERE maint_id = ? AND deleted_at IS NULL AND updated_at <=> ?",
+    )
+    .bind(id)
+    .bind(before.updated_at)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
+        Ok(_) => {
+            AuditEntry::delete(
+                &firebase_user_id,
+                Some(before.car_id),
+                "maintenance",
+                id,
+                &before,
+            )
+            .log(&db_pool)
+            .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
         Err(e) => {
             eprintln!("Failed to delete maintenance: {:?}", e);
             StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -1,8 +1,25 @@
 pub mod accidents;
+pub mod account;
+pub mod attachments;
+pub mod audit_logs;
+pub mod car_members;
+pub mod car_transfers;
 pub mod cars;
+pub mod exchange_rates;
+pub mod files;
 pub mod fuel_efficiencies;
+pub mod fuel_efficiency_csv;
 pub mod images;
+pub mod insurance_policies;
 pub mod maintenances;
+pub mod parts;
 pub mod periodic_inspections;
+pub mod share_links;
+pub mod tire_sets;
+pub mod trash;
 pub mod tunings;
+pub mod user_preferences;
 pub mod users;
+pub mod vehicle_specs;
+pub mod vehicle_taxes;
+pub mod vin;
@@ -0,0 +1,574 @@
+use crate::access;
+use crate::audit::AuditEntry;
+use crate::etag;
+use crate::middleware::auth::AppError;
+use crate::models::part::{
+    Part, PartUsage, PartUsageOwner, PartUsageRequest, PART_COLUMNS, PART_PATCH, PART_USAGE_COLUMNS,
+};
+use crate::models::user_car::CarRole;
+use crate::patch;
+use crate::state::AppState;
+use crate::validation::ValidatedJson;
+use axum::{
+    extract::{Extension, Json, Path},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use serde_json::Value;
+use sqlx::{query, query_as, query_scalar, MySqlConnection, MySqlPool};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// ユーザーの在庫にある部品。ほかのユーザーの部品は None
+async fn fetch_part(
+    db_pool: &MySqlPool,
+    firebase_user_id: &str,
+    id: i32,
+) -> Result<Option<Part>, sqlx::Error> {
+    query_as::<_, Part>(&format!(
+        "SELECT {} FROM Parts WHERE part_id = ? AND firebase_user_id = ?",
+        PART_COLUMNS
+    ))
+    .bind(id)
+    .bind(firebase_user_id)
+    .fetch_optional(db_pool)
+    .await
+}
+
+async fn fetch_usage(conn: &mut MySqlConnection, id: u64) -> Result<PartUsage, sqlx::Error> {
+    query_as::<_, PartUsage>(&format!(
+        "SELECT {} FROM PartUsages WHERE usage_id = ?",
+        PART_USAGE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(&mut *conn)
+    .await
+}
+
+pub async fn create_part(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    ValidatedJson(new_part): ValidatedJson<Part>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    match query(
+        "INSERT INTO Parts (firebase_user_id, part_number, part_name, part_brand, quantity, unit_cost, cost_currency, supplier, notes) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&firebase_user_id)
+    .bind(&new_part.part_number)
+    .bind(&new_part.part_name)
+    .bind(&new_part.part_brand)
+    .bind(new_part.quantity)
+    .bind(&new_part.unit_cost)
+    .bind(&new_part.cost_currency)
+    .bind(&new_part.supplier)
+    .bind(&new_part.notes)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) => {
+            let id = result.last_insert_id() as i32;
+            match fetch_part(&db_pool, &firebase_user_id, id).await {
+                Ok(Some(part)) => {
+                    AuditEntry::create(&firebase_user_id, None, "part", id, &part)
+                        .log(&db_pool)
+                        .await;
+                    etag::with_etag(StatusCode::CREATED, part.updated_at, part)
+                }
+                Ok(None) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+                Err(e) => {
+                    eprintln!("Failed to fetch part after creation: {:?}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to create part: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// ログイン中のユーザーの在庫
+pub async fn get_parts(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    match query_as::<_, Part>(&format!(
+        "SELECT {} FROM Parts WHERE firebase_user_id = ? ORDER BY part_name, part_id",
+        PART_COLUMNS
+    ))
+    .bind(&firebase_user_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(parts) => (StatusCode::OK, Json(parts)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch parts: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn get_part(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(part_id): Path<i32>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    match fetch_part(&db_pool, &firebase_user_id, part_id).await {
+        Ok(Some(part)) => etag::conditional_get(&headers, part.updated_at, part),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch part: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn update_part(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(part_id): Path<i32>,
+    headers: HeaderMap,
+    ValidatedJson(updated_part): ValidatedJson<Part>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let before = match fetch_part(&db_pool, &firebase_user_id, part_id).await {
+        Ok(Some(part)) => part,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch part: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE Parts SET part_number = ?, part_name = ?, part_brand = ?, quantity = ?, unit_cost = ?, cost_currency = ?, supplier = ?, notes = ? WHERE part_id = ? AND updated_at <=> ?",
+    )
+    .bind(&updated_part.part_number)
+    .bind(&updated_part.part_name)
+    .bind(&updated_part.part_brand)
+    .bind(updated_part.quantity)
+    .bind(&updated_part.unit_cost)
+    .bind(&updated_part.cost_currency)
+    .bind(&updated_part.supplier)
+    .bind(&updated_part.notes)
+    .bind(part_id)
+    .bind(before.updated_at)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
+        Ok(_) => match fetch_part(&db_pool, &firebase_user_id, part_id).await {
+            Ok(Some(part)) => {
+                AuditEntry::update(&firebase_user_id, None, "part", part_id, &before, &part)
+                    .log(&db_pool)
+                    .await;
+                etag::with_etag(StatusCode::OK, part.updated_at, part)
+            }
+            Ok(None) => StatusCode::NOT_FOUND.into_response(),
+            Err(e) => {
+                eprintln!("Failed to fetch part after update: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to update part: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// JSON Merge Patch で指定された項目だけを更新する
+pub async fn patch_part(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let before = match fetch_part(&db_pool, &firebase_user_id, id).await {
+        Ok(Some(part)) => part,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch part: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    let part = match patch::apply(&PART_PATCH, &before, &patch) {
+        Ok(part) => part,
+        Err(e) => return e.into_response(),
+    };
+
+    match patch::update(&db_pool, &PART_PATCH, id, &before, &part, before.updated_at).await {
+        Ok(false) => etag::precondition_failed().into_response(),
+        Ok(true) => match fetch_part(&db_pool, &firebase_user_id, id).await {
+            Ok(Some(part)) => {
+                AuditEntry::update(&firebase_user_id, None, "part", id, &before, &part)
+                    .log(&db_pool)
+                    .await;
+                etag::with_etag(StatusCode::OK, part.updated_at, part)
+            }
+            Ok(None) => StatusCode::NOT_FOUND.into_response(),
+            Err(e) => {
+                eprintln!("Failed to fetch part after update: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to update part: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 部品を在庫から削除する。使用の記録は使った時点の品番と名前で残る
+pub async fn delete_part(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(part_id): Path<i32>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let before = match fetch_part(&db_pool, &firebase_user_id, part_id).await {
+        Ok(Some(part)) => part,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch part: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    match query("DELETE FROM Parts WHERE part_id = ? AND updated_at <=> ?")
+        .bind(part_id)
+        .bind(before.updated_at)
+        .execute(&db_pool)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
+        Ok(_) => {
+            AuditEntry::delete(&firebase_user_id, None, "part", part_id, &before)
+                .log(&db_pool)
+                .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to delete part: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 部品を使った整備・チューニングの記録。ゴミ箱にある記録のものは含めない
+pub async fn get_part_usages(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(part_id): Path<i32>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    match fetch_part(&db_pool, &firebase_user_id, part_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch part: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    match query_as::<_, PartUsage>(&format!(
+        "SELECT {} FROM PartUsages WHERE part_id = ?
+         AND (maint_id IN (SELECT maint_id FROM Maintenances WHERE deleted_at IS NULL)
+           OR tuning_id IN (SELECT tuning_id FROM Tunings WHERE deleted_at IS NULL))
+         ORDER BY usage_id",
+        PART_USAGE_COLUMNS
+    ))
+    .bind(part_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(usages) => (StatusCode::OK, Json(usages)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch part usages: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn get_record_parts(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(owner): Extension<PartUsageOwner>,
+    Extension(firebase_user_id): Extension<String>,
+    Path((owner_id,)): Path<(i32,)>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        owner.table(),
+        owner.id_column(),
+        owner_id,
+        CarRole::Viewer,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    match query_as::<_, PartUsage>(&format!(
+        "SELECT {} FROM PartUsages WHERE {} = ? ORDER BY usage_id",
+        PART_USAGE_COLUMNS,
+        owner.id_column()
+    ))
+    .bind(owner_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(usages) => (StatusCode::OK, Json(usages)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch part usages: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 在庫から部品を引き当てて記録に追加する
+async fn consume_part(
+    conn: &mut MySqlConnection,
+    firebase_user_id: &str,
+    owner: PartUsageOwner,
+    owner_id: i32,
+    request: &PartUsageRequest,
+) -> Result<Result<PartUsage, AppError>, sqlx::Error> {
+    let part = query_as::<_, Part>(&format!(
+        "SELECT {} FROM Parts WHERE part_id = ? AND firebase_user_id = ? FOR UPDATE",
+        PART_COLUMNS
+    ))
+    .bind(request.part_id)
+    .bind(firebase_user_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(part) = part else {
+        return Ok(Err(AppError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "part/invalid-part".to_string(),
+            "The part is not in your inventory.".to_string(),
+        )));
+    };
+    if part.quantity < request.quantity {
+        return Ok(Err(AppError::new(
+            StatusCode::CONFLICT,
+            "part/insufficient-stock".to_string(),
+            "There is not enough stock of the part.".to_string(),
+        )));
+    }
+
+    query("UPDATE Parts SET quantity = quantity - ? WHERE part_id = ?")
+        .bind(request.quantity)
+        .bind(request.part_id)
+        .execute(&mut *conn)
+        .await?;
+    let id = query(&format!(
+        "INSERT INTO PartUsages (part_id, {}, part_number, part_name, part_brand, quantity, unit_cost, cost_currency) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        owner.id_column()
+    ))
+    .bind(request.part_id)
+    .bind(owner_id)
+    .bind(&part.part_number)
+    .bind(&part.part_name)
+    .bind(&part.part_brand)
+    .bind(request.quantity)
+    .bind(&part.unit_cost)
+    .bind(&part.cost_currency)
+    .execute(&mut *conn)
+    .await?
+    .last_insert_id();
+    Ok(Ok(fetch_usage(conn, id).await?))
+}
+
+pub async fn add_record_part(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(owner): Extension<PartUsageOwner>,
+    Extension(firebase_user_id): Extension<String>,
+    Path((owner_id,)): Path<(i32,)>,
+    ValidatedJson(request): ValidatedJson<PartUsageRequest>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let car_id = match access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        owner.table(),
+        owner.id_column(),
+        owner_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        Ok(car_id) => car_id,
+        Err(e) => return e.into_response(),
+    };
+
+    if owner == PartUsageOwner::Tuning {
+        match query_scalar::<_, Option<String>>(
+            "SELECT tuning_removed_date FROM Tunings WHERE tuning_id = ?",
+        )
+        .bind(owner_id)
+        .fetch_one(&db_pool)
+        .await
+        {
+            Ok(Some(_)) => {
+                return AppError::new(
+                    StatusCode::CONFLICT,
+                    "tuning/removed".to_string(),
+                    "The tuning has been removed.".to_string(),
+                )
+                .into_response()
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Failed to fetch tuning: {:?}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+    }
+
+    let mut tx = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            eprintln!("Failed to begin transaction: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let usage = match consume_part(&mut tx, &firebase_user_id, owner, owner_id, &request).await {
+        Ok(Ok(usage)) => usage,
+        Ok(Err(e)) => return e.into_response(),
+        Err(e) => {
+            eprintln!("Failed to add part usage: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    if let Err(e) = tx.commit().await {
+        eprintln!("Failed to commit part usage: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    AuditEntry::create(
+        &firebase_user_id,
+        Some(car_id),
+        "part_usage",
+        usage.usage_id,
+        &usage,
+    )
+    .log(&db_pool)
+    .await;
+    (StatusCode::CREATED, Json(usage)).into_response()
+}
+
+/// 記録から部品を外す。在庫に戻していない部品は在庫に戻す
+pub async fn delete_record_part(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(owner): Extension<PartUsageOwner>,
+    Extension(firebase_user_id): Extension<String>,
+    Path((owner_id, usage_id)): Path<(i32, i32)>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let car_id = match access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        owner.table(),
+        owner.id_column(),
+        owner_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        Ok(car_id) => car_id,
+        Err(e) => return e.into_response(),
+    };
+
+    let mut tx = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            eprintln!("Failed to begin transaction: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let before = match query_as::<_, PartUsage>(&format!(
+        "SELECT {} FROM PartUsages WHERE usage_id = ? AND {} = ? FOR UPDATE",
+        PART_USAGE_COLUMNS,
+        owner.id_column()
+    ))
+    .bind(usage_id)
+    .bind(owner_id)
+    .fetch_optional(&mut *tx)
+    .await
+    {
+        Ok(Some(usage)) => usage,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch part usage: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let (Some(part_id), false) = (before.part_id, before.returned) {
+        if let Err(e) = query("UPDATE Parts SET quantity = quantity + ? WHERE part_id = ?")
+            .bind(before.quantity)
+            .bind(part_id)
+            .execute(&mut *tx)
+            .await
+        {
+            eprintln!("Failed to restore part stock: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+    if let Err(e) = query("DELETE FROM PartUsages WHERE usage_id = ?")
+        .bind(usage_id)
+        .execute(&mut *tx)
+        .await
+    {
+        eprintln!("Failed to delete part usage: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    if let Err(e) = tx.commit().await {
+        eprintln!("Failed to commit part usage deletion: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    AuditEntry::delete(
+        &firebase_user_id,
+        Some(car_id),
+        "part_usage",
+        usage_id,
+        &before,
+    )
+    .log(&db_pool)
+    .await;
+    StatusCode::NO_CONTENT.into_response()
+}
@@ -1,20 +1,54 @@
-use crate::models::periodic_inspection::PeriodicInspection;
+use crate::access;
+use crate::audit::AuditEntry;
+use crate::etag;
+use crate::models::periodic_inspection::{
+    PeriodicInspection, PERIODIC_INSPECTION_COLUMNS, PERIODIC_INSPECTION_PATCH,
+};
+use crate::models::user_car::CarRole;
+use crate::patch;
 use crate::state::AppState;
+use crate::validation::{self, UpdateJson, ValidatedJson};
 use axum::{
     extract::{Extension, Json, Path},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
 };
-use sqlx::{query, query_as};
+use serde_json::Value;
+use sqlx::{query, query_as, MySqlPool};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+async fn fetch_periodic_inspection(
+    db_pool: &MySqlPool,
+    id: i32,
+) -> Result<PeriodicInspection, sqlx::Error> {
+    query_as::<_, PeriodicInspection>(&format!(
+        "SELECT {} FROM PeriodicInspection WHERE pi_id = ?",
+        PERIODIC_INSPECTION_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(db_pool)
+    .await
+}
+
 pub async fn create_periodic_inspection(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
-    Json(new_periodic_inspection): Json<PeriodicInspection>,
+    Extension(firebase_user_id): Extension<String>,
+    ValidatedJson(new_periodic_inspection): ValidatedJson<PeriodicInspection>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
+    if let Err(e) = access::require_car_role(
+        &db_pool,
+        &firebase_user_id,
+        new_periodic_inspection.car_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
     match query!(
         "INSERT INTO PeriodicInspection (car_id, pi_name, pi_date, pi_nextdate) VALUES (?, ?, ?, ?)",
         new_periodic_inspection.car_id,
@@ -26,15 +60,26 @@ pub async fn create_periodic_inspection(
     .await
     {
         Ok(result) => {
-            match query_as!(
-                PeriodicInspection,
-                "SELECT pi_id, car_id, pi_name, pi_date, pi_nextdate, created_at, updated_at FROM PeriodicInspection WHERE pi_id = ?",
-                result.last_insert_id()
-            )
+            match query_as::<_, PeriodicInspection>(&format!(
+                "SELECT {} FROM PeriodicInspection WHERE pi_id = ? AND deleted_at IS NULL",
+                PERIODIC_INSPECTION_COLUMNS
+            ))
+            .bind(result.last_insert_id())
             .fetch_one(&db_pool)
             .await
             {
-                Ok(periodic_inspection) => (StatusCode::CREATED, Json(periodic_inspection)).into_response(),
+                Ok(periodic_inspection) => {
+                    AuditEntry::create(
+                        &firebase_user_id,
+                        Some(periodic_inspection.car_id),
+                        "periodic_inspection",
+                        result.last_insert_id(),
+                        &periodic_inspection,
+                    )
+                    .log(&db_pool)
+                    .await;
+                    etag::with_etag(StatusCode::CREATED, periodic_inspection.updated_at, periodic_inspection)
+                }
                 Err(e) => {
                     eprintln!("Failed to fetch periodic inspection after creation: {:?}", e);
                     StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -53,9 +98,12 @@ pub async fn get_periodic_inspections(
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query_as!(PeriodicInspection, "SELECT * FROM PeriodicInspection")
-        .fetch_all(&db_pool)
-        .await
+    match query_as::<_, PeriodicInspection>(&format!(
+        "SELECT {} FROM PeriodicInspection WHERE deleted_at IS NULL",
+        PERIODIC_INSPECTION_COLUMNS
+    ))
+    .fetch_all(&db_pool)
+    .await
     {
         Ok(periodic_inspections) => (StatusCode::OK, Json(periodic_inspections)).into_response(),
         Err(e) => {
@@ -68,18 +116,23 @@ pub async fn get_periodic_inspections(
 pub async fn get_periodic_inspection(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
     Path(pi_id): Path<i32>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query_as!(
-        PeriodicInspection,
-        "SELECT * FROM PeriodicInspection WHERE pi_id = ?",
-        pi_id
-    )
+    match query_as::<_, PeriodicInspection>(&format!(
+        "SELECT {} FROM PeriodicInspection WHERE pi_id = ? AND deleted_at IS NULL",
+        PERIODIC_INSPECTION_COLUMNS
+    ))
+    .bind(pi_id)
     .fetch_one(&db_pool)
     .await
     {
-        Ok(periodic_inspection) => (StatusCode::OK, Json(periodic_inspection)).into_response(),
+        Ok(periodic_inspection) => etag::conditional_get(
+            &headers,
+            periodic_inspection.updated_at,
+            periodic_inspection,
+        ),
         Err(e) => {
             eprintln!("Failed to fetch periodic inspection: {:?}", e);
             StatusCode::NOT_FOUND.into_response()
@@ -89,32 +142,88 @@ pub async fn get_periodic_inspection(
 
 pub async fn update_periodic_inspection(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
     Path(pi_id): Path<i32>,
-    Json(updated_periodic_inspection): Json<PeriodicInspection>,
+    headers: HeaderMap,
+    UpdateJson(updated_periodic_inspection): UpdateJson<PeriodicInspection>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query!(
-        "UPDATE PeriodicInspection SET car_id = ?, pi_name = ?, pi_date = ?, pi_nextdate = ? WHERE pi_id = ?",
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "PeriodicInspection",
+        "pi_id",
+        pi_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    if let Err(e) = access::require_car_role(
+        &db_pool,
+        &firebase_user_id,
         updated_periodic_inspection.car_id,
-        updated_periodic_inspection.pi_name,
-        updated_periodic_inspection.pi_date,
-        updated_periodic_inspection.pi_nextdate,
-        pi_id
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_periodic_inspection(&db_pool, pi_id).await {
+        Ok(periodic_inspection) => periodic_inspection,
+        Err(e) => {
+            eprintln!("Failed to fetch periodic inspection: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    if let Err(e) = validation::validate_changes(&before, &updated_periodic_inspection) {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE PeriodicInspection SET car_id = ?, pi_name = ?, pi_date = ?, pi_nextdate = ? WHERE pi_id = ? AND updated_at <=> ?",
     )
+    .bind(updated_periodic_inspection.car_id)
+    .bind(&updated_periodic_inspection.pi_name)
+    .bind(&updated_periodic_inspection.pi_date)
+    .bind(&updated_periodic_inspection.pi_nextdate)
+    .bind(pi_id)
+    .bind(before.updated_at)
     .execute(&db_pool)
     .await
     {
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
         Ok(_) => {
-            match query_as!(
-                PeriodicInspection,
-                "SELECT pi_id, car_id, pi_name, pi_date, pi_nextdate, created_at, updated_at FROM PeriodicInspection WHERE pi_id = ?",
-                pi_id
-            )
+            match query_as::<_, PeriodicInspection>(&format!(
+                "SELECT {} FROM PeriodicInspection WHERE pi_id = ? AND deleted_at IS NULL",
+                PERIODIC_INSPECTION_COLUMNS
+            ))
+            .bind(pi_id)
             .fetch_one(&db_pool)
             .await
             {
-                Ok(periodic_inspection) => (StatusCode::OK, Json(periodic_inspection)).into_response(),
+                Ok(periodic_inspection) => {
+                    AuditEntry::update(
+                        &firebase_user_id,
+                        Some(periodic_inspection.car_id),
+                        "periodic_inspection",
+                        pi_id,
+                        &before,
+                        &periodic_inspection,
+                    )
+                    .log(&db_pool)
+                    .await;
+                    etag::with_etag(StatusCode::OK, periodic_inspection.updated_at, periodic_inspection)
+                }
                 Err(e) => {
                     eprintln!("Failed to fetch periodic inspection after update: {:?}", e);
                     StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -128,17 +237,156 @@ pub async fn update_periodic_inspection(
     }
 }
 
+/// JSON Merge Patch で指定された項目だけを更新する
+pub async fn patch_periodic_inspection(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "PeriodicInspection",
+        "pi_id",
+        id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_periodic_inspection(&db_pool, id).await {
+        Ok(periodic_inspection) => periodic_inspection,
+        Err(e) => {
+            eprintln!("Failed to fetch periodic inspection: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    let periodic_inspection = match patch::apply(&PERIODIC_INSPECTION_PATCH, &before, &patch) {
+        Ok(periodic_inspection) => periodic_inspection,
+        Err(e) => return e.into_response(),
+    };
+
+    // 別の車両に移す場合は移動先の車両にも編集者以上のロールが必要
+    if periodic_inspection.car_id != before.car_id {
+        if let Err(e) = access::require_car_role(
+            &db_pool,
+            &firebase_user_id,
+            periodic_inspection.car_id,
+            CarRole::Editor,
+        )
+        .await
+        {
+            return e.into_response();
+        }
+    }
+
+    match patch::update(
+        &db_pool,
+        &PERIODIC_INSPECTION_PATCH,
+        id,
+        &before,
+        &periodic_inspection,
+        before.updated_at,
+    )
+    .await
+    {
+        Ok(false) => etag::precondition_failed().into_response(),
+        Ok(true) => match fetch_periodic_inspection(&db_pool, id).await {
+            Ok(periodic_inspection) => {
+                AuditEntry::update(
+                    &firebase_user_id,
+                    Some(periodic_inspection.car_id),
+                    "periodic_inspection",
+                    id,
+                    &before,
+                    &periodic_inspection,
+                )
+                .log(&db_pool)
+                .await;
+                etag::with_etag(
+                    StatusCode::OK,
+                    periodic_inspection.updated_at,
+                    periodic_inspection,
+                )
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch periodic inspection after update: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to update periodic inspection: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 定期点検記録をゴミ箱に移す。添付ファイルは復元に備えて残す
 pub async fn delete_periodic_inspection(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
     Path(pi_id): Path<i32>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query!("DELETE FROM PeriodicInspection WHERE pi_id = ?", pi_id)
-        .execute(&db_pool)
-        .await
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "PeriodicInspection",
+        "pi_id",
+        pi_id,
+        CarRole::Editor,
+    )
+    .await
     {
-        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        return e.into_response();
+    }
+
+    let before = match fetch_periodic_inspection(&db_pool, pi_id).await {
+        Ok(periodic_inspection) => periodic_inspection,
+        Err(e) => {
+            eprintln!("Failed to fetch periodic inspection: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE PeriodicInspection SET deleted_at = CURRENT_TIMESTAMP WHERE pi_id = ? AND deleted_at IS NULL AND updated_at <=> ?",
+    )
+    .bind(pi_id)
+    .bind(before.updated_at)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
+        Ok(_) => {
+            AuditEntry::delete(
+                &firebase_user_id,
+                Some(before.car_id),
+                "periodic_inspection",
+                pi_id,
+                &before,
+            )
+            .log(&db_pool)
+            .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
         Err(e) => {
             eprintln!("Failed to delete periodic inspection: {:?}", e);
             StatusCode::INTERNAL_SERVER_ERROR.into_response()
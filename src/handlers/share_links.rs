@@ -0,0 +1,254 @@
+use crate::access;
+use crate::audit::AuditEntry;
+use crate::history;
+use crate::i18n::Lang;
+use crate::middleware::auth::AppError;
+use crate::models::car_share_link::{
+    CarShareLink, CreateShareLinkRequest, CAR_SHARE_LINK_COLUMNS, SHARE_SECTIONS,
+};
+use crate::models::history_report::HistoryReport;
+use crate::models::user_car::CarRole;
+use crate::report;
+use crate::state::AppState;
+use crate::validation::ValidatedJson;
+use axum::{
+    extract::{Extension, Json, Path},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Response},
+};
+use serde_json::json;
+use sqlx::types::Json as SqlJson;
+use sqlx::{query, query_as, MySqlPool};
+use std::sync::Arc;
+use time::OffsetDateTime;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+async fn fetch_share_link(
+    db_pool: &MySqlPool,
+    share_link_id: i32,
+) -> Result<Option<CarShareLink>, sqlx::Error> {
+    query_as::<_, CarShareLink>(&format!(
+        "SELECT {} FROM CarShareLinks WHERE share_link_id = ?",
+        CAR_SHARE_LINK_COLUMNS
+    ))
+    .bind(share_link_id)
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// 車両履歴の公開リンクを発行する
+pub async fn create_share_link(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+    ValidatedJson(req): ValidatedJson<CreateShareLinkRequest>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Owner).await
+    {
+        return e.into_response();
+    }
+
+    let sections = req.sections.unwrap_or_else(|| SHARE_SECTIONS.to_vec());
+    if let Some(expires_at) = req.expires_at {
+        if expires_at <= OffsetDateTime::now_utc() {
+            return AppError::new(
+                StatusCode::BAD_REQUEST,
+                "share/invalid-expiry".to_string(),
+                "expires_at must be in the future.".to_string(),
+            )
+            .into_response();
+        }
+    }
+
+    let token = Uuid::new_v4().simple().to_string();
+
+    match query(
+        "INSERT INTO CarShareLinks (car_id, token, sections, expires_at, created_by) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(car_id)
+    .bind(&token)
+    .bind(SqlJson(&sections))
+    .bind(req.expires_at)
+    .bind(&firebase_user_id)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) => match fetch_share_link(&db_pool, result.last_insert_id() as i32).await {
+            Ok(Some(share_link)) => {
+                AuditEntry::create(
+                    &firebase_user_id,
+                    Some(car_id),
+                    "car_share_link",
+                    share_link.share_link_id,
+                    &share_link,
+                )
+                .log(&db_pool)
+                .await;
+                (StatusCode::CREATED, Json(share_link)).into_response()
+            }
+            Ok(None) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            Err(e) => {
+                eprintln!("Failed to fetch share link after creation: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to create share link: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn get_share_links(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Owner).await
+    {
+        return e.into_response();
+    }
+
+    match query_as::<_, CarShareLink>(&format!(
+        "SELECT {} FROM CarShareLinks WHERE car_id = ? ORDER BY created_at DESC",
+        CAR_SHARE_LINK_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(share_links) => (StatusCode::OK, Json(share_links)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch share links: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 公開リンクを無効にする。リンク自体は履歴として残す
+pub async fn revoke_share_link(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path((car_id, share_link_id)): Path<(i32, i32)>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Owner).await
+    {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE CarShareLinks SET revoked_at = CURRENT_TIMESTAMP WHERE share_link_id = ? AND car_id = ? AND revoked_at IS NULL",
+    )
+    .bind(share_link_id)
+    .bind(car_id)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => StatusCode::NOT_FOUND.into_response(),
+        Ok(_) => {
+            AuditEntry::update(
+                &firebase_user_id,
+                Some(car_id),
+                "car_share_link",
+                share_link_id,
+                &json!({ "revoked": false }),
+                &json!({ "revoked": true }),
+            )
+            .log(&db_pool)
+            .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to revoke share link: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+// トークンから公開レポートを組み立てる。無効・期限切れのリンクは 404 とする
+async fn load_shared_report(
+    state: &Arc<Mutex<AppState>>,
+    token: &str,
+    lang: Lang,
+) -> Result<HistoryReport, Response> {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let share_link = match query_as::<_, CarShareLink>(&format!(
+        "SELECT {} FROM CarShareLinks WHERE token = ? AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > CURRENT_TIMESTAMP)",
+        CAR_SHARE_LINK_COLUMNS
+    ))
+    .bind(token)
+    .fetch_optional(&db_pool)
+    .await
+    {
+        Ok(Some(share_link)) => share_link,
+        Ok(None) => return Err(StatusCode::NOT_FOUND.into_response()),
+        Err(e) => {
+            eprintln!("Failed to fetch share link: {:?}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        }
+    };
+
+    let mut conn = db_pool.acquire().await.map_err(|e| {
+        eprintln!("Failed to acquire connection: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    })?;
+
+    match history::load_car_history(&mut conn, share_link.car_id).await {
+        Ok(Some(history)) => Ok(report::build_history_report(
+            history,
+            &share_link.sections,
+            share_link.expires_at,
+            lang,
+        )),
+        Ok(None) => Err(StatusCode::NOT_FOUND.into_response()),
+        Err(e) => {
+            eprintln!("Failed to load car history: {:?}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+/// 公開リンクのレポートを JSON で返す（認証不要）
+pub async fn get_shared_report(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(token): Path<String>,
+    lang: Lang,
+) -> impl IntoResponse {
+    match load_shared_report(&state, &token, lang).await {
+        Ok(report) => (
+            StatusCode::OK,
+            [(header::CACHE_CONTROL, "no-store")],
+            Json(report),
+        )
+            .into_response(),
+        Err(response) => response,
+    }
+}
+
+/// 公開リンクのレポートを HTML で返す（認証不要）
+pub async fn get_shared_report_html(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(token): Path<String>,
+    lang: Lang,
+) -> impl IntoResponse {
+    match load_shared_report(&state, &token, lang).await {
+        Ok(report) => (
+            StatusCode::OK,
+            [(header::CACHE_CONTROL, "no-store")],
+            Html(report::render_history_report_html(&report, lang)),
+        )
+            .into_response(),
+        Err(response) => response,
+    }
+}
@@ -0,0 +1,998 @@
+use crate::access;
+use crate::audit::AuditEntry;
+use crate::etag;
+use crate::handlers::{cars, user_preferences};
+use crate::middleware::auth::AppError;
+use crate::models::car::{Car, CAR_COLUMNS};
+use crate::models::tire::{
+    TireAlert, TireEvent, TireEventRequest, TireEventType, TireMeasurement, TireSet, TireSetStatus,
+    TIRE_EVENT_COLUMNS, TIRE_MEASUREMENT_COLUMNS, TIRE_SET_COLUMNS, TIRE_SET_PATCH,
+};
+use crate::models::user_car::CarRole;
+use crate::patch;
+use crate::state::AppState;
+use crate::tires;
+use crate::validation::{self, ValidatedJson};
+use axum::{
+    extract::{Extension, Json, Path},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::Datelike;
+use serde_json::Value;
+use sqlx::{query, query_as, query_scalar, MySqlConnection, MySqlPool};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+async fn fetch_tire_set(db_pool: &MySqlPool, id: i32) -> Result<TireSet, sqlx::Error> {
+    query_as::<_, TireSet>(&format!(
+        "SELECT {} FROM TireSets WHERE tire_set_id = ?",
+        TIRE_SET_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(db_pool)
+    .await
+}
+
+async fn fetch_event(conn: &mut MySqlConnection, id: u64) -> Result<TireEvent, sqlx::Error> {
+    query_as::<_, TireEvent>(&format!(
+        "SELECT {} FROM TireEvents WHERE tire_event_id = ?",
+        TIRE_EVENT_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(&mut *conn)
+    .await
+}
+
+/// 車両のゴミ箱にないタイヤセットの状態
+pub(crate) async fn load_car_statuses(
+    db_pool: &MySqlPool,
+    car: &Car,
+) -> Result<Vec<TireSetStatus>, sqlx::Error> {
+    let car_id = car.car_id.unwrap_or_default();
+    let tire_sets = query_as::<_, TireSet>(&format!(
+        "SELECT {} FROM TireSets WHERE car_id = ? AND deleted_at IS NULL ORDER BY tire_set_id",
+        TIRE_SET_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(db_pool)
+    .await?;
+    let events = query_as::<_, TireEvent>(&format!(
+        "SELECT {} FROM TireEvents WHERE car_id = ? ORDER BY tire_event_id",
+        TIRE_EVENT_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(db_pool)
+    .await?;
+    let measurements = query_as::<_, TireMeasurement>(&format!(
+        "SELECT {} FROM TireMeasurements WHERE tire_set_id IN (SELECT tire_set_id FROM TireSets WHERE car_id = ?) ORDER BY measured_date, measurement_id",
+        TIRE_MEASUREMENT_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(db_pool)
+    .await?;
+
+    Ok(tire_sets
+        .into_iter()
+        .map(|tire_set| {
+            let id = tire_set.tire_set_id.unwrap_or_default();
+            let set_events: Vec<&TireEvent> =
+                events.iter().filter(|e| e.tire_set_id == id).collect();
+            let set_measurements: Vec<&TireMeasurement> = measurements
+                .iter()
+                .filter(|m| m.tire_set_id == id)
+                .collect();
+            tires::set_status(tire_set, &set_events, &set_measurements, car.car_mileage)
+        })
+        .collect())
+}
+
+pub async fn create_tire_set(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    ValidatedJson(new_tire_set): ValidatedJson<TireSet>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_car_role(
+        &db_pool,
+        &firebase_user_id,
+        new_tire_set.car_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    match query(
+        "INSERT INTO TireSets (car_id, tire_season, tire_size, tire_brand, tire_model, purchase_date, initial_tread_depth, notes) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(new_tire_set.car_id)
+    .bind(new_tire_set.tire_season)
+    .bind(&new_tire_set.tire_size)
+    .bind(&new_tire_set.tire_brand)
+    .bind(&new_tire_set.tire_model)
+    .bind(&new_tire_set.purchase_date)
+    .bind(new_tire_set.initial_tread_depth)
+    .bind(&new_tire_set.notes)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) => match fetch_tire_set(&db_pool, result.last_insert_id() as i32).await {
+            Ok(tire_set) => {
+                AuditEntry::create(
+                    &firebase_user_id,
+                    Some(tire_set.car_id),
+                    "tire_set",
+                    result.last_insert_id(),
+                    &tire_set,
+                )
+                .log(&db_pool)
+                .await;
+                etag::with_etag(StatusCode::CREATED, tire_set.updated_at, tire_set)
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch tire set after creation: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to create tire set: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// アクセスできる車両のタイヤセット
+pub async fn get_tire_sets(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    match query_as::<_, TireSet>(&format!(
+        "SELECT {} FROM TireSets WHERE deleted_at IS NULL AND car_id IN (SELECT uc.car_id FROM user_car uc JOIN Cars c ON c.car_id = uc.car_id WHERE uc.firebase_user_id = ? AND c.deleted_at IS NULL) ORDER BY car_id, tire_set_id",
+        TIRE_SET_COLUMNS
+    ))
+    .bind(&firebase_user_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(tire_sets) => (StatusCode::OK, Json(tire_sets)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch tire sets: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 車両のタイヤセットと装着・摩耗の状態
+pub async fn get_car_tire_sets(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Viewer).await
+    {
+        return e.into_response();
+    }
+
+    let car = match cars::fetch_car(&db_pool, car_id).await {
+        Ok(car) => car,
+        Err(e) => {
+            eprintln!("Failed to fetch car: {:?}", e);
+            return StatusCode::NOT_FOUND.into_response();
+        }
+    };
+
+    match load_car_statuses(&db_pool, &car).await {
+        Ok(statuses) => (StatusCode::OK, Json(statuses)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch car tire sets: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// アクセスできる車両の履き替えの時期と溝の摩耗の通知。時期はユーザーのタイムゾーンの今日の月で決める
+pub async fn get_tire_alerts(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+    let month = user_preferences::preferences_or_default(&db_pool, &firebase_user_id)
+        .await
+        .today()
+        .month();
+
+    let user_cars = match query_as::<_, Car>(&format!(
+        "SELECT {} FROM Cars WHERE deleted_at IS NULL AND car_id IN (SELECT car_id FROM user_car WHERE firebase_user_id = ?) ORDER BY car_id",
+        CAR_COLUMNS
+    ))
+    .bind(&firebase_user_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(cars) => cars,
+        Err(e) => {
+            eprintln!("Failed to fetch user cars: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let mut alerts: Vec<TireAlert> = Vec::new();
+    for car in &user_cars {
+        match load_car_statuses(&db_pool, car).await {
+            Ok(statuses) => alerts.extend(tires::car_alerts(
+                car.car_id.unwrap_or_default(),
+                &car.car_name,
+                &statuses,
+                month,
+            )),
+            Err(e) => {
+                eprintln!("Failed to fetch car tire sets: {:?}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+    }
+
+    (StatusCode::OK, Json(alerts)).into_response()
+}
+
+pub async fn get_tire_set(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(tire_set_id): Path<i32>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "TireSets",
+        "tire_set_id",
+        tire_set_id,
+        CarRole::Viewer,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    match query_as::<_, TireSet>(&format!(
+        "SELECT {} FROM TireSets WHERE tire_set_id = ? AND deleted_at IS NULL",
+        TIRE_SET_COLUMNS
+    ))
+    .bind(tire_set_id)
+    .fetch_one(&db_pool)
+    .await
+    {
+        Ok(tire_set) => etag::conditional_get(&headers, tire_set.updated_at, tire_set),
+        Err(e) => {
+            eprintln!("Failed to fetch tire set: {:?}", e);
+            StatusCode::NOT_FOUND.into_response()
+        }
+    }
+}
+
+/// タイヤセットを更新する。装着の記録があるので車両は変更できない
+pub async fn update_tire_set(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(tire_set_id): Path<i32>,
+    headers: HeaderMap,
+    ValidatedJson(updated_tire_set): ValidatedJson<TireSet>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "TireSets",
+        "tire_set_id",
+        tire_set_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_tire_set(&db_pool, tire_set_id).await {
+        Ok(tire_set) => tire_set,
+        Err(e) => {
+            eprintln!("Failed to fetch tire set: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if updated_tire_set.car_id != before.car_id {
+        return AppError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "tire/car-immutable".to_string(),
+            "A tire set cannot be moved to another car.".to_string(),
+        )
+        .into_response();
+    }
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE TireSets SET tire_season = ?, tire_size = ?, tire_brand = ?, tire_model = ?, purchase_date = ?, initial_tread_depth = ?, notes = ? WHERE tire_set_id = ? AND updated_at <=> ?",
+    )
+    .bind(updated_tire_set.tire_season)
+    .bind(&updated_tire_set.tire_size)
+    .bind(&updated_tire_set.tire_brand)
+    .bind(&updated_tire_set.tire_model)
+    .bind(&updated_tire_set.purchase_date)
+    .bind(updated_tire_set.initial_tread_depth)
+    .bind(&updated_tire_set.notes)
+    .bind(tire_set_id)
+    .bind(before.updated_at)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
+        Ok(_) => match fetch_tire_set(&db_pool, tire_set_id).await {
+            Ok(tire_set) => {
+                AuditEntry::update(
+                    &firebase_user_id,
+                    Some(tire_set.car_id),
+                    "tire_set",
+                    tire_set_id,
+                    &before,
+                    &tire_set,
+                )
+                .log(&db_pool)
+                .await;
+                etag::with_etag(StatusCode::OK, tire_set.updated_at, tire_set)
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch tire set after update: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to update tire set: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// JSON Merge Patch で指定された項目だけを更新する
+pub async fn patch_tire_set(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "TireSets",
+        "tire_set_id",
+        id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_tire_set(&db_pool, id).await {
+        Ok(tire_set) => tire_set,
+        Err(e) => {
+            eprintln!("Failed to fetch tire set: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    let tire_set = match patch::apply(&TIRE_SET_PATCH, &before, &patch) {
+        Ok(tire_set) => tire_set,
+        Err(e) => return e.into_response(),
+    };
+
+    match patch::update(
+        &db_pool,
+        &TIRE_SET_PATCH,
+        id,
+        &before,
+        &tire_set,
+        before.updated_at,
+    )
+    .await
+    {
+        Ok(false) => etag::precondition_failed().into_response(),
+        Ok(true) => match fetch_tire_set(&db_pool, id).await {
+            Ok(tire_set) => {
+                AuditEntry::update(
+                    &firebase_user_id,
+                    Some(tire_set.car_id),
+                    "tire_set",
+                    id,
+                    &before,
+                    &tire_set,
+                )
+                .log(&db_pool)
+                .await;
+                etag::with_etag(StatusCode::OK, tire_set.updated_at, tire_set)
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch tire set after update: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to update tire set: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// タイヤセットをゴミ箱に移す。装着と測定の記録は復元に備えて残す
+pub async fn delete_tire_set(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(tire_set_id): Path<i32>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "TireSets",
+        "tire_set_id",
+        tire_set_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_tire_set(&db_pool, tire_set_id).await {
+        Ok(tire_set) => tire_set,
+        Err(e) => {
+            eprintln!("Failed to fetch tire set: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE TireSets SET deleted_at = CURRENT_TIMESTAMP WHERE tire_set_id = ? AND deleted_at IS NULL AND updated_at <=> ?",
+    )
+    .bind(tire_set_id)
+    .bind(before.updated_at)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
+        Ok(_) => {
+            AuditEntry::delete(
+                &firebase_user_id,
+                Some(before.car_id),
+                "tire_set",
+                tire_set_id,
+                &before,
+            )
+            .log(&db_pool)
+            .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to delete tire set: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn get_tire_events(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(tire_set_id): Path<i32>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "TireSets",
+        "tire_set_id",
+        tire_set_id,
+        CarRole::Viewer,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    match query_as::<_, TireEvent>(&format!(
+        "SELECT {} FROM TireEvents WHERE tire_set_id = ? ORDER BY tire_event_id",
+        TIRE_EVENT_COLUMNS
+    ))
+    .bind(tire_set_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(events) => (StatusCode::OK, Json(events)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch tire events: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+fn conflict(code: &str, message: &str) -> AppError {
+    AppError::new(StatusCode::CONFLICT, code.to_string(), message.to_string())
+}
+
+// 車両の最後の装着・取り外し
+async fn latest_car_event(
+    conn: &mut MySqlConnection,
+    car_id: i32,
+) -> Result<Option<TireEvent>, sqlx::Error> {
+    query_as::<_, TireEvent>(&format!(
+        "SELECT {} FROM TireEvents WHERE car_id = ? ORDER BY tire_event_id DESC LIMIT 1",
+        TIRE_EVENT_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_optional(&mut *conn)
+    .await
+}
+
+// 車両に装着中のゴミ箱にないタイヤセット
+async fn mounted_set(conn: &mut MySqlConnection, car_id: i32) -> Result<Option<i32>, sqlx::Error> {
+    query_scalar::<_, i32>(
+        "SELECT e.tire_set_id FROM TireEvents e JOIN TireSets s ON s.tire_set_id = e.tire_set_id
+         WHERE e.car_id = ? AND s.deleted_at IS NULL AND e.event_type = 'mount'
+         AND e.tire_event_id = (SELECT MAX(l.tire_event_id) FROM TireEvents l WHERE l.tire_set_id = e.tire_set_id)
+         LIMIT 1",
+    )
+    .bind(car_id)
+    .fetch_optional(&mut *conn)
+    .await
+}
+
+async fn insert_event(
+    conn: &mut MySqlConnection,
+    tire_set_id: i32,
+    car_id: i32,
+    event_type: TireEventType,
+    request: &TireEventRequest,
+) -> Result<TireEvent, sqlx::Error> {
+    let id = query(
+        "INSERT INTO TireEvents (tire_set_id, car_id, event_type, event_date, odometer, notes) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(tire_set_id)
+    .bind(car_id)
+    .bind(event_type)
+    .bind(&request.event_date)
+    .bind(request.odometer)
+    .bind(&request.notes)
+    .execute(&mut *conn)
+    .await?
+    .last_insert_id();
+    fetch_event(conn, id).await
+}
+
+/// 装着・取り外しを記録する。別のセットを装着中に装着すると、同じ日付と走行距離でそのセットを取り外す
+async fn record_events(
+    conn: &mut MySqlConnection,
+    tire_set: &TireSet,
+    event_type: TireEventType,
+    request: &TireEventRequest,
+) -> Result<Result<Vec<TireEvent>, AppError>, sqlx::Error> {
+    let tire_set_id = tire_set.tire_set_id.unwrap_or_default();
+
+    // 同じ車両の記録を順に書き込むため車両の行をロックする
+    query("SELECT car_id FROM Cars WHERE car_id = ? FOR UPDATE")
+        .bind(tire_set.car_id)
+        .execute(&mut *conn)
+        .await?;
+
+    if let Some(last) = latest_car_event(conn, tire_set.car_id).await? {
+        if request.event_date < last.event_date || request.odometer < last.odometer {
+            return Ok(Err(AppError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "tire/out-of-order".to_string(),
+                "The date and odometer must not be before the last tire change.".to_string(),
+            )));
+        }
+    }
+
+    let mounted = mounted_set(conn, tire_set.car_id).await?;
+    let mut events = Vec::new();
+    match event_type {
+        TireEventType::Mount if mounted == Some(tire_set_id) => {
+            return Ok(Err(conflict(
+                "tire/already-mounted",
+                "The tire set is already mounted.",
+            )));
+        }
+        TireEventType::Mount => {
+            if let Some(other) = mounted {
+                events.push(
+                    insert_event(
+                        conn,
+                        other,
+                        tire_set.car_id,
+                        TireEventType::Unmount,
+                        request,
+                    )
+                    .await?,
+                );
+            }
+        }
+        TireEventType::Unmount if mounted != Some(tire_set_id) => {
+            return Ok(Err(conflict(
+                "tire/not-mounted",
+                "The tire set is not mounted.",
+            )));
+        }
+        TireEventType::Unmount => {}
+    }
+    events.push(insert_event(conn, tire_set_id, tire_set.car_id, event_type, request).await?);
+    Ok(Ok(events))
+}
+
+async fn change_tires(
+    state: Arc<Mutex<AppState>>,
+    firebase_user_id: String,
+    tire_set_id: i32,
+    event_type: TireEventType,
+    request: TireEventRequest,
+) -> Response {
+    if let Err(e) = validation::validate(&request) {
+        return e.into_response();
+    }
+
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "TireSets",
+        "tire_set_id",
+        tire_set_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let tire_set = match query_as::<_, TireSet>(&format!(
+        "SELECT {} FROM TireSets WHERE tire_set_id = ? AND deleted_at IS NULL",
+        TIRE_SET_COLUMNS
+    ))
+    .bind(tire_set_id)
+    .fetch_one(&db_pool)
+    .await
+    {
+        Ok(tire_set) => tire_set,
+        Err(e) => {
+            eprintln!("Failed to fetch tire set: {:?}", e);
+            return StatusCode::NOT_FOUND.into_response();
+        }
+    };
+
+    let mut tx = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            eprintln!("Failed to begin transaction: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let events = match record_events(&mut tx, &tire_set, event_type, &request).await {
+        Ok(Ok(events)) => events,
+        Ok(Err(e)) => return e.into_response(),
+        Err(e) => {
+            eprintln!("Failed to record tire change: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    if let Err(e) = tx.commit().await {
+        eprintln!("Failed to commit tire change: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    for event in &events {
+        AuditEntry::create(
+            &firebase_user_id,
+            Some(event.car_id),
+            "tire_event",
+            event.tire_event_id,
+            event,
+        )
+        .log(&db_pool)
+        .await;
+    }
+    (StatusCode::CREATED, Json(events)).into_response()
+}
+
+pub async fn mount_tire_set(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(tire_set_id): Path<i32>,
+    Json(request): Json<TireEventRequest>,
+) -> impl IntoResponse {
+    change_tires(
+        state,
+        firebase_user_id,
+        tire_set_id,
+        TireEventType::Mount,
+        request,
+    )
+    .await
+}
+
+pub async fn unmount_tire_set(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(tire_set_id): Path<i32>,
+    Json(request): Json<TireEventRequest>,
+) -> impl IntoResponse {
+    change_tires(
+        state,
+        firebase_user_id,
+        tire_set_id,
+        TireEventType::Unmount,
+        request,
+    )
+    .await
+}
+
+/// 装着・取り外しの記録を取り消す。取り消せるのは車両の最後の記録だけ
+pub async fn delete_tire_event(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path((tire_set_id, tire_event_id)): Path<(i32, i32)>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let car_id = match access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "TireSets",
+        "tire_set_id",
+        tire_set_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        Ok(car_id) => car_id,
+        Err(e) => return e.into_response(),
+    };
+
+    let mut tx = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            eprintln!("Failed to begin transaction: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let latest = match latest_car_event(&mut tx, car_id).await {
+        Ok(latest) => latest,
+        Err(e) => {
+            eprintln!("Failed to fetch tire events: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let Some(event) = latest
+        .filter(|event| event.tire_event_id == tire_event_id && event.tire_set_id == tire_set_id)
+    else {
+        return conflict(
+            "tire/not-latest-event",
+            "Only the car's last tire change can be removed.",
+        )
+        .into_response();
+    };
+
+    if let Err(e) = query("DELETE FROM TireEvents WHERE tire_event_id = ?")
+        .bind(tire_event_id)
+        .execute(&mut *tx)
+        .await
+    {
+        eprintln!("Failed to delete tire event: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    if let Err(e) = tx.commit().await {
+        eprintln!("Failed to commit tire event deletion: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    AuditEntry::delete(
+        &firebase_user_id,
+        Some(car_id),
+        "tire_event",
+        tire_event_id,
+        &event,
+    )
+    .log(&db_pool)
+    .await;
+    StatusCode::NO_CONTENT.into_response()
+}
+
+pub async fn get_tire_measurements(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(tire_set_id): Path<i32>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "TireSets",
+        "tire_set_id",
+        tire_set_id,
+        CarRole::Viewer,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    match query_as::<_, TireMeasurement>(&format!(
+        "SELECT {} FROM TireMeasurements WHERE tire_set_id = ? ORDER BY measured_date, measurement_id",
+        TIRE_MEASUREMENT_COLUMNS
+    ))
+    .bind(tire_set_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(measurements) => (StatusCode::OK, Json(measurements)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch tire measurements: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn create_tire_measurement(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(tire_set_id): Path<i32>,
+    ValidatedJson(mut measurement): ValidatedJson<TireMeasurement>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let car_id = match access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "TireSets",
+        "tire_set_id",
+        tire_set_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        Ok(car_id) => car_id,
+        Err(e) => return e.into_response(),
+    };
+    measurement.tire_set_id = tire_set_id;
+
+    let result = match query(
+        "INSERT INTO TireMeasurements (tire_set_id, measured_date, position, tread_depth) VALUES (?, ?, ?, ?)",
+    )
+    .bind(tire_set_id)
+    .bind(&measurement.measured_date)
+    .bind(measurement.position)
+    .bind(measurement.tread_depth)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Failed to create tire measurement: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    match query_as::<_, TireMeasurement>(&format!(
+        "SELECT {} FROM TireMeasurements WHERE measurement_id = ?",
+        TIRE_MEASUREMENT_COLUMNS
+    ))
+    .bind(result.last_insert_id())
+    .fetch_one(&db_pool)
+    .await
+    {
+        Ok(measurement) => {
+            AuditEntry::create(
+                &firebase_user_id,
+                Some(car_id),
+                "tire_measurement",
+                result.last_insert_id(),
+                &measurement,
+            )
+            .log(&db_pool)
+            .await;
+            (StatusCode::CREATED, Json(measurement)).into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to fetch tire measurement after creation: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn delete_tire_measurement(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path((tire_set_id, measurement_id)): Path<(i32, i32)>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let car_id = match access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "TireSets",
+        "tire_set_id",
+        tire_set_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        Ok(car_id) => car_id,
+        Err(e) => return e.into_response(),
+    };
+
+    let before = match query_as::<_, TireMeasurement>(&format!(
+        "SELECT {} FROM TireMeasurements WHERE measurement_id = ? AND tire_set_id = ?",
+        TIRE_MEASUREMENT_COLUMNS
+    ))
+    .bind(measurement_id)
+    .bind(tire_set_id)
+    .fetch_optional(&db_pool)
+    .await
+    {
+        Ok(Some(measurement)) => measurement,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch tire measurement: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    match query("DELETE FROM TireMeasurements WHERE measurement_id = ?")
+        .bind(measurement_id)
+        .execute(&db_pool)
+        .await
+    {
+        Ok(_) => {
+            AuditEntry::delete(
+                &firebase_user_id,
+                Some(car_id),
+                "tire_measurement",
+                measurement_id,
+                &before,
+            )
+            .log(&db_pool)
+            .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to delete tire measurement: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use crate::audit::AuditEntry;
+use crate::i18n::Lang;
+use crate::middleware::auth::AppError;
+use crate::models::audit_log::AuditAction;
+use crate::models::maintenance::MaintenanceType;
+use crate::models::trash::{TrashItem, TrashKind, RECORD_KINDS};
+use crate::models::user_car::CarRole;
+use crate::state::AppState;
+use crate::trash;
+use axum::{
+    extract::{Extension, Json, Path},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use sqlx::{query, query_as, query_scalar, MySqlExecutor, MySqlPool};
+use std::sync::Arc;
+use time::{Duration, OffsetDateTime};
+use tokio::sync::Mutex;
+
+type TrashRow = (i32, i32, String, String, OffsetDateTime);
+
+fn not_found() -> AppError {
+    AppError::new(
+        StatusCode::NOT_FOUND,
+        "trash/not-found".to_string(),
+        "The item was not found in the trash.".to_string(),
+    )
+}
+
+/// ゴミ箱にある車両も含めて、ユーザーの車両に対するロールを確認する
+async fn require_role_including_trash(
+    db_pool: &MySqlPool,
+    firebase_user_id: &str,
+    car_id: i32,
+    required: CarRole,
+) -> Result<(), AppError> {
+    let role: Option<CarRole> =
+        query_scalar("SELECT role FROM user_car WHERE firebase_user_id = ? AND car_id = ?")
+            .bind(firebase_user_id)
+            .bind(car_id)
+            .fetch_optional(db_pool)
+            .await
+            .map_err(|e| {
+                eprintln!("Failed to fetch car role: {:?}", e);
+                AppError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "car/internal-error".to_string(),
+                    "Failed to check car access.".to_string(),
+                )
+            })?;
+
+    match role {
+        Some(role) if role >= required => Ok(()),
+        Some(_) => Err(AppError::new(
+            StatusCode::FORBIDDEN,
+            "car/insufficient-role".to_string(),
+            format!("This operation requires the {} role.", required.as_str()),
+        )),
+        // 車両のメンバーでなければ存在も明かさない
+        None => Err(not_found()),
+    }
+}
+
+/// ゴミ箱から戻したことを監査ログに残す
+async fn log_restore<'e>(
+    executor: impl MySqlExecutor<'e>,
+    firebase_user_id: &str,
+    kind: TrashKind,
+    id: i32,
+    car_id: i32,
+) {
+    AuditEntry::new::<()>(
+        firebase_user_id,
+        Some(car_id),
+        kind.as_str(),
+        id,
+        AuditAction::Restore,
+        None,
+        None,
+    )
+    .log(executor)
+    .await;
+}
+
+/// ゴミ箱の中身を削除日時の新しい順に返す。
+/// 車両はオーナー、記録は編集者以上のロールを持つものだけを返し、ゴミ箱にある車両の記録は車両にまとめる
+pub async fn get_trash(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    lang: Lang,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+    let retention = Duration::days(trash::retention_days());
+
+    let mut items = Vec::new();
+
+    let cars: Vec<TrashRow> = match query_as(
+        "SELECT c.car_id, c.car_id, c.car_name, c.car_name, c.deleted_at FROM Cars c
+         JOIN user_car uc ON uc.car_id = c.car_id
+         WHERE uc.firebase_user_id = ? AND uc.role = 'owner' AND c.deleted_at IS NOT NULL",
+    )
+    .bind(&firebase_user_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(cars) => cars,
+        Err(e) => {
+            eprintln!("Failed to fetch trashed cars: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    items.extend(cars.into_iter().map(|row| (TrashKind::Car, row)));
+
+    for kind in RECORD_KINDS {
+        let records: Vec<TrashRow> = match query_as(&format!(
+            "SELECT t.{}, t.car_id, c.car_name, {}, t.deleted_at FROM {} t
+             JOIN Cars c ON c.car_id = t.car_id
+             JOIN user_car uc ON uc.car_id = t.car_id
+             WHERE uc.firebase_user_id = ? AND uc.role IN ('owner', 'editor')
+             AND t.deleted_at IS NOT NULL AND c.deleted_at IS NULL",
+            kind.id_column(),
+            kind.label_column(),
+            kind.table()
+        ))
+        .bind(&firebase_user_id)
+        .fetch_all(&db_pool)
+        .await
+        {
+            Ok(records) => records,
+            Err(e) => {
+                eprintln!("Failed to fetch trashed {}: {:?}", kind.table(), e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+        items.extend(records.into_iter().map(|row| (kind, row)));
+    }
+
+    let mut items: Vec<TrashItem> = items
+        .into_iter()
+        .map(
+            |(kind, (id, car_id, car_name, label, deleted_at))| TrashItem {
+                kind,
+                id,
+                car_id,
+                car_name,
+                label: match kind {
+                    TrashKind::Maintenance => MaintenanceType::from_name(&label)
+                        .map_or(label, |maint_type| maint_type.label(lang).to_string()),
+                    _ => label,
+                },
+                deleted_at,
+                purge_at: deleted_at + retention,
+            },
+        )
+        .collect();
+    items.sort_by_key(|item| std::cmp::Reverse(item.deleted_at));
+
+    (StatusCode::OK, Json(items)).into_response()
+}
+
+/// ゴミ箱から車両または記録を戻す
+pub async fn restore_trash_item(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path((kind, id)): Path<(TrashKind, i32)>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if kind == TrashKind::Car {
+        if let Err(e) =
+            require_role_including_trash(&db_pool, &firebase_user_id, id, CarRole::Owner).await
+        {
+            return e.into_response();
+        }
+
+        let mut tx = match db_pool.begin().await {
+            Ok(tx) => tx,
+            Err(e) => {
+                eprintln!("Failed to start transaction: {:?}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+
+        return match trash::restore_car(&mut tx, id).await {
+            Ok(true) => {
+                log_restore(&mut *tx, &firebase_user_id, kind, id, id).await;
+                match tx.commit().await {
+                    Ok(_) => StatusCode::NO_CONTENT.into_response(),
+                    Err(e) => {
+                        eprintln!("Failed to commit transaction: {:?}", e);
+                        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                    }
+                }
+            }
+            Ok(false) => {
+                tx.rollback().await.unwrap();
+                not_found().into_response()
+            }
+            Err(e) => {
+                tx.rollback().await.unwrap();
+                eprintln!("Failed to restore car: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        };
+    }
+
+    let record: Option<(i32, Option<OffsetDateTime>)> = match query_as(&format!(
+        "SELECT t.car_id, c.deleted_at FROM {} t
+         JOIN Cars c ON c.car_id = t.car_id
+         WHERE t.{} = ? AND t.deleted_at IS NOT NULL",
+        kind.table(),
+        kind.id_column()
+    ))
+    .bind(id)
+    .fetch_optional(&db_pool)
+    .await
+    {
+        Ok(record) => record,
+        Err(e) => {
+            eprintln!("Failed to fetch trashed record: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let Some((car_id, car_deleted_at)) = record else {
+        return not_found().into_response();
+    };
+
+    if let Err(e) =
+        require_role_including_trash(&db_pool, &firebase_user_id, car_id, CarRole::Editor).await
+    {
+        return e.into_response();
+    }
+
+    if car_deleted_at.is_some() {
+        return AppError::new(
+            StatusCode::CONFLICT,
+            "trash/car-in-trash".to_string(),
+            "Restore the car before restoring its records.".to_string(),
+        )
+        .into_response();
+    }
+
+    match query(&format!(
+        "UPDATE {} SET deleted_at = NULL WHERE {} = ? AND deleted_at IS NOT NULL",
+        kind.table(),
+        kind.id_column()
+    ))
+    .bind(id)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => not_found().into_response(),
+        Ok(_) => {
+            log_restore(&db_pool, &firebase_user_id, kind, id, car_id).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to restore {}: {:?}", kind.as_str(), e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
@@ -1,20 +1,51 @@
-use crate::models::tuning::Tuning;
+use crate::access;
+use crate::audit::AuditEntry;
+use crate::etag;
+use crate::middleware::auth::AppError;
+use crate::models::part::RemoveTuningRequest;
+use crate::models::tuning::{Tuning, TUNING_COLUMNS, TUNING_PATCH};
+use crate::models::user_car::CarRole;
+use crate::patch;
 use crate::state::AppState;
+use crate::validation::{self, UpdateJson, ValidatedJson};
 use axum::{
     extract::{Extension, Json, Path},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
 };
-use sqlx::{query, query_as};
+use serde_json::Value;
+use sqlx::{query, query_as, MySqlConnection, MySqlPool};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+async fn fetch_tuning(db_pool: &MySqlPool, id: i32) -> Result<Tuning, sqlx::Error> {
+    query_as::<_, Tuning>(&format!(
+        "SELECT {} FROM Tunings WHERE tuning_id = ?",
+        TUNING_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(db_pool)
+    .await
+}
+
 pub async fn create_tuning(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
-    Json(new_tuning): Json<Tuning>,
+    Extension(firebase_user_id): Extension<String>,
+    ValidatedJson(new_tuning): ValidatedJson<Tuning>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
+    if let Err(e) = access::require_car_role(
+        &db_pool,
+        &firebase_user_id,
+        new_tuning.car_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
     match query!(
         "INSERT INTO Tunings (car_id, tuning_name, tuning_date, tuning_description) VALUES (?, ?, ?, ?)",
         new_tuning.car_id,
@@ -25,16 +56,27 @@ pub async fn create_tuning(
     .execute(&db_pool)
     .await
     {
-        Ok(_) => {
-            match query_as!(
-                Tuning,
-                "SELECT tuning_id, car_id, tuning_name, tuning_date, tuning_description, created_at, updated_at FROM Tunings WHERE car_id = ?",
-                new_tuning.car_id
-            )
+        Ok(result) => {
+            match query_as::<_, Tuning>(&format!(
+                "SELECT {} FROM Tunings WHERE tuning_id = ? AND deleted_at IS NULL",
+                TUNING_COLUMNS
+            ))
+            .bind(result.last_insert_id())
             .fetch_one(&db_pool)
             .await
             {
-                Ok(tuning) => (StatusCode::CREATED, Json(tuning)).into_response(),
+                Ok(tuning) => {
+                    AuditEntry::create(
+                        &firebase_user_id,
+                        Some(tuning.car_id),
+                        "tuning",
+                        result.last_insert_id(),
+                        &tuning,
+                    )
+                    .log(&db_pool)
+                    .await;
+                    etag::with_etag(StatusCode::CREATED, tuning.updated_at, tuning)
+                }
                 Err(e) => {
                     eprintln!("Failed to fetch tuning after creation: {:?}", e);
                     StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -51,10 +93,10 @@ pub async fn create_tuning(
 pub async fn get_tunings(Extension(state): Extension<Arc<Mutex<AppState>>>) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query_as!(
-        Tuning,
-        "SELECT tuning_id, car_id, tuning_name, tuning_date as 'tuning_date: _', tuning_description, created_at, updated_at FROM Tunings"
-    )
+    match query_as::<_, Tuning>(&format!(
+        "SELECT {} FROM Tunings WHERE deleted_at IS NULL",
+        TUNING_COLUMNS
+    ))
     .fetch_all(&db_pool)
     .await
     {
@@ -69,18 +111,19 @@ pub async fn get_tunings(Extension(state): Extension<Arc<Mutex<AppState>>>) -> i
 pub async fn get_tuning(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
     Path(id): Path<i32>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query_as!(
-        Tuning,
-        "SELECT tuning_id, car_id, tuning_name, tuning_date as 'tuning_date: _', tuning_description, created_at, updated_at FROM Tunings WHERE tuning_id = ?",
-        id
-    )
+    match query_as::<_, Tuning>(&format!(
+        "SELECT {} FROM Tunings WHERE tuning_id = ? AND deleted_at IS NULL",
+        TUNING_COLUMNS
+    ))
+    .bind(id)
     .fetch_one(&db_pool)
     .await
     {
-        Ok(tuning) => (StatusCode::OK, Json(tuning)).into_response(),
+        Ok(tuning) => etag::conditional_get(&headers, tuning.updated_at, tuning),
         Err(e) => {
             eprintln!("Failed to fetch tuning: {:?}", e);
             StatusCode::NOT_FOUND.into_response()
@@ -90,32 +133,88 @@ pub async fn get_tuning(
 
 pub async fn update_tuning(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
     Path(id): Path<i32>,
-    Json(updated_tuning): Json<Tuning>,
+    headers: HeaderMap,
+    UpdateJson(updated_tuning): UpdateJson<Tuning>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query!(
-        "UPDATE Tunings SET car_id = ?, tuning_name = ?, tuning_date = ?, tuning_description = ? WHERE tuning_id = ?",
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "Tunings",
+        "tuning_id",
+        id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    if let Err(e) = access::require_car_role(
+        &db_pool,
+        &firebase_user_id,
         updated_tuning.car_id,
-        updated_tuning.tuning_name,
-        updated_tuning.tuning_date,
-        updated_tuning.tuning_description,
-        id
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_tuning(&db_pool, id).await {
+        Ok(tuning) => tuning,
+        Err(e) => {
+            eprintln!("Failed to fetch tuning: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    if let Err(e) = validation::validate_changes(&before, &updated_tuning) {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE Tunings SET car_id = ?, tuning_name = ?, tuning_date = ?, tuning_description = ? WHERE tuning_id = ? AND updated_at <=> ?",
     )
+    .bind(updated_tuning.car_id)
+    .bind(&updated_tuning.tuning_name)
+    .bind(&updated_tuning.tuning_date)
+    .bind(&updated_tuning.tuning_description)
+    .bind(id)
+    .bind(before.updated_at)
     .execute(&db_pool)
     .await
     {
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
         Ok(_) => {
-            match query_as!(
-                Tuning,
-                "SELECT tuning_id, car_id, tuning_name, tuning_date, tuning_description, created_at, updated_at FROM Tunings WHERE tuning_id = ?",
-                id
-            )
+            match query_as::<_, Tuning>(&format!(
+                "SELECT {} FROM Tunings WHERE tuning_id = ? AND deleted_at IS NULL",
+                TUNING_COLUMNS
+            ))
+            .bind(id)
             .fetch_one(&db_pool)
             .await
             {
-                Ok(tuning) => (StatusCode::OK, Json(tuning)).into_response(),
+                Ok(tuning) => {
+                    AuditEntry::update(
+                        &firebase_user_id,
+                        Some(tuning.car_id),
+                        "tuning",
+                        id,
+                        &before,
+                        &tuning,
+                    )
+                    .log(&db_pool)
+                    .await;
+                    etag::with_etag(StatusCode::OK, tuning.updated_at, tuning)
+                }
                 Err(e) => {
                     eprintln!("Failed to fetch tuning after update: {:?}", e);
                     StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -129,20 +228,282 @@ pub async fn update_tuning(
     }
 }
 
+/// JSON Merge Patch で指定された項目だけを更新する
+pub async fn patch_tuning(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "Tunings",
+        "tuning_id",
+        id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_tuning(&db_pool, id).await {
+        Ok(tuning) => tuning,
+        Err(e) => {
+            eprintln!("Failed to fetch tuning: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    let tuning = match patch::apply(&TUNING_PATCH, &before, &patch) {
+        Ok(tuning) => tuning,
+        Err(e) => return e.into_response(),
+    };
+
+    // 別の車両に移す場合は移動先の車両にも編集者以上のロールが必要
+    if tuning.car_id != before.car_id {
+        if let Err(e) =
+            access::require_car_role(&db_pool, &firebase_user_id, tuning.car_id, CarRole::Editor)
+                .await
+        {
+            return e.into_response();
+        }
+    }
+
+    match patch::update(
+        &db_pool,
+        &TUNING_PATCH,
+        id,
+        &before,
+        &tuning,
+        before.updated_at,
+    )
+    .await
+    {
+        Ok(false) => etag::precondition_failed().into_response(),
+        Ok(true) => match fetch_tuning(&db_pool, id).await {
+            Ok(tuning) => {
+                AuditEntry::update(
+                    &firebase_user_id,
+                    Some(tuning.car_id),
+                    "tuning",
+                    id,
+                    &before,
+                    &tuning,
+                )
+                .log(&db_pool)
+                .await;
+                etag::with_etag(StatusCode::OK, tuning.updated_at, tuning)
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch tuning after update: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to update tuning: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// カスタム記録をゴミ箱に移す。添付ファイルは復元に備えて残す
 pub async fn delete_tuning(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
     Path(id): Path<i32>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query!("DELETE FROM Tunings WHERE tuning_id = ?", id)
-        .execute(&db_pool)
-        .await
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "Tunings",
+        "tuning_id",
+        id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_tuning(&db_pool, id).await {
+        Ok(tuning) => tuning,
+        Err(e) => {
+            eprintln!("Failed to fetch tuning: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE Tunings SET deleted_at = CURRENT_TIMESTAMP WHERE tuning_id = ? AND deleted_at IS NULL AND updated_at <=> ?",
+    )
+    .bind(id)
+    .bind(before.updated_at)
+    .execute(&db_pool)
+    .await
     {
-        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
+        Ok(_) => {
+            AuditEntry::delete(
+                &firebase_user_id,
+                Some(before.car_id),
+                "tuning",
+                id,
+                &before,
+            )
+            .log(&db_pool)
+            .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
         Err(e) => {
             eprintln!("Failed to delete tuning: {:?}", e);
             StatusCode::INTERNAL_SERVER_ERROR.into_response()
         }
     }
 }
+
+/// カスタムを外して純正に戻す。return_parts が true なら取り付けた部品を在庫に戻す
+pub async fn remove_tuning(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+    ValidatedJson(request): ValidatedJson<RemoveTuningRequest>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "Tunings",
+        "tuning_id",
+        id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_tuning(&db_pool, id).await {
+        Ok(tuning) => tuning,
+        Err(e) => {
+            eprintln!("Failed to fetch tuning: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    if before.tuning_removed_date.is_some() {
+        return AppError::new(
+            StatusCode::CONFLICT,
+            "tuning/already-removed".to_string(),
+            "The tuning has already been removed.".to_string(),
+        )
+        .into_response();
+    }
+    if validation::parse_date(&request.removed_date) < validation::parse_date(&before.tuning_date) {
+        return AppError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "tuning/invalid-removed-date".to_string(),
+            "The removal date must not be before the tuning date.".to_string(),
+        )
+        .into_response();
+    }
+
+    let mut tx = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            eprintln!("Failed to begin transaction: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    match query(
+        "UPDATE Tunings SET tuning_removed_date = ? WHERE tuning_id = ? AND updated_at <=> ?",
+    )
+    .bind(&request.removed_date)
+    .bind(id)
+    .bind(before.updated_at)
+    .execute(&mut *tx)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => {
+            return etag::precondition_failed().into_response()
+        }
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("Failed to remove tuning: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+    if request.return_parts {
+        if let Err(e) = return_tuning_parts(&mut tx, id).await {
+            eprintln!("Failed to return tuning parts: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+    if let Err(e) = tx.commit().await {
+        eprintln!("Failed to commit tuning removal: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    match fetch_tuning(&db_pool, id).await {
+        Ok(tuning) => {
+            AuditEntry::update(
+                &firebase_user_id,
+                Some(tuning.car_id),
+                "tuning",
+                id,
+                &before,
+                &tuning,
+            )
+            .log(&db_pool)
+            .await;
+            etag::with_etag(StatusCode::OK, tuning.updated_at, tuning)
+        }
+        Err(e) => {
+            eprintln!("Failed to fetch tuning after removal: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+// 同じ部品を複数回使っていても 1 行にまとめて戻す。在庫から削除した部品は戻す先がないので戻さない
+async fn return_tuning_parts(
+    conn: &mut MySqlConnection,
+    tuning_id: i32,
+) -> Result<(), sqlx::Error> {
+    query(
+        "UPDATE Parts p JOIN (
+           SELECT part_id, SUM(quantity) AS quantity FROM PartUsages
+           WHERE tuning_id = ? AND returned = FALSE AND part_id IS NOT NULL GROUP BY part_id
+         ) u ON u.part_id = p.part_id
+         SET p.quantity = p.quantity + u.quantity",
+    )
+    .bind(tuning_id)
+    .execute(&mut *conn)
+    .await?;
+    query("UPDATE PartUsages SET returned = TRUE WHERE tuning_id = ? AND returned = FALSE")
+        .bind(tuning_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use crate::audit::AuditEntry;
+use crate::etag;
+use crate::models::user_preferences::{
+    UserPreferences, USER_PREFERENCES_COLUMNS, USER_PREFERENCES_PATCH,
+};
+use crate::patch;
+use crate::state::AppState;
+use crate::validation::ValidatedJson;
+use axum::{
+    extract::{Extension, Json},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+use sqlx::{query, query_as, MySqlPool};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// ユーザーの設定を読み込む。ユーザーが存在しない場合は None
+pub async fn fetch_preferences(
+    db_pool: &MySqlPool,
+    firebase_user_id: &str,
+) -> Result<Option<UserPreferences>, sqlx::Error> {
+    query_as::<_, UserPreferences>(&format!(
+        "SELECT {} FROM Users WHERE firebase_user_id = ?",
+        USER_PREFERENCES_COLUMNS
+    ))
+    .bind(firebase_user_id)
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// 計算結果の単位に使う設定。読み込めない場合は既定の設定にする
+pub async fn preferences_or_default(
+    db_pool: &MySqlPool,
+    firebase_user_id: &str,
+) -> UserPreferences {
+    match fetch_preferences(db_pool, firebase_user_id).await {
+        Ok(preferences) => preferences.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("Failed to fetch user preferences: {:?}", e);
+            UserPreferences::default()
+        }
+    }
+}
+
+pub async fn get_preferences(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    match fetch_preferences(&db_pool, &firebase_user_id).await {
+        Ok(Some(preferences)) => {
+            etag::conditional_get(&headers, preferences.updated_at, preferences)
+        }
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch user preferences: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 設定をまとめて置き換える。省略した項目は既定値に戻す
+pub async fn update_preferences(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    headers: HeaderMap,
+    ValidatedJson(preferences): ValidatedJson<UserPreferences>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let before = match fetch_preferences(&db_pool, &firebase_user_id).await {
+        Ok(Some(preferences)) => preferences,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch user preferences: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE Users SET distance_unit = ?, volume_unit = ?, fuel_economy_unit = ?, currency = ?, time_zone = ?, language = ? WHERE firebase_user_id = ? AND updated_at <=> ?",
+    )
+    .bind(preferences.distance_unit)
+    .bind(preferences.volume_unit)
+    .bind(preferences.fuel_economy_unit)
+    .bind(&preferences.currency)
+    .bind(&preferences.time_zone)
+    .bind(preferences.language)
+    .bind(&firebase_user_id)
+    .bind(before.updated_at)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
+        Ok(_) => respond_updated(&db_pool, &firebase_user_id, &before).await,
+        Err(e) => {
+            eprintln!("Failed to update user preferences: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// JSON Merge Patch で指定された設定だけを変更する
+pub async fn patch_preferences(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let before = match fetch_preferences(&db_pool, &firebase_user_id).await {
+        Ok(Some(preferences)) => preferences,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch user preferences: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    let preferences = match patch::apply(&USER_PREFERENCES_PATCH, &before, &patch) {
+        Ok(preferences) => preferences,
+        Err(e) => return e.into_response(),
+    };
+
+    match patch::update(
+        &db_pool,
+        &USER_PREFERENCES_PATCH,
+        firebase_user_id.clone(),
+        &before,
+        &preferences,
+        before.updated_at,
+    )
+    .await
+    {
+        Ok(false) => etag::precondition_failed().into_response(),
+        Ok(true) => respond_updated(&db_pool, &firebase_user_id, &before).await,
+        Err(e) => {
+            eprintln!("Failed to update user preferences: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+// 更新後の設定を読み直し、監査ログを残して返す
+async fn respond_updated(
+    db_pool: &MySqlPool,
+    firebase_user_id: &str,
+    before: &UserPreferences,
+) -> Response {
+    match fetch_preferences(db_pool, firebase_user_id).await {
+        Ok(Some(preferences)) => {
+            AuditEntry::update(
+                firebase_user_id,
+                None,
+                "user_preferences",
+                firebase_user_id,
+                before,
+                &preferences,
+            )
+            .log(db_pool)
+            .await;
+            etag::with_etag(StatusCode::OK, preferences.updated_at, preferences)
+        }
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch user preferences after update: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
@@ -1,17 +1,24 @@
-use crate::models::user::User;
+use crate::account_deletion;
+use crate::audit::AuditEntry;
+use crate::etag;
+use crate::models::user::{User, USER_COLUMNS, USER_PATCH};
+use crate::patch;
 use crate::state::AppState;
+use crate::validation::{self, UpdateJson, ValidatedJson};
 use axum::{
     extract::{Extension, Json, Path},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
 };
+use serde_json::Value;
 use sqlx::{query, query_as};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
 pub async fn create_user(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
-    Json(new_user): Json<User>,
+    Extension(auth_user_id): Extension<String>,
+    ValidatedJson(new_user): ValidatedJson<User>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
@@ -25,15 +32,20 @@ pub async fn create_user(
     .await
     {
         Ok(_) => {
-            match query_as!(
-                User,
-                "SELECT * FROM Users WHERE user_email = ?",
-                new_user.user_email
-            )
+            match query_as::<_, User>(&format!(
+                "SELECT {} FROM Users WHERE user_email = ?",
+                USER_COLUMNS
+            ))
+            .bind(&new_user.user_email)
             .fetch_one(&db_pool)
             .await
             {
-                Ok(user) => (StatusCode::CREATED, Json(user)).into_response(),
+                Ok(user) => {
+                    AuditEntry::create(&auth_user_id, None, "user", &user.firebase_user_id, &user)
+                        .log(&db_pool)
+                        .await;
+                    (StatusCode::CREATED, Json(user)).into_response()
+                }
                 Err(e) => {
                     eprintln!("Failed to fetch user after creation: {:?}", e);
                     StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -50,7 +62,7 @@ pub async fn create_user(
 pub async fn get_users(Extension(state): Extension<Arc<Mutex<AppState>>>) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query_as!(User, "SELECT * FROM Users")
+    match query_as::<_, User>(&format!("SELECT {} FROM Users", USER_COLUMNS))
         .fetch_all(&db_pool)
         .await
     {
@@ -65,18 +77,19 @@ pub async fn get_users(Extension(state): Extension<Arc<Mutex<AppState>>>) -> imp
 pub async fn get_user(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
     Path(firebase_user_id): Path<String>, // firebase_user_id は String 型
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query_as!(
-        User,
-        "SELECT * FROM Users WHERE firebase_user_id = ?",
-        firebase_user_id
-    )
+    match query_as::<_, User>(&format!(
+        "SELECT {} FROM Users WHERE firebase_user_id = ?",
+        USER_COLUMNS
+    ))
+    .bind(&firebase_user_id)
     .fetch_one(&db_pool)
     .await
     {
-        Ok(user) => (StatusCode::OK, Json(user)).into_response(),
+        Ok(user) => etag::conditional_get(&headers, user.updated_at, user),
         Err(e) => {
             eprintln!("Failed to fetch user: {:?}", e);
             StatusCode::NOT_FOUND.into_response()
@@ -84,28 +97,80 @@ pub async fn get_user(
     }
 }
 
+/// ユーザーを更新する。変更できるのは user_email と user_name だけで、自分以外のユーザーは更新できない
 pub async fn update_user(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(auth_user_id): Extension<String>,
     Path(firebase_user_id): Path<String>, // firebase_user_id は String 型
-    Json(updated_user): Json<User>,
+    headers: HeaderMap,
+    UpdateJson(updated_user): UpdateJson<User>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query!(
-        "UPDATE Users SET user_email = ?, user_name = ?, firebase_user_id = ? WHERE firebase_user_id = ?",
-        updated_user.user_email,
-        updated_user.user_name,
-        updated_user.firebase_user_id,
-        firebase_user_id
+    if auth_user_id != firebase_user_id {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let before = match query_as::<_, User>(&format!(
+        "SELECT {} FROM Users WHERE firebase_user_id = ?",
+        USER_COLUMNS
+    ))
+    .bind(&firebase_user_id)
+    .fetch_one(&db_pool)
+    .await
+    {
+        Ok(user) => user,
+        Err(e) => {
+            eprintln!("Failed to fetch user: {:?}", e);
+            return StatusCode::NOT_FOUND.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    // firebase_user_id は変更しないので検証からも外す
+    let updated_user = User {
+        firebase_user_id: firebase_user_id.clone(),
+        ..updated_user
+    };
+    if let Err(e) = validation::validate_changes(&before, &updated_user) {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE Users SET user_email = ?, user_name = ? WHERE firebase_user_id = ? AND updated_at <=> ?",
     )
+    .bind(&updated_user.user_email)
+    .bind(&updated_user.user_name)
+    .bind(&firebase_user_id)
+    .bind(before.updated_at)
     .execute(&db_pool)
     .await
     {
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
         Ok(_) => {
-            match query_as!(User, "SELECT * FROM Users WHERE firebase_user_id = ?", firebase_user_id)
+            match query_as::<_, User>(&format!(
+        "SELECT {} FROM Users WHERE firebase_user_id = ?",
+        USER_COLUMNS
+    ))
+    .bind(&firebase_user_id)
             .fetch_one(&db_pool)
             .await {
-                Ok(user) => (StatusCode::OK, Json(user)).into_response(),
+                Ok(user) => {
+                    AuditEntry::update(
+                        &auth_user_id,
+                        None,
+                        "user",
+                        &firebase_user_id,
+                        &before,
+                        &user,
+                    )
+                    .log(&db_pool)
+                    .await;
+                    etag::with_etag(StatusCode::OK, user.updated_at, user)
+                }
                 Err(e) => {
                     eprintln!("Failed to fetch user after update: {:?}", e);
                     StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -119,18 +184,125 @@ pub async fn update_user(
     }
 }
 
-pub async fn delete_user(
+/// JSON Merge Patch で指定された項目だけを更新する。自分以外のユーザーは更新できない
+pub async fn patch_user(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(auth_user_id): Extension<String>,
     Path(firebase_user_id): Path<String>, // firebase_user_id は String 型
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
 ) -> impl IntoResponse {
     let db_pool = state.lock().await.db_pool.clone();
 
-    match query!(
-        "DELETE FROM Users WHERE firebase_user_id = ?",
-        firebase_user_id
+    if auth_user_id != firebase_user_id {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let before = match query_as::<_, User>(&format!(
+        "SELECT {} FROM Users WHERE firebase_user_id = ?",
+        USER_COLUMNS
+    ))
+    .bind(&firebase_user_id)
+    .fetch_one(&db_pool)
+    .await
+    {
+        Ok(user) => user,
+        Err(e) => {
+            eprintln!("Failed to fetch user: {:?}", e);
+            return StatusCode::NOT_FOUND.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    let user = match patch::apply(&USER_PATCH, &before, &patch) {
+        Ok(user) => user,
+        Err(e) => return e.into_response(),
+    };
+
+    match patch::update(
+        &db_pool,
+        &USER_PATCH,
+        firebase_user_id.clone(),
+        &before,
+        &user,
+        before.updated_at,
     )
-    .execute(&db_pool)
     .await
+    {
+        Ok(false) => etag::precondition_failed().into_response(),
+        Ok(true) => match query_as::<_, User>(&format!(
+            "SELECT {} FROM Users WHERE firebase_user_id = ?",
+            USER_COLUMNS
+        ))
+        .bind(&firebase_user_id)
+        .fetch_one(&db_pool)
+        .await
+        {
+            Ok(user) => {
+                AuditEntry::update(
+                    &auth_user_id,
+                    None,
+                    "user",
+                    &firebase_user_id,
+                    &before,
+                    &user,
+                )
+                .log(&db_pool)
+                .await;
+                etag::with_etag(StatusCode::OK, user.updated_at, user)
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch user after update: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to update user: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// ユーザーを直ちに削除する。Firebase Authentication のユーザーは削除しない
+pub async fn delete_user(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(auth_user_id): Extension<String>,
+    Path(firebase_user_id): Path<String>, // firebase_user_id は String 型
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let (db_pool, storage) = {
+        let state = state.lock().await;
+        (state.db_pool.clone(), state.storage.clone())
+    };
+
+    // 自分以外のアカウントは削除できない
+    if auth_user_id != firebase_user_id {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let user = match query_as::<_, User>(&format!(
+        "SELECT {} FROM Users WHERE firebase_user_id = ?",
+        USER_COLUMNS
+    ))
+    .bind(&firebase_user_id)
+    .fetch_one(&db_pool)
+    .await
+    {
+        Ok(user) => user,
+        Err(e) => {
+            eprintln!("Failed to fetch user: {:?}", e);
+            return StatusCode::NOT_FOUND.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, user.updated_at) {
+        return e.into_response();
+    }
+
+    match account_deletion::delete_account(&db_pool, &storage, None, &firebase_user_id, false).await
     {
         Ok(_) => StatusCode::NO_CONTENT.into_response(),
         Err(e) => {
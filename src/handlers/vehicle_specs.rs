@@ -0,0 +1,83 @@
+use crate::models::car::Car;
+use crate::models::vehicle_spec::{
+    normalize_model_code, VehicleSpec, VehicleSpecQuery, VEHICLE_SPEC_COLUMNS,
+};
+use crate::state::AppState;
+use axum::{
+    extract::{Extension, Json, Path, Query},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use sqlx::{query_as, MySqlPool};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// 型式でカタログを引く。表記の揺れは normalize_model_code でそろえる
+pub async fn find_spec(
+    db_pool: &MySqlPool,
+    model_code: &str,
+) -> Result<Option<VehicleSpec>, sqlx::Error> {
+    let model_code = normalize_model_code(model_code);
+    if model_code.is_empty() {
+        return Ok(None);
+    }
+    query_as::<_, VehicleSpec>(&format!(
+        "SELECT {} FROM VehicleSpecs WHERE model_code = ?",
+        VEHICLE_SPEC_COLUMNS
+    ))
+    .bind(model_code)
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// 型式がカタログにあれば空欄の諸元を埋める。カタログを読めなくても車両の保存は続ける
+pub async fn fill_specs(db_pool: &MySqlPool, car: &mut Car) {
+    match find_spec(db_pool, &car.carmodelnum).await {
+        Ok(Some(spec)) => spec.fill(car),
+        Ok(None) => {}
+        Err(e) => eprintln!("Failed to look up vehicle spec: {:?}", e),
+    }
+}
+
+/// カタログの一覧。q でメーカー名・車名・型式を絞り込む
+pub async fn get_vehicle_specs(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Query(params): Query<VehicleSpecQuery>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let pattern = format!("%{}%", params.q.as_deref().unwrap_or_default().trim());
+    match query_as::<_, VehicleSpec>(&format!(
+        "SELECT {} FROM VehicleSpecs WHERE maker LIKE ? OR model LIKE ? OR model_code LIKE ? ORDER BY maker, model, year_from",
+        VEHICLE_SPEC_COLUMNS
+    ))
+    .bind(&pattern)
+    .bind(&pattern)
+    .bind(&pattern)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(specs) => (StatusCode::OK, Json(specs)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch vehicle specs: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 型式の諸元を返す。`DAA-ZVW30` のような識別記号つきの型式でも引ける
+pub async fn get_vehicle_spec(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(model_code): Path<String>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    match find_spec(&db_pool, &model_code).await {
+        Ok(Some(spec)) => (StatusCode::OK, Json(spec)).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch vehicle spec: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
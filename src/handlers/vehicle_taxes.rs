@@ -0,0 +1,622 @@
+use crate::access;
+use crate::audit::AuditEntry;
+use crate::etag;
+use crate::exchange_rates::BASE_CURRENCY;
+use crate::handlers::{cars, user_preferences};
+use crate::middleware::auth::AppError;
+use crate::models::user_car::CarRole;
+use crate::models::vehicle_tax::{
+    PaymentStatus, TaxDue, TaxDueQuery, TaxEstimate, TaxEstimateQuery, TaxType, VehicleTax,
+    VEHICLE_TAX_COLUMNS, VEHICLE_TAX_PATCH,
+};
+use crate::patch;
+use crate::state::AppState;
+use crate::validation::{self, ValidatedJson};
+use crate::vehicle_tax::{self as tax, TaxError};
+use axum::{
+    extract::{Extension, Json, Path, Query},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use serde_json::Value;
+use sqlx::{query, query_as, query_scalar, MySqlPool};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+async fn fetch_tax(db_pool: &MySqlPool, id: i32) -> Result<VehicleTax, sqlx::Error> {
+    query_as::<_, VehicleTax>(&format!(
+        "SELECT {} FROM VehicleTaxes WHERE tax_id = ?",
+        VEHICLE_TAX_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(db_pool)
+    .await
+}
+
+// 重量税は車検のときに納めるので、最新の定期点検の次回予定日を納期限にする
+async fn next_inspection_date(
+    db_pool: &MySqlPool,
+    car_id: i32,
+) -> Result<Option<String>, sqlx::Error> {
+    query_scalar::<_, Option<String>>(
+        "SELECT MAX(pi_nextdate) FROM PeriodicInspection WHERE car_id = ? AND deleted_at IS NULL",
+    )
+    .bind(car_id)
+    .fetch_one(db_pool)
+    .await
+}
+
+fn tax_error(e: TaxError) -> AppError {
+    AppError::new(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        e.code.to_string(),
+        e.message.to_string(),
+    )
+}
+
+fn internal_error(e: sqlx::Error) -> AppError {
+    eprintln!("Failed to complete vehicle tax: {:?}", e);
+    AppError::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "tax/internal-error".to_string(),
+        "Failed to calculate the tax.".to_string(),
+    )
+}
+
+/// 省略された納期限と税額を補い、納付日を納付の状態に合わせる
+async fn complete_tax(
+    db_pool: &MySqlPool,
+    firebase_user_id: &str,
+    vehicle_tax: &mut VehicleTax,
+) -> Result<(), AppError> {
+    if vehicle_tax.due_date.is_none() {
+        vehicle_tax.due_date = match vehicle_tax.tax_type {
+            TaxType::WeightTax => next_inspection_date(db_pool, vehicle_tax.car_id)
+                .await
+                .map_err(internal_error)?,
+            TaxType::AutomobileTax | TaxType::LightVehicleTax => {
+                Some(tax::annual_due_date(vehicle_tax.fiscal_year))
+            }
+        };
+    }
+    let Some(due_date) = vehicle_tax
+        .due_date
+        .as_deref()
+        .and_then(validation::parse_date)
+    else {
+        return Err(AppError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "tax/missing-due-date".to_string(),
+            "Set the due date or the car's next inspection date.".to_string(),
+        ));
+    };
+
+    if vehicle_tax.tax_amount.is_none() {
+        let car = cars::fetch_car(db_pool, vehicle_tax.car_id)
+            .await
+            .map_err(internal_error)?;
+        let assessment = tax::assess(
+            &car,
+            vehicle_tax.tax_type,
+            vehicle_tax.fiscal_year,
+            due_date,
+        )
+        .map_err(tax_error)?;
+        vehicle_tax.tax_amount = Some(BigDecimal::from(assessment.amount));
+        vehicle_tax.tax_currency = BASE_CURRENCY.to_string();
+    }
+
+    match vehicle_tax.payment_status {
+        PaymentStatus::Paid if vehicle_tax.paid_date.is_none() => {
+            let today = user_preferences::preferences_or_default(db_pool, firebase_user_id)
+                .await
+                .today();
+            vehicle_tax.paid_date = Some(today.format("%Y-%m-%d").to_string());
+        }
+        PaymentStatus::Paid => {}
+        PaymentStatus::Unpaid => vehicle_tax.paid_date = None,
+    }
+    Ok(())
+}
+
+pub async fn create_vehicle_tax(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    ValidatedJson(mut new_tax): ValidatedJson<VehicleTax>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, new_tax.car_id, CarRole::Editor).await
+    {
+        return e.into_response();
+    }
+
+    if let Err(e) = complete_tax(&db_pool, &firebase_user_id, &mut new_tax).await {
+        return e.into_response();
+    }
+
+    match query(
+        "INSERT INTO VehicleTaxes (car_id, tax_type, fiscal_year, due_date, tax_amount, tax_currency, payment_status, paid_date, notes) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(new_tax.car_id)
+    .bind(new_tax.tax_type)
+    .bind(new_tax.fiscal_year)
+    .bind(&new_tax.due_date)
+    .bind(&new_tax.tax_amount)
+    .bind(&new_tax.tax_currency)
+    .bind(new_tax.payment_status)
+    .bind(&new_tax.paid_date)
+    .bind(&new_tax.notes)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) => match fetch_tax(&db_pool, result.last_insert_id() as i32).await {
+            Ok(vehicle_tax) => {
+                AuditEntry::create(
+                    &firebase_user_id,
+                    Some(vehicle_tax.car_id),
+                    "vehicle_tax",
+                    result.last_insert_id(),
+                    &vehicle_tax,
+                )
+                .log(&db_pool)
+                .await;
+                etag::with_etag(StatusCode::CREATED, vehicle_tax.updated_at, vehicle_tax)
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch vehicle tax after creation: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to create vehicle tax: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// アクセスできる車両の税
+pub async fn get_vehicle_taxes(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    match query_as::<_, VehicleTax>(&format!(
+        "SELECT {} FROM VehicleTaxes WHERE deleted_at IS NULL AND car_id IN (SELECT uc.car_id FROM user_car uc JOIN Cars c ON c.car_id = uc.car_id WHERE uc.firebase_user_id = ? AND c.deleted_at IS NULL) ORDER BY car_id, due_date, tax_id",
+        VEHICLE_TAX_COLUMNS
+    ))
+    .bind(&firebase_user_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(taxes) => (StatusCode::OK, Json(taxes)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch vehicle taxes: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn get_car_vehicle_taxes(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Viewer).await
+    {
+        return e.into_response();
+    }
+
+    match query_as::<_, VehicleTax>(&format!(
+        "SELECT {} FROM VehicleTaxes WHERE car_id = ? AND deleted_at IS NULL ORDER BY due_date, tax_id",
+        VEHICLE_TAX_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(taxes) => (StatusCode::OK, Json(taxes)).into_response(),
+        Err(e) => {
+            eprintln!("Failed to fetch car vehicle taxes: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 車両の諸元から年度の自動車税（軽自動車税）と次回の車検の重量税を見積もる。記録は作らない
+pub async fn get_car_tax_estimate(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(car_id): Path<i32>,
+    Query(params): Query<TaxEstimateQuery>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) =
+        access::require_car_role(&db_pool, &firebase_user_id, car_id, CarRole::Viewer).await
+    {
+        return e.into_response();
+    }
+
+    let car = match cars::fetch_car(&db_pool, car_id).await {
+        Ok(car) => car,
+        Err(e) => {
+            eprintln!("Failed to fetch car: {:?}", e);
+            return StatusCode::NOT_FOUND.into_response();
+        }
+    };
+    let inspection_date = match next_inspection_date(&db_pool, car_id).await {
+        Ok(date) => date,
+        Err(e) => {
+            eprintln!("Failed to fetch next inspection date: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let fiscal_year = match params.fiscal_year {
+        Some(fiscal_year) => fiscal_year,
+        None => {
+            let today = user_preferences::preferences_or_default(&db_pool, &firebase_user_id)
+                .await
+                .today();
+            tax::current_fiscal_year(today)
+        }
+    };
+
+    let annual = match tax::annual_tax_type(&car) {
+        Ok(tax_type) => TaxEstimate::new(
+            tax_type,
+            fiscal_year,
+            Some(tax::annual_due_date(fiscal_year)),
+            tax::annual_tax(&car, tax_type, fiscal_year),
+        ),
+        Err(e) => TaxEstimate::new(TaxType::AutomobileTax, fiscal_year, None, Err(e)),
+    };
+    let weight = match inspection_date.as_deref().and_then(validation::parse_date) {
+        Some(date) => TaxEstimate::new(
+            TaxType::WeightTax,
+            tax::fiscal_year_of(date),
+            inspection_date.clone(),
+            tax::weight_tax(&car, date),
+        ),
+        None => TaxEstimate::new(
+            TaxType::WeightTax,
+            fiscal_year,
+            None,
+            Err(TaxError {
+                code: "tax/missing-due-date",
+                message: "Set the due date or the car's next inspection date.",
+            }),
+        ),
+    };
+
+    (StatusCode::OK, Json(vec![annual, weight])).into_response()
+}
+
+/// 納期限が近い未納の税と納期限を過ぎた未納の税。日数はユーザーのタイムゾーンの今日から数える
+pub async fn get_vehicle_taxes_due(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Query(params): Query<TaxDueQuery>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+    let today: NaiveDate = user_preferences::preferences_or_default(&db_pool, &firebase_user_id)
+        .await
+        .today();
+
+    let taxes = match query_as::<_, VehicleTax>(&format!(
+        "SELECT {} FROM VehicleTaxes WHERE deleted_at IS NULL AND payment_status = 'unpaid' AND car_id IN (SELECT uc.car_id FROM user_car uc JOIN Cars c ON c.car_id = uc.car_id WHERE uc.firebase_user_id = ? AND c.deleted_at IS NULL) ORDER BY due_date, tax_id",
+        VEHICLE_TAX_COLUMNS
+    ))
+    .bind(&firebase_user_id)
+    .fetch_all(&db_pool)
+    .await
+    {
+        Ok(taxes) => taxes,
+        Err(e) => {
+            eprintln!("Failed to fetch vehicle taxes: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let due: Vec<TaxDue> = taxes
+        .into_iter()
+        .filter_map(|vehicle_tax| TaxDue::new(vehicle_tax, today))
+        .filter(|due| due.days_until_due <= params.within_days)
+        .collect();
+
+    (StatusCode::OK, Json(due)).into_response()
+}
+
+pub async fn get_vehicle_tax(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(tax_id): Path<i32>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "VehicleTaxes",
+        "tax_id",
+        tax_id,
+        CarRole::Viewer,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    match query_as::<_, VehicleTax>(&format!(
+        "SELECT {} FROM VehicleTaxes WHERE tax_id = ? AND deleted_at IS NULL",
+        VEHICLE_TAX_COLUMNS
+    ))
+    .bind(tax_id)
+    .fetch_one(&db_pool)
+    .await
+    {
+        Ok(vehicle_tax) => etag::conditional_get(&headers, vehicle_tax.updated_at, vehicle_tax),
+        Err(e) => {
+            eprintln!("Failed to fetch vehicle tax: {:?}", e);
+            StatusCode::NOT_FOUND.into_response()
+        }
+    }
+}
+
+pub async fn update_vehicle_tax(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(tax_id): Path<i32>,
+    headers: HeaderMap,
+    ValidatedJson(mut updated_tax): ValidatedJson<VehicleTax>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "VehicleTaxes",
+        "tax_id",
+        tax_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    if let Err(e) = access::require_car_role(
+        &db_pool,
+        &firebase_user_id,
+        updated_tax.car_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_tax(&db_pool, tax_id).await {
+        Ok(vehicle_tax) => vehicle_tax,
+        Err(e) => {
+            eprintln!("Failed to fetch vehicle tax: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    if let Err(e) = complete_tax(&db_pool, &firebase_user_id, &mut updated_tax).await {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE VehicleTaxes SET car_id = ?, tax_type = ?, fiscal_year = ?, due_date = ?, tax_amount = ?, tax_currency = ?, payment_status = ?, paid_date = ?, notes = ? WHERE tax_id = ? AND updated_at <=> ?",
+    )
+    .bind(updated_tax.car_id)
+    .bind(updated_tax.tax_type)
+    .bind(updated_tax.fiscal_year)
+    .bind(&updated_tax.due_date)
+    .bind(&updated_tax.tax_amount)
+    .bind(&updated_tax.tax_currency)
+    .bind(updated_tax.payment_status)
+    .bind(&updated_tax.paid_date)
+    .bind(&updated_tax.notes)
+    .bind(tax_id)
+    .bind(before.updated_at)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
+        Ok(_) => match fetch_tax(&db_pool, tax_id).await {
+            Ok(vehicle_tax) => {
+                AuditEntry::update(
+                    &firebase_user_id,
+                    Some(vehicle_tax.car_id),
+                    "vehicle_tax",
+                    tax_id,
+                    &before,
+                    &vehicle_tax,
+                )
+                .log(&db_pool)
+                .await;
+                etag::with_etag(StatusCode::OK, vehicle_tax.updated_at, vehicle_tax)
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch vehicle tax after update: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to update vehicle tax: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// JSON Merge Patch で指定された項目だけを更新する。納付済みにするだけなら `{"payment_status": "paid"}` でよい
+pub async fn patch_vehicle_tax(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "VehicleTaxes",
+        "tax_id",
+        id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_tax(&db_pool, id).await {
+        Ok(vehicle_tax) => vehicle_tax,
+        Err(e) => {
+            eprintln!("Failed to fetch vehicle tax: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    let mut vehicle_tax = match patch::apply(&VEHICLE_TAX_PATCH, &before, &patch) {
+        Ok(vehicle_tax) => vehicle_tax,
+        Err(e) => return e.into_response(),
+    };
+
+    // 別の車両に移す場合は移動先の車両にも編集者以上のロールが必要
+    if vehicle_tax.car_id != before.car_id {
+        if let Err(e) = access::require_car_role(
+            &db_pool,
+            &firebase_user_id,
+            vehicle_tax.car_id,
+            CarRole::Editor,
+        )
+        .await
+        {
+            return e.into_response();
+        }
+    }
+
+    if let Err(e) = complete_tax(&db_pool, &firebase_user_id, &mut vehicle_tax).await {
+        return e.into_response();
+    }
+
+    match patch::update(
+        &db_pool,
+        &VEHICLE_TAX_PATCH,
+        id,
+        &before,
+        &vehicle_tax,
+        before.updated_at,
+    )
+    .await
+    {
+        Ok(false) => etag::precondition_failed().into_response(),
+        Ok(true) => match fetch_tax(&db_pool, id).await {
+            Ok(vehicle_tax) => {
+                AuditEntry::update(
+                    &firebase_user_id,
+                    Some(vehicle_tax.car_id),
+                    "vehicle_tax",
+                    id,
+                    &before,
+                    &vehicle_tax,
+                )
+                .log(&db_pool)
+                .await;
+                etag::with_etag(StatusCode::OK, vehicle_tax.updated_at, vehicle_tax)
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch vehicle tax after update: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => {
+            eprintln!("Failed to update vehicle tax: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 税の記録をゴミ箱に移す
+pub async fn delete_vehicle_tax(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(firebase_user_id): Extension<String>,
+    Path(tax_id): Path<i32>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let db_pool = state.lock().await.db_pool.clone();
+
+    if let Err(e) = access::require_record_role(
+        &db_pool,
+        &firebase_user_id,
+        "VehicleTaxes",
+        "tax_id",
+        tax_id,
+        CarRole::Editor,
+    )
+    .await
+    {
+        return e.into_response();
+    }
+
+    let before = match fetch_tax(&db_pool, tax_id).await {
+        Ok(vehicle_tax) => vehicle_tax,
+        Err(e) => {
+            eprintln!("Failed to fetch vehicle tax: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if let Err(e) = etag::check_if_match(&headers, before.updated_at) {
+        return e.into_response();
+    }
+
+    match query(
+        "UPDATE VehicleTaxes SET deleted_at = CURRENT_TIMESTAMP WHERE tax_id = ? AND deleted_at IS NULL AND updated_at <=> ?",
+    )
+    .bind(tax_id)
+    .bind(before.updated_at)
+    .execute(&db_pool)
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => etag::precondition_failed().into_response(),
+        Ok(_) => {
+            AuditEntry::delete(
+                &firebase_user_id,
+                Some(before.car_id),
+                "vehicle_tax",
+                tax_id,
+                &before,
+            )
+            .log(&db_pool)
+            .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to delete vehicle tax: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
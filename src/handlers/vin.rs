@@ -0,0 +1,60 @@
+use crate::handlers::vehicle_specs;
+use crate::middleware::auth::AppError;
+use crate::models::vehicle_spec::VehicleSpec;
+use crate::state::AppState;
+use crate::vin::{self, DecodedVin};
+use axum::{
+    extract::{Extension, Json, Query},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+#[derive(Debug, Deserialize)]
+pub struct DecodeVinQuery {
+    pub vin: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DecodeVinResponse {
+    #[serde(flatten)]
+    pub decoded: DecodedVin,
+    /// 車台番号の型式がカタログにあればその諸元
+    pub spec: Option<VehicleSpec>,
+}
+
+/// VIN・車台番号を検証して読み取る。外部のサービスには問い合わせない
+pub async fn decode_vin(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Query(params): Query<DecodeVinQuery>,
+) -> impl IntoResponse {
+    let decoded = match vin::decode(&params.vin) {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            return AppError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                e.code.to_string(),
+                e.message.to_string(),
+            )
+            .into_response();
+        }
+    };
+
+    let spec = match &decoded.model_code {
+        Some(model_code) => {
+            let db_pool = state.lock().await.db_pool.clone();
+            match vehicle_specs::find_spec(&db_pool, model_code).await {
+                Ok(spec) => spec,
+                Err(e) => {
+                    eprintln!("Failed to look up vehicle spec: {:?}", e);
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+
+    (StatusCode::OK, Json(DecodeVinResponse { decoded, spec })).into_response()
+}
@@ -0,0 +1,140 @@
+use crate::models::accident::{Accident, ACCIDENT_COLUMNS};
+use crate::models::car::{Car, CAR_COLUMNS};
+use crate::models::car_history::CarHistory;
+use crate::models::fuel_efficiency::{FuelEfficiency, FUEL_EFFICIENCY_COLUMNS};
+use crate::models::insurance_policy::{InsurancePolicy, INSURANCE_POLICY_COLUMNS};
+use crate::models::maintenance::{Maintenance, MAINTENANCE_COLUMNS};
+use crate::models::part::{PartUsage, PART_USAGE_COLUMNS};
+use crate::models::periodic_inspection::{PeriodicInspection, PERIODIC_INSPECTION_COLUMNS};
+use crate::models::tire::{
+    TireEvent, TireMeasurement, TireSet, TIRE_EVENT_COLUMNS, TIRE_MEASUREMENT_COLUMNS,
+    TIRE_SET_COLUMNS,
+};
+use crate::models::tuning::{Tuning, TUNING_COLUMNS};
+use crate::models::vehicle_tax::{VehicleTax, VEHICLE_TAX_COLUMNS};
+use sqlx::{query_as, MySqlConnection};
+
+/// 車両とゴミ箱にないすべての記録を日付順に読み込む。車両が存在しない場合やゴミ箱にある場合は None
+pub async fn load_car_history(
+    conn: &mut MySqlConnection,
+    car_id: i32,
+) -> Result<Option<CarHistory>, sqlx::Error> {
+    let Some(car) = query_as::<_, Car>(&format!(
+        "SELECT {} FROM Cars WHERE car_id = ? AND deleted_at IS NULL",
+        CAR_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let maintenances = query_as::<_, Maintenance>(&format!(
+        "SELECT {} FROM Maintenances WHERE car_id = ? AND deleted_at IS NULL ORDER BY maint_date, maint_id",
+        MAINTENANCE_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let tunings = query_as::<_, Tuning>(&format!(
+        "SELECT {} FROM Tunings WHERE car_id = ? AND deleted_at IS NULL ORDER BY tuning_date, tuning_id",
+        TUNING_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let fuel_efficiencies = query_as::<_, FuelEfficiency>(&format!(
+        "SELECT {} FROM FuelEfficiencies WHERE car_id = ? AND deleted_at IS NULL ORDER BY fe_date, fe_id",
+        FUEL_EFFICIENCY_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let accidents = query_as::<_, Accident>(&format!(
+        "SELECT {} FROM Accidents WHERE car_id = ? AND deleted_at IS NULL ORDER BY accident_date, accident_id",
+        ACCIDENT_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let insurance_policies = query_as::<_, InsurancePolicy>(&format!(
+        "SELECT {} FROM InsurancePolicies WHERE car_id = ? AND deleted_at IS NULL ORDER BY coverage_start, policy_id",
+        INSURANCE_POLICY_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let vehicle_taxes = query_as::<_, VehicleTax>(&format!(
+        "SELECT {} FROM VehicleTaxes WHERE car_id = ? AND deleted_at IS NULL ORDER BY due_date, tax_id",
+        VEHICLE_TAX_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let periodic_inspections = query_as::<_, PeriodicInspection>(&format!(
+        "SELECT {} FROM PeriodicInspection WHERE car_id = ? AND deleted_at IS NULL ORDER BY pi_date, pi_id",
+        PERIODIC_INSPECTION_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let tire_sets = query_as::<_, TireSet>(&format!(
+        "SELECT {} FROM TireSets WHERE car_id = ? AND deleted_at IS NULL ORDER BY tire_set_id",
+        TIRE_SET_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    // ゴミ箱にあるタイヤセットの記録は含めない
+    let tire_events = query_as::<_, TireEvent>(&format!(
+        "SELECT {} FROM TireEvents WHERE tire_set_id IN (SELECT tire_set_id FROM TireSets WHERE car_id = ? AND deleted_at IS NULL) ORDER BY tire_event_id",
+        TIRE_EVENT_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let tire_measurements = query_as::<_, TireMeasurement>(&format!(
+        "SELECT {} FROM TireMeasurements WHERE tire_set_id IN (SELECT tire_set_id FROM TireSets WHERE car_id = ? AND deleted_at IS NULL) ORDER BY measured_date, measurement_id",
+        TIRE_MEASUREMENT_COLUMNS
+    ))
+    .bind(car_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let part_usages = query_as::<_, PartUsage>(&format!(
+        "SELECT {} FROM PartUsages
+         WHERE maint_id IN (SELECT maint_id FROM Maintenances WHERE car_id = ? AND deleted_at IS NULL)
+         OR tuning_id IN (SELECT tuning_id FROM Tunings WHERE car_id = ? AND deleted_at IS NULL)
+         ORDER BY usage_id",
+        PART_USAGE_COLUMNS
+    ))
+    .bind(car_id)
+    .bind(car_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(Some(CarHistory {
+        car,
+        maintenances,
+        tunings,
+        fuel_efficiencies,
+        accidents,
+        insurance_policies,
+        vehicle_taxes,
+        periodic_inspections,
+        tire_sets,
+        tire_events,
+        tire_measurements,
+        part_usages,
+    }))
+}
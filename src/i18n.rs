@@ -0,0 +1,450 @@
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap},
+};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::convert::Infallible;
+
+/// 応答の言語。Accept-Language で決め、指定がなければ日本語にする
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Lang {
+    #[default]
+    Ja,
+    En,
+}
+
+impl Lang {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Lang::Ja => "ja",
+            Lang::En => "en",
+        }
+    }
+
+    /// `ja-JP` のような言語タグを読む。地域は無視する
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.split(['-', '_']).next()?.trim();
+        if primary.eq_ignore_ascii_case("ja") {
+            Some(Lang::Ja)
+        } else if primary.eq_ignore_ascii_case("en") {
+            Some(Lang::En)
+        } else {
+            None
+        }
+    }
+
+    /// Accept-Language のうち対応している言語で q の最も大きいものを選ぶ
+    pub fn from_accept_language(headers: &HeaderMap) -> Option<Self> {
+        let mut best: Option<(Lang, f32)> = None;
+        for value in headers
+            .get_all(header::ACCEPT_LANGUAGE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+        {
+            let mut params = value.split(';');
+            let Some(lang) = params.next().and_then(Lang::from_tag) else {
+                continue;
+            };
+            let q = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((lang, q));
+            }
+        }
+        best.map(|(lang, _)| lang)
+    }
+}
+
+tokio::task_local! {
+    static LANG: Lang;
+}
+
+/// リクエストの言語で future を実行する。エラーの応答などはこの言語で作る
+pub async fn scope<F: std::future::Future>(lang: Lang, future: F) -> F::Output {
+    LANG.scope(lang, future).await
+}
+
+/// 処理中のリクエストの言語
+pub fn current() -> Lang {
+    LANG.try_with(|lang| *lang).unwrap_or_default()
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Lang {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<Lang>()
+            .copied()
+            .or_else(|| Lang::from_accept_language(&parts.headers))
+            .unwrap_or_default())
+    }
+}
+
+// 英語の文言と日本語訳。`{}` は値に置き換える
+const MESSAGES: &[(&str, &str)] = &[
+    // 認証
+    (
+        "Authorization header is missing.",
+        "Authorization ヘッダーがありません。",
+    ),
+    (
+        "Authorization header is invalid. It should start with 'Bearer'.",
+        "Authorization ヘッダーが正しくありません。'Bearer' で始めてください。",
+    ),
+    (
+        "Email is not verified.",
+        "メールアドレスが確認されていません。",
+    ),
+    // 権限
+    (
+        "Failed to check car access.",
+        "車両へのアクセス権を確認できませんでした。",
+    ),
+    (
+        "This operation requires the {} role.",
+        "この操作には {} ロールが必要です。",
+    ),
+    (
+        "You do not have access to this car.",
+        "この車両へのアクセス権がありません。",
+    ),
+    ("The record was not found.", "記録が見つかりません。"),
+    (
+        "This operation requires an administrator.",
+        "この操作は管理者だけが行えます。",
+    ),
+    // リクエスト
+    (
+        "The resource has been modified by someone else. Fetch it again and retry.",
+        "他のユーザーが更新しています。取得し直してからやり直してください。",
+    ),
+    (
+        "The request body must be a JSON object.",
+        "リクエストの本文は JSON オブジェクトにしてください。",
+    ),
+    (
+        "The field `{}` cannot be changed.",
+        "項目 `{}` は変更できません。",
+    ),
+    (
+        "The request contains invalid fields.",
+        "正しくない項目があります。",
+    ),
+    // 検証
+    (
+        "Use a valid date in YYYY-MM-DD format.",
+        "YYYY-MM-DD 形式の正しい日付を指定してください。",
+    ),
+    (
+        "Must be {} to {} characters long.",
+        "{} 文字以上 {} 文字以下にしてください。",
+    ),
+    ("Must not be empty.", "入力してください。"),
+    (
+        "Must be at least {} characters long.",
+        "{} 文字以上にしてください。",
+    ),
+    (
+        "Must be at most {} characters long.",
+        "{} 文字以下にしてください。",
+    ),
+    ("Must be at least {}.", "{} 以上にしてください。"),
+    ("Must be at most {}.", "{} 以下にしてください。"),
+    (
+        "Must be greater than {}.",
+        "{} より大きい値にしてください。",
+    ),
+    ("Out of range.", "範囲外の値です。"),
+    (
+        "Must be a valid email address.",
+        "正しいメールアドレスを指定してください。",
+    ),
+    (
+        "The next inspection date must be after the inspection date.",
+        "次回の点検日は点検日より後にしてください。",
+    ),
+    (
+        "The coverage end date must be after the start date.",
+        "保険期間の終了日は開始日より後にしてください。",
+    ),
+    (
+        "The insurance policy must belong to the same car.",
+        "同じ車両の保険を指定してください。",
+    ),
+    (
+        "Specify both the latitude and the longitude.",
+        "緯度と経度は両方指定してください。",
+    ),
+    (
+        "The repair completion date must not be before the accident date.",
+        "修理の完了日は事故の日以降にしてください。",
+    ),
+    (
+        "The claim status cannot change from {} to {}.",
+        "請求の状態は {} から {} に変更できません。",
+    ),
+    (
+        "Set the car's displacement to calculate the tax.",
+        "税額を計算するには車両の排気量を設定してください。",
+    ),
+    (
+        "Set the car's first registration date to calculate the tax.",
+        "税額を計算するには車両の初度登録年月日を設定してください。",
+    ),
+    (
+        "Set the car's weight to calculate the weight tax.",
+        "重量税を計算するには車両重量を設定してください。",
+    ),
+    (
+        "Set the due date or the car's next inspection date.",
+        "納期限か、車両の次回の点検日を設定してください。",
+    ),
+    (
+        "Failed to calculate the tax.",
+        "税額を計算できませんでした。",
+    ),
+    (
+        "A tire set cannot be moved to another car.",
+        "タイヤセットを別の車両に移すことはできません。",
+    ),
+    (
+        "The date and odometer must not be before the last tire change.",
+        "日付と走行距離は前回のタイヤの付け替えより前にできません。",
+    ),
+    (
+        "The tire set is already mounted.",
+        "このタイヤセットはすでに装着されています。",
+    ),
+    (
+        "The tire set is not mounted.",
+        "このタイヤセットは装着されていません。",
+    ),
+    (
+        "Only the car's last tire change can be removed.",
+        "取り消せるのは車両の最後のタイヤの付け替えだけです。",
+    ),
+    (
+        "The part is not in your inventory.",
+        "この部品は在庫にありません。",
+    ),
+    (
+        "There is not enough stock of the part.",
+        "部品の在庫が足りません。",
+    ),
+    (
+        "The tuning has been removed.",
+        "このカスタムは取り外されています。",
+    ),
+    (
+        "The tuning has already been removed.",
+        "このカスタムはすでに取り外されています。",
+    ),
+    (
+        "The removal date must not be before the tuning date.",
+        "取り外した日はカスタムの日より前にできません。",
+    ),
+    (
+        "Failed to check the insurance policy.",
+        "保険を確認できませんでした。",
+    ),
+    (
+        "Use a three-letter ISO 4217 currency code.",
+        "ISO 4217 の 3 文字の通貨コードを指定してください。",
+    ),
+    ("Must not be negative.", "0 以上にしてください。"),
+    (
+        "Use a 17-character VIN or a chassis number such as ZVW30-1234567.",
+        "17 桁の VIN か、ZVW30-1234567 のような車台番号を指定してください。",
+    ),
+    (
+        "A VIN cannot contain the letters I, O or Q.",
+        "VIN には I、O、Q の文字は使えません。",
+    ),
+    (
+        "The check digit of the VIN does not match. Check the VIN for typos.",
+        "VIN のチェックディジットが一致しません。入力に誤りがないか確認してください。",
+    ),
+    (
+        "Use an IANA time zone name such as Asia/Tokyo.",
+        "Asia/Tokyo のような IANA のタイムゾーン名を指定してください。",
+    ),
+    // 車両の共有・譲渡
+    (
+        "Use the transfer_ownership endpoint to change the owner.",
+        "オーナーの変更には transfer_ownership を使ってください。",
+    ),
+    (
+        "The owner's role cannot be changed.",
+        "オーナーのロールは変更できません。",
+    ),
+    (
+        "Transfer ownership before removing the owner.",
+        "オーナーを外す前に所有権を移してください。",
+    ),
+    (
+        "Ownership can only be transferred to a member of the car.",
+        "所有権は車両のメンバーにだけ移せます。",
+    ),
+    (
+        "Invitations can only grant the editor or viewer role.",
+        "招待で付与できるのは editor か viewer のロールだけです。",
+    ),
+    (
+        "A valid email address is required.",
+        "正しいメールアドレスを指定してください。",
+    ),
+    (
+        "The user already has access to this car.",
+        "このユーザーはすでに車両にアクセスできます。",
+    ),
+    (
+        "A car cannot be transferred to its current owner.",
+        "現在のオーナーには譲渡できません。",
+    ),
+    (
+        "The recipient must be an AutoTrack user.",
+        "譲渡先は AutoTrack のユーザーにしてください。",
+    ),
+    (
+        "This car already has a pending transfer.",
+        "この車両には処理中の譲渡があります。",
+    ),
+    (
+        "The seller no longer owns this car.",
+        "譲渡元のユーザーはこの車両のオーナーではなくなりました。",
+    ),
+    (
+        "This transfer is no longer pending.",
+        "この譲渡はすでに処理されています。",
+    ),
+    (
+        "expires_at must be in the future.",
+        "expires_at には未来の日時を指定してください。",
+    ),
+    // ゴミ箱・アカウント
+    (
+        "The item was not found in the trash.",
+        "ゴミ箱に見つかりません。",
+    ),
+    (
+        "Restore the car before restoring its records.",
+        "記録を復元する前に車両を復元してください。",
+    ),
+    ("Invalid archive: {}", "アーカイブが正しくありません: {}"),
+    (
+        "Archives can only be imported into an account without cars.",
+        "アーカイブは車両のないアカウントにだけインポートできます。",
+    ),
+    (
+        "Account deletion has already been requested.",
+        "アカウントの削除はすでに申請されています。",
+    ),
+    (
+        "The font for the PDF report is not available.",
+        "PDF レポートのフォントを利用できません。",
+    ),
+    // レポート
+    ("Vehicle History Report", "車両履歴レポート"),
+    ("Service Record Book", "整備記録簿"),
+    ("Created on", "作成日"),
+    ("Generated at", "作成日時"),
+    ("Vehicle", "車両情報"),
+    ("Car name", "車名"),
+    ("Model code", "型式"),
+    ("Color", "色"),
+    ("Maker", "メーカー"),
+    ("Model", "車種"),
+    ("Grade", "グレード"),
+    ("Year", "年式"),
+    ("First registration", "初度登録年月日"),
+    ("Plate number", "登録番号"),
+    ("VIN", "車台番号"),
+    ("Mileage", "走行距離"),
+    ("Flood damage", "冠水歴"),
+    ("Smoked in", "喫煙歴"),
+    ("Yes", "あり"),
+    ("No", "なし"),
+    ("Maintenance", "整備記録"),
+    ("Periodic Inspections", "定期点検"),
+    ("Tuning", "カスタム"),
+    ("Accidents", "事故歴"),
+    ("Fuel Log", "給油記録"),
+    ("No records.", "記録はありません。"),
+    ("Date", "日付"),
+    ("Type", "種類"),
+    ("Title", "内容"),
+    ("Details", "詳細"),
+    ("Name", "名称"),
+    ("Inspection date", "実施日"),
+    ("Next inspection", "次回予定日"),
+    ("Next", "次回"),
+    ("Fuel (L)", "給油量 (L)"),
+    ("Mileage (km)", "走行距離 (km)"),
+];
+
+// 文言がテンプレートに一致すれば `{}` に当たる部分を返す
+fn match_template<'a>(template: &str, message: &'a str) -> Option<Vec<&'a str>> {
+    let mut parts = template.split("{}");
+    let mut rest = message.strip_prefix(parts.next()?)?;
+    let parts: Vec<&str> = parts.collect();
+    let mut args = Vec::with_capacity(parts.len());
+    for (index, part) in parts.iter().enumerate() {
+        let end = if index + 1 == parts.len() {
+            rest.strip_suffix(part)?.len()
+        } else {
+            rest.find(part)?
+        };
+        args.push(&rest[..end]);
+        rest = &rest[end + part.len()..];
+    }
+    rest.is_empty().then_some(args)
+}
+
+fn fill<T: AsRef<str>>(template: &str, args: &[T]) -> String {
+    let mut parts = template.split("{}");
+    let mut filled = parts.next().unwrap_or_default().to_string();
+    for (index, part) in parts.enumerate() {
+        if let Some(arg) = args.get(index) {
+            filled.push_str(arg.as_ref());
+        }
+        filled.push_str(part);
+    }
+    filled
+}
+
+/// 固定の文言を訳す。訳がなければ英語のまま返す
+pub fn text(lang: Lang, message: &'static str) -> &'static str {
+    match lang {
+        Lang::En => message,
+        Lang::Ja => MESSAGES
+            .iter()
+            .find(|(en, _)| *en == message)
+            .map_or(message, |(_, ja)| ja),
+    }
+}
+
+/// `{}` を含む文言を訳してから値を埋める
+pub fn format<T: AsRef<str>>(lang: Lang, template: &'static str, args: &[T]) -> String {
+    fill(text(lang, template), args)
+}
+
+/// 値を埋めた後の英語の文言を訳す。エラーのメッセージに使う
+pub fn translate(lang: Lang, message: &str) -> Cow<'_, str> {
+    if lang == Lang::En {
+        return Cow::Borrowed(message);
+    }
+    MESSAGES
+        .iter()
+        .find_map(|(en, ja)| match_template(en, message).map(|args| fill(ja, &args)))
+        .map_or(Cow::Borrowed(message), Cow::Owned)
+}
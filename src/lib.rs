@@ -1,7 +1,24 @@
+pub mod access;
+pub mod account_deletion;
+pub mod audit;
 pub mod db;
+pub mod etag;
+pub mod exchange_rates;
+pub mod expenses;
 pub mod firebase;
 pub mod handlers;
+pub mod history;
+pub mod i18n;
 pub mod middleware;
 pub mod models;
+pub mod patch;
+pub mod report;
+pub mod report_pdf;
 pub mod routes;
 pub mod state;
+pub mod storage;
+pub mod tires;
+pub mod trash;
+pub mod validation;
+pub mod vehicle_tax;
+pub mod vin;
@@ -1,12 +1,7 @@
-mod db;
-mod handlers;
-mod middleware;
-mod models;
-mod routes;
-mod state;
-
-use axum;
-use state::AppState;
+use auto_track_backend::firebase::FirebaseAuth;
+use auto_track_backend::state::AppState;
+use auto_track_backend::storage::Storage;
+use auto_track_backend::{account_deletion, db, routes, trash};
 use std::env;
 use std::sync::Arc;
 use tokio::net::TcpListener;
@@ -31,8 +26,21 @@ async fn main() {
             .unwrap_or_else(|_| "true".to_string())
             .parse()
             .expect("REQUIRE_EMAIL_VERIFICATION must be a boolean"),
+        storage: Storage::from_env().await,
+        firebase_auth: match env::var("FIREBASE_SERVICE_ACCOUNT_KEY") {
+            Ok(service_account_key) => Some(Arc::new(
+                FirebaseAuth::new(&service_account_key)
+                    .await
+                    .expect("Failed to initialize Firebase Auth"),
+            )),
+            Err(_) => None,
+        },
     }));
 
+    // 猶予期間を過ぎたアカウントを削除するジョブ
+    tokio::spawn(account_deletion::run_deletion_job(Arc::clone(&state)));
+    tokio::spawn(trash::run_purge_job(Arc::clone(&state)));
+
     let app = routes::create_routes(state);
 
     let addr = std::net::SocketAddr::from(([0, 0, 0, 0], 8369));
@@ -1,6 +1,7 @@
 use std::sync::Arc;
 use std::time::Duration;
 
+use crate::i18n;
 use crate::state::AppState;
 use anyhow::Context as _;
 use axum::{
@@ -48,12 +49,13 @@ impl AppError {
 }
 
 impl IntoResponse for AppError {
+    // message は英語で作り、リクエストの言語に訳して返す
     fn into_response(self) -> Response {
         (
             self.status_code,
             Json(json!({
                 "code": self.code,
-                "message": self.message,
+                "message": i18n::translate(i18n::current(), &self.message),
             })),
         )
             .into_response()
@@ -0,0 +1,62 @@
+use crate::i18n::{self, Lang};
+use crate::state::AppState;
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use sqlx::query_scalar;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Accept-Language から応答の言語を決め、ハンドラーとエラーの応答で使えるようにする
+pub async fn negotiate_language(mut request: Request, next: Next) -> Response {
+    let lang = Lang::from_accept_language(request.headers()).unwrap_or_default();
+    request.extensions_mut().insert(lang);
+
+    let mut response = i18n::scope(lang, next.run(request)).await;
+    // ユーザーの設定で言語を変えた場合はその言語を返す
+    let lang = response.extensions().get::<Lang>().copied().unwrap_or(lang);
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_LANGUAGE,
+        HeaderValue::from_static(lang.as_str()),
+    );
+    headers.append(header::VARY, HeaderValue::from_static("accept-language"));
+    response
+}
+
+/// 認証済みのユーザーが言語を設定していれば、Accept-Language よりその言語を優先する
+pub async fn apply_user_language(
+    State(state): State<Arc<Mutex<AppState>>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(firebase_user_id) = request.extensions().get::<String>().cloned() else {
+        return next.run(request).await;
+    };
+    let db_pool = state.lock().await.db_pool.clone();
+
+    let lang = match query_scalar::<_, Option<Lang>>(
+        "SELECT language FROM Users WHERE firebase_user_id = ?",
+    )
+    .bind(&firebase_user_id)
+    .fetch_optional(&db_pool)
+    .await
+    {
+        Ok(lang) => lang.flatten(),
+        Err(e) => {
+            eprintln!("Failed to fetch user language: {:?}", e);
+            None
+        }
+    };
+    let Some(lang) = lang else {
+        return next.run(request).await;
+    };
+
+    request.extensions_mut().insert(lang);
+    let mut response = i18n::scope(lang, next.run(request)).await;
+    response.extensions_mut().insert(lang);
+    response
+}
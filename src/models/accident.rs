@@ -1,12 +1,221 @@
+use crate::exchange_rates::{Money, BASE_CURRENCY};
+use crate::patch::PatchTarget;
+use crate::validation::{self, validate_amount, validate_currency, validate_date};
+use bigdecimal::BigDecimal;
 use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::BTreeMap;
 use time::OffsetDateTime;
+use validator::{Validate, ValidationError};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// 事故の程度
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum AccidentSeverity {
+    Minor,
+    Moderate,
+    Severe,
+    /// 全損
+    TotalLoss,
+}
+
+/// 保険請求の状態
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum ClaimStatus {
+    #[default]
+    NotFiled,
+    Filed,
+    InReview,
+    Approved,
+    Denied,
+    Paid,
+    Withdrawn,
+}
+
+impl ClaimStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ClaimStatus::NotFiled => "not_filed",
+            ClaimStatus::Filed => "filed",
+            ClaimStatus::InReview => "in_review",
+            ClaimStatus::Approved => "approved",
+            ClaimStatus::Denied => "denied",
+            ClaimStatus::Paid => "paid",
+            ClaimStatus::Withdrawn => "withdrawn",
+        }
+    }
+
+    /// この状態から next に進めるか。否認された請求は再審査に戻せる
+    pub fn can_transition_to(self, next: ClaimStatus) -> bool {
+        use ClaimStatus::*;
+        matches!(
+            (self, next),
+            (NotFiled, Filed)
+                | (Filed, InReview)
+                | (Filed, Withdrawn)
+                | (InReview, Approved)
+                | (InReview, Denied)
+                | (InReview, Withdrawn)
+                | (Approved, Paid)
+                | (Denied, InReview)
+        )
+    }
+
+    /// 請求が終わっていない
+    pub fn is_open(self) -> bool {
+        matches!(
+            self,
+            ClaimStatus::Filed | ClaimStatus::InReview | ClaimStatus::Approved
+        )
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Validate)]
+#[validate(schema(function = "validate_accident"))]
 pub struct Accident {
     pub accident_id: Option<i32>,
     pub car_id: i32,
+    #[validate(custom(function = "validate_date"))]
     pub accident_date: String,
+    #[validate(length(max = 10000))]
     pub accident_description: String,
+    /// 事故の場所の住所
+    #[serde(default)]
+    #[validate(length(max = 255))]
+    pub accident_location: Option<String>,
+    #[serde(default)]
+    #[validate(range(min = -90.0, max = 90.0))]
+    pub accident_latitude: Option<f64>,
+    #[serde(default)]
+    #[validate(range(min = -180.0, max = 180.0))]
+    pub accident_longitude: Option<f64>,
+    #[serde(default)]
+    pub accident_severity: Option<AccidentSeverity>,
+    /// 相手方
+    #[serde(default)]
+    #[validate(length(max = 255))]
+    pub counterparty: Option<String>,
+    /// 交通事故証明書などの番号
+    #[serde(default)]
+    #[validate(length(max = 255))]
+    pub police_report_number: Option<String>,
+    /// 自分の過失割合（%）
+    #[serde(default)]
+    #[validate(range(min = 0, max = 100))]
+    pub fault_ratio: Option<i32>,
+    /// 請求に使った保険。同じ車両の保険に限る
+    #[serde(default)]
+    pub policy_id: Option<i32>,
+    #[serde(default)]
+    #[validate(length(max = 255))]
+    pub claim_number: Option<String>,
+    #[serde(default)]
+    pub claim_status: ClaimStatus,
+    #[serde(default)]
+    #[validate(length(max = 255))]
+    pub repair_shop: Option<String>,
+    /// 修理費。repair_currency の通貨で保存する
+    #[serde(default)]
+    #[validate(custom(function = "validate_amount"))]
+    pub repair_cost: Option<BigDecimal>,
+    #[serde(default = "default_currency")]
+    #[validate(custom(function = "validate_currency"))]
+    pub repair_currency: String,
+    #[serde(default)]
+    #[validate(custom(function = "validate_date"))]
+    pub repair_completed_date: Option<String>,
     pub created_at: Option<OffsetDateTime>,
     pub updated_at: Option<OffsetDateTime>,
 }
+
+fn default_currency() -> String {
+    BASE_CURRENCY.to_string()
+}
+
+/// 緯度と経度はそろって指定し、修理の完了日は事故の日以降にする
+fn validate_accident(accident: &Accident) -> Result<(), ValidationError> {
+    if accident.accident_latitude.is_some() != accident.accident_longitude.is_some() {
+        return Err(validation::field_error(
+            "accident_longitude",
+            "coordinates",
+            "Specify both the latitude and the longitude.",
+        ));
+    }
+    let dates = (
+        validation::parse_date(&accident.accident_date),
+        accident
+            .repair_completed_date
+            .as_deref()
+            .and_then(validation::parse_date),
+    );
+    match dates {
+        (Some(accident_date), Some(completed)) if completed < accident_date => {
+            Err(validation::field_error(
+                "repair_completed_date",
+                "after_accident_date",
+                "The repair completion date must not be before the accident date.",
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub const ACCIDENT_COLUMNS: &str =
+    "accident_id, car_id, accident_date, accident_description, accident_location, accident_latitude, accident_longitude, accident_severity, counterparty, police_report_number, fault_ratio, policy_id, claim_number, claim_status, repair_shop, repair_cost, repair_currency, repair_completed_date, created_at, updated_at";
+
+pub const ACCIDENT_PATCH: PatchTarget = PatchTarget {
+    table: "Accidents",
+    id_column: "accident_id",
+    fields: &[
+        "car_id",
+        "accident_date",
+        "accident_description",
+        "accident_location",
+        "accident_latitude",
+        "accident_longitude",
+        "accident_severity",
+        "counterparty",
+        "police_report_number",
+        "fault_ratio",
+        "policy_id",
+        "claim_number",
+        "claim_status",
+        "repair_shop",
+        "repair_cost",
+        "repair_currency",
+        "repair_completed_date",
+    ],
+};
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct AccidentSummaryQuery {
+    /// 修理費の換算先の通貨。省略した場合はユーザーの設定の通貨
+    #[validate(custom(function = "validate_currency"))]
+    pub currency: Option<String>,
+}
+
+/// 車両の事故の集計
+#[derive(Debug, Serialize)]
+pub struct AccidentSummary {
+    pub car_id: i32,
+    pub accident_count: usize,
+    /// 程度ごとの件数。程度を記録していない事故は含めない
+    pub by_severity: BTreeMap<AccidentSeverity, usize>,
+    pub by_claim_status: BTreeMap<ClaimStatus, usize>,
+    /// 請求中（filed / in_review / approved）の件数
+    pub open_claim_count: usize,
+    /// 過失割合を記録した事故の平均
+    pub average_fault_ratio: Option<f64>,
+    /// 換算できた修理費の合計
+    pub total_repair_cost: Money,
+    /// レートがなく合計に含めていない修理費の件数
+    pub unconverted_count: usize,
+    pub last_accident_date: Option<String>,
+}
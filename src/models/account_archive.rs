@@ -0,0 +1,43 @@
+use crate::models::attachment::Attachment;
+use crate::models::car_history::CarHistory;
+use crate::models::part::Part;
+use crate::models::user::User;
+use crate::models::user_car::CarRole;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+pub const ACCOUNT_ARCHIVE_VERSION: u32 = 1;
+
+// ZIP 内の JSON と画像の配置
+pub const ACCOUNT_ARCHIVE_JSON: &str = "account.json";
+pub const ACCOUNT_ARCHIVE_FILES_DIR: &str = "files/";
+
+/// `/api/me/export` で出力するアカウントの全データ
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountArchive {
+    pub version: u32,
+    #[serde(with = "time::serde::rfc3339")]
+    pub exported_at: OffsetDateTime,
+    pub user: User,
+    pub cars: Vec<ArchivedCar>,
+    /// 部品の在庫。以前のアーカイブには含まれない
+    #[serde(default)]
+    pub parts: Vec<Part>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchivedCar {
+    pub role: CarRole,
+    #[serde(flatten)]
+    pub history: CarHistory,
+    pub attachments: Vec<Attachment>,
+    /// 車両画像のZIP内のパス。アーカイブに含まれない場合は None
+    pub image_file: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountImportResult {
+    pub imported_cars: usize,
+    pub imported_records: usize,
+    pub imported_files: usize,
+}
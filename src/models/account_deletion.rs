@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct AccountDeletion {
+    pub firebase_user_id: String,
+    pub delete_firebase_user: bool,
+    #[serde(with = "time::serde::rfc3339")]
+    pub requested_at: OffsetDateTime,
+    /// この日時を過ぎるとアカウントが削除される
+    #[serde(with = "time::serde::rfc3339")]
+    pub scheduled_for: OffsetDateTime,
+}
+
+pub const ACCOUNT_DELETION_COLUMNS: &str =
+    "firebase_user_id, delete_firebase_user, requested_at, scheduled_for";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RequestAccountDeletionRequest {
+    /// Firebase Authentication のユーザーも削除するか
+    #[serde(default = "default_delete_firebase_user")]
+    pub delete_firebase_user: bool,
+}
+
+fn default_delete_firebase_user() -> bool {
+    true
+}
@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Attachment {
+    pub attachment_id: i32,
+    pub owner_type: String,
+    pub owner_id: i32,
+    pub file_name: String,
+    pub content_type: String,
+    pub storage_key: String,
+    pub file_url: Option<String>, // 非公開の添付ファイルでは取得時に署名付きURLが入る
+    pub is_private: bool,
+    pub sort_order: i32,
+    pub created_at: Option<OffsetDateTime>,
+    pub updated_at: Option<OffsetDateTime>,
+    #[sqlx(skip)]
+    pub url_expires_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentOwner {
+    Maintenance,
+    Tuning,
+    Accident,
+    PeriodicInspection,
+    InsurancePolicy,
+}
+
+impl AttachmentOwner {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttachmentOwner::Maintenance => "maintenance",
+            AttachmentOwner::Tuning => "tuning",
+            AttachmentOwner::Accident => "accident",
+            AttachmentOwner::PeriodicInspection => "periodic_inspection",
+            AttachmentOwner::InsurancePolicy => "insurance_policy",
+        }
+    }
+
+    pub fn table(&self) -> &'static str {
+        match self {
+            AttachmentOwner::Maintenance => "Maintenances",
+            AttachmentOwner::Tuning => "Tunings",
+            AttachmentOwner::Accident => "Accidents",
+            AttachmentOwner::PeriodicInspection => "PeriodicInspection",
+            AttachmentOwner::InsurancePolicy => "InsurancePolicies",
+        }
+    }
+
+    pub fn id_column(&self) -> &'static str {
+        match self {
+            AttachmentOwner::Maintenance => "maint_id",
+            AttachmentOwner::Tuning => "tuning_id",
+            AttachmentOwner::Accident => "accident_id",
+            AttachmentOwner::PeriodicInspection => "pi_id",
+            AttachmentOwner::InsurancePolicy => "policy_id",
+        }
+    }
+}
+
+pub const ATTACHMENT_OWNERS: [AttachmentOwner; 5] = [
+    AttachmentOwner::Maintenance,
+    AttachmentOwner::Tuning,
+    AttachmentOwner::Accident,
+    AttachmentOwner::PeriodicInspection,
+    AttachmentOwner::InsurancePolicy,
+];
+
+// 画像とPDFのみ受け付ける
+pub const ALLOWED_CONTENT_TYPES: [(&str, &str); 6] = [
+    ("image/jpeg", "jpg"),
+    ("image/png", "png"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+    ("image/heic", "heic"),
+    ("application/pdf", "pdf"),
+];
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UploadAttachmentsQuery {
+    #[serde(default)]
+    pub private: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReorderAttachmentsRequest {
+    pub attachment_ids: Vec<i32>,
+}
@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::FromRow;
+use time::OffsetDateTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+    Restore,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct AuditLog {
+    pub audit_log_id: i64,
+    pub car_id: Option<i32>,
+    pub actor_user_id: String,
+    /// 変更したユーザーの名前。ユーザーが削除されている場合は None
+    pub actor_name: Option<String>,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub action: AuditAction,
+    /// 項目ごとの `{"before": .., "after": ..}`
+    pub changes: Json<serde_json::Value>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+pub const AUDIT_LOG_COLUMNS: &str =
+    "a.audit_log_id, a.car_id, a.actor_user_id, u.user_name AS actor_name, a.entity_type, a.entity_id, a.action, a.changes, a.created_at";
+
+#[derive(Debug, Deserialize)]
+pub struct AuditLogQuery {
+    pub entity_type: Option<String>,
+    pub entity_id: Option<String>,
+    /// この ID より古い記録を返す（ページ送り用）
+    pub before_id: Option<i64>,
+    pub limit: Option<u32>,
+}
@@ -1,16 +1,152 @@
+use crate::patch::PatchTarget;
+use crate::validation::{self, validate_date};
+use crate::vin;
 use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
 use time::OffsetDateTime;
+use validator::{Validate, ValidationError};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// 燃料の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum FuelType {
+    Gasoline,
+    PremiumGasoline,
+    Diesel,
+    Hybrid,
+    PluginHybrid,
+    Electric,
+    Hydrogen,
+    Lpg,
+}
+
+/// 変速機の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum Transmission {
+    Mt,
+    At,
+    Cvt,
+    Dct,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, Validate)]
 pub struct Car {
     pub car_id: Option<i32>,
+    #[validate(length(min = 1, max = 255))]
     pub car_name: String,
+    /// 型式。カタログにある型式なら空欄の諸元を補う
+    #[validate(length(max = 255))]
     pub carmodelnum: String,
+    #[validate(length(max = 255))]
     pub car_color: String,
+    #[validate(range(min = 0))]
     pub car_mileage: i32,
     pub car_isflooding: bool,
     pub car_issmoked: bool,
+    #[validate(length(max = 255))]
     pub car_image_url: Option<String>,
+    #[serde(default)]
+    #[validate(length(max = 255))]
+    pub car_maker: Option<String>,
+    #[serde(default)]
+    #[validate(length(max = 255))]
+    pub car_model: Option<String>,
+    #[serde(default)]
+    #[validate(length(max = 255))]
+    pub car_grade: Option<String>,
+    /// 年式
+    #[serde(default)]
+    #[validate(range(min = 1886, max = 2100))]
+    pub car_year: Option<i32>,
+    /// 排気量（cc）
+    #[serde(default)]
+    #[validate(range(min = 0, max = 20000))]
+    pub car_displacement: Option<i32>,
+    #[serde(default)]
+    pub car_fuel_type: Option<FuelType>,
+    /// 燃料タンク容量（L）
+    #[serde(default)]
+    #[validate(range(exclusive_min = 0.0, max = 1000.0))]
+    pub car_tank_capacity: Option<f32>,
+    /// 車両重量（kg）。重量税の計算に使う
+    #[serde(default)]
+    #[validate(range(min = 1, max = 100000))]
+    pub car_weight: Option<i32>,
+    #[serde(default)]
+    pub car_transmission: Option<Transmission>,
+    /// 初度登録年月日
+    #[serde(default)]
+    #[validate(custom(function = "validate_date"))]
+    pub car_first_registration: Option<String>,
+    /// 自動車登録番号（ナンバー）
+    #[serde(default)]
+    #[validate(length(max = 32))]
+    pub car_plate_number: Option<String>,
+    /// 車台番号または VIN
+    #[serde(default)]
+    #[validate(custom(function = "validate_vin"))]
+    pub car_vin: Option<String>,
     pub created_at: Option<OffsetDateTime>,
     pub updated_at: Option<OffsetDateTime>,
 }
+
+/// VIN はチェックディジットも確かめる。大文字・半角にそろえる前の値でもよい
+fn validate_vin(value: &str) -> Result<(), ValidationError> {
+    match vin::decode(value) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(validation::error(e.code, e.message)),
+    }
+}
+
+impl Car {
+    /// VIN・車台番号の表記をそろえ、読み取れた型式・メーカー・年式で空欄を埋める
+    pub fn apply_vin(&mut self) {
+        let Some(decoded) = self.car_vin.as_deref().and_then(|v| vin::decode(v).ok()) else {
+            return;
+        };
+        if self.carmodelnum.trim().is_empty() {
+            if let Some(model_code) = &decoded.model_code {
+                self.carmodelnum = model_code.clone();
+            }
+        }
+        if self.car_maker.is_none() {
+            self.car_maker = decoded.manufacturer.map(str::to_string);
+        }
+        if self.car_year.is_none() {
+            self.car_year = decoded.model_year;
+        }
+        self.car_vin = Some(decoded.normalized);
+    }
+}
+
+pub const CAR_COLUMNS: &str =
+    "car_id, car_name, carmodelnum, car_color, car_mileage, car_isflooding, car_issmoked, car_image_url, car_maker, car_model, car_grade, car_year, car_displacement, car_fuel_type, car_tank_capacity, car_weight, car_transmission, car_first_registration, car_plate_number, car_vin, created_at, updated_at";
+
+pub const CAR_PATCH: PatchTarget = PatchTarget {
+    table: "Cars",
+    id_column: "car_id",
+    fields: &[
+        "car_name",
+        "carmodelnum",
+        "car_color",
+        "car_mileage",
+        "car_isflooding",
+        "car_issmoked",
+        "car_image_url",
+        "car_maker",
+        "car_model",
+        "car_grade",
+        "car_year",
+        "car_displacement",
+        "car_fuel_type",
+        "car_tank_capacity",
+        "car_weight",
+        "car_transmission",
+        "car_first_registration",
+        "car_plate_number",
+        "car_vin",
+    ],
+};
@@ -0,0 +1,38 @@
+use crate::models::accident::Accident;
+use crate::models::car::Car;
+use crate::models::fuel_efficiency::FuelEfficiency;
+use crate::models::insurance_policy::InsurancePolicy;
+use crate::models::maintenance::Maintenance;
+use crate::models::part::PartUsage;
+use crate::models::periodic_inspection::PeriodicInspection;
+use crate::models::tire::{TireEvent, TireMeasurement, TireSet};
+use crate::models::tuning::Tuning;
+use crate::models::vehicle_tax::VehicleTax;
+use serde::{Deserialize, Serialize};
+
+/// 車両とそのすべての記録
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CarHistory {
+    pub car: Car,
+    pub maintenances: Vec<Maintenance>,
+    pub tunings: Vec<Tuning>,
+    pub fuel_efficiencies: Vec<FuelEfficiency>,
+    pub accidents: Vec<Accident>,
+    /// 以前のアーカイブには含まれない
+    #[serde(default)]
+    pub insurance_policies: Vec<InsurancePolicy>,
+    /// 以前のアーカイブには含まれない
+    #[serde(default)]
+    pub vehicle_taxes: Vec<VehicleTax>,
+    pub periodic_inspections: Vec<PeriodicInspection>,
+    /// 以前のアーカイブには含まれない
+    #[serde(default)]
+    pub tire_sets: Vec<TireSet>,
+    #[serde(default)]
+    pub tire_events: Vec<TireEvent>,
+    #[serde(default)]
+    pub tire_measurements: Vec<TireMeasurement>,
+    /// 整備・チューニングで使った部品。以前のアーカイブには含まれない
+    #[serde(default)]
+    pub part_usages: Vec<PartUsage>,
+}
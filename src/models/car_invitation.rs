@@ -0,0 +1,24 @@
+use crate::models::user_car::CarRole;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+use validator::Validate;
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct CarInvitation {
+    pub invitation_id: i32,
+    pub car_id: i32,
+    pub inviter_user_id: String,
+    pub invitee_email: String,
+    pub role: CarRole,
+    pub status: String,
+    pub created_at: Option<OffsetDateTime>,
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreateInvitationRequest {
+    #[validate(email)]
+    pub email: String,
+    pub role: CarRole,
+}
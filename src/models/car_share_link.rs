@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::FromRow;
+use time::OffsetDateTime;
+use validator::Validate;
+
+/// 公開レポートに含める記録の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareSection {
+    Maintenances,
+    Tunings,
+    FuelEfficiencies,
+    Accidents,
+    PeriodicInspections,
+}
+
+pub const SHARE_SECTIONS: [ShareSection; 5] = [
+    ShareSection::Maintenances,
+    ShareSection::Tunings,
+    ShareSection::FuelEfficiencies,
+    ShareSection::Accidents,
+    ShareSection::PeriodicInspections,
+];
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct CarShareLink {
+    pub share_link_id: i32,
+    pub car_id: i32,
+    pub token: String,
+    pub sections: Json<Vec<ShareSection>>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub expires_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub revoked_at: Option<OffsetDateTime>,
+    pub created_by: String,
+    pub created_at: Option<OffsetDateTime>,
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+pub const CAR_SHARE_LINK_COLUMNS: &str =
+    "share_link_id, car_id, token, sections, expires_at, revoked_at, created_by, created_at, updated_at";
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreateShareLinkRequest {
+    /// 省略した場合はすべてのセクションを含める
+    #[validate(length(min = 1))]
+    pub sections: Option<Vec<ShareSection>>,
+    /// 省略した場合は無期限
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub expires_at: Option<OffsetDateTime>,
+}
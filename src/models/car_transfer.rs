@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+use validator::Validate;
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct CarTransfer {
+    pub transfer_id: i32,
+    pub car_id: i32,
+    pub from_user_id: String,
+    pub to_email: String,
+    pub to_user_id: Option<String>,
+    pub keep_snapshot: bool,
+    pub status: String,
+    pub completed_at: Option<OffsetDateTime>,
+    pub created_at: Option<OffsetDateTime>,
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+pub const CAR_TRANSFER_COLUMNS: &str = "transfer_id, car_id, from_user_id, to_email, to_user_id, keep_snapshot, status, completed_at, created_at, updated_at";
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreateTransferRequest {
+    #[validate(email)]
+    pub to_email: String,
+    #[serde(default)]
+    pub keep_snapshot: bool,
+}
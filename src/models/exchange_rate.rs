@@ -0,0 +1,29 @@
+use crate::models::fuel_efficiency::CsvRowError;
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// 通貨 1 単位あたりの基準通貨（円）の額
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ExchangeRate {
+    pub currency: String,
+    pub rate_date: String,
+    pub rate: BigDecimal,
+}
+
+pub const EXCHANGE_RATE_COLUMNS: &str =
+    "currency, DATE_FORMAT(rate_date, '%Y-%m-%d') AS rate_date, rate";
+
+#[derive(Debug, Deserialize)]
+pub struct ExchangeRateQuery {
+    pub currency: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExchangeRateImportResult {
+    pub total_rows: usize,
+    pub imported_rows: usize,
+    pub errors: Vec<CsvRowError>,
+}
@@ -0,0 +1,67 @@
+use crate::exchange_rates::Money;
+use crate::validation::{validate_currency, validate_date};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+/// 支出の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpenseCategory {
+    Fuel,
+    Insurance,
+    Repair,
+    Tax,
+    /// 整備・チューニングで使った部品
+    Parts,
+}
+
+/// 記録から集めた 1 件の支出
+#[derive(Debug, Clone, Serialize)]
+pub struct Expense {
+    pub category: ExpenseCategory,
+    /// 元の記録の ID（給油記録なら fe_id、保険なら policy_id、修理なら accident_id、税なら tax_id、部品なら usage_id）
+    pub source_id: Option<i32>,
+    pub date: String,
+    /// 記録した通貨での金額
+    pub amount: Money,
+    /// 支出日のレートで換算した金額。レートがない場合は None
+    pub converted: Option<Money>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ExpenseQuery {
+    #[validate(custom(function = "validate_date"))]
+    pub from: Option<String>,
+    #[validate(custom(function = "validate_date"))]
+    pub to: Option<String>,
+    /// 換算先の通貨。省略した場合はユーザーの設定の通貨
+    #[validate(custom(function = "validate_currency"))]
+    pub currency: Option<String>,
+}
+
+/// 月（YYYY-MM）ごとの合計
+#[derive(Debug, Serialize)]
+pub struct MonthlyExpense {
+    pub month: String,
+    pub total: Money,
+}
+
+/// 種類ごとの合計
+#[derive(Debug, Serialize)]
+pub struct CategoryExpense {
+    pub category: ExpenseCategory,
+    pub total: Money,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExpenseReport {
+    pub car_id: i32,
+    pub currency: String,
+    /// 換算できた支出の合計
+    pub total: Money,
+    pub by_month: Vec<MonthlyExpense>,
+    pub by_category: Vec<CategoryExpense>,
+    /// レートがなく合計に含めていない支出の件数
+    pub unconverted_count: usize,
+    pub expenses: Vec<Expense>,
+}
@@ -1,14 +1,123 @@
+use crate::exchange_rates::BASE_CURRENCY;
+use crate::patch::PatchTarget;
+use crate::validation::{validate_amount, validate_currency, validate_date};
+use bigdecimal::BigDecimal;
 use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
 use time::OffsetDateTime;
+use validator::Validate;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, FromRow, Validate)]
 pub struct FuelEfficiency {
     pub fe_id: Option<i32>,
     pub car_id: i32,
+    #[validate(custom(function = "validate_date"))]
     pub fe_date: String,
+    #[validate(range(exclusive_min = 0.0))]
     pub fe_amount: f32,
-    pub fe_unitprice: i32,
+    /// 1 L あたりの価格。fe_currency の通貨で保存する
+    #[validate(custom(function = "validate_amount"))]
+    pub fe_unitprice: BigDecimal,
+    #[serde(default = "default_currency")]
+    #[validate(custom(function = "validate_currency"))]
+    pub fe_currency: String,
+    #[validate(range(min = 0.0))]
     pub fe_mileage: f32,
     pub created_at: Option<OffsetDateTime>,
     pub updated_at: Option<OffsetDateTime>,
 }
+
+fn default_currency() -> String {
+    BASE_CURRENCY.to_string()
+}
+
+pub const FUEL_EFFICIENCY_COLUMNS: &str =
+    "fe_id, car_id, fe_date, fe_amount, fe_unitprice, fe_currency, fe_mileage, created_at, updated_at";
+
+pub const FUEL_EFFICIENCY_PATCH: PatchTarget = PatchTarget {
+    table: "FuelEfficiencies",
+    id_column: "fe_id",
+    fields: &[
+        "car_id",
+        "fe_date",
+        "fe_amount",
+        "fe_unitprice",
+        "fe_currency",
+        "fe_mileage",
+    ],
+};
+
+/// CSV の列を見出し名または 0 始まりの列番号で指定する
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CsvColumn {
+    Index(usize),
+    Name(String),
+}
+
+/// 各項目をどの CSV 列から読み込むか。省略した項目は項目名と同じ見出しの列を使う
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FuelEfficiencyCsvMapping {
+    #[serde(default = "default_fe_date_column")]
+    pub fe_date: CsvColumn,
+    #[serde(default = "default_fe_amount_column")]
+    pub fe_amount: CsvColumn,
+    #[serde(default = "default_fe_unitprice_column")]
+    pub fe_unitprice: CsvColumn,
+    #[serde(default = "default_fe_mileage_column")]
+    pub fe_mileage: CsvColumn,
+    /// 省略した場合は fe_currency の見出しの列を使い、その列もなければ円とする
+    #[serde(default)]
+    pub fe_currency: Option<CsvColumn>,
+}
+
+fn default_fe_date_column() -> CsvColumn {
+    CsvColumn::Name("fe_date".to_string())
+}
+
+fn default_fe_amount_column() -> CsvColumn {
+    CsvColumn::Name("fe_amount".to_string())
+}
+
+fn default_fe_unitprice_column() -> CsvColumn {
+    CsvColumn::Name("fe_unitprice".to_string())
+}
+
+fn default_fe_mileage_column() -> CsvColumn {
+    CsvColumn::Name("fe_mileage".to_string())
+}
+
+impl Default for FuelEfficiencyCsvMapping {
+    fn default() -> Self {
+        Self {
+            fe_date: default_fe_date_column(),
+            fe_amount: default_fe_amount_column(),
+            fe_unitprice: default_fe_unitprice_column(),
+            fe_mileage: default_fe_mileage_column(),
+            fe_currency: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportFuelEfficienciesQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CsvRowError {
+    /// CSV ファイル上の行番号（見出し行が 1）
+    pub row: u64,
+    pub column: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FuelEfficiencyImportResult {
+    pub dry_run: bool,
+    pub total_rows: usize,
+    pub imported_rows: usize,
+    pub errors: Vec<CsvRowError>,
+    pub rows: Vec<FuelEfficiency>,
+}
@@ -0,0 +1,73 @@
+use crate::models::maintenance::MaintenanceType;
+use serde::Serialize;
+use time::OffsetDateTime;
+
+/// 公開用の車両履歴レポート。所有者や内部IDは含めない
+#[derive(Debug, Serialize)]
+pub struct HistoryReport {
+    pub vehicle: ReportVehicle,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maintenances: Option<Vec<ReportMaintenance>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tunings: Option<Vec<ReportTuning>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fuel_efficiencies: Option<Vec<ReportFuelEfficiency>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accidents: Option<Vec<ReportAccident>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub periodic_inspections: Option<Vec<ReportPeriodicInspection>>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub generated_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub expires_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportVehicle {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub car_maker: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub car_model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub car_year: Option<i32>,
+    pub carmodelnum: String,
+    pub car_color: String,
+    pub car_mileage: i32,
+    pub car_isflooding: bool,
+    pub car_issmoked: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportMaintenance {
+    pub maint_type: MaintenanceType,
+    pub maint_title: String,
+    pub maint_date: String,
+    pub maint_description: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportTuning {
+    pub tuning_name: String,
+    pub tuning_date: String,
+    pub tuning_description: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportFuelEfficiency {
+    pub fe_date: String,
+    pub fe_amount: f32,
+    pub fe_mileage: f32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportAccident {
+    pub accident_date: String,
+    pub accident_description: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportPeriodicInspection {
+    pub pi_name: String,
+    pub pi_date: String,
+    pub pi_nextdate: String,
+}
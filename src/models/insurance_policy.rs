@@ -0,0 +1,139 @@
+use crate::exchange_rates::BASE_CURRENCY;
+use crate::patch::PatchTarget;
+use crate::validation::{self, validate_amount, validate_currency, validate_date};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+use validator::{Validate, ValidationError};
+
+/// 保険の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum InsuranceType {
+    /// 自賠責保険
+    Compulsory,
+    /// 任意保険
+    Voluntary,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Validate)]
+#[validate(schema(function = "validate_coverage_dates"))]
+pub struct InsurancePolicy {
+    pub policy_id: Option<i32>,
+    pub car_id: i32,
+    pub policy_type: InsuranceType,
+    #[validate(length(min = 1, max = 255))]
+    pub insurer: String,
+    #[validate(length(min = 1, max = 255))]
+    pub policy_number: String,
+    #[validate(custom(function = "validate_date"))]
+    pub coverage_start: String,
+    #[validate(custom(function = "validate_date"))]
+    pub coverage_end: String,
+    /// 保険料。premium_currency の通貨で保存する
+    #[validate(custom(function = "validate_amount"))]
+    pub premium: BigDecimal,
+    #[serde(default = "default_currency")]
+    #[validate(custom(function = "validate_currency"))]
+    pub premium_currency: String,
+    #[serde(default)]
+    #[validate(length(max = 10000))]
+    pub notes: String,
+    pub created_at: Option<OffsetDateTime>,
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+fn default_currency() -> String {
+    BASE_CURRENCY.to_string()
+}
+
+pub const INSURANCE_POLICY_COLUMNS: &str =
+    "policy_id, car_id, policy_type, insurer, policy_number, coverage_start, coverage_end, premium, premium_currency, notes, created_at, updated_at";
+
+pub const INSURANCE_POLICY_PATCH: PatchTarget = PatchTarget {
+    table: "InsurancePolicies",
+    id_column: "policy_id",
+    fields: &[
+        "car_id",
+        "policy_type",
+        "insurer",
+        "policy_number",
+        "coverage_start",
+        "coverage_end",
+        "premium",
+        "premium_currency",
+        "notes",
+    ],
+};
+
+/// 保険期間の終了日が開始日より後か確認する。日付の形式が正しくない場合は項目ごとの検証に任せる
+fn validate_coverage_dates(policy: &InsurancePolicy) -> Result<(), ValidationError> {
+    let dates = (
+        validation::parse_date(&policy.coverage_start),
+        validation::parse_date(&policy.coverage_end),
+    );
+    match dates {
+        (Some(start), Some(end)) if end <= start => Err(validation::field_error(
+            "coverage_end",
+            "after_coverage_start",
+            "The coverage end date must be after the start date.",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// 更新時期の状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenewalStatus {
+    Active,
+    /// 満了日まで within_days 日以内
+    DueSoon,
+    Expired,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PolicyRenewal {
+    #[serde(flatten)]
+    pub policy: InsurancePolicy,
+    /// 満了日までの日数。満了日を過ぎている場合は負の値
+    pub days_until_expiry: i64,
+    pub status: RenewalStatus,
+}
+
+impl PolicyRenewal {
+    /// today 時点の更新時期を求める。満了日が読めない場合は None
+    pub fn new(policy: InsurancePolicy, today: NaiveDate, within_days: i64) -> Option<Self> {
+        let end = NaiveDate::parse_from_str(&policy.coverage_end, "%Y-%m-%d").ok()?;
+        let days_until_expiry = (end - today).num_days();
+        let status = if days_until_expiry < 0 {
+            RenewalStatus::Expired
+        } else if days_until_expiry <= within_days {
+            RenewalStatus::DueSoon
+        } else {
+            RenewalStatus::Active
+        };
+        Some(Self {
+            policy,
+            days_until_expiry,
+            status,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenewalQuery {
+    /// 満了日まで何日以内を更新時期とするか
+    #[serde(default = "default_within_days")]
+    pub within_days: i64,
+    /// 満了済みの保険も含める
+    #[serde(default)]
+    pub include_expired: bool,
+}
+
+fn default_within_days() -> i64 {
+    60
+}
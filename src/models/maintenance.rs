@@ -1,40 +1,182 @@
+use crate::i18n::Lang;
+use crate::patch::PatchTarget;
+use crate::validation::validate_date;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sqlx::FromRow;
 use time::OffsetDateTime;
+use validator::Validate;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// 整備の種類を定義する。列挙値、一覧、値の名前、日本語の表示名はこの表から作る
+macro_rules! maintenance_types {
+    ($($variant:ident => $name:literal, $ja:literal;)+) => {
+        /// 整備の種類。DB の ENUM と同じ値で、追加するときは db/init.sql の ENUM も合わせて変更する
+        #[derive(
+            Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, sqlx::Type,
+        )]
+        pub enum MaintenanceType {
+            $(
+                #[serde(rename = $name)]
+                #[sqlx(rename = $name)]
+                $variant,
+            )+
+        }
+
+        pub const MAINTENANCE_TYPES: &[MaintenanceType] = &[$(MaintenanceType::$variant),+];
+
+        impl MaintenanceType {
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $(MaintenanceType::$variant => $name,)+
+                }
+            }
+
+            fn label_ja(self) -> &'static str {
+                match self {
+                    $(MaintenanceType::$variant => $ja,)+
+                }
+            }
+        }
+    };
+}
+
+maintenance_types! {
+    OilChange => "Oil Change", "オイル交換";
+    OilFilterChange => "Oil Filter Change", "オイルエレメント交換";
+    HeadlightChange => "Headlight Change", "ヘッドライト交換";
+    PositionLightChange => "Position Light Change", "ポジションライト交換";
+    FogLightChange => "Fog Light Change", "フォグライト交換";
+    TurnSignalChange => "Turn Signal Change", "ウインカー交換";
+    BrakeLightChange => "Brake Light Change", "ブレーキライト交換";
+    LicensePlateLightChange => "License Plate Light Change", "ナンバー灯交換";
+    BackupLightChange => "Backup Light Change", "バックライト交換";
+    CarWash => "Car Wash", "洗車";
+    WiperBladeChange => "Wiper Blade Change", "ワイパーブレード交換";
+    BrakePadChange => "Brake Pad Change", "ブレーキパッド交換";
+    BrakeDiscChange => "Brake Disc Change", "ブレーキディスク交換";
+    TireChange => "Tire Change", "タイヤ交換";
+    BatteryChange => "Battery Change", "バッテリー交換";
+    TimingBeltChange => "Timing Belt Change", "タイミングベルト交換";
+    CoolantRefill => "Coolant Refill", "クーラント補充";
+    WasherFluidRefill => "Washer Fluid Refill", "ウォッシャー液補充";
+    Other => "Other", "その他";
+}
+
+impl MaintenanceType {
+    /// 表示名。Other 以外は整備記録のタイトルにも使う
+    pub fn label(self, lang: Lang) -> &'static str {
+        match lang {
+            Lang::Ja => self.label_ja(),
+            Lang::En => self.as_str(),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        MAINTENANCE_TYPES
+            .iter()
+            .copied()
+            .find(|maint_type| maint_type.as_str() == name)
+    }
+}
+
+/// 整備の種類と表示名
+#[derive(Debug, Serialize)]
+pub struct MaintenanceTypeOption {
+    pub value: MaintenanceType,
+    pub labels: MaintenanceTypeLabels,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MaintenanceTypeLabels {
+    pub ja: &'static str,
+    pub en: &'static str,
+}
+
+impl From<MaintenanceType> for MaintenanceTypeOption {
+    fn from(value: MaintenanceType) -> Self {
+        Self {
+            value,
+            labels: MaintenanceTypeLabels {
+                ja: value.label(Lang::Ja),
+                en: value.label(Lang::En),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Validate)]
 pub struct Maintenance {
     pub maint_id: Option<i32>,
     pub car_id: i32,
-    pub maint_type: String, // You can use an enum here if you want to strictly type the maintenance types
+    pub maint_type: MaintenanceType,
+    #[validate(length(max = 255))]
     pub maint_title: String,
+    #[validate(custom(function = "validate_date"))]
     pub maint_date: String,
+    #[validate(length(max = 10000))]
     pub maint_description: String,
     pub created_at: Option<OffsetDateTime>,
     pub updated_at: Option<OffsetDateTime>,
 }
 
-lazy_static::lazy_static! {
-    pub static ref MAINTENANCE_TITLES: HashMap<&'static str, &'static str> = {
-        let mut m = HashMap::new();
-        m.insert("Oil Change", "オイル交換");
-        m.insert("Oil Filter Change", "オイルエレメント交換");
-        m.insert("Headlight Change", "ヘッドライト交換");
-        m.insert("Position Light Change", "ポジションライト交換");
-        m.insert("Fog Light Change", "フォグライト交換");
-        m.insert("Turn Signal Change", "ウインカー交換");
-        m.insert("Brake Light Change", "ブレーキライト交換");
-        m.insert("License Plate Light Change", "ナンバー灯交換");
-        m.insert("Backup Light Change", "バックライト交換");
-        m.insert("Car Wash", "洗車");
-        m.insert("Wiper Blade Change", "ワイパーブレード交換");
-        m.insert("Brake Pad Change", "ブレーキパッド交換");
-        m.insert("Brake Disc Change", "ブレーキディスク交換");
-        m.insert("Tire Change", "タイヤ交換");
-        m.insert("Battery Change", "バッテリー交換");
-        m.insert("Timing Belt Change", "タイミングベルト交換");
-        m.insert("Coolant Refill", "クーラント補充");
-        m.insert("Washer Fluid Refill", "ウォッシャー液補充");
-        m
-    };
+impl Maintenance {
+    /// 表示するタイトル。Other 以外のタイトルは保存せず、読むときに種類の表示名を使う
+    pub fn title(&self, lang: Lang) -> &str {
+        match self.maint_type {
+            MaintenanceType::Other => &self.maint_title,
+            maint_type => maint_type.label(lang),
+        }
+    }
+
+    /// タイトルを指定した言語にしたもの。応答を返す前に使う
+    pub fn localized(mut self, lang: Lang) -> Self {
+        if self.maint_type != MaintenanceType::Other {
+            self.maint_title = self.maint_type.label(lang).to_string();
+        }
+        self
+    }
+}
+
+pub const MAINTENANCE_COLUMNS: &str =
+    "maint_id, car_id, maint_type, maint_title, maint_date, maint_description, created_at, updated_at";
+
+pub const MAINTENANCE_PATCH: PatchTarget = PatchTarget {
+    table: "Maintenances",
+    id_column: "maint_id",
+    fields: &[
+        "car_id",
+        "maint_type",
+        "maint_title",
+        "maint_date",
+        "maint_description",
+    ],
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn db_enum_matches_maintenance_types() {
+        let schema = include_str!("../../db/init.sql");
+        let start = schema.find("maint_type ENUM(").unwrap() + "maint_type ENUM(".len();
+        let end = start + schema[start..].find(')').unwrap();
+        let values: Vec<&str> = schema[start..end]
+            .split(',')
+            .map(|value| value.trim().trim_matches('\''))
+            .collect();
+        let names: Vec<&str> = MAINTENANCE_TYPES.iter().map(|t| t.as_str()).collect();
+        assert_eq!(values, names);
+    }
+
+    #[test]
+    fn serde_uses_the_db_names() {
+        for maint_type in MAINTENANCE_TYPES {
+            let json = serde_json::to_string(maint_type).unwrap();
+            assert_eq!(json, format!("\"{}\"", maint_type.as_str()));
+            assert_eq!(
+                MaintenanceType::from_name(maint_type.as_str()),
+                Some(*maint_type)
+            );
+        }
+    }
 }
@@ -1,8 +1,26 @@
 pub mod accident;
+pub mod account_archive;
+pub mod account_deletion;
+pub mod attachment;
+pub mod audit_log;
 pub mod car;
+pub mod car_history;
+pub mod car_invitation;
+pub mod car_share_link;
+pub mod car_transfer;
+pub mod exchange_rate;
+pub mod expense;
 pub mod fuel_efficiency;
+pub mod history_report;
+pub mod insurance_policy;
 pub mod maintenance;
+pub mod part;
 pub mod periodic_inspection;
+pub mod tire;
+pub mod trash;
 pub mod tuning;
 pub mod user;
 pub mod user_car;
+pub mod user_preferences;
+pub mod vehicle_spec;
+pub mod vehicle_tax;
@@ -0,0 +1,138 @@
+use crate::exchange_rates::BASE_CURRENCY;
+use crate::patch::PatchTarget;
+use crate::validation::{validate_amount, validate_currency, validate_date};
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+use validator::Validate;
+
+/// ユーザーの部品・消耗品の在庫
+#[derive(Debug, Serialize, Deserialize, FromRow, Validate)]
+pub struct Part {
+    pub part_id: Option<i32>,
+    /// 品番
+    #[validate(length(min = 1, max = 255))]
+    pub part_number: String,
+    #[validate(length(min = 1, max = 255))]
+    pub part_name: String,
+    #[serde(default)]
+    #[validate(length(max = 255))]
+    pub part_brand: Option<String>,
+    /// 在庫数
+    #[serde(default)]
+    #[validate(range(min = 0))]
+    pub quantity: i32,
+    /// 1 個あたりの価格。cost_currency の通貨で保存する
+    #[serde(default)]
+    #[validate(custom(function = "validate_amount"))]
+    pub unit_cost: Option<BigDecimal>,
+    #[serde(default = "default_currency")]
+    #[validate(custom(function = "validate_currency"))]
+    pub cost_currency: String,
+    /// 購入先
+    #[serde(default)]
+    #[validate(length(max = 255))]
+    pub supplier: Option<String>,
+    #[serde(default)]
+    #[validate(length(max = 10000))]
+    pub notes: String,
+    pub created_at: Option<OffsetDateTime>,
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+fn default_currency() -> String {
+    BASE_CURRENCY.to_string()
+}
+
+pub const PART_COLUMNS: &str =
+    "part_id, part_number, part_name, part_brand, quantity, unit_cost, cost_currency, supplier, notes, created_at, updated_at";
+
+pub const PART_PATCH: PatchTarget = PatchTarget {
+    table: "Parts",
+    id_column: "part_id",
+    fields: &[
+        "part_number",
+        "part_name",
+        "part_brand",
+        "quantity",
+        "unit_cost",
+        "cost_currency",
+        "supplier",
+        "notes",
+    ],
+};
+
+/// 部品を使った記録の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartUsageOwner {
+    Maintenance,
+    Tuning,
+}
+
+impl PartUsageOwner {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PartUsageOwner::Maintenance => "maintenance",
+            PartUsageOwner::Tuning => "tuning",
+        }
+    }
+
+    pub fn table(&self) -> &'static str {
+        match self {
+            PartUsageOwner::Maintenance => "Maintenances",
+            PartUsageOwner::Tuning => "Tunings",
+        }
+    }
+
+    /// 記録の ID の列。PartUsages でも同じ名前の列で記録を参照する
+    pub fn id_column(&self) -> &'static str {
+        match self {
+            PartUsageOwner::Maintenance => "maint_id",
+            PartUsageOwner::Tuning => "tuning_id",
+        }
+    }
+}
+
+/// 整備で交換した部品・チューニングで取り付けた部品。
+/// 在庫から部品を削除しても残るように、品番・名前・価格は使った時点のものを写しておく
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PartUsage {
+    pub usage_id: i32,
+    /// 在庫から削除した部品では None
+    pub part_id: Option<i32>,
+    pub maint_id: Option<i32>,
+    pub tuning_id: Option<i32>,
+    pub part_number: String,
+    pub part_name: String,
+    pub part_brand: Option<String>,
+    pub quantity: i32,
+    pub unit_cost: Option<BigDecimal>,
+    pub cost_currency: String,
+    /// チューニングを外したときに在庫に戻した
+    pub returned: bool,
+    pub created_at: Option<OffsetDateTime>,
+}
+
+pub const PART_USAGE_COLUMNS: &str =
+    "usage_id, part_id, maint_id, tuning_id, part_number, part_name, part_brand, quantity, unit_cost, cost_currency, returned, created_at";
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct PartUsageRequest {
+    pub part_id: i32,
+    #[validate(range(min = 1))]
+    pub quantity: i32,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct RemoveTuningRequest {
+    #[validate(custom(function = "validate_date"))]
+    pub removed_date: String,
+    /// 取り付けた部品を在庫に戻す
+    #[serde(default = "default_return_parts")]
+    pub return_parts: bool,
+}
+
+fn default_return_parts() -> bool {
+    true
+}
@@ -1,13 +1,46 @@
+use crate::patch::PatchTarget;
+use crate::validation::{self, validate_date};
 use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
 use time::OffsetDateTime;
+use validator::{Validate, ValidationError};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, FromRow, Validate)]
+#[validate(schema(function = "validate_inspection_dates"))]
 pub struct PeriodicInspection {
     pub pi_id: Option<i32>,
     pub car_id: i32,
+    #[validate(length(min = 1, max = 255))]
     pub pi_name: String,
+    #[validate(custom(function = "validate_date"))]
     pub pi_date: String,
+    #[validate(custom(function = "validate_date"))]
     pub pi_nextdate: String,
     pub created_at: Option<OffsetDateTime>,
     pub updated_at: Option<OffsetDateTime>,
 }
+
+pub const PERIODIC_INSPECTION_COLUMNS: &str =
+    "pi_id, car_id, pi_name, pi_date, pi_nextdate, created_at, updated_at";
+
+pub const PERIODIC_INSPECTION_PATCH: PatchTarget = PatchTarget {
+    table: "PeriodicInspection",
+    id_column: "pi_id",
+    fields: &["car_id", "pi_name", "pi_date", "pi_nextdate"],
+};
+
+/// 次回の点検日が点検日より後か確認する。日付の形式が正しくない場合は項目ごとの検証に任せる
+fn validate_inspection_dates(inspection: &PeriodicInspection) -> Result<(), ValidationError> {
+    let dates = (
+        validation::parse_date(&inspection.pi_date),
+        validation::parse_date(&inspection.pi_nextdate),
+    );
+    match dates {
+        (Some(date), Some(nextdate)) if nextdate <= date => Err(validation::field_error(
+            "pi_nextdate",
+            "after_pi_date",
+            "The next inspection date must be after the inspection date.",
+        )),
+        _ => Ok(()),
+    }
+}
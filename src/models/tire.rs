@@ -0,0 +1,168 @@
+use crate::patch::PatchTarget;
+use crate::validation::validate_date;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+use validator::Validate;
+
+/// タイヤの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum TireSeason {
+    Summer,
+    /// スタッドレスタイヤ
+    Winter,
+    AllSeason,
+}
+
+/// 車両のタイヤ 1 セット
+#[derive(Debug, Serialize, Deserialize, FromRow, Validate)]
+pub struct TireSet {
+    pub tire_set_id: Option<i32>,
+    pub car_id: i32,
+    pub tire_season: TireSeason,
+    /// 195/65R15 のようなサイズ表記
+    #[validate(length(min = 1, max = 32))]
+    pub tire_size: String,
+    #[validate(length(min = 1, max = 255))]
+    pub tire_brand: String,
+    #[serde(default)]
+    #[validate(length(max = 255))]
+    pub tire_model: Option<String>,
+    #[serde(default)]
+    #[validate(custom(function = "validate_date"))]
+    pub purchase_date: Option<String>,
+    /// 新品時の溝の深さ（mm）
+    #[serde(default)]
+    #[validate(range(min = 0.0, max = 30.0))]
+    pub initial_tread_depth: Option<f32>,
+    #[serde(default)]
+    #[validate(length(max = 10000))]
+    pub notes: String,
+    pub created_at: Option<OffsetDateTime>,
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+pub const TIRE_SET_COLUMNS: &str =
+    "tire_set_id, car_id, tire_season, tire_size, tire_brand, tire_model, purchase_date, initial_tread_depth, notes, created_at, updated_at";
+
+// 装着の記録があるので車両は変更できない
+pub const TIRE_SET_PATCH: PatchTarget = PatchTarget {
+    table: "TireSets",
+    id_column: "tire_set_id",
+    fields: &[
+        "tire_season",
+        "tire_size",
+        "tire_brand",
+        "tire_model",
+        "purchase_date",
+        "initial_tread_depth",
+        "notes",
+    ],
+};
+
+/// 装着・取り外し
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum TireEventType {
+    Mount,
+    Unmount,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TireEvent {
+    pub tire_event_id: i32,
+    pub tire_set_id: i32,
+    pub car_id: i32,
+    pub event_type: TireEventType,
+    pub event_date: String,
+    /// そのときの走行距離（km）
+    pub odometer: i32,
+    pub notes: String,
+    pub created_at: Option<OffsetDateTime>,
+}
+
+pub const TIRE_EVENT_COLUMNS: &str =
+    "tire_event_id, tire_set_id, car_id, event_type, event_date, odometer, notes, created_at";
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct TireEventRequest {
+    #[validate(custom(function = "validate_date"))]
+    pub event_date: String,
+    #[validate(range(min = 0))]
+    pub odometer: i32,
+    #[serde(default)]
+    #[validate(length(max = 10000))]
+    pub notes: String,
+}
+
+/// 溝を測った位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum TirePosition {
+    FrontLeft,
+    FrontRight,
+    RearLeft,
+    RearRight,
+    Spare,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, Validate)]
+pub struct TireMeasurement {
+    pub measurement_id: Option<i32>,
+    #[serde(default)]
+    pub tire_set_id: i32,
+    #[validate(custom(function = "validate_date"))]
+    pub measured_date: String,
+    /// 位置を省略した場合はセットで最も浅い溝の深さ
+    #[serde(default)]
+    pub position: Option<TirePosition>,
+    /// 溝の深さ（mm）
+    #[validate(range(min = 0.0, max = 30.0))]
+    pub tread_depth: f32,
+    pub created_at: Option<OffsetDateTime>,
+}
+
+pub const TIRE_MEASUREMENT_COLUMNS: &str =
+    "measurement_id, tire_set_id, measured_date, position, tread_depth, created_at";
+
+/// タイヤセットと装着・摩耗の状態
+#[derive(Debug, Serialize)]
+pub struct TireSetStatus {
+    #[serde(flatten)]
+    pub tire_set: TireSet,
+    pub mounted: bool,
+    /// 装着中なら装着した日
+    pub mounted_since: Option<String>,
+    /// 装着していた間の走行距離（km）。装着中の区間は車両の現在の走行距離まで数える
+    pub distance_km: i64,
+    /// 最後に測った日の最も浅い溝の深さ（mm）
+    pub latest_tread_depth: Option<f32>,
+    pub last_measured_date: Option<String>,
+}
+
+/// 通知の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TireAlertKind {
+    /// スタッドレスタイヤに替える時期
+    SwapToWinter,
+    /// 夏タイヤに戻す時期
+    SwapToSummer,
+    /// 溝が浅くなっている
+    TreadWear,
+    /// 使用限度（スタッドレスはプラットフォーム）に達している
+    TreadLimit,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TireAlert {
+    pub kind: TireAlertKind,
+    pub car_id: i32,
+    pub car_name: String,
+    pub tire_set_id: i32,
+    pub tread_depth: Option<f32>,
+}
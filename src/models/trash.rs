@@ -0,0 +1,118 @@
+use crate::models::attachment::AttachmentOwner;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// ゴミ箱に入る車両と記録の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrashKind {
+    Car,
+    Maintenance,
+    Tuning,
+    FuelEfficiency,
+    Accident,
+    PeriodicInspection,
+    InsurancePolicy,
+    VehicleTax,
+    TireSet,
+}
+
+impl TrashKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrashKind::Car => "car",
+            TrashKind::Maintenance => "maintenance",
+            TrashKind::Tuning => "tuning",
+            TrashKind::FuelEfficiency => "fuel_efficiency",
+            TrashKind::Accident => "accident",
+            TrashKind::PeriodicInspection => "periodic_inspection",
+            TrashKind::InsurancePolicy => "insurance_policy",
+            TrashKind::VehicleTax => "vehicle_tax",
+            TrashKind::TireSet => "tire_set",
+        }
+    }
+
+    pub fn table(&self) -> &'static str {
+        match self {
+            TrashKind::Car => "Cars",
+            TrashKind::Maintenance => "Maintenances",
+            TrashKind::Tuning => "Tunings",
+            TrashKind::FuelEfficiency => "FuelEfficiencies",
+            TrashKind::Accident => "Accidents",
+            TrashKind::PeriodicInspection => "PeriodicInspection",
+            TrashKind::InsurancePolicy => "InsurancePolicies",
+            TrashKind::VehicleTax => "VehicleTaxes",
+            TrashKind::TireSet => "TireSets",
+        }
+    }
+
+    pub fn id_column(&self) -> &'static str {
+        match self {
+            TrashKind::Car => "car_id",
+            TrashKind::Maintenance => "maint_id",
+            TrashKind::Tuning => "tuning_id",
+            TrashKind::FuelEfficiency => "fe_id",
+            TrashKind::Accident => "accident_id",
+            TrashKind::PeriodicInspection => "pi_id",
+            TrashKind::InsurancePolicy => "policy_id",
+            TrashKind::VehicleTax => "tax_id",
+            TrashKind::TireSet => "tire_set_id",
+        }
+    }
+
+    /// ゴミ箱の一覧で名前として表示する値。テーブルの別名 `t` で参照する。
+    /// 整備記録は Other 以外のタイトルを保存しないので、種類を返して表示するときに訳す
+    pub fn label_column(&self) -> &'static str {
+        match self {
+            TrashKind::Car => "t.car_name",
+            TrashKind::Maintenance => "IF(t.maint_type = 'Other', t.maint_title, t.maint_type)",
+            TrashKind::Tuning => "t.tuning_name",
+            TrashKind::FuelEfficiency => "t.fe_date",
+            TrashKind::Accident => "t.accident_date",
+            TrashKind::PeriodicInspection => "t.pi_name",
+            TrashKind::InsurancePolicy => "t.insurer",
+            TrashKind::VehicleTax => "t.due_date",
+            TrashKind::TireSet => "t.tire_brand",
+        }
+    }
+
+    pub fn attachment_owner(&self) -> Option<AttachmentOwner> {
+        match self {
+            TrashKind::Maintenance => Some(AttachmentOwner::Maintenance),
+            TrashKind::Tuning => Some(AttachmentOwner::Tuning),
+            TrashKind::Accident => Some(AttachmentOwner::Accident),
+            TrashKind::PeriodicInspection => Some(AttachmentOwner::PeriodicInspection),
+            TrashKind::InsurancePolicy => Some(AttachmentOwner::InsurancePolicy),
+            TrashKind::Car
+            | TrashKind::FuelEfficiency
+            | TrashKind::VehicleTax
+            | TrashKind::TireSet => None,
+        }
+    }
+}
+
+/// 車両に属する記録の種類
+pub const RECORD_KINDS: [TrashKind; 8] = [
+    TrashKind::Maintenance,
+    TrashKind::Tuning,
+    TrashKind::FuelEfficiency,
+    TrashKind::Accident,
+    TrashKind::PeriodicInspection,
+    TrashKind::InsurancePolicy,
+    TrashKind::VehicleTax,
+    TrashKind::TireSet,
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashItem {
+    pub kind: TrashKind,
+    pub id: i32,
+    pub car_id: i32,
+    pub car_name: String,
+    pub label: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub deleted_at: OffsetDateTime,
+    /// この日時を過ぎると完全に削除される
+    #[serde(with = "time::serde::rfc3339")]
+    pub purge_at: OffsetDateTime,
+}
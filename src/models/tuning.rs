@@ -1,13 +1,40 @@
+use crate::patch::PatchTarget;
+use crate::validation::validate_date;
 use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
 use time::OffsetDateTime;
+use validator::Validate;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, FromRow, Validate)]
 pub struct Tuning {
     pub tuning_id: Option<i32>,
     pub car_id: i32,
+    #[validate(length(min = 1, max = 255))]
     pub tuning_name: String,
+    #[validate(custom(function = "validate_date"))]
     pub tuning_date: String,
+    #[validate(length(max = 10000))]
     pub tuning_description: String,
+    /// 外して純正に戻した日。`POST /api/tunings/:tuning_id/remove` でだけ設定する
+    #[serde(default)]
+    #[validate(custom(function = "validate_date"))]
+    pub tuning_removed_date: Option<String>,
     pub created_at: Option<OffsetDateTime>,
     pub updated_at: Option<OffsetDateTime>,
 }
+
+pub const TUNING_COLUMNS: &str =
+    "tuning_id, car_id, tuning_name, tuning_date, tuning_description, tuning_removed_date, created_at, updated_at";
+
+pub const TUNING_PATCH: PatchTarget = PatchTarget {
+    table: "Tunings",
+    id_column: "tuning_id",
+    fields: &["car_id", "tuning_name", "tuning_date", "tuning_description"],
+};
+
+#[derive(Debug, Deserialize)]
+pub struct CarTuningQuery {
+    /// true なら外していない（現在の仕様の）カスタムだけを返す
+    #[serde(default)]
+    pub active: bool,
+}
@@ -1,11 +1,25 @@
+use crate::patch::PatchTarget;
 use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
 use time::OffsetDateTime;
+use validator::Validate;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, FromRow, Validate)]
 pub struct User {
+    #[validate(length(min = 1, max = 255))]
     pub firebase_user_id: String,
+    #[validate(email, length(max = 255))]
     pub user_email: String,
+    #[validate(length(min = 1, max = 255))]
     pub user_name: String,
     pub created_at: Option<OffsetDateTime>,
     pub updated_at: Option<OffsetDateTime>,
 }
+
+pub const USER_COLUMNS: &str = "firebase_user_id, user_email, user_name, created_at, updated_at";
+
+pub const USER_PATCH: PatchTarget = PatchTarget {
+    table: "Users",
+    id_column: "firebase_user_id",
+    fields: &["user_email", "user_name"],
+};
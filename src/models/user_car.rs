@@ -1,7 +1,49 @@
 use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum CarRole {
+    // 権限の弱い順に並べる（比較に使う）
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl CarRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CarRole::Viewer => "viewer",
+            CarRole::Editor => "editor",
+            CarRole::Owner => "owner",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct UserCar {
     pub firebase_user_id: String,
     pub car_id: i32,
+    pub role: CarRole,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct CarMember {
+    pub firebase_user_id: String,
+    pub user_email: String,
+    pub user_name: String,
+    pub role: CarRole,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateMemberRoleRequest {
+    pub role: CarRole,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransferOwnershipRequest {
+    pub firebase_user_id: String,
 }
@@ -0,0 +1,169 @@
+use crate::i18n::Lang;
+use crate::patch::PatchTarget;
+use crate::validation;
+use chrono::{NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+use validator::{Validate, ValidationError};
+
+const KM_PER_MILE: f64 = 1.609344;
+const LITRES_PER_US_GALLON: f64 = 3.785411784;
+const LITRES_PER_IMPERIAL_GALLON: f64 = 4.54609;
+
+/// 距離の単位。記録は km で保存する
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum DistanceUnit {
+    #[default]
+    Km,
+    Mi,
+}
+
+impl DistanceUnit {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DistanceUnit::Km => "km",
+            DistanceUnit::Mi => "mi",
+        }
+    }
+
+    pub fn from_km(self, km: f64) -> f64 {
+        match self {
+            DistanceUnit::Km => km,
+            DistanceUnit::Mi => km / KM_PER_MILE,
+        }
+    }
+}
+
+/// 体積の単位。給油量は L で保存する
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum VolumeUnit {
+    #[default]
+    L,
+    UsGal,
+    ImpGal,
+}
+
+impl VolumeUnit {
+    pub fn from_litres(self, litres: f64) -> f64 {
+        match self {
+            VolumeUnit::L => litres,
+            VolumeUnit::UsGal => litres / LITRES_PER_US_GALLON,
+            VolumeUnit::ImpGal => litres / LITRES_PER_IMPERIAL_GALLON,
+        }
+    }
+}
+
+/// 燃費の表し方
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum FuelEconomyUnit {
+    #[default]
+    KmPerL,
+    #[serde(rename = "l_per_100km")]
+    #[sqlx(rename = "l_per_100km")]
+    LPer100km,
+    MpgUs,
+    MpgImp,
+}
+
+impl FuelEconomyUnit {
+    /// km/L の燃費をこの単位にする。L/100km は値が小さいほど燃費が良い
+    pub fn from_km_per_litre(self, km_per_litre: f64) -> f64 {
+        match self {
+            FuelEconomyUnit::KmPerL => km_per_litre,
+            FuelEconomyUnit::LPer100km => 100.0 / km_per_litre,
+            FuelEconomyUnit::MpgUs => km_per_litre * LITRES_PER_US_GALLON / KM_PER_MILE,
+            FuelEconomyUnit::MpgImp => km_per_litre * LITRES_PER_IMPERIAL_GALLON / KM_PER_MILE,
+        }
+    }
+}
+
+/// ユーザーごとの表示の設定。Users に保存する
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, Validate)]
+pub struct UserPreferences {
+    #[serde(default)]
+    pub distance_unit: DistanceUnit,
+    #[serde(default)]
+    pub volume_unit: VolumeUnit,
+    #[serde(default)]
+    pub fuel_economy_unit: FuelEconomyUnit,
+    /// ISO 4217 の通貨コード
+    #[serde(default = "default_currency")]
+    #[validate(custom(function = "validation::validate_currency"))]
+    pub currency: String,
+    /// IANA のタイムゾーン名
+    #[serde(default = "default_time_zone")]
+    #[validate(custom(function = "validate_time_zone"))]
+    pub time_zone: String,
+    /// None の場合は Accept-Language に従う
+    #[serde(default)]
+    pub language: Option<Lang>,
+    #[serde(skip_deserializing)]
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+fn default_currency() -> String {
+    "JPY".to_string()
+}
+
+fn default_time_zone() -> String {
+    "Asia/Tokyo".to_string()
+}
+
+impl Default for UserPreferences {
+    fn default() -> Self {
+        Self {
+            distance_unit: DistanceUnit::default(),
+            volume_unit: VolumeUnit::default(),
+            fuel_economy_unit: FuelEconomyUnit::default(),
+            currency: default_currency(),
+            time_zone: default_time_zone(),
+            language: None,
+            updated_at: None,
+        }
+    }
+}
+
+impl UserPreferences {
+    pub fn tz(&self) -> Tz {
+        self.time_zone.parse().unwrap_or(Tz::Asia__Tokyo)
+    }
+
+    /// ユーザーのタイムゾーンでの今日の日付
+    pub fn today(&self) -> NaiveDate {
+        Utc::now().with_timezone(&self.tz()).date_naive()
+    }
+}
+
+fn validate_time_zone(time_zone: &str) -> Result<(), ValidationError> {
+    match time_zone.parse::<Tz>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(validation::error(
+            "time_zone",
+            "Use an IANA time zone name such as Asia/Tokyo.",
+        )),
+    }
+}
+
+pub const USER_PREFERENCES_COLUMNS: &str =
+    "distance_unit, volume_unit, fuel_economy_unit, currency, time_zone, language, updated_at";
+
+pub const USER_PREFERENCES_PATCH: PatchTarget = PatchTarget {
+    table: "Users",
+    id_column: "firebase_user_id",
+    fields: &[
+        "distance_unit",
+        "volume_unit",
+        "fuel_economy_unit",
+        "currency",
+        "time_zone",
+        "language",
+    ],
+};
@@ -0,0 +1,58 @@
+use crate::models::car::{Car, FuelType, Transmission};
+use crate::vin;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// 型式ごとの諸元。VehicleSpecs に初期データとして登録しておく
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct VehicleSpec {
+    /// 排出ガス規制の識別記号を除いた型式（例: ZVW30）
+    pub model_code: String,
+    pub maker: String,
+    pub model: String,
+    pub year_from: i32,
+    pub year_to: Option<i32>,
+    pub displacement: Option<i32>,
+    pub fuel_type: FuelType,
+    pub tank_capacity: Option<f32>,
+    pub transmission: Option<Transmission>,
+}
+
+pub const VEHICLE_SPEC_COLUMNS: &str =
+    "model_code, maker, model, year_from, year_to, displacement, fuel_type, tank_capacity, transmission";
+
+#[derive(Debug, Deserialize)]
+pub struct VehicleSpecQuery {
+    /// メーカー名・車名・型式の部分一致
+    pub q: Option<String>,
+}
+
+/// 入力された型式をカタログの表記にそろえる。
+/// 全角英数字は半角に、英字は大文字にし、`DAA-ZVW30` のような識別記号は除く
+pub fn normalize_model_code(code: &str) -> String {
+    let code = vin::normalize(code);
+    match code.split_once('-') {
+        Some((prefix, rest)) if (1..=3).contains(&prefix.len()) && !rest.is_empty() => {
+            rest.to_string()
+        }
+        _ => code,
+    }
+}
+
+impl VehicleSpec {
+    /// 空欄の諸元だけをカタログの値で埋める。入力済みの値は変えない
+    pub fn fill(&self, car: &mut Car) {
+        car.car_maker.get_or_insert_with(|| self.maker.clone());
+        car.car_model.get_or_insert_with(|| self.model.clone());
+        if car.car_displacement.is_none() {
+            car.car_displacement = self.displacement;
+        }
+        car.car_fuel_type.get_or_insert(self.fuel_type);
+        if car.car_tank_capacity.is_none() {
+            car.car_tank_capacity = self.tank_capacity;
+        }
+        if car.car_transmission.is_none() {
+            car.car_transmission = self.transmission;
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use crate::exchange_rates::BASE_CURRENCY;
+use crate::patch::PatchTarget;
+use crate::validation::{validate_amount, validate_currency, validate_date};
+use crate::vehicle_tax::{Surcharge, TaxAssessment, TaxError};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+use validator::Validate;
+
+/// 税の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum TaxType {
+    /// 自動車税種別割
+    AutomobileTax,
+    /// 軽自動車税種別割
+    LightVehicleTax,
+    /// 自動車重量税。車検のときに納める
+    WeightTax,
+}
+
+/// 納付の状態
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum PaymentStatus {
+    #[default]
+    Unpaid,
+    Paid,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Validate)]
+pub struct VehicleTax {
+    pub tax_id: Option<i32>,
+    pub car_id: i32,
+    pub tax_type: TaxType,
+    /// 4 月始まりの年度
+    #[validate(range(min = 1950, max = 2100))]
+    pub fiscal_year: i32,
+    /// 納期限。省略すると年税は 5 月末日、重量税は次回の車検の日にする
+    #[serde(default)]
+    #[validate(custom(function = "validate_date"))]
+    pub due_date: Option<String>,
+    /// 税額。省略すると車両の排気量・重量・初度登録年月日から計算する
+    #[serde(default)]
+    #[validate(custom(function = "validate_amount"))]
+    pub tax_amount: Option<BigDecimal>,
+    #[serde(default = "default_currency")]
+    #[validate(custom(function = "validate_currency"))]
+    pub tax_currency: String,
+    #[serde(default)]
+    pub payment_status: PaymentStatus,
+    /// 納付日。納付済みで省略すると今日にする
+    #[serde(default)]
+    #[validate(custom(function = "validate_date"))]
+    pub paid_date: Option<String>,
+    #[serde(default)]
+    #[validate(length(max = 10000))]
+    pub notes: String,
+    pub created_at: Option<OffsetDateTime>,
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+fn default_currency() -> String {
+    BASE_CURRENCY.to_string()
+}
+
+pub const VEHICLE_TAX_COLUMNS: &str =
+    "tax_id, car_id, tax_type, fiscal_year, due_date, tax_amount, tax_currency, payment_status, paid_date, notes, created_at, updated_at";
+
+pub const VEHICLE_TAX_PATCH: PatchTarget = PatchTarget {
+    table: "VehicleTaxes",
+    id_column: "tax_id",
+    fields: &[
+        "car_id",
+        "tax_type",
+        "fiscal_year",
+        "due_date",
+        "tax_amount",
+        "tax_currency",
+        "payment_status",
+        "paid_date",
+        "notes",
+    ],
+};
+
+#[derive(Debug, Deserialize)]
+pub struct TaxEstimateQuery {
+    /// 省略した場合は今日の年度
+    pub fiscal_year: Option<i32>,
+}
+
+/// 車両の税額の見積もり
+#[derive(Debug, Serialize)]
+pub struct TaxEstimate {
+    pub tax_type: TaxType,
+    pub fiscal_year: i32,
+    pub due_date: Option<String>,
+    /// 円。計算できない場合は None
+    pub amount: Option<i64>,
+    pub surcharge: Option<Surcharge>,
+    /// 計算できない理由のエラーコード
+    pub error: Option<String>,
+}
+
+impl TaxEstimate {
+    pub fn new(
+        tax_type: TaxType,
+        fiscal_year: i32,
+        due_date: Option<String>,
+        assessment: Result<TaxAssessment, TaxError>,
+    ) -> Self {
+        let (amount, surcharge, error) = match assessment {
+            Ok(assessment) => (Some(assessment.amount), Some(assessment.surcharge), None),
+            Err(e) => (None, None, Some(e.code.to_string())),
+        };
+        Self {
+            tax_type,
+            fiscal_year,
+            due_date,
+            amount,
+            surcharge,
+            error,
+        }
+    }
+}
+
+/// 納期限が近い未納の税
+#[derive(Debug, Serialize)]
+pub struct TaxDue {
+    #[serde(flatten)]
+    pub tax: VehicleTax,
+    /// 納期限までの日数。過ぎている場合は負の値
+    pub days_until_due: i64,
+    pub overdue: bool,
+}
+
+impl TaxDue {
+    /// 納期限が読めない場合は None
+    pub fn new(tax: VehicleTax, today: NaiveDate) -> Option<Self> {
+        let due = NaiveDate::parse_from_str(tax.due_date.as_deref()?, "%Y-%m-%d").ok()?;
+        let days_until_due = (due - today).num_days();
+        Some(Self {
+            tax,
+            days_until_due,
+            overdue: days_until_due < 0,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TaxDueQuery {
+    /// 納期限まで何日以内の税を含めるか。納期限を過ぎた未納の税は常に含める
+    #[serde(default = "default_within_days")]
+    pub within_days: i64,
+}
+
+fn default_within_days() -> i64 {
+    30
+}
@@ -0,0 +1,162 @@
+use crate::middleware::auth::AppError;
+use crate::validation::{self, ValidationFailed};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use sqlx::mysql::MySqlArguments;
+use sqlx::query::Query;
+use sqlx::{query, Encode, MySql, MySqlPool, Type};
+use time::OffsetDateTime;
+use validator::Validate;
+
+/// PATCH で更新できるテーブルと列
+pub struct PatchTarget {
+    pub table: &'static str,
+    pub id_column: &'static str,
+    /// 変更できる項目。モデルの項目名と列名は同じ
+    pub fields: &'static [&'static str],
+}
+
+pub enum PatchError {
+    Invalid(AppError),
+    Validation(ValidationFailed),
+}
+
+impl IntoResponse for PatchError {
+    fn into_response(self) -> Response {
+        match self {
+            PatchError::Invalid(e) => e.into_response(),
+            PatchError::Validation(e) => e.into_response(),
+        }
+    }
+}
+
+fn invalid_patch(message: String) -> AppError {
+    AppError::new(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "request/invalid-patch".to_string(),
+        message,
+    )
+}
+
+fn to_object<T: Serialize>(value: &T) -> Map<String, Value> {
+    match serde_json::to_value(value) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+/// RFC 7396 (JSON Merge Patch) に従って patch を target に重ねる。null の項目は削除する
+pub fn merge(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(target) = target else {
+        unreachable!();
+    };
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// 現在の値に patch を重ね、モデルとして読み直した結果を返す。
+/// 変更できない項目を含む場合や、変更した項目がモデルとして正しくない場合は 422 を返す
+pub fn apply<T: Serialize + DeserializeOwned + Validate>(
+    target: &PatchTarget,
+    current: &T,
+    patch: &Value,
+) -> Result<T, PatchError> {
+    let Value::Object(changes) = patch else {
+        return Err(PatchError::Invalid(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "request/invalid-patch".to_string(),
+            "The request body must be a JSON object.".to_string(),
+        )));
+    };
+    if let Some(field) = changes
+        .keys()
+        .find(|key| !target.fields.contains(&key.as_str()))
+    {
+        return Err(PatchError::Invalid(invalid_patch(format!(
+            "The field `{}` cannot be changed.",
+            field
+        ))));
+    }
+
+    let mut merged = Value::Object(to_object(current));
+    merge(&mut merged, patch);
+    let merged: T = serde_json::from_value(merged)
+        .map_err(|e| PatchError::Invalid(invalid_patch(e.to_string())))?;
+    validation::validate_changes(current, &merged).map_err(PatchError::Validation)?;
+    Ok(merged)
+}
+
+fn bind_value(
+    query: Query<'_, MySql, MySqlArguments>,
+    value: Value,
+) -> Query<'_, MySql, MySqlArguments> {
+    match value {
+        Value::Null => query.bind(None::<String>),
+        Value::Bool(value) => query.bind(value),
+        Value::Number(number) => match number.as_i64() {
+            Some(value) => query.bind(value),
+            None => query.bind(number.as_f64()),
+        },
+        Value::String(value) => query.bind(value),
+        value => query.bind(sqlx::types::Json(value)),
+    }
+}
+
+/// 変更できる項目のうち値が変わった列だけを更新する。
+/// `updated_at` は読み込んだときの値で、その後に他の人が更新していて書き込めなかったときは false を返す
+pub async fn update<T: Serialize, Id>(
+    db_pool: &MySqlPool,
+    target: &PatchTarget,
+    id: Id,
+    before: &T,
+    after: &T,
+    updated_at: Option<OffsetDateTime>,
+) -> Result<bool, sqlx::Error>
+where
+    Id: for<'e> Encode<'e, MySql> + Type<MySql> + Send + 'static,
+{
+    let before = to_object(before);
+    let mut after = to_object(after);
+    let changes: Vec<(&str, Value)> = target
+        .fields
+        .iter()
+        .filter_map(|field| {
+            let value = after.remove(*field).unwrap_or(Value::Null);
+            (before.get(*field).unwrap_or(&Value::Null) != &value).then_some((*field, value))
+        })
+        .collect();
+    if changes.is_empty() {
+        return Ok(true);
+    }
+
+    let sql = format!(
+        "UPDATE {} SET {} WHERE {} = ? AND updated_at <=> ?",
+        target.table,
+        changes
+            .iter()
+            .map(|(field, _)| format!("{} = ?", field))
+            .collect::<Vec<_>>()
+            .join(", "),
+        target.id_column
+    );
+    let mut update = query(&sql);
+    for (_, value) in changes {
+        update = bind_value(update, value);
+    }
+    let result = update.bind(id).bind(updated_at).execute(db_pool).await?;
+    Ok(result.rows_affected() > 0)
+}
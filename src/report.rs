@@ -0,0 +1,280 @@
+use crate::i18n::{self, Lang};
+use crate::models::car_history::CarHistory;
+use crate::models::car_share_link::ShareSection;
+use crate::models::history_report::{
+    HistoryReport, ReportAccident, ReportFuelEfficiency, ReportMaintenance,
+    ReportPeriodicInspection, ReportTuning, ReportVehicle,
+};
+use std::fmt::Write;
+use time::OffsetDateTime;
+
+/// 車両の記録から、指定したセクションだけを含む公開用レポートを作る。整備のタイトルは lang で表示する
+pub fn build_history_report(
+    history: CarHistory,
+    sections: &[ShareSection],
+    expires_at: Option<OffsetDateTime>,
+    lang: Lang,
+) -> HistoryReport {
+    let includes = |section: ShareSection| sections.contains(&section);
+
+    HistoryReport {
+        vehicle: ReportVehicle {
+            car_maker: history.car.car_maker,
+            car_model: history.car.car_model,
+            car_year: history.car.car_year,
+            carmodelnum: history.car.carmodelnum,
+            car_color: history.car.car_color,
+            car_mileage: history.car.car_mileage,
+            car_isflooding: history.car.car_isflooding,
+            car_issmoked: history.car.car_issmoked,
+        },
+        maintenances: includes(ShareSection::Maintenances).then(|| {
+            history
+                .maintenances
+                .into_iter()
+                .map(|m| m.localized(lang))
+                .map(|m| ReportMaintenance {
+                    maint_type: m.maint_type,
+                    maint_title: m.maint_title,
+                    maint_date: m.maint_date,
+                    maint_description: m.maint_description,
+                })
+                .collect()
+        }),
+        tunings: includes(ShareSection::Tunings).then(|| {
+            history
+                .tunings
+                .into_iter()
+                .map(|t| ReportTuning {
+                    tuning_name: t.tuning_name,
+                    tuning_date: t.tuning_date,
+                    tuning_description: t.tuning_description,
+                })
+                .collect()
+        }),
+        fuel_efficiencies: includes(ShareSection::FuelEfficiencies).then(|| {
+            history
+                .fuel_efficiencies
+                .into_iter()
+                .map(|f| ReportFuelEfficiency {
+                    fe_date: f.fe_date,
+                    fe_amount: f.fe_amount,
+                    fe_mileage: f.fe_mileage,
+                })
+                .collect()
+        }),
+        accidents: includes(ShareSection::Accidents).then(|| {
+            history
+                .accidents
+                .into_iter()
+                .map(|a| ReportAccident {
+                    accident_date: a.accident_date,
+                    accident_description: a.accident_description,
+                })
+                .collect()
+        }),
+        periodic_inspections: includes(ShareSection::PeriodicInspections).then(|| {
+            history
+                .periodic_inspections
+                .into_iter()
+                .map(|p| ReportPeriodicInspection {
+                    pi_name: p.pi_name,
+                    pi_date: p.pi_date,
+                    pi_nextdate: p.pi_nextdate,
+                })
+                .collect()
+        }),
+        generated_at: OffsetDateTime::now_utc(),
+        expires_at,
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn yes_no(value: bool, lang: Lang) -> &'static str {
+    i18n::text(lang, if value { "Yes" } else { "No" })
+}
+
+// 1行分のセルをエスケープして出力する
+fn write_row(html: &mut String, cells: &[&str]) {
+    html.push_str("<tr>");
+    for cell in cells {
+        let _ = write!(html, "<td>{}</td>", escape_html(cell));
+    }
+    html.push_str("</tr>\n");
+}
+
+fn write_table(
+    html: &mut String,
+    lang: Lang,
+    title: &'static str,
+    headers: &[&'static str],
+    rows: Vec<Vec<String>>,
+) {
+    let _ = writeln!(html, "<h2>{}</h2>", i18n::text(lang, title));
+    if rows.is_empty() {
+        let _ = writeln!(html, "<p>{}</p>", i18n::text(lang, "No records."));
+        return;
+    }
+    html.push_str("<table>\n<tr>");
+    for header in headers {
+        let _ = write!(html, "<th>{}</th>", i18n::text(lang, header));
+    }
+    html.push_str("</tr>\n");
+    for row in rows {
+        write_row(html, &row.iter().map(String::as_str).collect::<Vec<_>>());
+    }
+    html.push_str("</table>\n");
+}
+
+/// 公開用レポートを閲覧用の HTML にする
+pub fn render_history_report_html(report: &HistoryReport, lang: Lang) -> String {
+    let title = i18n::text(lang, "Vehicle History Report");
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"{lang}\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <meta name=\"robots\" content=\"noindex\">\n<title>{title}</title>\n\
+         <style>body{{font-family:sans-serif;max-width:960px;margin:0 auto;padding:16px;}}\
+         table{{border-collapse:collapse;width:100%;margin-bottom:16px;}}\
+         th,td{{border:1px solid #ccc;padding:6px;text-align:left;vertical-align:top;}}\
+         th{{background:#f4f4f4;}}</style>\n</head>\n<body>\n<h1>{title}</h1>\n",
+        lang = lang.as_str(),
+    );
+
+    let vehicle = &report.vehicle;
+    html.push_str("<table>\n");
+    for (label, value) in [
+        ("Model code", vehicle.carmodelnum.clone()),
+        ("Color", vehicle.car_color.clone()),
+        ("Mileage", format!("{} km", vehicle.car_mileage)),
+        (
+            "Flood damage",
+            yes_no(vehicle.car_isflooding, lang).to_string(),
+        ),
+        ("Smoked in", yes_no(vehicle.car_issmoked, lang).to_string()),
+    ]
+    .into_iter()
+    .chain(
+        [
+            ("Maker", vehicle.car_maker.clone()),
+            ("Model", vehicle.car_model.clone()),
+            ("Year", vehicle.car_year.map(|year| year.to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(label, value)| Some((label, value?))),
+    ) {
+        let _ = writeln!(
+            html,
+            "<tr><th>{}</th><td>{}</td></tr>",
+            i18n::text(lang, label),
+            escape_html(&value)
+        );
+    }
+    html.push_str("</table>\n");
+
+    if let Some(maintenances) = &report.maintenances {
+        write_table(
+            &mut html,
+            lang,
+            "Maintenance",
+            &["Date", "Type", "Title", "Details"],
+            maintenances
+                .iter()
+                .map(|m| {
+                    vec![
+                        m.maint_date.clone(),
+                        m.maint_type.label(lang).to_string(),
+                        m.maint_title.clone(),
+                        m.maint_description.clone(),
+                    ]
+                })
+                .collect(),
+        );
+    }
+
+    if let Some(accidents) = &report.accidents {
+        write_table(
+            &mut html,
+            lang,
+            "Accidents",
+            &["Date", "Details"],
+            accidents
+                .iter()
+                .map(|a| vec![a.accident_date.clone(), a.accident_description.clone()])
+                .collect(),
+        );
+    }
+
+    if let Some(periodic_inspections) = &report.periodic_inspections {
+        write_table(
+            &mut html,
+            lang,
+            "Periodic Inspections",
+            &["Name", "Inspection date", "Next inspection"],
+            periodic_inspections
+                .iter()
+                .map(|p| vec![p.pi_name.clone(), p.pi_date.clone(), p.pi_nextdate.clone()])
+                .collect(),
+        );
+    }
+
+    if let Some(tunings) = &report.tunings {
+        write_table(
+            &mut html,
+            lang,
+            "Tuning",
+            &["Date", "Name", "Details"],
+            tunings
+                .iter()
+                .map(|t| {
+                    vec![
+                        t.tuning_date.clone(),
+                        t.tuning_name.clone(),
+                        t.tuning_description.clone(),
+                    ]
+                })
+                .collect(),
+        );
+    }
+
+    if let Some(fuel_efficiencies) = &report.fuel_efficiencies {
+        write_table(
+            &mut html,
+            lang,
+            "Fuel Log",
+            &["Date", "Fuel (L)", "Mileage (km)"],
+            fuel_efficiencies
+                .iter()
+                .map(|f| {
+                    vec![
+                        f.fe_date.clone(),
+                        f.fe_amount.to_string(),
+                        f.fe_mileage.to_string(),
+                    ]
+                })
+                .collect(),
+        );
+    }
+
+    let _ = write!(
+        html,
+        "<footer><p>{}: {}</p></footer>\n</body>\n</html>\n",
+        i18n::text(lang, "Generated at"),
+        report.generated_at.date()
+    );
+
+    html
+}
@@ -0,0 +1,283 @@
+use crate::i18n::{self, Lang};
+use crate::models::car_history::CarHistory;
+use crate::models::maintenance::{Maintenance, MaintenanceType};
+use crate::models::user_preferences::UserPreferences;
+use printpdf::{
+    IndirectFontRef, Line, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference, Point,
+};
+use std::collections::BTreeMap;
+use std::io::Cursor;
+
+// A4 縦
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+
+const TITLE_SIZE: f32 = 18.0;
+const HEADING_SIZE: f32 = 13.0;
+const SUBHEADING_SIZE: f32 = 11.0;
+const BODY_SIZE: f32 = 9.5;
+
+// pt から mm への換算
+const PT_TO_MM: f32 = 0.3528;
+
+/// 日本語を含む文字列を複数ページに書き出す
+struct PdfWriter {
+    doc: PdfDocumentReference,
+    font: IndirectFontRef,
+    layer: PdfLayerReference,
+    page_number: usize,
+    y: f32,
+}
+
+impl PdfWriter {
+    fn new(title: &str, font_data: &[u8]) -> anyhow::Result<Self> {
+        let (doc, page, layer) =
+            PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+        let font = doc.add_external_font(Cursor::new(font_data))?;
+        let layer = doc.get_page(page).get_layer(layer);
+
+        let writer = Self {
+            doc,
+            font,
+            layer,
+            page_number: 1,
+            y: PAGE_HEIGHT - MARGIN,
+        };
+        writer.write_page_number();
+        Ok(writer)
+    }
+
+    fn write_page_number(&self) {
+        self.layer.use_text(
+            format!("- {} -", self.page_number),
+            BODY_SIZE,
+            Mm(PAGE_WIDTH / 2.0 - 4.0),
+            Mm(MARGIN / 2.0),
+            &self.font,
+        );
+    }
+
+    fn new_page(&mut self) {
+        let (page, layer) = self
+            .doc
+            .add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+        self.layer = self.doc.get_page(page).get_layer(layer);
+        self.page_number += 1;
+        self.y = PAGE_HEIGHT - MARGIN;
+        self.write_page_number();
+    }
+
+    // 残りの高さが足りなければ改ページする
+    fn ensure_space(&mut self, height: f32) {
+        if self.y - height < MARGIN {
+            self.new_page();
+        }
+    }
+
+    fn text(&mut self, text: &str, size: f32, indent: f32) {
+        let line_height = size * PT_TO_MM * 1.5;
+        let max_width = PAGE_WIDTH - MARGIN * 2.0 - indent;
+        for line in wrap(text, size, max_width) {
+            self.ensure_space(line_height);
+            self.y -= line_height;
+            self.layer
+                .use_text(line, size, Mm(MARGIN + indent), Mm(self.y), &self.font);
+        }
+    }
+
+    fn heading(&mut self, text: &str, size: f32) {
+        // 見出しだけがページ末尾に残らないようにする
+        self.ensure_space(size * PT_TO_MM * 6.0);
+        self.y -= size * PT_TO_MM * 0.8;
+        self.text(text, size, 0.0);
+        self.rule();
+    }
+
+    fn rule(&mut self) {
+        self.y -= 1.5;
+        self.layer.add_line(Line {
+            points: vec![
+                (Point::new(Mm(MARGIN), Mm(self.y)), false),
+                (Point::new(Mm(PAGE_WIDTH - MARGIN), Mm(self.y)), false),
+            ],
+            is_closed: false,
+        });
+        self.y -= 1.5;
+    }
+
+    fn gap(&mut self, height: f32) {
+        self.y -= height;
+    }
+
+    fn finish(self) -> anyhow::Result<Vec<u8>> {
+        Ok(self.doc.save_to_bytes()?)
+    }
+}
+
+// 全角文字は半角文字の 2 倍の幅として折り返す
+fn wrap(text: &str, size: f32, max_width: f32) -> Vec<String> {
+    let half_width = size * PT_TO_MM * 0.5;
+    let max_units = (max_width / half_width).floor() as usize;
+
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        let mut units = 0;
+        for c in paragraph.chars() {
+            let width = if c.is_ascii() { 1 } else { 2 };
+            if units + width > max_units {
+                lines.push(std::mem::take(&mut line));
+                units = 0;
+            }
+            line.push(c);
+            units += width;
+        }
+        lines.push(line);
+    }
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+    lines
+}
+
+fn yes_no(value: bool, lang: Lang) -> &'static str {
+    i18n::text(lang, if value { "Yes" } else { "No" })
+}
+
+/// 車両の記録を整備記録簿形式の PDF にする。距離と作成日はユーザーの設定に合わせる
+pub fn render_history_pdf(
+    history: &CarHistory,
+    lang: Lang,
+    preferences: &UserPreferences,
+    font_data: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let no_records = i18n::text(lang, "No records.");
+    let title = i18n::text(lang, "Service Record Book");
+    let mut writer = PdfWriter::new(title, font_data)?;
+
+    writer.text(title, TITLE_SIZE, 0.0);
+    writer.text(
+        &format!(
+            "{}: {}",
+            i18n::text(lang, "Created on"),
+            preferences.today()
+        ),
+        BODY_SIZE,
+        0.0,
+    );
+    writer.gap(4.0);
+
+    let car = &history.car;
+    writer.heading(i18n::text(lang, "Vehicle"), HEADING_SIZE);
+    for (label, value) in [
+        ("Car name", car.car_name.clone()),
+        ("Model code", car.carmodelnum.clone()),
+        ("Color", car.car_color.clone()),
+        (
+            "Mileage",
+            format!(
+                "{:.0} {}",
+                preferences.distance_unit.from_km(car.car_mileage.into()),
+                preferences.distance_unit.as_str()
+            ),
+        ),
+        ("Flood damage", yes_no(car.car_isflooding, lang).to_string()),
+        ("Smoked in", yes_no(car.car_issmoked, lang).to_string()),
+    ]
+    .into_iter()
+    .chain(
+        [
+            ("Maker", car.car_maker.clone()),
+            ("Model", car.car_model.clone()),
+            ("Grade", car.car_grade.clone()),
+            ("Year", car.car_year.map(|year| year.to_string())),
+            ("First registration", car.car_first_registration.clone()),
+            ("Plate number", car.car_plate_number.clone()),
+            ("VIN", car.car_vin.clone()),
+        ]
+        .into_iter()
+        // 未入力の諸元は載せない
+        .filter_map(|(label, value)| Some((label, value?))),
+    ) {
+        writer.text(
+            &format!("{}: {}", i18n::text(lang, label), value),
+            BODY_SIZE,
+            0.0,
+        );
+    }
+    writer.gap(4.0);
+
+    writer.heading(i18n::text(lang, "Maintenance"), HEADING_SIZE);
+    if history.maintenances.is_empty() {
+        writer.text(no_records, BODY_SIZE, 0.0);
+    }
+    let mut maintenances_by_type: BTreeMap<MaintenanceType, Vec<&Maintenance>> = BTreeMap::new();
+    for maintenance in &history.maintenances {
+        maintenances_by_type
+            .entry(maintenance.maint_type)
+            .or_default()
+            .push(maintenance);
+    }
+    for (maint_type, maintenances) in maintenances_by_type {
+        writer.gap(2.0);
+        writer.text(maint_type.label(lang), SUBHEADING_SIZE, 0.0);
+        for maintenance in maintenances {
+            writer.text(
+                &format!("{}  {}", maintenance.maint_date, maintenance.title(lang)),
+                BODY_SIZE,
+                4.0,
+            );
+            if !maintenance.maint_description.is_empty() {
+                writer.text(&maintenance.maint_description, BODY_SIZE, 8.0);
+            }
+        }
+    }
+    writer.gap(4.0);
+
+    writer.heading(i18n::text(lang, "Periodic Inspections"), HEADING_SIZE);
+    if history.periodic_inspections.is_empty() {
+        writer.text(no_records, BODY_SIZE, 0.0);
+    }
+    for inspection in &history.periodic_inspections {
+        writer.text(
+            &format!(
+                "{}  {} ({}: {})",
+                inspection.pi_date,
+                inspection.pi_name,
+                i18n::text(lang, "Next"),
+                inspection.pi_nextdate
+            ),
+            BODY_SIZE,
+            4.0,
+        );
+    }
+    writer.gap(4.0);
+
+    writer.heading(i18n::text(lang, "Tuning"), HEADING_SIZE);
+    if history.tunings.is_empty() {
+        writer.text(no_records, BODY_SIZE, 0.0);
+    }
+    for tuning in &history.tunings {
+        writer.text(
+            &format!("{}  {}", tuning.tuning_date, tuning.tuning_name),
+            BODY_SIZE,
+            4.0,
+        );
+        if !tuning.tuning_description.is_empty() {
+            writer.text(&tuning.tuning_description, BODY_SIZE, 8.0);
+        }
+    }
+    writer.gap(4.0);
+
+    writer.heading(i18n::text(lang, "Accidents"), HEADING_SIZE);
+    if history.accidents.is_empty() {
+        writer.text(no_records, BODY_SIZE, 0.0);
+    }
+    for accident in &history.accidents {
+        writer.text(&accident.accident_date, BODY_SIZE, 4.0);
+        writer.text(&accident.accident_description, BODY_SIZE, 8.0);
+    }
+
+    writer.finish()
+}
@@ -1,8 +1,8 @@
 use crate::state::AppState;
 use axum::http::{header, Method};
 use axum::{
-    extract::DefaultBodyLimit, middleware::from_fn_with_state, routing::get, routing::post,
-    routing::put, Extension, Router,
+    extract::DefaultBodyLimit, middleware::from_fn, middleware::from_fn_with_state,
+    routing::delete, routing::get, routing::post, routing::put, Extension, Router,
 };
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -13,9 +13,15 @@ use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
 use utoipa::Modify;
 
 use crate::handlers::{
-    accidents, cars, fuel_efficiencies, images, maintenances, periodic_inspections, tunings, users,
+    accidents, account, attachments, audit_logs, car_members, car_transfers, cars, exchange_rates,
+    files, fuel_efficiencies, fuel_efficiency_csv, images, insurance_policies, maintenances, parts,
+    periodic_inspections, share_links, tire_sets, trash, tunings, user_preferences, users,
+    vehicle_specs, vehicle_taxes, vin,
 };
 use crate::middleware::auth::jwt_auth;
+use crate::middleware::language::{apply_user_language, negotiate_language};
+use crate::models::attachment::AttachmentOwner;
+use crate::models::part::PartUsageOwner;
 
 use axum::Json;
 use serde_json::json;
@@ -24,6 +30,27 @@ pub async fn test() -> Json<serde_json::Value> {
     Json(json!({ "message": "Hello! Type changed!" }))
 }
 
+fn attachment_routes(owner: AttachmentOwner) -> Router<Arc<Mutex<AppState>>> {
+    Router::new()
+        .route(
+            "/",
+            post(attachments::upload_attachments).get(attachments::get_attachments),
+        )
+        .route("/order", put(attachments::reorder_attachments))
+        .route("/:attachment_id", delete(attachments::delete_attachment))
+        .layer(Extension(owner))
+}
+
+fn part_usage_routes(owner: PartUsageOwner) -> Router<Arc<Mutex<AppState>>> {
+    Router::new()
+        .route(
+            "/",
+            post(parts::add_record_part).get(parts::get_record_parts),
+        )
+        .route("/:usage_id", delete(parts::delete_record_part))
+        .layer(Extension(owner))
+}
+
 pub fn create_routes(state: Arc<Mutex<AppState>>) -> Router {
     let user_routes = Router::new()
         .route("/", post(users::create_user).get(users::get_users))
@@ -31,16 +58,34 @@ pub fn create_routes(state: Arc<Mutex<AppState>>) -> Router {
             "/:user_id",
             get(users::get_user)
                 .put(users::update_user)
+                .patch(users::patch_user)
                 .delete(users::delete_user),
         )
         .route("/:user_id/cars", get(cars::get_user_cars));
 
+    let me_routes = Router::new()
+        .route("/export", get(account::export_account))
+        .route("/import", post(account::import_account))
+        .route(
+            "/preferences",
+            get(user_preferences::get_preferences)
+                .put(user_preferences::update_preferences)
+                .patch(user_preferences::patch_preferences),
+        )
+        .route(
+            "/deletion",
+            post(account::request_account_deletion)
+                .get(account::get_account_deletion)
+                .delete(account::cancel_account_deletion),
+        );
+
     let car_routes = Router::new()
         .route("/", post(cars::create_car).get(cars::get_cars))
         .route(
             "/:car_id",
             get(cars::get_car)
                 .put(cars::update_car)
+                .patch(cars::patch_car)
                 .delete(cars::delete_car),
         )
         .route(
@@ -52,6 +97,90 @@ pub fn create_routes(state: Arc<Mutex<AppState>>) -> Router {
         .route(
             "/:car_id/fuel_efficiency",
             get(cars::get_car_fuel_efficiency),
+        )
+        .route(
+            "/:car_id/fuel_efficiency/export",
+            get(fuel_efficiency_csv::export_fuel_efficiencies),
+        )
+        .route(
+            "/:car_id/fuel_efficiency/import",
+            post(fuel_efficiency_csv::import_fuel_efficiencies),
+        )
+        .route(
+            "/:car_id/insurance_policies",
+            get(insurance_policies::get_car_insurance_policies),
+        )
+        .route(
+            "/:car_id/accidents/summary",
+            get(accidents::get_car_accident_summary),
+        )
+        .route(
+            "/:car_id/vehicle_taxes",
+            get(vehicle_taxes::get_car_vehicle_taxes),
+        )
+        .route(
+            "/:car_id/vehicle_taxes/estimate",
+            get(vehicle_taxes::get_car_tax_estimate),
+        )
+        .route("/:car_id/tire_sets", get(tire_sets::get_car_tire_sets))
+        .route("/:car_id/expenses", get(cars::get_car_expenses))
+        .route("/:car_id/report.pdf", get(cars::get_car_report_pdf))
+        .route("/:car_id/audit_logs", get(audit_logs::get_car_audit_logs))
+        .route("/:car_id/members", get(car_members::get_car_members))
+        .route(
+            "/:car_id/members/:user_id",
+            put(car_members::update_member_role).delete(car_members::remove_member),
+        )
+        .route(
+            "/:car_id/invitations",
+            post(car_members::create_invitation).get(car_members::get_car_invitations),
+        )
+        .route(
+            "/:car_id/invitations/:invitation_id",
+            delete(car_members::revoke_invitation),
+        )
+        .route(
+            "/:car_id/transfer_ownership",
+            post(car_members::transfer_ownership),
+        )
+        .route(
+            "/:car_id/transfers",
+            post(car_transfers::create_transfer).get(car_transfers::get_car_transfers),
+        )
+        .route(
+            "/:car_id/transfers/:transfer_id",
+            delete(car_transfers::cancel_transfer),
+        )
+        .route(
+            "/:car_id/share_links",
+            post(share_links::create_share_link).get(share_links::get_share_links),
+        )
+        .route(
+            "/:car_id/share_links/:share_link_id",
+            delete(share_links::revoke_share_link),
+        );
+
+    let invitation_routes = Router::new()
+        .route("/", get(car_members::get_my_invitations))
+        .route(
+            "/:invitation_id/accept",
+            post(car_members::accept_invitation),
+        )
+        .route(
+            "/:invitation_id/decline",
+            post(car_members::decline_invitation),
+        );
+
+    let transfer_routes = Router::new()
+        .route("/", get(car_transfers::get_my_transfers))
+        .route("/:transfer_id/accept", post(car_transfers::accept_transfer))
+        .route(
+            "/:transfer_id/decline",
+            post(car_transfers::decline_transfer),
+        )
+        .route(
+            "/:transfer_id/snapshot",
+            get(car_transfers::get_transfer_snapshot),
         );
 
     let tuning_routes = Router::new()
@@ -60,7 +189,17 @@ pub fn create_routes(state: Arc<Mutex<AppState>>) -> Router {
             "/:tuning_id",
             get(tunings::get_tuning)
                 .put(tunings::update_tuning)
+                .patch(tunings::patch_tuning)
                 .delete(tunings::delete_tuning),
+        )
+        .route("/:tuning_id/remove", post(tunings::remove_tuning))
+        .nest(
+            "/:tuning_id/attachments",
+            attachment_routes(AttachmentOwner::Tuning),
+        )
+        .nest(
+            "/:tuning_id/parts",
+            part_usage_routes(PartUsageOwner::Tuning),
         );
 
     let maintenance_routes = Router::new()
@@ -72,7 +211,16 @@ pub fn create_routes(state: Arc<Mutex<AppState>>) -> Router {
             "/:maint_id",
             get(maintenances::get_maintenance)
                 .put(maintenances::update_maintenance)
+                .patch(maintenances::patch_maintenance)
                 .delete(maintenances::delete_maintenance),
+        )
+        .nest(
+            "/:maint_id/attachments",
+            attachment_routes(AttachmentOwner::Maintenance),
+        )
+        .nest(
+            "/:maint_id/parts",
+            part_usage_routes(PartUsageOwner::Maintenance),
         );
 
     let fuel_efficiency_routes = Router::new()
@@ -85,6 +233,7 @@ pub fn create_routes(state: Arc<Mutex<AppState>>) -> Router {
             "/:fe_id",
             get(fuel_efficiencies::get_fuel_efficiency)
                 .put(fuel_efficiencies::update_fuel_efficiency)
+                .patch(fuel_efficiencies::patch_fuel_efficiency)
                 .delete(fuel_efficiencies::delete_fuel_efficiency),
         )
         .route(
@@ -101,7 +250,85 @@ pub fn create_routes(state: Arc<Mutex<AppState>>) -> Router {
             "/:accident_id",
             get(accidents::get_accident)
                 .put(accidents::update_accident)
+                .patch(accidents::patch_accident)
                 .delete(accidents::delete_accident),
+        )
+        .nest(
+            "/:accident_id/attachments",
+            attachment_routes(AttachmentOwner::Accident),
+        );
+
+    let insurance_policy_routes = Router::new()
+        .route(
+            "/",
+            post(insurance_policies::create_insurance_policy)
+                .get(insurance_policies::get_insurance_policies),
+        )
+        .route("/renewals", get(insurance_policies::get_insurance_renewals))
+        .route(
+            "/:policy_id",
+            get(insurance_policies::get_insurance_policy)
+                .put(insurance_policies::update_insurance_policy)
+                .patch(insurance_policies::patch_insurance_policy)
+                .delete(insurance_policies::delete_insurance_policy),
+        )
+        .nest(
+            "/:policy_id/attachments",
+            attachment_routes(AttachmentOwner::InsurancePolicy),
+        );
+
+    let vehicle_tax_routes = Router::new()
+        .route(
+            "/",
+            post(vehicle_taxes::create_vehicle_tax).get(vehicle_taxes::get_vehicle_taxes),
+        )
+        .route("/due", get(vehicle_taxes::get_vehicle_taxes_due))
+        .route(
+            "/:tax_id",
+            get(vehicle_taxes::get_vehicle_tax)
+                .put(vehicle_taxes::update_vehicle_tax)
+                .patch(vehicle_taxes::patch_vehicle_tax)
+                .delete(vehicle_taxes::delete_vehicle_tax),
+        );
+
+    let part_routes = Router::new()
+        .route("/", post(parts::create_part).get(parts::get_parts))
+        .route(
+            "/:part_id",
+            get(parts::get_part)
+                .put(parts::update_part)
+                .patch(parts::patch_part)
+                .delete(parts::delete_part),
+        )
+        .route("/:part_id/usages", get(parts::get_part_usages));
+
+    let tire_set_routes = Router::new()
+        .route(
+            "/",
+            post(tire_sets::create_tire_set).get(tire_sets::get_tire_sets),
+        )
+        .route("/alerts", get(tire_sets::get_tire_alerts))
+        .route(
+            "/:tire_set_id",
+            get(tire_sets::get_tire_set)
+                .put(tire_sets::update_tire_set)
+                .patch(tire_sets::patch_tire_set)
+                .delete(tire_sets::delete_tire_set),
+        )
+        .route("/:tire_set_id/mount", post(tire_sets::mount_tire_set))
+        .route("/:tire_set_id/unmount", post(tire_sets::unmount_tire_set))
+        .route("/:tire_set_id/events", get(tire_sets::get_tire_events))
+        .route(
+            "/:tire_set_id/events/:tire_event_id",
+            delete(tire_sets::delete_tire_event),
+        )
+        .route(
+            "/:tire_set_id/measurements",
+            post(tire_sets::create_tire_measurement).get(tire_sets::get_tire_measurements),
+        )
+        .route(
+            "/:tire_set_id/measurements/:measurement_id",
+            delete(tire_sets::delete_tire_measurement),
         );
 
     let periodic_inspection_routes = Router::new()
@@ -114,31 +341,71 @@ pub fn create_routes(state: Arc<Mutex<AppState>>) -> Router {
             "/:pi_id",
             get(periodic_inspections::get_periodic_inspection)
                 .put(periodic_inspections::update_periodic_inspection)
+                .patch(periodic_inspections::patch_periodic_inspection)
                 .delete(periodic_inspections::delete_periodic_inspection),
+        )
+        .nest(
+            "/:pi_id/attachments",
+            attachment_routes(AttachmentOwner::PeriodicInspection),
         );
 
+    let trash_routes = Router::new()
+        .route("/", get(trash::get_trash))
+        .route("/:kind/:id/restore", post(trash::restore_trash_item));
+
     let test_routes = Router::new().route("/", get(test));
 
     let image_routes = Router::new().route("/", post(images::upload_image));
 
-    let public_routes = Router::new().route("/test", get(test));
+    let public_routes = Router::new()
+        .route("/test", get(test))
+        .route("/files/*key", get(files::get_file))
+        .route("/share/:token", get(share_links::get_shared_report))
+        .route(
+            "/share/:token/html",
+            get(share_links::get_shared_report_html),
+        );
 
     let private_routes = Router::new()
         .nest("/users", user_routes)
+        .nest("/me", me_routes)
         .nest("/cars", car_routes)
+        .nest("/invitations", invitation_routes)
+        .nest("/transfers", transfer_routes)
         .nest("/tunings", tuning_routes)
         .nest("/maintenances", maintenance_routes)
+        .route(
+            "/maintenance_types",
+            get(maintenances::get_maintenance_types),
+        )
+        .route("/vehicle_specs", get(vehicle_specs::get_vehicle_specs))
+        .route(
+            "/vehicle_specs/:model_code",
+            get(vehicle_specs::get_vehicle_spec),
+        )
+        .route("/vin/decode", get(vin::decode_vin))
         .nest("/fuel_efficiencies", fuel_efficiency_routes)
+        .route(
+            "/exchange_rates",
+            get(exchange_rates::get_exchange_rates).post(exchange_rates::upload_exchange_rates),
+        )
         .nest("/accidents", accident_routes)
+        .nest("/insurance_policies", insurance_policy_routes)
+        .nest("/vehicle_taxes", vehicle_tax_routes)
+        .nest("/tire_sets", tire_set_routes)
+        .nest("/parts", part_routes)
         .nest("/periodic_inspections", periodic_inspection_routes)
+        .nest("/trash", trash_routes)
         .nest("/images", image_routes)
         .nest("/test", test_routes)
+        .layer(from_fn_with_state(Arc::clone(&state), apply_user_language))
         .layer(from_fn_with_state(Arc::clone(&state), jwt_auth));
 
     Router::new()
         .merge(public_routes)
         .nest("/api", private_routes)
         .with_state(state.clone())
+        .layer(from_fn(negotiate_language))
         .layer(
             TraceLayer::new_for_http()
                 .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
@@ -147,14 +414,26 @@ pub fn create_routes(state: Arc<Mutex<AppState>>) -> Router {
         )
         .layer(
             CorsLayer::new()
-                .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE])
-                .expose_headers([header::CONTENT_DISPOSITION])
-                .allow_methods([Method::GET, Method::PUT, Method::POST, Method::DELETE])
+                .allow_headers([
+                    header::AUTHORIZATION,
+                    header::CONTENT_TYPE,
+                    header::IF_MATCH,
+                    header::IF_NONE_MATCH,
+                ])
+                .expose_headers([header::CONTENT_DISPOSITION, header::ETAG])
+                .allow_methods([
+                    Method::GET,
+                    Method::PUT,
+                    Method::PATCH,
+                    Method::POST,
+                    Method::DELETE,
+                ])
                 .allow_origin(Any),
         )
         .layer(DefaultBodyLimit::max(100 * 1024 * 1024)) // 100MBのボディサイズ制限
         .layer(Extension(state))
 }
+#[allow(dead_code)]
 struct SecurityAddon;
 impl Modify for SecurityAddon {
     fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
@@ -1,9 +1,15 @@
 // src/state.rs
+use crate::firebase::FirebaseAuth;
+use crate::storage::Storage;
 use sqlx::MySqlPool;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db_pool: MySqlPool,
     pub firebase_project_id: String,
     pub require_email_verification: bool,
+    pub storage: Storage,
+    // サービスアカウントが設定されていない場合は None
+    pub firebase_auth: Option<Arc<FirebaseAuth>>,
 }
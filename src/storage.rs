@@ -0,0 +1,290 @@
+use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+use aws_sdk_s3::presigning::PresigningConfig;
+use cloudflare_r2_rs::r2::R2Manager;
+use dotenv::dotenv;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::env;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+use time::OffsetDateTime;
+
+pub const PUBLIC_BASE_URL: &str = "https://r2.autotrack.work";
+
+// このプレフィックスで始まるキーは公開されない別のバケットに保存し、署名付きURLでのみ取得できる
+pub const PRIVATE_PREFIX: &str = "private/";
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Clone)]
+enum Backend {
+    R2 {
+        manager: R2Manager,
+        private_manager: R2Manager,
+        presign_client: aws_sdk_s3::Client,
+        private_bucket: String,
+    },
+    Local {
+        root: PathBuf,
+        private_root: PathBuf,
+        base_url: String,
+        signing_secret: Vec<u8>,
+    },
+}
+
+#[derive(Clone)]
+pub struct Storage {
+    backend: Backend,
+    signed_url_ttl: Duration,
+}
+
+pub struct SignedUrl {
+    pub url: String,
+    pub expires_at: OffsetDateTime,
+}
+
+impl Storage {
+    /// `STORAGE_BACKEND` が `local` の場合はファイルシステムに、それ以外は R2 に保存する
+    pub async fn from_env() -> Self {
+        dotenv().ok(); // 環境変数をロード
+
+        let signed_url_ttl = Duration::from_secs(
+            env::var("SIGNED_URL_TTL_SECONDS")
+                .unwrap_or_else(|_| "300".to_string())
+                .parse()
+                .expect("SIGNED_URL_TTL_SECONDS must be a number"),
+        );
+
+        let backend = match env::var("STORAGE_BACKEND").as_deref() {
+            Ok("local") => Backend::Local {
+                root: PathBuf::from(
+                    env::var("LOCAL_STORAGE_DIR").expect("LOCAL_STORAGE_DIR must be set"),
+                ),
+                private_root: PathBuf::from(
+                    env::var("LOCAL_PRIVATE_STORAGE_DIR")
+                        .expect("LOCAL_PRIVATE_STORAGE_DIR must be set"),
+                ),
+                base_url: env::var("LOCAL_STORAGE_BASE_URL")
+                    .expect("LOCAL_STORAGE_BASE_URL must be set"),
+                signing_secret: env::var("STORAGE_SIGNING_SECRET")
+                    .expect("STORAGE_SIGNING_SECRET must be set")
+                    .into_bytes(),
+            },
+            _ => {
+                let bucket = env::var("BUCKET_NAME").expect("BUCKET_NAME must be set");
+                let private_bucket =
+                    env::var("PRIVATE_BUCKET_NAME").expect("PRIVATE_BUCKET_NAME must be set");
+                let endpoint = env::var("R2_ENDPOINT_URL").expect("R2_ENDPOINT_URL must be set");
+                let client_id = env::var("R2_ACCESS_KEY_ID").expect("R2_ACCESS_KEY_ID must be set");
+                let secret =
+                    env::var("R2_SECRET_ACCESS_KEY").expect("R2_SECRET_ACCESS_KEY must be set");
+
+                let manager = R2Manager::new(&bucket, &endpoint, &client_id, &secret).await;
+                let private_manager =
+                    R2Manager::new(&private_bucket, &endpoint, &client_id, &secret).await;
+
+                // 署名付きURLの発行には S3 API を直接使う
+                let presign_config = aws_sdk_s3::Config::builder()
+                    .behavior_version(BehaviorVersion::latest())
+                    .credentials_provider(Credentials::new(client_id, secret, None, None, "r2"))
+                    .region(Region::new("auto"))
+                    .endpoint_url(endpoint)
+                    .force_path_style(true)
+                    .build();
+
+                Backend::R2 {
+                    manager,
+                    private_manager,
+                    presign_client: aws_sdk_s3::Client::from_conf(presign_config),
+                    private_bucket,
+                }
+            }
+        };
+
+        Self {
+            backend,
+            signed_url_ttl,
+        }
+    }
+
+    pub async fn put(&self, key: &str, content: &[u8], content_type: &str) -> anyhow::Result<()> {
+        match &self.backend {
+            Backend::R2 {
+                manager,
+                private_manager,
+                ..
+            } => {
+                by_visibility(key, manager, private_manager)
+                    .upload(key, content, None, Some(content_type))
+                    .await;
+                Ok(())
+            }
+            Backend::Local {
+                root, private_root, ..
+            } => {
+                let path = local_path(by_visibility::<PathBuf>(key, root, private_root), key)?;
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                tokio::fs::write(path, content).await?;
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        match &self.backend {
+            Backend::R2 {
+                manager,
+                private_manager,
+                ..
+            } => by_visibility(key, manager, private_manager).get(key).await,
+            Backend::Local {
+                root, private_root, ..
+            } => match local_path(by_visibility::<PathBuf>(key, root, private_root), key) {
+                Ok(path) => tokio::fs::read(path).await.ok(),
+                Err(_) => None,
+            },
+        }
+    }
+
+    pub async fn delete(&self, key: &str) {
+        match &self.backend {
+            Backend::R2 {
+                manager,
+                private_manager,
+                ..
+            } => {
+                by_visibility(key, manager, private_manager)
+                    .delete(key)
+                    .await
+            }
+            Backend::Local {
+                root, private_root, ..
+            } => {
+                if let Ok(path) = local_path(by_visibility::<PathBuf>(key, root, private_root), key)
+                {
+                    if let Err(e) = tokio::fs::remove_file(path).await {
+                        tracing::error!("Deletion of {} failed: {}", key, e);
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn delete_objects(&self, keys: &[String]) {
+        for key in keys {
+            self.delete(key).await;
+        }
+    }
+
+    pub fn public_url(&self, key: &str) -> String {
+        match &self.backend {
+            Backend::R2 { .. } => format!("{}/{}", PUBLIC_BASE_URL, key),
+            Backend::Local { base_url, .. } => format!("{}/files/{}", base_url, key),
+        }
+    }
+
+    /// 公開URLからストレージのキーを取り出す。このストレージのURLでなければ None
+    pub fn key_from_url(&self, url: &str) -> Option<String> {
+        url.strip_prefix(&self.public_url(""))
+            .filter(|key| !key.is_empty())
+            .map(|key| key.to_string())
+    }
+
+    /// 非公開のオブジェクトに有効期限付きの URL を発行する
+    pub async fn signed_url(&self, key: &str) -> anyhow::Result<SignedUrl> {
+        if !is_private(key) {
+            anyhow::bail!("Signed URLs are only issued for private keys: {}", key);
+        }
+        let expires_at = OffsetDateTime::now_utc() + self.signed_url_ttl;
+
+        let url = match &self.backend {
+            Backend::R2 {
+                presign_client,
+                private_bucket,
+                ..
+            } => presign_client
+                .get_object()
+                .bucket(private_bucket)
+                .key(key)
+                .presigned(PresigningConfig::expires_in(self.signed_url_ttl)?)
+                .await?
+                .uri()
+                .to_string(),
+            Backend::Local {
+                base_url,
+                signing_secret,
+                ..
+            } => {
+                let expires = expires_at.unix_timestamp();
+                format!(
+                    "{}/files/{}?expires={}&signature={}",
+                    base_url,
+                    key,
+                    expires,
+                    sign(signing_secret, key, expires)
+                )
+            }
+        };
+
+        Ok(SignedUrl { url, expires_at })
+    }
+
+    /// ローカルストレージの署名付きURLを検証する。R2 の場合は常に false を返す
+    pub fn verify_signature(&self, key: &str, expires: i64, signature: &str) -> bool {
+        let Backend::Local { signing_secret, .. } = &self.backend else {
+            return false;
+        };
+        if !is_private(key) || expires < OffsetDateTime::now_utc().unix_timestamp() {
+            return false;
+        }
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+
+        mac(signing_secret, key, expires)
+            .verify_slice(&signature)
+            .is_ok()
+    }
+}
+
+/// ユーザーがアップロードした車両画像のキーのプレフィックス
+pub fn image_prefix(firebase_user_id: &str) -> String {
+    format!("images/{}/", firebase_user_id)
+}
+
+pub fn is_private(key: &str) -> bool {
+    key.starts_with(PRIVATE_PREFIX)
+}
+
+/// キーに応じて公開用と非公開用のどちらかを選ぶ
+fn by_visibility<'a, T>(key: &str, public: &'a T, private: &'a T) -> &'a T {
+    if is_private(key) {
+        private
+    } else {
+        public
+    }
+}
+
+fn mac(secret: &[u8], key: &str, expires: i64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC can take key of any size");
+    mac.update(format!("{}:{}", key, expires).as_bytes());
+    mac
+}
+
+fn sign(secret: &[u8], key: &str, expires: i64) -> String {
+    hex::encode(mac(secret, key, expires).finalize().into_bytes())
+}
+
+// キーに `..` などが含まれていてもストレージのルート外にはアクセスさせない
+fn local_path(root: &Path, key: &str) -> anyhow::Result<PathBuf> {
+    let relative = Path::new(key);
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        anyhow::bail!("Invalid storage key: {}", key);
+    }
+    Ok(root.join(relative))
+}
@@ -0,0 +1,297 @@
+use crate::models::tire::{
+    TireAlert, TireAlertKind, TireEvent, TireEventType, TireMeasurement, TireSeason, TireSet,
+    TireSetStatus,
+};
+use std::ops::RangeInclusive;
+
+/// 夏タイヤ・オールシーズンタイヤの使用限度（mm）。スリップサインの高さ
+pub const TREAD_LIMIT_MM: f32 = 1.6;
+
+/// 新品時の溝の深さが分からないスタッドレスタイヤのプラットフォームの目安（mm）
+pub const WINTER_TREAD_LIMIT_MM: f32 = 4.0;
+
+/// 使用限度のこの深さ手前から摩耗を通知する（mm）
+pub const TREAD_WARNING_MARGIN_MM: f32 = 1.4;
+
+/// スタッドレスタイヤに替える時期（月）
+pub const WINTER_SWAP_MONTHS: RangeInclusive<u32> = 11..=12;
+
+/// 夏タイヤに戻す時期（月）
+pub const SUMMER_SWAP_MONTHS: RangeInclusive<u32> = 3..=4;
+
+/// タイヤセットの使用限度。スタッドレスタイヤは新品時の半分の深さ（プラットフォーム）
+pub fn tread_limit(tire_set: &TireSet) -> f32 {
+    match (tire_set.tire_season, tire_set.initial_tread_depth) {
+        (TireSeason::Winter, Some(initial)) => initial / 2.0,
+        (TireSeason::Winter, None) => WINTER_TREAD_LIMIT_MM,
+        _ => TREAD_LIMIT_MM,
+    }
+}
+
+/// セットの装着記録（日付順）と測定記録から状態を求める
+pub fn set_status(
+    tire_set: TireSet,
+    events: &[&TireEvent],
+    measurements: &[&TireMeasurement],
+    car_mileage: i32,
+) -> TireSetStatus {
+    let mut distance_km: i64 = 0;
+    let mut mounted_at: Option<&TireEvent> = None;
+    for event in events {
+        match event.event_type {
+            TireEventType::Mount => mounted_at = Some(event),
+            TireEventType::Unmount => {
+                if let Some(mount) = mounted_at.take() {
+                    distance_km += i64::from(event.odometer - mount.odometer).max(0);
+                }
+            }
+        }
+    }
+    if let Some(mount) = mounted_at {
+        distance_km += i64::from(car_mileage - mount.odometer).max(0);
+    }
+
+    let last_measured_date = measurements
+        .iter()
+        .map(|m| m.measured_date.as_str())
+        .max()
+        .map(str::to_string);
+    let latest_tread_depth = last_measured_date.as_deref().and_then(|date| {
+        measurements
+            .iter()
+            .filter(|m| m.measured_date == date)
+            .map(|m| m.tread_depth)
+            .reduce(f32::min)
+    });
+
+    TireSetStatus {
+        mounted: mounted_at.is_some(),
+        mounted_since: mounted_at.map(|mount| mount.event_date.clone()),
+        distance_km,
+        latest_tread_depth,
+        last_measured_date,
+        tire_set,
+    }
+}
+
+/// 最後に測った溝の深さから摩耗の通知を決める
+pub fn tread_alert(status: &TireSetStatus) -> Option<TireAlertKind> {
+    let depth = status.latest_tread_depth?;
+    let limit = tread_limit(&status.tire_set);
+    if depth <= limit {
+        Some(TireAlertKind::TreadLimit)
+    } else if depth <= limit + TREAD_WARNING_MARGIN_MM {
+        Some(TireAlertKind::TreadWear)
+    } else {
+        None
+    }
+}
+
+/// 車両のタイヤセットの通知。month は今日の月で、履き替えの時期に替えるセットを挙げる
+pub fn car_alerts(
+    car_id: i32,
+    car_name: &str,
+    statuses: &[TireSetStatus],
+    month: u32,
+) -> Vec<TireAlert> {
+    let alert = |kind, status: &TireSetStatus| TireAlert {
+        kind,
+        car_id,
+        car_name: car_name.to_string(),
+        tire_set_id: status.tire_set.tire_set_id.unwrap_or_default(),
+        tread_depth: status.latest_tread_depth,
+    };
+
+    let mut alerts = Vec::new();
+    if let Some(mounted) = statuses.iter().find(|status| status.mounted) {
+        let swap = match mounted.tire_set.tire_season {
+            TireSeason::Summer if WINTER_SWAP_MONTHS.contains(&month) => statuses
+                .iter()
+                .find(|s| s.tire_set.tire_season == TireSeason::Winter)
+                .map(|s| (TireAlertKind::SwapToWinter, s)),
+            TireSeason::Winter if SUMMER_SWAP_MONTHS.contains(&month) => statuses
+                .iter()
+                .find(|s| s.tire_set.tire_season != TireSeason::Winter)
+                .map(|s| (TireAlertKind::SwapToSummer, s)),
+            _ => None,
+        };
+        if let Some((kind, status)) = swap {
+            alerts.push(alert(kind, status));
+        }
+    }
+    for status in statuses {
+        if let Some(kind) = tread_alert(status) {
+            alerts.push(alert(kind, status));
+        }
+    }
+    alerts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(
+        id: i32,
+        season: TireSeason,
+        initial: Option<f32>,
+        mounted: bool,
+        depth: Option<f32>,
+    ) -> TireSetStatus {
+        TireSetStatus {
+            tire_set: TireSet {
+                tire_set_id: Some(id),
+                car_id: 1,
+                tire_season: season,
+                tire_size: "195/65R15".to_string(),
+                tire_brand: "test".to_string(),
+                tire_model: None,
+                purchase_date: None,
+                initial_tread_depth: initial,
+                notes: String::new(),
+                created_at: None,
+                updated_at: None,
+            },
+            mounted,
+            mounted_since: None,
+            distance_km: 0,
+            latest_tread_depth: depth,
+            last_measured_date: depth.map(|_| "2024-01-01".to_string()),
+        }
+    }
+
+    fn event(event_type: TireEventType, odometer: i32) -> TireEvent {
+        TireEvent {
+            tire_event_id: 0,
+            tire_set_id: 1,
+            car_id: 1,
+            event_type,
+            event_date: "2024-01-01".to_string(),
+            odometer,
+            notes: String::new(),
+            created_at: None,
+        }
+    }
+
+    fn kinds(alerts: &[TireAlert]) -> Vec<(TireAlertKind, i32)> {
+        alerts.iter().map(|a| (a.kind, a.tire_set_id)).collect()
+    }
+
+    #[test]
+    fn tread_limit_by_season() {
+        let summer = status(1, TireSeason::Summer, Some(8.0), false, None);
+        assert_eq!(tread_limit(&summer.tire_set), TREAD_LIMIT_MM);
+        let all_season = status(1, TireSeason::AllSeason, None, false, None);
+        assert_eq!(tread_limit(&all_season.tire_set), TREAD_LIMIT_MM);
+        let winter = status(1, TireSeason::Winter, Some(10.0), false, None);
+        assert_eq!(tread_limit(&winter.tire_set), 5.0);
+        let winter = status(1, TireSeason::Winter, None, false, None);
+        assert_eq!(tread_limit(&winter.tire_set), WINTER_TREAD_LIMIT_MM);
+    }
+
+    #[test]
+    fn tread_alert_boundaries() {
+        for (depth, expected) in [
+            (1.5, Some(TireAlertKind::TreadLimit)),
+            (1.6, Some(TireAlertKind::TreadLimit)),
+            (1.7, Some(TireAlertKind::TreadWear)),
+            (3.0, Some(TireAlertKind::TreadWear)),
+            (3.1, None),
+        ] {
+            let summer = status(1, TireSeason::Summer, None, true, Some(depth));
+            assert_eq!(tread_alert(&summer), expected, "{} mm", depth);
+        }
+        for (depth, expected) in [
+            (5.0, Some(TireAlertKind::TreadLimit)),
+            (5.1, Some(TireAlertKind::TreadWear)),
+            (6.4, Some(TireAlertKind::TreadWear)),
+            (6.5, None),
+        ] {
+            let winter = status(1, TireSeason::Winter, Some(10.0), true, Some(depth));
+            assert_eq!(tread_alert(&winter), expected, "{} mm", depth);
+        }
+        let unmeasured = status(1, TireSeason::Summer, None, true, None);
+        assert_eq!(tread_alert(&unmeasured), None);
+    }
+
+    #[test]
+    fn swap_to_winter_in_november_and_december() {
+        let statuses = [
+            status(1, TireSeason::Summer, None, true, None),
+            status(2, TireSeason::Winter, None, false, None),
+        ];
+        for month in 1..=12 {
+            let alerts = car_alerts(1, "car", &statuses, month);
+            let expected = if (11..=12).contains(&month) {
+                vec![(TireAlertKind::SwapToWinter, 2)]
+            } else {
+                vec![]
+            };
+            assert_eq!(kinds(&alerts), expected, "month {}", month);
+        }
+    }
+
+    #[test]
+    fn swap_to_summer_in_march_and_april() {
+        let statuses = [
+            status(1, TireSeason::Winter, None, true, None),
+            status(2, TireSeason::AllSeason, None, false, None),
+        ];
+        for month in 1..=12 {
+            let alerts = car_alerts(1, "car", &statuses, month);
+            let expected = if (3..=4).contains(&month) {
+                vec![(TireAlertKind::SwapToSummer, 2)]
+            } else {
+                vec![]
+            };
+            assert_eq!(kinds(&alerts), expected, "month {}", month);
+        }
+    }
+
+    #[test]
+    fn no_swap_without_other_set_or_mounted_set() {
+        let only_summer = [status(1, TireSeason::Summer, None, true, None)];
+        assert!(car_alerts(1, "car", &only_summer, 11).is_empty());
+        let all_season = [
+            status(1, TireSeason::AllSeason, None, true, None),
+            status(2, TireSeason::Winter, None, false, None),
+        ];
+        assert!(car_alerts(1, "car", &all_season, 11).is_empty());
+        let unmounted = [
+            status(1, TireSeason::Summer, None, false, None),
+            status(2, TireSeason::Winter, None, false, None),
+        ];
+        assert!(car_alerts(1, "car", &unmounted, 11).is_empty());
+    }
+
+    #[test]
+    fn swap_and_tread_alerts_together() {
+        let statuses = [
+            status(1, TireSeason::Summer, None, true, Some(2.0)),
+            status(2, TireSeason::Winter, Some(10.0), false, Some(4.0)),
+        ];
+        let alerts = car_alerts(1, "car", &statuses, 12);
+        assert_eq!(
+            kinds(&alerts),
+            vec![
+                (TireAlertKind::SwapToWinter, 2),
+                (TireAlertKind::TreadWear, 1),
+                (TireAlertKind::TreadLimit, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn distance_counts_mounted_periods() {
+        let set = status(1, TireSeason::Summer, None, false, None).tire_set;
+        let events = [
+            event(TireEventType::Mount, 1000),
+            event(TireEventType::Unmount, 4000),
+            event(TireEventType::Mount, 10000),
+        ];
+        let events: Vec<&TireEvent> = events.iter().collect();
+        let status = set_status(set, &events, &[], 12000);
+        assert!(status.mounted);
+        assert_eq!(status.distance_km, 5000);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::handlers::{attachments, cars};
+use crate::models::trash::{TrashKind, RECORD_KINDS};
+use crate::state::AppState;
+use crate::storage::Storage;
+use sqlx::{query, query_scalar, MySqlConnection, MySqlPool};
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+// 保存期間を過ぎた車両と記録を確認する間隔
+const PURGE_JOB_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// ゴミ箱に移してから完全に削除するまでの日数
+pub fn retention_days() -> i64 {
+    env::var("TRASH_RETENTION_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(30)
+}
+
+/// 車両とまだゴミ箱にないその記録を、同じ削除日時でゴミ箱に移す
+pub async fn move_car_to_trash(conn: &mut MySqlConnection, car_id: i32) -> Result<(), sqlx::Error> {
+    query("UPDATE Cars SET deleted_at = CURRENT_TIMESTAMP WHERE car_id = ? AND deleted_at IS NULL")
+        .bind(car_id)
+        .execute(&mut *conn)
+        .await?;
+
+    for kind in RECORD_KINDS {
+        query(&format!(
+            "UPDATE {} SET deleted_at = (SELECT deleted_at FROM Cars WHERE car_id = ?) WHERE car_id = ? AND deleted_at IS NULL",
+            kind.table()
+        ))
+        .bind(car_id)
+        .bind(car_id)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    query("UPDATE CarTransfers SET status = 'cancelled' WHERE car_id = ? AND status = 'pending'")
+        .bind(car_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// 車両と、車両と一緒にゴミ箱に移した記録を戻す。
+/// 車両より前に個別に削除した記録はゴミ箱に残す。車両がゴミ箱になければ false
+pub async fn restore_car(conn: &mut MySqlConnection, car_id: i32) -> Result<bool, sqlx::Error> {
+    for kind in RECORD_KINDS {
+        query(&format!(
+            "UPDATE {} SET deleted_at = NULL WHERE car_id = ? AND deleted_at = (SELECT deleted_at FROM Cars WHERE car_id = ?)",
+            kind.table()
+        ))
+        .bind(car_id)
+        .bind(car_id)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    let result =
+        query("UPDATE Cars SET deleted_at = NULL WHERE car_id = ? AND deleted_at IS NOT NULL")
+            .bind(car_id)
+            .execute(&mut *conn)
+            .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+async fn purge_car(db_pool: &MySqlPool, storage: &Storage, car_id: i32) -> anyhow::Result<()> {
+    let mut tx = db_pool.begin().await?;
+
+    let mut storage_keys: Vec<String> = cars::image_key_to_delete(&mut tx, storage, car_id)
+        .await?
+        .into_iter()
+        .collect();
+    storage_keys.extend(cars::delete_car_data(&mut tx, car_id).await?);
+
+    tx.commit().await?;
+    storage.delete_objects(&storage_keys).await;
+    Ok(())
+}
+
+async fn purge_record(
+    db_pool: &MySqlPool,
+    storage: &Storage,
+    kind: TrashKind,
+    id: i32,
+) -> anyhow::Result<()> {
+    let mut tx = db_pool.begin().await?;
+
+    let storage_keys = match kind.attachment_owner() {
+        Some(owner) => attachments::delete_owner_attachments(&mut tx, owner, id).await?,
+        None => Vec::new(),
+    };
+    query(&format!(
+        "DELETE FROM {} WHERE {} = ?",
+        kind.table(),
+        kind.id_column()
+    ))
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    storage.delete_objects(&storage_keys).await;
+    Ok(())
+}
+
+/// 保存期間を過ぎた車両と記録を完全に削除する
+pub async fn purge_expired(state: &Arc<Mutex<AppState>>) -> anyhow::Result<()> {
+    let (db_pool, storage) = {
+        let state = state.lock().await;
+        (state.db_pool.clone(), state.storage.clone())
+    };
+    let retention_days = retention_days();
+
+    // 車両を先に削除し、一緒にゴミ箱に移した記録もまとめて消す
+    let car_ids: Vec<i32> = query_scalar(
+        "SELECT car_id FROM Cars WHERE deleted_at <= CURRENT_TIMESTAMP - INTERVAL ? DAY",
+    )
+    .bind(retention_days)
+    .fetch_all(&db_pool)
+    .await?;
+    for car_id in car_ids {
+        if let Err(e) = purge_car(&db_pool, &storage, car_id).await {
+            tracing::error!("Failed to purge car {}: {:?}", car_id, e);
+        }
+    }
+
+    for kind in RECORD_KINDS {
+        let ids: Vec<i32> = query_scalar(&format!(
+            "SELECT {} FROM {} WHERE deleted_at <= CURRENT_TIMESTAMP - INTERVAL ? DAY",
+            kind.id_column(),
+            kind.table()
+        ))
+        .bind(retention_days)
+        .fetch_all(&db_pool)
+        .await?;
+        for id in ids {
+            if let Err(e) = purge_record(&db_pool, &storage, kind, id).await {
+                tracing::error!("Failed to purge {} {}: {:?}", kind.as_str(), id, e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// ゴミ箱を定期的に空にする
+pub async fn run_purge_job(state: Arc<Mutex<AppState>>) {
+    let mut interval = tokio::time::interval(PURGE_JOB_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = purge_expired(&state).await {
+            tracing::error!("Trash purge job failed: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use crate::i18n::{self, Lang};
+use crate::middleware::auth::AppError;
+use axum::{
+    async_trait,
+    extract::{rejection::JsonRejection, FromRequest, Request},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use bigdecimal::num_bigint::Sign;
+use bigdecimal::BigDecimal;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Map, Value};
+use std::borrow::Cow;
+use time::{Date, Month};
+use validator::{Validate, ValidationError, ValidationErrors, ValidationErrorsKind};
+
+// 項目をまたぐ検証のエラーで、どの項目のエラーとして返すかを指定するパラメータ
+const FIELD_PARAM: &str = "field";
+
+/// `YYYY-MM-DD` 形式の日付を読む
+pub fn parse_date(value: &str) -> Option<Date> {
+    let mut parts = value.split('-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() || year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    let month = Month::try_from(month.parse::<u8>().ok()?).ok()?;
+    Date::from_calendar_date(year.parse().ok()?, month, day.parse().ok()?).ok()
+}
+
+pub fn validate_date(value: &str) -> Result<(), ValidationError> {
+    match parse_date(value) {
+        Some(_) => Ok(()),
+        None => Err(error("date", "Use a valid date in YYYY-MM-DD format.")),
+    }
+}
+
+/// ISO 4217 の通貨コード（英大文字 3 文字）か
+pub fn validate_currency(currency: &str) -> Result<(), ValidationError> {
+    if currency.len() == 3 && currency.bytes().all(|c| c.is_ascii_uppercase()) {
+        Ok(())
+    } else {
+        Err(error(
+            "currency",
+            "Use a three-letter ISO 4217 currency code.",
+        ))
+    }
+}
+
+/// 金額が 0 以上か
+pub fn validate_amount(amount: &BigDecimal) -> Result<(), ValidationError> {
+    if amount.sign() == Sign::Minus {
+        Err(error("amount", "Must not be negative."))
+    } else {
+        Ok(())
+    }
+}
+
+/// 検証エラーを作る
+pub fn error(code: &'static str, message: &'static str) -> ValidationError {
+    ValidationError::new(code).with_message(Cow::Borrowed(message))
+}
+
+/// 項目をまたぐ検証のエラーを作る。`field` の項目のエラーとして返す
+pub fn field_error(
+    field: &'static str,
+    code: &'static str,
+    message: &'static str,
+) -> ValidationError {
+    let mut error = error(code, message);
+    error.add_param(Cow::Borrowed(FIELD_PARAM), &field);
+    error
+}
+
+// 組み込みの検証にはメッセージがないので、パラメータから作る
+fn default_message(error: &ValidationError, lang: Lang) -> String {
+    let param = |name: &str| error.params.get(name).map(Value::to_string);
+    match (error.code.as_ref(), param("min"), param("max")) {
+        ("length", Some(min), Some(max)) => {
+            i18n::format(lang, "Must be {} to {} characters long.", &[min, max])
+        }
+        ("length", Some(min), None) if min == "1" => {
+            i18n::text(lang, "Must not be empty.").to_string()
+        }
+        ("length", Some(min), None) => {
+            i18n::format(lang, "Must be at least {} characters long.", &[min])
+        }
+        ("length", None, Some(max)) => {
+            i18n::format(lang, "Must be at most {} characters long.", &[max])
+        }
+        ("range", Some(min), _) => i18n::format(lang, "Must be at least {}.", &[min]),
+        ("range", None, Some(max)) => i18n::format(lang, "Must be at most {}.", &[max]),
+        ("range", None, None) => match param("exclusive_min") {
+            Some(min) => i18n::format(lang, "Must be greater than {}.", &[min]),
+            None => i18n::text(lang, "Out of range.").to_string(),
+        },
+        ("email", _, _) => i18n::text(lang, "Must be a valid email address.").to_string(),
+        (code, _, _) => code.to_string(),
+    }
+}
+
+fn collect(errors: &ValidationErrors, prefix: &str, lang: Lang, fields: &mut Map<String, Value>) {
+    let path = |field: &str| {
+        if prefix.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", prefix, field)
+        }
+    };
+
+    for (field, kind) in errors.errors() {
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                for error in errors {
+                    let field = match error.params.get(FIELD_PARAM).and_then(Value::as_str) {
+                        Some(field) => path(field),
+                        None => path(field),
+                    };
+                    let message = match &error.message {
+                        Some(message) => i18n::translate(lang, message).into_owned(),
+                        None => default_message(error, lang),
+                    };
+                    if let Value::Array(list) = fields
+                        .entry(field)
+                        .or_insert_with(|| Value::Array(Vec::new()))
+                    {
+                        list.push(json!({ "code": error.code, "message": message }));
+                    }
+                }
+            }
+            ValidationErrorsKind::Struct(errors) => collect(errors, &path(field), lang, fields),
+            ValidationErrorsKind::List(list) => {
+                for (index, errors) in list {
+                    collect(
+                        errors,
+                        &path(&format!("{}[{}]", field, index)),
+                        lang,
+                        fields,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// 検証エラー。項目ごとにまとめた 422 の応答になる
+#[derive(Debug)]
+pub struct ValidationFailed(pub ValidationErrors);
+
+impl IntoResponse for ValidationFailed {
+    fn into_response(self) -> Response {
+        let lang = i18n::current();
+        let mut fields = Map::new();
+        collect(&self.0, "", lang, &mut fields);
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({
+                "code": "request/validation-failed",
+                "message": i18n::text(lang, "The request contains invalid fields."),
+                "errors": fields,
+            })),
+        )
+            .into_response()
+    }
+}
+
+pub fn validate<T: Validate>(value: &T) -> Result<(), ValidationFailed> {
+    value.validate().map_err(ValidationFailed)
+}
+
+/// 更新後の値を検証し、`before` から値の変わった項目のエラーだけを返す。
+/// 検証を厳しくする前に保存された値は、書き換えない限りそのまま受け付ける
+pub fn validate_changes<T: Serialize + Validate>(
+    before: &T,
+    after: &T,
+) -> Result<(), ValidationFailed> {
+    let Err(mut errors) = after.validate() else {
+        return Ok(());
+    };
+
+    let before = serde_json::to_value(before).unwrap_or_default();
+    let after = serde_json::to_value(after).unwrap_or_default();
+    let changed = |field: &str| before.get(field) != after.get(field);
+    errors.errors_mut().retain(|field, kind| match kind {
+        ValidationErrorsKind::Field(list) => {
+            list.retain(|error| {
+                changed(
+                    error
+                        .params
+                        .get(FIELD_PARAM)
+                        .and_then(Value::as_str)
+                        .unwrap_or(field),
+                )
+            });
+            !list.is_empty()
+        }
+        _ => changed(field),
+    });
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationFailed(errors))
+    }
+}
+
+fn map_json_rejection(rejection: JsonRejection) -> Response {
+    match rejection {
+        // 型の合わない値や未知の列挙値は、受け付ける値とともに 422 で返す
+        JsonRejection::JsonDataError(e) => AppError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "request/invalid-body".to_string(),
+            e.body_text(),
+        )
+        .into_response(),
+        rejection => rejection.into_response(),
+    }
+}
+
+/// JSON の本文を読み、モデルの検証に通ったものだけを受け付ける
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(map_json_rejection)?;
+        validate(&value).map_err(IntoResponse::into_response)?;
+        Ok(Self(value))
+    }
+}
+
+/// 既存の記録を置き換える JSON の本文。変更前の値を読んでから `validate_changes` で検証する
+pub struct UpdateJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for UpdateJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(map_json_rejection)?;
+        Ok(Self(value))
+    }
+}
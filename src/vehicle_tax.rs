@@ -0,0 +1,455 @@
+use crate::models::car::{Car, FuelType};
+use crate::models::vehicle_tax::TaxType;
+use crate::validation;
+use chrono::Datelike;
+use serde::Serialize;
+use time::{Date, Month};
+
+// 自動車税種別割（自家用乗用車・年額）。総排気量の上限（cc）ごとの税額
+const AUTOMOBILE_TAX_RATES: &[(i32, i64)] = &[
+    (1000, 29_500),
+    (1500, 34_500),
+    (2000, 39_500),
+    (2500, 45_000),
+    (3000, 51_000),
+    (3500, 58_000),
+    (4000, 66_500),
+    (4500, 76_500),
+    (6000, 88_000),
+    (i32::MAX, 111_000),
+];
+
+// 2019 年 10 月 1 日以降に初度登録した車の税額
+const AUTOMOBILE_TAX_RATES_2019: &[(i32, i64)] = &[
+    (1000, 25_000),
+    (1500, 30_500),
+    (2000, 36_000),
+    (2500, 43_500),
+    (3000, 50_000),
+    (3500, 57_000),
+    (4000, 65_500),
+    (4500, 75_500),
+    (6000, 87_000),
+    (i32::MAX, 110_000),
+];
+
+// 軽自動車税種別割（自家用乗用・年額）
+const LIGHT_VEHICLE_TAX: i64 = 10_800;
+// 2015 年 3 月 31 日以前に最初の新規検査を受けた車の税額
+const LIGHT_VEHICLE_TAX_BEFORE_2015: i64 = 7_200;
+const LIGHT_VEHICLE_TAX_SURCHARGED: i64 = 12_900;
+
+// 自動車重量税（自家用乗用・1 年あたり）。登録車は 0.5t ごと、軽自動車は 1 台あたり。
+// エコカー減税の対象外の税額で、経過年数が 13 年未満・13 年以上・18 年以上の順
+const WEIGHT_TAX_PER_HALF_TONNE: [i64; 3] = [4_100, 5_700, 6_300];
+const LIGHT_WEIGHT_TAX: [i64; 3] = [3_300, 4_100, 4_400];
+
+/// 軽自動車として扱う排気量の上限（cc）
+pub const LIGHT_VEHICLE_MAX_DISPLACEMENT: i32 = 660;
+
+/// 車検の有効期間（年）。重量税はこの年数分をまとめて納める
+pub const INSPECTION_PERIOD_YEARS: i64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaxError {
+    pub code: &'static str,
+    pub message: &'static str,
+}
+
+const MISSING_DISPLACEMENT: TaxError = TaxError {
+    code: "tax/missing-displacement",
+    message: "Set the car's displacement to calculate the tax.",
+};
+
+const MISSING_FIRST_REGISTRATION: TaxError = TaxError {
+    code: "tax/missing-first-registration",
+    message: "Set the car's first registration date to calculate the tax.",
+};
+
+const MISSING_WEIGHT: TaxError = TaxError {
+    code: "tax/missing-weight",
+    message: "Set the car's weight to calculate the weight tax.",
+};
+
+/// 経年による重課
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Surcharge {
+    None,
+    /// 13 年超（ディーゼル車は 11 年超）
+    Over13Years,
+    /// 重量税の 18 年超
+    Over18Years,
+}
+
+/// 計算した税額と根拠
+#[derive(Debug, Clone, Serialize)]
+pub struct TaxAssessment {
+    pub tax_type: TaxType,
+    /// 円
+    pub amount: i64,
+    pub surcharge: Surcharge,
+}
+
+/// 4 月始まりの年度
+pub fn fiscal_year_of(date: Date) -> i32 {
+    if u8::from(date.month()) >= 4 {
+        date.year()
+    } else {
+        date.year() - 1
+    }
+}
+
+/// 今日の年度
+pub fn current_fiscal_year(today: chrono::NaiveDate) -> i32 {
+    if today.month() >= 4 {
+        today.year()
+    } else {
+        today.year() - 1
+    }
+}
+
+/// 年税の納期限。5 月末日
+pub fn annual_due_date(fiscal_year: i32) -> String {
+    format!("{:04}-05-31", fiscal_year)
+}
+
+/// 排気量から年税の種類を決める。電気自動車と燃料電池車は排気量 0 の登録車として扱う
+pub fn annual_tax_type(car: &Car) -> Result<TaxType, TaxError> {
+    match (car.car_displacement, car.car_fuel_type) {
+        (Some(cc), _) if cc > 0 && cc <= LIGHT_VEHICLE_MAX_DISPLACEMENT => {
+            Ok(TaxType::LightVehicleTax)
+        }
+        (Some(_), _) | (None, Some(FuelType::Electric | FuelType::Hydrogen)) => {
+            Ok(TaxType::AutomobileTax)
+        }
+        (None, _) => Err(MISSING_DISPLACEMENT),
+    }
+}
+
+// 電気自動車、ハイブリッド車と燃料電池車は重課の対象外
+fn surcharge_exempt(car: &Car) -> bool {
+    matches!(
+        car.car_fuel_type,
+        Some(FuelType::Electric | FuelType::Hybrid | FuelType::PluginHybrid | FuelType::Hydrogen)
+    )
+}
+
+fn first_registration(car: &Car) -> Result<Date, TaxError> {
+    car.car_first_registration
+        .as_deref()
+        .and_then(validation::parse_date)
+        .ok_or(MISSING_FIRST_REGISTRATION)
+}
+
+/// 税の種類に応じて税額を計算する。重量税は納期限を車検の日とする
+pub fn assess(
+    car: &Car,
+    tax_type: TaxType,
+    fiscal_year: i32,
+    due_date: Date,
+) -> Result<TaxAssessment, TaxError> {
+    match tax_type {
+        TaxType::WeightTax => weight_tax(car, due_date),
+        TaxType::AutomobileTax | TaxType::LightVehicleTax => annual_tax(car, tax_type, fiscal_year),
+    }
+}
+
+/// 年度の自動車税・軽自動車税。初度登録から 13 年（ディーゼル車は 11 年）を経過した年度の翌年度から重課する
+pub fn annual_tax(
+    car: &Car,
+    tax_type: TaxType,
+    fiscal_year: i32,
+) -> Result<TaxAssessment, TaxError> {
+    let registered = first_registration(car)?;
+    let limit_years = match car.car_fuel_type {
+        Some(FuelType::Diesel) => 11,
+        _ => 13,
+    };
+    let surcharge =
+        if !surcharge_exempt(car) && fiscal_year > fiscal_year_of(registered) + limit_years {
+            Surcharge::Over13Years
+        } else {
+            Surcharge::None
+        };
+
+    let amount = match tax_type {
+        TaxType::LightVehicleTax => match surcharge {
+            Surcharge::None if registered < date(2015, Month::April, 1) => {
+                LIGHT_VEHICLE_TAX_BEFORE_2015
+            }
+            Surcharge::None => LIGHT_VEHICLE_TAX,
+            _ => LIGHT_VEHICLE_TAX_SURCHARGED,
+        },
+        _ => {
+            let displacement = match (car.car_displacement, car.car_fuel_type) {
+                (Some(cc), _) => cc,
+                (None, Some(FuelType::Electric | FuelType::Hydrogen)) => 0,
+                (None, _) => return Err(MISSING_DISPLACEMENT),
+            };
+            let rates = if registered >= date(2019, Month::October, 1) {
+                AUTOMOBILE_TAX_RATES_2019
+            } else {
+                AUTOMOBILE_TAX_RATES
+            };
+            let base = rates
+                .iter()
+                .find(|(max, _)| displacement <= *max)
+                .map_or(0, |(_, amount)| *amount);
+            match surcharge {
+                // おおむね 15% 重課し、100 円未満を切り捨てる
+                Surcharge::None => base,
+                _ => base * 115 / 100 / 100 * 100,
+            }
+        }
+    };
+
+    Ok(TaxAssessment {
+        tax_type,
+        amount,
+        surcharge,
+    })
+}
+
+/// 車検時に納める重量税。経過年数は初度登録から車検の日までの月数で数える
+pub fn weight_tax(car: &Car, inspection_date: Date) -> Result<TaxAssessment, TaxError> {
+    let registered = first_registration(car)?;
+    let months = (inspection_date.year() - registered.year()) * 12
+        + i32::from(u8::from(inspection_date.month()))
+        - i32::from(u8::from(registered.month()));
+    let surcharge = if surcharge_exempt(car) {
+        Surcharge::None
+    } else if months >= 18 * 12 {
+        Surcharge::Over18Years
+    } else if months >= 13 * 12 {
+        Surcharge::Over13Years
+    } else {
+        Surcharge::None
+    };
+    let level = match surcharge {
+        Surcharge::None => 0,
+        Surcharge::Over13Years => 1,
+        Surcharge::Over18Years => 2,
+    };
+
+    let per_year = match annual_tax_type(car) {
+        Ok(TaxType::LightVehicleTax) => LIGHT_WEIGHT_TAX[level],
+        _ => {
+            let weight = car.car_weight.ok_or(MISSING_WEIGHT)?;
+            let half_tonnes = (i64::from(weight) + 499) / 500;
+            WEIGHT_TAX_PER_HALF_TONNE[level] * half_tonnes
+        }
+    };
+
+    Ok(TaxAssessment {
+        tax_type: TaxType::WeightTax,
+        amount: per_year * INSPECTION_PERIOD_YEARS,
+        surcharge,
+    })
+}
+
+// 税率の切り替え日。定数なので日付として必ず正しい
+fn date(year: i32, month: Month, day: u8) -> Date {
+    Date::from_calendar_date(year, month, day).unwrap_or(Date::MIN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn car(displacement: Option<i32>, fuel_type: FuelType, first_registration: &str) -> Car {
+        Car {
+            car_id: None,
+            car_name: "test".to_string(),
+            carmodelnum: String::new(),
+            car_color: String::new(),
+            car_mileage: 0,
+            car_isflooding: false,
+            car_issmoked: false,
+            car_image_url: None,
+            car_maker: None,
+            car_model: None,
+            car_grade: None,
+            car_year: None,
+            car_displacement: displacement,
+            car_fuel_type: Some(fuel_type),
+            car_tank_capacity: None,
+            car_weight: Some(1000),
+            car_transmission: None,
+            car_first_registration: Some(first_registration.to_string()),
+            car_plate_number: None,
+            car_vin: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn annual(car: &Car, fiscal_year: i32) -> TaxAssessment {
+        let tax_type = annual_tax_type(car).unwrap();
+        annual_tax(car, tax_type, fiscal_year).unwrap()
+    }
+
+    #[test]
+    fn fiscal_year_starts_in_april() {
+        assert_eq!(fiscal_year_of(date(2024, Month::March, 31)), 2023);
+        assert_eq!(fiscal_year_of(date(2024, Month::April, 1)), 2024);
+        let today = chrono::NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+        assert_eq!(current_fiscal_year(today), 2023);
+        let today = chrono::NaiveDate::from_ymd_opt(2024, 4, 1).unwrap();
+        assert_eq!(current_fiscal_year(today), 2024);
+    }
+
+    #[test]
+    fn light_vehicle_up_to_660cc() {
+        let light = car(Some(660), FuelType::Gasoline, "2020-01-01");
+        assert_eq!(annual_tax_type(&light), Ok(TaxType::LightVehicleTax));
+        let registered = car(Some(661), FuelType::Gasoline, "2020-01-01");
+        assert_eq!(annual_tax_type(&registered), Ok(TaxType::AutomobileTax));
+        let electric = car(None, FuelType::Electric, "2020-01-01");
+        assert_eq!(annual_tax_type(&electric), Ok(TaxType::AutomobileTax));
+        let unknown = car(None, FuelType::Gasoline, "2020-01-01");
+        assert_eq!(annual_tax_type(&unknown), Err(MISSING_DISPLACEMENT));
+    }
+
+    #[test]
+    fn automobile_tax_brackets_are_inclusive_upper_bounds() {
+        for (cc, amount) in [
+            (661, 29_500),
+            (1000, 29_500),
+            (1001, 34_500),
+            (2000, 39_500),
+            (2001, 45_000),
+            (4500, 76_500),
+            (4501, 88_000),
+            (6000, 88_000),
+            (6001, 111_000),
+        ] {
+            let car = car(Some(cc), FuelType::Gasoline, "2019-09-30");
+            assert_eq!(annual(&car, 2020).amount, amount, "{} cc", cc);
+        }
+    }
+
+    #[test]
+    fn rates_for_cars_registered_from_october_2019() {
+        for (cc, amount) in [
+            (1000, 25_000),
+            (1001, 30_500),
+            (6000, 87_000),
+            (6001, 110_000),
+        ] {
+            let car = car(Some(cc), FuelType::Gasoline, "2019-10-01");
+            assert_eq!(annual(&car, 2020).amount, amount, "{} cc", cc);
+        }
+        // 排気量のない電気自動車は最も低い区分
+        let electric = car(None, FuelType::Electric, "2019-10-01");
+        assert_eq!(annual(&electric, 2020).amount, 25_000);
+    }
+
+    #[test]
+    fn surcharge_from_fourteenth_fiscal_year() {
+        // 2011 年 3 月の登録は 2010 年度
+        let car = car(Some(2000), FuelType::Gasoline, "2011-03-31");
+        let before = annual(&car, 2023);
+        assert_eq!(before.surcharge, Surcharge::None);
+        assert_eq!(before.amount, 39_500);
+        let after = annual(&car, 2024);
+        assert_eq!(after.surcharge, Surcharge::Over13Years);
+        // 39,500 円の 15% 増しは 45,425 円。100 円未満を切り捨てる
+        assert_eq!(after.amount, 45_400);
+    }
+
+    #[test]
+    fn surcharge_rounds_down_to_100_yen() {
+        for (cc, amount) in [
+            (1000, 33_900),
+            (1500, 39_600),
+            (2500, 51_700),
+            (6001, 127_600),
+        ] {
+            let car = car(Some(cc), FuelType::Gasoline, "2005-04-01");
+            assert_eq!(annual(&car, 2024).amount, amount, "{} cc", cc);
+        }
+    }
+
+    #[test]
+    fn diesel_surcharge_after_11_years() {
+        let car = car(Some(2000), FuelType::Diesel, "2010-04-01");
+        assert_eq!(annual(&car, 2021).surcharge, Surcharge::None);
+        assert_eq!(annual(&car, 2022).surcharge, Surcharge::Over13Years);
+    }
+
+    #[test]
+    fn hybrids_and_electric_cars_are_exempt() {
+        for fuel_type in [
+            FuelType::Hybrid,
+            FuelType::PluginHybrid,
+            FuelType::Electric,
+            FuelType::Hydrogen,
+        ] {
+            let car = car(Some(2000), fuel_type, "2000-04-01");
+            let assessment = annual(&car, 2024);
+            assert_eq!(assessment.surcharge, Surcharge::None);
+            assert_eq!(assessment.amount, 39_500);
+        }
+    }
+
+    #[test]
+    fn light_vehicle_tax_by_first_inspection() {
+        let old = car(Some(660), FuelType::Gasoline, "2015-03-31");
+        assert_eq!(annual(&old, 2020).amount, 7_200);
+        let new = car(Some(660), FuelType::Gasoline, "2015-04-01");
+        assert_eq!(annual(&new, 2020).amount, 10_800);
+        let surcharged = car(Some(660), FuelType::Gasoline, "2005-04-01");
+        assert_eq!(annual(&surcharged, 2024).amount, 12_900);
+    }
+
+    #[test]
+    fn annual_tax_needs_first_registration() {
+        let mut car = car(Some(2000), FuelType::Gasoline, "2020-01-01");
+        car.car_first_registration = None;
+        let result = annual_tax(&car, TaxType::AutomobileTax, 2024);
+        assert_eq!(result.unwrap_err(), MISSING_FIRST_REGISTRATION);
+    }
+
+    #[test]
+    fn weight_tax_per_started_half_tonne() {
+        let mut car = car(Some(2000), FuelType::Gasoline, "2020-01-01");
+        let inspection = date(2023, Month::January, 1);
+        for (weight, amount) in [(500, 8_200), (501, 16_400), (1000, 16_400), (1001, 24_600)] {
+            car.car_weight = Some(weight);
+            assert_eq!(weight_tax(&car, inspection).unwrap().amount, amount);
+        }
+        car.car_weight = None;
+        assert_eq!(weight_tax(&car, inspection).unwrap_err(), MISSING_WEIGHT);
+    }
+
+    #[test]
+    fn weight_tax_surcharge_by_months_since_registration() {
+        let car = car(Some(2000), FuelType::Gasoline, "2010-05-15");
+        for (inspection, surcharge, amount) in [
+            (date(2023, Month::April, 30), Surcharge::None, 16_400),
+            (date(2023, Month::May, 1), Surcharge::Over13Years, 22_800),
+            (date(2028, Month::April, 30), Surcharge::Over13Years, 22_800),
+            (date(2028, Month::May, 1), Surcharge::Over18Years, 25_200),
+        ] {
+            let assessment = weight_tax(&car, inspection).unwrap();
+            assert_eq!(assessment.surcharge, surcharge, "{}", inspection);
+            assert_eq!(assessment.amount, amount, "{}", inspection);
+        }
+    }
+
+    #[test]
+    fn light_vehicle_weight_tax() {
+        let light = car(Some(660), FuelType::Gasoline, "2010-05-15");
+        for (inspection, amount) in [
+            (date(2023, Month::April, 30), 6_600),
+            (date(2023, Month::May, 1), 8_200),
+            (date(2028, Month::May, 1), 8_800),
+        ] {
+            assert_eq!(weight_tax(&light, inspection).unwrap().amount, amount);
+        }
+        let hybrid = car(Some(1500), FuelType::Hybrid, "2000-01-01");
+        let assessment = weight_tax(&hybrid, date(2030, Month::May, 1)).unwrap();
+        assert_eq!(assessment.surcharge, Surcharge::None);
+    }
+}
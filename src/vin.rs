@@ -0,0 +1,441 @@
+use serde::Serialize;
+use time::OffsetDateTime;
+
+// ISO 3779 の VIN で使える文字ごとの値（I・O・Q は使わない）
+const TRANSLITERATION: &[(u8, u32)] = &[
+    (b'A', 1),
+    (b'B', 2),
+    (b'C', 3),
+    (b'D', 4),
+    (b'E', 5),
+    (b'F', 6),
+    (b'G', 7),
+    (b'H', 8),
+    (b'J', 1),
+    (b'K', 2),
+    (b'L', 3),
+    (b'M', 4),
+    (b'N', 5),
+    (b'P', 7),
+    (b'R', 9),
+    (b'S', 2),
+    (b'T', 3),
+    (b'U', 4),
+    (b'V', 5),
+    (b'W', 6),
+    (b'X', 7),
+    (b'Y', 8),
+    (b'Z', 9),
+];
+
+// チェックディジット（9 桁目）を計算するときの桁ごとの重み
+const WEIGHTS: [u32; 17] = [8, 7, 6, 5, 4, 3, 2, 10, 0, 9, 8, 7, 6, 5, 4, 3, 2];
+
+// 10 桁目の年式コード。1980 年から 30 年周期で繰り返す
+const MODEL_YEAR_CODES: &[u8] = b"ABCDEFGHJKLMNPRSTVWXY123456789";
+
+// 製造者識別コード（WMI）の先頭とメーカー。長いものから照合する
+const MANUFACTURERS: &[(&str, &str)] = &[
+    ("JA3", "Mitsubishi"),
+    ("JA4", "Mitsubishi"),
+    ("JA7", "Mitsubishi"),
+    ("JAA", "Isuzu"),
+    ("JAL", "Isuzu"),
+    ("JD", "Daihatsu"),
+    ("JF", "Subaru"),
+    ("JH", "Honda"),
+    ("JM", "Mazda"),
+    ("JN", "Nissan"),
+    ("JS", "Suzuki"),
+    ("JT", "Toyota"),
+    ("1C", "Chrysler"),
+    ("1FA", "Ford"),
+    ("1FM", "Ford"),
+    ("1FT", "Ford"),
+    ("1G", "General Motors"),
+    ("1HG", "Honda"),
+    ("1N", "Nissan"),
+    ("2HG", "Honda"),
+    ("2T", "Toyota"),
+    ("3VW", "Volkswagen"),
+    ("4T", "Toyota"),
+    ("5YJ", "Tesla"),
+    ("KMH", "Hyundai"),
+    ("KNA", "Kia"),
+    ("LRW", "Tesla"),
+    ("SAJ", "Jaguar"),
+    ("SAL", "Land Rover"),
+    ("SCC", "Lotus"),
+    ("VF1", "Renault"),
+    ("VF3", "Peugeot"),
+    ("W1K", "Mercedes-Benz"),
+    ("WAU", "Audi"),
+    ("WBA", "BMW"),
+    ("WBS", "BMW"),
+    ("WDB", "Mercedes-Benz"),
+    ("WDD", "Mercedes-Benz"),
+    ("WP0", "Porsche"),
+    ("WVW", "Volkswagen"),
+    ("YV1", "Volvo"),
+    ("ZFA", "Fiat"),
+    ("ZFF", "Ferrari"),
+];
+
+// 先頭 2 文字の範囲と国
+const COUNTRIES: &[(&str, &str, &str)] = &[
+    ("J0", "JZ", "Japan"),
+    ("KL", "KR", "South Korea"),
+    ("L0", "LZ", "China"),
+    ("SA", "SM", "United Kingdom"),
+    ("VF", "VR", "France"),
+    ("W0", "WZ", "Germany"),
+    ("YS", "YW", "Sweden"),
+    ("ZA", "ZR", "Italy"),
+    ("10", "1Z", "United States"),
+    ("2A", "2W", "Canada"),
+    ("3A", "3W", "Mexico"),
+    ("40", "5Z", "United States"),
+    ("6A", "6W", "Australia"),
+    ("9A", "9E", "Brazil"),
+];
+
+/// VIN・車台番号の誤り。code は API のエラーコードに使う
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VinError {
+    pub code: &'static str,
+    pub message: &'static str,
+}
+
+const INVALID_FORMAT: VinError = VinError {
+    code: "vin/invalid-format",
+    message: "Use a 17-character VIN or a chassis number such as ZVW30-1234567.",
+};
+
+const INVALID_CHARACTER: VinError = VinError {
+    code: "vin/invalid-character",
+    message: "A VIN cannot contain the letters I, O or Q.",
+};
+
+const INVALID_CHECK_DIGIT: VinError = VinError {
+    code: "vin/invalid-check-digit",
+    message: "The check digit of the VIN does not match. Check the VIN for typos.",
+};
+
+/// 番号の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VinKind {
+    /// ISO 3779 の 17 桁の VIN
+    Vin,
+    /// 国内の車台番号（型式-製造番号）
+    ChassisNumber,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckDigit {
+    pub expected: char,
+    pub actual: char,
+    /// 北米と中国ではチェックディジットが必須。それ以外の地域では一致しなくても誤りとしない
+    pub required: bool,
+}
+
+/// VIN・車台番号を読み取った結果
+#[derive(Debug, Clone, Serialize)]
+pub struct DecodedVin {
+    pub kind: VinKind,
+    /// 大文字・半角にそろえた番号
+    pub normalized: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wmi: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manufacturer: Option<&'static str>,
+    /// 10 桁目から読んだ年式。メーカーによっては年式を表さない
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_year: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub check_digit: Option<CheckDigit>,
+    /// 車台番号の型式の部分
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serial_number: Option<String>,
+}
+
+/// 全角英数字を半角に、英字を大文字にし、空白を除く
+pub fn normalize(input: &str) -> String {
+    input
+        .chars()
+        .filter_map(|c| match c {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0),
+            'ー' | '―' | '−' => Some('-'),
+            c => Some(c),
+        })
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+fn value(c: u8) -> Option<u32> {
+    if c.is_ascii_digit() {
+        return Some(u32::from(c - b'0'));
+    }
+    TRANSLITERATION
+        .iter()
+        .find(|(letter, _)| *letter == c)
+        .map(|(_, value)| *value)
+}
+
+fn check_digit(vin: &[u8]) -> Option<char> {
+    let mut sum = 0;
+    for (c, weight) in vin.iter().zip(WEIGHTS) {
+        sum += value(*c)? * weight;
+    }
+    Some(match sum % 11 {
+        10 => 'X',
+        remainder => char::from_digit(remainder, 10)?,
+    })
+}
+
+fn in_range(prefix: &str, (from, to): (&str, &str)) -> bool {
+    from <= prefix && prefix <= to
+}
+
+fn region(first: u8) -> &'static str {
+    match first {
+        b'A'..=b'H' => "Africa",
+        b'J'..=b'R' => "Asia",
+        b'S'..=b'Z' => "Europe",
+        b'1'..=b'5' => "North America",
+        b'6' | b'7' => "Oceania",
+        _ => "South America",
+    }
+}
+
+// 10 桁目の年式。北米では 7 桁目が英字なら 2010 年以降の周期になる。
+// それ以外は来年までで最も新しい年とする
+fn model_year(vin: &[u8], north_america: bool) -> Option<i32> {
+    let index = MODEL_YEAR_CODES.iter().position(|c| *c == vin[9])? as i32;
+    if north_america {
+        let cycle = if vin[6].is_ascii_alphabetic() { 1 } else { 0 };
+        return Some(1980 + index + 30 * cycle);
+    }
+    let latest = OffsetDateTime::now_utc().year() + 1;
+    (0..)
+        .map(|cycle| 1980 + index + 30 * cycle)
+        .take_while(|year| *year <= latest)
+        .last()
+}
+
+fn decode_vin(vin: String) -> Result<DecodedVin, VinError> {
+    let bytes = vin.as_bytes();
+    if !bytes.iter().all(u8::is_ascii_alphanumeric) {
+        return Err(INVALID_FORMAT);
+    }
+    if bytes.iter().any(|c| b"IOQ".contains(c)) {
+        return Err(INVALID_CHARACTER);
+    }
+
+    let north_america = (b'1'..=b'5').contains(&bytes[0]);
+    let required = north_america || bytes[0] == b'L';
+    let expected = check_digit(bytes).ok_or(INVALID_FORMAT)?;
+    let actual = char::from(bytes[8]);
+    if required && expected != actual {
+        return Err(INVALID_CHECK_DIGIT);
+    }
+
+    let manufacturer = MANUFACTURERS
+        .iter()
+        .filter(|(wmi, _)| vin.starts_with(wmi))
+        .max_by_key(|(wmi, _)| wmi.len())
+        .map(|(_, name)| *name);
+    let country = COUNTRIES
+        .iter()
+        .find(|(from, to, _)| in_range(&vin[..2], (from, to)))
+        .map(|(_, _, name)| *name);
+
+    Ok(DecodedVin {
+        kind: VinKind::Vin,
+        wmi: Some(vin[..3].to_string()),
+        region: Some(region(bytes[0])),
+        country,
+        manufacturer,
+        model_year: model_year(bytes, north_america),
+        check_digit: Some(CheckDigit {
+            expected,
+            actual,
+            required,
+        }),
+        model_code: None,
+        serial_number: None,
+        normalized: vin,
+    })
+}
+
+// 型式（英数字 10 文字まで）と製造番号（数字 8 桁まで）をハイフンでつないだもの
+fn decode_chassis_number(number: String) -> Result<DecodedVin, VinError> {
+    let Some((model_code, serial)) = number.split_once('-') else {
+        return Err(INVALID_FORMAT);
+    };
+    let valid_model_code = (2..=10).contains(&model_code.len())
+        && model_code.bytes().all(|c| c.is_ascii_alphanumeric())
+        && model_code.bytes().any(|c| c.is_ascii_alphabetic());
+    let valid_serial =
+        (1..=8).contains(&serial.len()) && serial.bytes().all(|c| c.is_ascii_digit());
+    if !valid_model_code || !valid_serial {
+        return Err(INVALID_FORMAT);
+    }
+
+    Ok(DecodedVin {
+        kind: VinKind::ChassisNumber,
+        wmi: None,
+        region: Some("Asia"),
+        country: Some("Japan"),
+        manufacturer: None,
+        model_year: None,
+        check_digit: None,
+        model_code: Some(model_code.to_string()),
+        serial_number: Some(serial.to_string()),
+        normalized: number,
+    })
+}
+
+/// VIN または車台番号を検証して読み取る
+pub fn decode(input: &str) -> Result<DecodedVin, VinError> {
+    let normalized = normalize(input);
+    if normalized.contains('-') {
+        decode_chassis_number(normalized)
+    } else if normalized.len() == 17 {
+        decode_vin(normalized)
+    } else {
+        Err(INVALID_FORMAT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_digit_x_for_remainder_ten() {
+        let decoded = decode("1M8GDM9AXKP042788").unwrap();
+        let check = decoded.check_digit.unwrap();
+        assert_eq!(check.expected, 'X');
+        assert!(check.required);
+        assert_eq!(decoded.region, Some("North America"));
+        assert_eq!(decoded.country, Some("United States"));
+    }
+
+    #[test]
+    fn check_digit_required_in_north_america_and_china() {
+        assert_eq!(
+            decode("1M8GDM9A1KP042788").unwrap_err(),
+            INVALID_CHECK_DIGIT
+        );
+        assert_eq!(
+            decode("LM8GDM9A1KP042788").unwrap_err(),
+            INVALID_CHECK_DIGIT
+        );
+        // それ以外の地域では不一致でも読み取る
+        let decoded = decode("JM8GDM9A1KP042788").unwrap();
+        let check = decoded.check_digit.unwrap();
+        assert!(!check.required);
+        assert_ne!(check.expected, check.actual);
+    }
+
+    #[test]
+    fn model_year_in_north_america_uses_seventh_character() {
+        // 7 桁目が数字なら 1980 年からの周期
+        assert_eq!(decode("1M8GDM9AXKP042788").unwrap().model_year, Some(1989));
+        // 7 桁目が英字なら 2010 年からの周期
+        assert_eq!(model_year(b"1HGCM8AA0AA004352", true), Some(2010));
+        assert_eq!(model_year(b"1HGCM8AA0YA004352", true), Some(2030));
+        assert_eq!(model_year(b"1HGCM820099004352", true), Some(2009));
+    }
+
+    #[test]
+    fn model_year_elsewhere_is_latest_cycle_until_next_year() {
+        let next_year = OffsetDateTime::now_utc().year() + 1;
+        for (code, year) in [(b'A', 1980), (b'Y', 2000), (b'1', 2001), (b'9', 2009)] {
+            let mut vin = *b"JHMCM56557C404453";
+            vin[9] = code;
+            let expected = (0..)
+                .map(|cycle| year + 30 * cycle)
+                .take_while(|y| *y <= next_year)
+                .last();
+            assert_eq!(model_year(&vin, false), expected);
+        }
+        assert_eq!(model_year(b"JHMCM56557C404453", false), Some(2007));
+        // 年式に使わない文字
+        assert_eq!(model_year(b"JHMCM5655UC404453", false), None);
+    }
+
+    #[test]
+    fn rejects_i_o_q_and_wrong_length() {
+        assert_eq!(decode("JHMCM56557I404453").unwrap_err(), INVALID_CHARACTER);
+        assert_eq!(decode("JHMCM56557O404453").unwrap_err(), INVALID_CHARACTER);
+        assert_eq!(decode("JHMCM56557Q404453").unwrap_err(), INVALID_CHARACTER);
+        assert_eq!(decode("JHMCM56557C40445").unwrap_err(), INVALID_FORMAT);
+        assert_eq!(decode("JHMCM56557C4044531").unwrap_err(), INVALID_FORMAT);
+        assert_eq!(decode("JHMCM56557C40445!").unwrap_err(), INVALID_FORMAT);
+    }
+
+    #[test]
+    fn manufacturer_prefers_longest_wmi() {
+        let decoded = decode("JHMCM56557C404453").unwrap();
+        assert_eq!(decoded.wmi.as_deref(), Some("JHM"));
+        assert_eq!(decoded.manufacturer, Some("Honda"));
+        assert_eq!(decoded.country, Some("Japan"));
+        assert_eq!(
+            decode("JAAXX565X7C404453").unwrap().manufacturer,
+            Some("Isuzu")
+        );
+        assert_eq!(decode("JAXXX565X7C404453").unwrap().manufacturer, None);
+    }
+
+    #[test]
+    fn country_ranges_are_inclusive() {
+        let country = |prefix: &str| {
+            COUNTRIES
+                .iter()
+                .find(|(from, to, _)| in_range(prefix, (from, to)))
+                .map(|(_, _, name)| *name)
+        };
+        assert_eq!(country("J0"), Some("Japan"));
+        assert_eq!(country("JZ"), Some("Japan"));
+        assert_eq!(country("KL"), Some("South Korea"));
+        assert_eq!(country("KR"), Some("South Korea"));
+        assert_eq!(country("KS"), None);
+        assert_eq!(country("SM"), Some("United Kingdom"));
+        assert_eq!(country("SN"), None);
+        assert_eq!(country("9E"), Some("Brazil"));
+        assert_eq!(country("9F"), None);
+    }
+
+    #[test]
+    fn normalizes_full_width_and_spaces() {
+        assert_eq!(normalize("ｚｖｗ３０ー１２３４５６７"), "ZVW30-1234567");
+        assert_eq!(normalize(" jhm cm56557c404453 "), "JHMCM56557C404453");
+    }
+
+    #[test]
+    fn chassis_number_length_limits() {
+        let decoded = decode("ZVW30-1234567").unwrap();
+        assert_eq!(decoded.kind, VinKind::ChassisNumber);
+        assert_eq!(decoded.model_code.as_deref(), Some("ZVW30"));
+        assert_eq!(decoded.serial_number.as_deref(), Some("1234567"));
+
+        assert!(decode("Z1-1").is_ok());
+        assert!(decode("ZVW30ABCDE-12345678").is_ok());
+        assert_eq!(decode("Z-1").unwrap_err(), INVALID_FORMAT);
+        assert_eq!(decode("ZVW30ABCDEF-1").unwrap_err(), INVALID_FORMAT);
+        assert_eq!(decode("ZVW30-123456789").unwrap_err(), INVALID_FORMAT);
+        assert_eq!(decode("ZVW30-").unwrap_err(), INVALID_FORMAT);
+        // 型式には英字が必要
+        assert_eq!(decode("12345-1234567").unwrap_err(), INVALID_FORMAT);
+        assert_eq!(decode("ZVW30-12A4567").unwrap_err(), INVALID_FORMAT);
+    }
+}